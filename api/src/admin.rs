@@ -0,0 +1,234 @@
+// Operator endpoints under /admin, served on their own listener so they can
+// be firewalled apart from the public API. TCP admin listeners must be on a
+// loopback address. With an admin token every request must carry it as
+// `Authorization: Bearer <token>`; without one only a Unix socket listener,
+// guarded by its file permissions, serves requests and TCP answers 403.
+use crate::alerts::Silence;
+use crate::listen::ListenAddr;
+use crate::webhooks::{Delivery, WebhookConfig, WebhookEvent, WebhookInfo};
+use crate::reload::{self, ReloadResponse};
+use crate::{now_ms, parse_duration, AppState, ErrorResponse, LiveLimits, TxDroppedEvent, ValidJson};
+use axum::extract::{Path, Request, State};
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Json, Response};
+use axum::routing::{delete, get, post, MethodRouter};
+use axum::Router;
+use serde::{Deserialize, Serialize};
+use std::sync::Arc;
+use trng::{EntropySource, SourceStatus};
+
+// Compared through blake3 hashes, whose equality is constant-time
+#[derive(Clone)]
+pub struct AdminToken(Arc<blake3::Hash>);
+
+impl AdminToken {
+    pub fn new(token: &str) -> Self {
+        Self(Arc::new(blake3::hash(token.as_bytes())))
+    }
+
+    fn matches(&self, presented: &str) -> bool {
+        *self.0 == blake3::hash(presented.as_bytes())
+    }
+}
+
+#[derive(Clone)]
+pub struct AdminConfig {
+    pub listen: ListenAddr,
+    pub token: Option<AdminToken>,
+}
+
+impl AdminConfig {
+    pub fn new(listen: ListenAddr) -> Result<Self, String> {
+        if !listen.is_local() {
+            return Err(format!("admin listener {} is not a loopback address", listen));
+        }
+        Ok(Self { listen, token: None })
+    }
+
+    pub fn with_token(mut self, token: AdminToken) -> Self {
+        self.token = Some(token);
+        self
+    }
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SilenceRequest {
+    pub rule: String,
+    // e.g. "30m" or "2h"
+    pub duration: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReseedResponse {
+    pub reseeds: u64,
+    pub sources: Vec<SourceStatus>,
+}
+
+pub(crate) fn routes(config: &AdminConfig, limits: &LiveLimits) -> Router<AppState> {
+    let routes: Vec<(&str, MethodRouter<AppState>)> = vec![
+        ("/admin/shutdown", post(shutdown)),
+        ("/admin/reload", post(reload_config)),
+        ("/admin/trng/reseed", post(reseed)),
+        ("/admin/trng/sources/:name/enable", post(enable_source)),
+        ("/admin/trng/sources/:name/disable", post(disable_source)),
+        ("/admin/webhooks", get(list_webhooks).post(register_webhook)),
+        ("/admin/webhooks/deliveries", get(webhook_deliveries)),
+        ("/admin/webhooks/:id", delete(remove_webhook)),
+        ("/admin/alerts/silences", get(list_silences).post(silence_alert)),
+        ("/admin/alerts/silences/:rule", delete(unsilence_alert)),
+        ("/admin/mempool/:hash", delete(remove_transaction)),
+    ];
+    let token = config.token.clone();
+    let unix = matches!(config.listen, ListenAddr::Unix(_));
+    routes
+        .into_iter()
+        .fold(Router::new(), |router, (path, handler)| router.route(path, limits.route(path, handler)))
+        .route_layer(middleware::from_fn(move |request: Request, next: Next| {
+            authorize(token.clone(), unix, request, next)
+        }))
+}
+
+async fn authorize(token: Option<AdminToken>, unix: bool, request: Request, next: Next) -> Response {
+    let Some(token) = token else {
+        if unix {
+            return next.run(request).await;
+        }
+        return denied(StatusCode::FORBIDDEN, "admin over TCP needs an admin token");
+    };
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix("Bearer "));
+    match presented {
+        Some(presented) if token.matches(presented) => next.run(request).await,
+        _ => denied(StatusCode::UNAUTHORIZED, "missing or invalid admin token"),
+    }
+}
+
+fn denied(status: StatusCode, error: &str) -> Response {
+    (status, Json(ErrorResponse { error: error.to_string() })).into_response()
+}
+
+// Stops both listeners; open requests finish and queued store writes are
+// flushed before the process exits
+async fn shutdown(State(state): State<AppState>) -> StatusCode {
+    println!("Admin: shutdown requested");
+    state.shutdown.send_replace(true);
+    StatusCode::ACCEPTED
+}
+
+// Same as SIGHUP, but reports a rejected config to the caller
+async fn reload_config(State(state): State<AppState>) -> Result<Json<ReloadResponse>, (StatusCode, Json<ErrorResponse>)> {
+    match reload::reload(&state) {
+        Ok(changes) => Ok(Json(ReloadResponse { changes })),
+        Err(error) => {
+            eprintln!("Config reload rejected: {}", error);
+            Err((StatusCode::UNPROCESSABLE_ENTITY, Json(ErrorResponse { error })))
+        }
+    }
+}
+
+async fn reseed(State(state): State<AppState>) -> Json<ReseedResponse> {
+    state.trng.reseed_from_sources().await;
+    println!("Admin: entropy pool reseeded");
+    Json(ReseedResponse {
+        reseeds: state.trng.reseeds(),
+        sources: state.trng.sources(),
+    })
+}
+
+async fn enable_source(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<SourceStatus>>, (StatusCode, Json<ErrorResponse>)> {
+    set_source(state, &name, true)
+}
+
+async fn disable_source(
+    State(state): State<AppState>,
+    Path(name): Path<String>,
+) -> Result<Json<Vec<SourceStatus>>, (StatusCode, Json<ErrorResponse>)> {
+    set_source(state, &name, false)
+}
+
+fn set_source(
+    state: AppState,
+    name: &str,
+    enabled: bool,
+) -> Result<Json<Vec<SourceStatus>>, (StatusCode, Json<ErrorResponse>)> {
+    let rejected = |status, e: trng::SourceError| (status, Json(ErrorResponse { error: e.to_string() }));
+    let source: EntropySource = name.parse().map_err(|e| rejected(StatusCode::NOT_FOUND, e))?;
+    state
+        .trng
+        .set_source_enabled(source, enabled)
+        .map_err(|e| rejected(StatusCode::CONFLICT, e))?;
+    println!("Admin: entropy source {} {}", source, if enabled { "enabled" } else { "disabled" });
+    Ok(Json(state.trng.sources()))
+}
+
+async fn list_webhooks(State(state): State<AppState>) -> Json<Vec<WebhookInfo>> {
+    Json(state.webhooks.list())
+}
+
+async fn register_webhook(
+    State(state): State<AppState>,
+    ValidJson(config): ValidJson<WebhookConfig>,
+) -> Result<(StatusCode, Json<WebhookInfo>), (StatusCode, Json<ErrorResponse>)> {
+    let info = state
+        .webhooks
+        .register(config)
+        .map_err(|error| (StatusCode::UNPROCESSABLE_ENTITY, Json(ErrorResponse { error })))?;
+    println!("Admin: webhook {} registered for {}", info.id, info.url);
+    Ok((StatusCode::CREATED, Json(info)))
+}
+
+async fn remove_webhook(State(state): State<AppState>, Path(id): Path<u64>) -> StatusCode {
+    if !state.webhooks.remove(id) {
+        return StatusCode::NOT_FOUND;
+    }
+    println!("Admin: webhook {} removed", id);
+    StatusCode::NO_CONTENT
+}
+
+// Every delivery attempt, oldest first, for the last 256 attempts
+async fn webhook_deliveries(State(state): State<AppState>) -> Json<Vec<Delivery>> {
+    Json(state.webhooks.deliveries())
+}
+
+async fn list_silences(State(state): State<AppState>) -> Json<Vec<Silence>> {
+    Json(state.alerts.silences())
+}
+
+// Mutes webhook notifications for a rule; its alerts stay listed at /alerts
+async fn silence_alert(
+    State(state): State<AppState>,
+    ValidJson(request): ValidJson<SilenceRequest>,
+) -> Result<Json<Silence>, (StatusCode, Json<ErrorResponse>)> {
+    let rejected = |status, error| (status, Json(ErrorResponse { error }));
+    let duration = parse_duration(&request.duration).map_err(|e| rejected(StatusCode::BAD_REQUEST, e))?;
+    let silence = state
+        .alerts
+        .silence(&request.rule, now_ms() + duration.as_millis() as u64)
+        .map_err(|e| rejected(StatusCode::NOT_FOUND, e))?;
+    println!("Admin: alert rule {} silenced for {}", request.rule, request.duration);
+    Ok(Json(silence))
+}
+
+async fn remove_transaction(State(state): State<AppState>, Path(hash): Path<String>) -> StatusCode {
+    let Some(tx) = state.consensus.remove_transaction(&hash) else {
+        return StatusCode::NOT_FOUND;
+    };
+    println!("Admin: transaction {} removed from the mempool", hash);
+    state.publish(WebhookEvent::TxDropped, &TxDroppedEvent::new(&tx, "evicted"));
+    StatusCode::NO_CONTENT
+}
+
+async fn unsilence_alert(State(state): State<AppState>, Path(rule): Path<String>) -> StatusCode {
+    if !state.alerts.unsilence(&rule) {
+        return StatusCode::NOT_FOUND;
+    }
+    println!("Admin: alert rule {} unsilenced", rule);
+    StatusCode::NO_CONTENT
+}
@@ -0,0 +1,323 @@
+// Alert rules over the node's own metrics. Every few seconds each rule is
+// checked against fresh observations; a rule whose condition holds becomes an
+// active alert until it stops holding. Alerts are sent to webhooks when they
+// fire and when they resolve, unless the rule is silenced, in which case
+// they are still listed but marked silenced.
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "condition", rename_all = "snake_case")]
+pub enum AlertCondition {
+    // Shannon entropy of the latest health sample, in bits per byte
+    EntropyBelow { threshold: f64 },
+    // Time since the finalized height last advanced
+    FinalizationStalled { seconds: u64 },
+}
+
+// e.g. `{"name": "low-entropy", "condition": "entropy_below", "threshold": 7.5}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct AlertRule {
+    pub name: String,
+    #[serde(flatten)]
+    pub condition: AlertCondition,
+}
+
+// What the rules are checked against
+#[derive(Debug, Clone, Copy)]
+pub struct Observations {
+    // None until the first health sample
+    pub entropy: Option<f64>,
+    pub stalled_for: Duration,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub rule: String,
+    pub state: AlertState,
+    // Unix time in milliseconds the rule started holding
+    pub since: u64,
+    // The observed value and the rule's threshold
+    pub value: f64,
+    pub threshold: f64,
+    pub message: String,
+    // Unix time in milliseconds the rule's silence ends, if silenced
+    pub silenced_until: Option<u64>,
+    // Whether webhooks were told it fired, so they hear of its resolution
+    #[serde(skip)]
+    notified: bool,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Silence {
+    pub rule: String,
+    pub until: u64,
+}
+
+#[derive(Default)]
+struct State {
+    rules: Vec<AlertRule>,
+    active: HashMap<String, Alert>,
+    silences: HashMap<String, u64>,
+}
+
+#[derive(Clone, Default)]
+pub struct AlertManager {
+    state: Arc<Mutex<State>>,
+}
+
+impl AlertManager {
+    pub fn new(rules: Vec<AlertRule>) -> Result<Self, String> {
+        validate(&rules)?;
+        Ok(Self {
+            state: Arc::new(Mutex::new(State { rules, ..State::default() })),
+        })
+    }
+
+    // Replaces the rules on a config reload. Alerts of rules that were
+    // removed or changed are dropped without notifying webhooks, and so are
+    // silences of rules that were removed
+    pub fn set_rules(&self, rules: Vec<AlertRule>) -> Result<Vec<String>, String> {
+        validate(&rules)?;
+        let mut state = self.state.lock().unwrap();
+        let mut changes = Vec::new();
+        for old in &state.rules {
+            match rules.iter().find(|rule| rule.name == old.name) {
+                None => changes.push(format!("alert rule {} removed", old.name)),
+                Some(rule) if rule != old => changes.push(format!("alert rule {} changed", old.name)),
+                Some(_) => {}
+            }
+        }
+        for rule in &rules {
+            if !state.rules.iter().any(|old| old.name == rule.name) {
+                changes.push(format!("alert rule {} added", rule.name));
+            }
+        }
+        let kept: Vec<String> = rules
+            .iter()
+            .filter(|rule| state.rules.contains(rule))
+            .map(|rule| rule.name.clone())
+            .collect();
+        state.active.retain(|name, _| kept.contains(name));
+        state.silences.retain(|name, _| rules.iter().any(|rule| &rule.name == name));
+        state.rules = rules;
+        Ok(changes)
+    }
+
+    // Checks every rule at `now` (Unix milliseconds) and returns the alerts
+    // that fired or resolved and are not silenced
+    pub fn evaluate(&self, observations: Observations, now: u64) -> Vec<Alert> {
+        let mut state = self.state.lock().unwrap();
+        state.silences.retain(|_, until| *until > now);
+        let mut changed = Vec::new();
+        for rule in state.rules.clone() {
+            let breach = breach(&rule.condition, &observations);
+            let silenced_until = state.silences.get(&rule.name).copied();
+            match (breach, state.active.contains_key(&rule.name)) {
+                (Some((value, threshold, message)), false) => {
+                    let alert = Alert {
+                        rule: rule.name.clone(),
+                        state: AlertState::Firing,
+                        since: now,
+                        value,
+                        threshold,
+                        message,
+                        silenced_until,
+                        notified: silenced_until.is_none(),
+                    };
+                    if alert.notified {
+                        changed.push(alert.clone());
+                    }
+                    state.active.insert(rule.name, alert);
+                }
+                (Some((value, _, message)), true) => {
+                    let alert = state.active.get_mut(&rule.name).expect("active");
+                    alert.value = value;
+                    alert.message = message;
+                    alert.silenced_until = silenced_until;
+                    // Still firing when its silence ran out
+                    if !alert.notified && silenced_until.is_none() {
+                        alert.notified = true;
+                        changed.push(alert.clone());
+                    }
+                }
+                (None, true) => {
+                    let mut alert = state.active.remove(&rule.name).expect("active");
+                    alert.state = AlertState::Resolved;
+                    if alert.notified && silenced_until.is_none() {
+                        changed.push(alert);
+                    }
+                }
+                (None, false) => {}
+            }
+        }
+        changed
+    }
+
+    // Firing alerts, by rule name
+    pub fn active(&self) -> Vec<Alert> {
+        let state = self.state.lock().unwrap();
+        let mut alerts: Vec<Alert> = state.active.values().cloned().collect();
+        alerts.sort_by(|a, b| a.rule.cmp(&b.rule));
+        alerts
+    }
+
+    pub fn silence(&self, rule: &str, until: u64) -> Result<Silence, String> {
+        let mut state = self.state.lock().unwrap();
+        if !state.rules.iter().any(|r| r.name == rule) {
+            return Err(format!("no alert rule '{}'", rule));
+        }
+        state.silences.insert(rule.to_string(), until);
+        if let Some(alert) = state.active.get_mut(rule) {
+            alert.silenced_until = Some(until);
+        }
+        Ok(Silence { rule: rule.to_string(), until })
+    }
+
+    // Returns false if the rule was not silenced
+    pub fn unsilence(&self, rule: &str) -> bool {
+        let mut state = self.state.lock().unwrap();
+        if let Some(alert) = state.active.get_mut(rule) {
+            alert.silenced_until = None;
+        }
+        state.silences.remove(rule).is_some()
+    }
+
+    pub fn silences(&self) -> Vec<Silence> {
+        let state = self.state.lock().unwrap();
+        let mut silences: Vec<Silence> = state
+            .silences
+            .iter()
+            .map(|(rule, &until)| Silence { rule: rule.clone(), until })
+            .collect();
+        silences.sort_by(|a, b| a.rule.cmp(&b.rule));
+        silences
+    }
+}
+
+fn validate(rules: &[AlertRule]) -> Result<(), String> {
+    let mut names = std::collections::HashSet::new();
+    for rule in rules {
+        if !names.insert(rule.name.as_str()) {
+            return Err(format!("alert rule '{}' is defined twice", rule.name));
+        }
+    }
+    Ok(())
+}
+
+// The observed value, threshold and a description when the condition holds
+fn breach(condition: &AlertCondition, observations: &Observations) -> Option<(f64, f64, String)> {
+    match *condition {
+        AlertCondition::EntropyBelow { threshold } => {
+            let entropy = observations.entropy?;
+            (entropy < threshold).then(|| {
+                (entropy, threshold, format!("TRNG entropy {:.4} bits per byte is below {}", entropy, threshold))
+            })
+        }
+        AlertCondition::FinalizationStalled { seconds } => {
+            let stalled = observations.stalled_for.as_secs();
+            (stalled >= seconds).then(|| {
+                (stalled as f64, seconds as f64, format!("no block finalized for {}s", stalled))
+            })
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entropy_below(name: &str, threshold: f64) -> AlertRule {
+        AlertRule { name: name.to_string(), condition: AlertCondition::EntropyBelow { threshold } }
+    }
+
+    fn entropy(value: f64) -> Observations {
+        Observations { entropy: Some(value), stalled_for: Duration::ZERO }
+    }
+
+    fn states(alerts: &[Alert]) -> Vec<(&str, AlertState)> {
+        alerts.iter().map(|alert| (alert.rule.as_str(), alert.state)).collect()
+    }
+
+    #[test]
+    fn test_invalid_rules_and_silences_are_rejected() {
+        let twice = vec![entropy_below("low", 7.5), entropy_below("low", 7.0)];
+        assert_eq!(AlertManager::new(twice.clone()).err().unwrap(), "alert rule 'low' is defined twice");
+
+        let alerts = AlertManager::new(vec![entropy_below("low", 7.5)]).unwrap();
+        assert!(alerts.set_rules(twice).is_err());
+        assert_eq!(alerts.silence("missing", 10).unwrap_err(), "no alert rule 'missing'");
+        assert!(!alerts.unsilence("low"));
+        assert!(alerts.silences().is_empty());
+
+        let json = r#"{"name": "low", "condition": "entropy_below", "threshold": 7.5}"#;
+        let rule: AlertRule = serde_json::from_str(json).unwrap();
+        assert_eq!(rule, entropy_below("low", 7.5));
+        assert!(serde_json::from_str::<AlertRule>(r#"{"name": "low", "condition": "peers_below"}"#).is_err());
+    }
+
+    #[test]
+    fn test_alerts_fire_and_resolve_once() {
+        let condition = AlertCondition::FinalizationStalled { seconds: 30 };
+        let stalled = AlertRule { name: "stalled".to_string(), condition };
+        let alerts = AlertManager::new(vec![entropy_below("low", 7.5), stalled]).unwrap();
+        // No health sample yet: the entropy rule cannot hold
+        let quiet = Observations { entropy: None, stalled_for: Duration::from_secs(29) };
+        assert!(alerts.evaluate(quiet, 1).is_empty());
+
+        assert_eq!(states(&alerts.evaluate(entropy(7.0), 2)), vec![("low", AlertState::Firing)]);
+        assert!(alerts.evaluate(entropy(6.0), 3).is_empty());
+        let active = alerts.active();
+        assert_eq!((active[0].since, active[0].value, active[0].threshold), (2, 6.0, 7.5));
+        assert_eq!(states(&alerts.evaluate(entropy(7.9), 4)), vec![("low", AlertState::Resolved)]);
+        assert!(alerts.active().is_empty());
+    }
+
+    #[test]
+    fn test_silenced_alerts_are_listed_but_not_sent() {
+        let alerts = AlertManager::new(vec![entropy_below("low", 7.5)]).unwrap();
+        alerts.silence("low", 100).unwrap();
+        assert!(alerts.evaluate(entropy(7.0), 10).is_empty());
+        assert_eq!(alerts.active()[0].silenced_until, Some(100));
+
+        // Still firing when the silence ends: sent then
+        let fired = alerts.evaluate(entropy(7.0), 100);
+        assert_eq!(states(&fired), vec![("low", AlertState::Firing)]);
+        assert_eq!((fired[0].since, fired[0].silenced_until), (10, None));
+        assert!(alerts.silences().is_empty());
+
+        // Resolving while silenced is not sent either
+        alerts.silence("low", 300).unwrap();
+        assert!(alerts.evaluate(entropy(7.9), 200).is_empty());
+        assert!(alerts.active().is_empty());
+        assert!(alerts.unsilence("low"));
+    }
+
+    #[test]
+    fn test_changed_rules_drop_their_alerts_and_silences() {
+        let alerts = AlertManager::new(vec![entropy_below("low", 7.5), entropy_below("lower", 7.2)]).unwrap();
+        alerts.evaluate(entropy(7.0), 1);
+        alerts.silence("lower", 100).unwrap();
+
+        let changes = alerts
+            .set_rules(vec![entropy_below("low", 7.6), entropy_below("other", 5.0)])
+            .unwrap();
+        assert_eq!(
+            changes,
+            vec!["alert rule low changed", "alert rule lower removed", "alert rule other added"]
+        );
+        assert!(alerts.active().is_empty());
+        assert!(alerts.silences().is_empty());
+        // The changed rule fires anew rather than resolving
+        assert_eq!(states(&alerts.evaluate(entropy(7.0), 2)), vec![("low", AlertState::Firing)]);
+    }
+}
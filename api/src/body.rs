@@ -0,0 +1,251 @@
+// JSON request bodies with errors a client can act on. Where axum's `Json`
+// answers with a bare line of text, a body that does not fit its type gets
+// a 422 naming the field by its path, what was expected there and, for an
+// enum, the values it accepts. Malformed JSON is still a 400, with the line
+// and column, and a body that is not JSON a 415.
+use axum::async_trait;
+use axum::body::Bytes;
+use axum::extract::{FromRequest, Request};
+use axum::http::{header, HeaderMap, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::error::Category;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BodyError {
+    pub error: String,
+    // Path of the offending field, e.g. `txs[0].nonce`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub field: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub expected: Option<String>,
+    // Values an enum field accepts
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub allowed: Vec<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub line: Option<usize>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub column: Option<usize>,
+}
+
+#[derive(Debug)]
+pub struct BodyRejection {
+    pub status: StatusCode,
+    // Boxed, as it is returned in every body's Result
+    pub body: Box<BodyError>,
+}
+
+impl BodyRejection {
+    pub fn new(status: StatusCode, error: impl Into<String>) -> Self {
+        let body = BodyError { error: error.into(), field: None, expected: None, allowed: Vec::new(), line: None, column: None };
+        Self { status, body: Box::new(body) }
+    }
+
+    // `path` is where in the body deserializing stopped
+    fn from_json(path: Option<String>, inner: &serde_json::Error) -> Self {
+        let text = inner.to_string();
+        // serde_json appends the position, which is reported on its own
+        let message = text.rsplit_once(" at line ").map_or(text.as_str(), |(message, _)| message);
+        if inner.classify() != Category::Data {
+            let mut rejection = Self::new(StatusCode::BAD_REQUEST, format!("malformed JSON: {}", message));
+            rejection.body.line = Some(inner.line());
+            rejection.body.column = Some(inner.column());
+            return rejection;
+        }
+
+        let missing = message.strip_prefix("missing field `").and_then(|field| field.strip_suffix('`'));
+        let field = match (path, missing) {
+            (Some(path), Some(missing)) => Some(format!("{}.{}", path, missing)),
+            (None, Some(missing)) => Some(missing.to_string()),
+            (path, None) => path,
+        };
+        let expected = message.split_once("expected ").map(|(_, expected)| expected);
+        let allowed = match expected {
+            Some(expected) if message.starts_with("unknown variant") => quoted(expected),
+            _ => Vec::new(),
+        };
+
+        let mut rejection = Self::new(
+            StatusCode::UNPROCESSABLE_ENTITY,
+            match &field {
+                Some(field) => format!("invalid field {}: {}", field, message),
+                None => format!("invalid body: {}", message),
+            },
+        );
+        rejection.body.field = field;
+        rejection.body.expected = expected.map(|expected| expected.replace('`', "\""));
+        rejection.body.allowed = allowed;
+        rejection
+    }
+}
+
+impl IntoResponse for BodyRejection {
+    fn into_response(self) -> Response {
+        (self.status, Json(*self.body)).into_response()
+    }
+}
+
+// The `backticked` names in serde's list of expected values
+fn quoted(expected: &str) -> Vec<String> {
+    expected.split('`').skip(1).step_by(2).map(str::to_string).collect()
+}
+
+fn is_json(headers: &HeaderMap) -> bool {
+    let Some(content_type) = headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let mime = content_type.split(';').next().unwrap_or_default().trim();
+    mime == "application/json" || (mime.starts_with("application/") && mime.ends_with("+json"))
+}
+
+pub fn parse<T: DeserializeOwned>(bytes: &[u8]) -> Result<T, BodyRejection> {
+    let mut deserializer = serde_json::Deserializer::from_slice(bytes);
+    let value = serde_path_to_error::deserialize(&mut deserializer).map_err(|e| {
+        let path = e.path().to_string();
+        BodyRejection::from_json((path != ".").then_some(path), e.inner())
+    })?;
+    deserializer.end().map_err(|e| BodyRejection::from_json(None, &e))?;
+    Ok(value)
+}
+
+// A JSON body of type `T`, in place of axum's `Json` extractor
+pub struct ValidJson<T>(pub T);
+
+#[async_trait]
+impl<T: DeserializeOwned, S: Send + Sync> FromRequest<S> for ValidJson<T> {
+    type Rejection = BodyRejection;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !is_json(request.headers()) {
+            return Err(BodyRejection::new(
+                StatusCode::UNSUPPORTED_MEDIA_TYPE,
+                "expected a request body with Content-Type: application/json",
+            ));
+        }
+        let bytes = Bytes::from_request(request, state)
+            .await
+            .map_err(|e| BodyRejection::new(e.status(), e.body_text()))?;
+        parse(&bytes).map(ValidJson)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::routing::post;
+    use axum::Router;
+    use serde::Deserialize;
+    use tower::ServiceExt;
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Submit {
+        sender: u32,
+        txs: Vec<Tx>,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[allow(dead_code)]
+    struct Tx {
+        nonce: u64,
+        kind: Kind,
+    }
+
+    #[derive(Debug, Deserialize)]
+    #[serde(rename_all = "snake_case")]
+    enum Kind {
+        Transfer,
+        Bond,
+    }
+
+    fn rejection(json: &str) -> BodyRejection {
+        parse::<Submit>(json.as_bytes()).unwrap_err()
+    }
+
+    #[test]
+    fn test_wrong_fields_are_422_with_their_path() {
+        let wrong_type = rejection(r#"{"sender": 1, "txs": [{"nonce": "x", "kind": "bond"}]}"#);
+        assert_eq!(wrong_type.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(wrong_type.body.error, "invalid field txs[0].nonce: invalid type: string \"x\", expected u64");
+        assert_eq!(wrong_type.body.field.as_deref(), Some("txs[0].nonce"));
+        assert_eq!(wrong_type.body.expected.as_deref(), Some("u64"));
+        assert!(wrong_type.body.allowed.is_empty() && wrong_type.body.line.is_none());
+
+        let missing = rejection(r#"{"txs": []}"#);
+        assert_eq!(missing.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(missing.body.error, "invalid field sender: missing field `sender`");
+        assert_eq!(missing.body.field.as_deref(), Some("sender"));
+        assert_eq!(missing.body.expected, None);
+
+        let nested = rejection(r#"{"sender": 1, "txs": [{"nonce": 1}, {"nonce": 2}]}"#);
+        assert_eq!(nested.body.field.as_deref(), Some("txs[0].kind"));
+
+        let variant = rejection(r#"{"sender": 1, "txs": [{"nonce": 1, "kind": "stake"}]}"#);
+        assert_eq!(variant.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(variant.body.field.as_deref(), Some("txs[0].kind"));
+        assert_eq!(variant.body.expected.as_deref(), Some("\"transfer\" or \"bond\""));
+        assert_eq!(variant.body.allowed, ["transfer", "bond"]);
+
+        let not_an_object = rejection(r#""text""#);
+        assert_eq!(not_an_object.status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(not_an_object.body.field, None);
+        assert_eq!(not_an_object.body.error, "invalid body: invalid type: string \"text\", expected struct Submit");
+    }
+
+    #[test]
+    fn test_malformed_json_is_400_with_its_position() {
+        let truncated = rejection("{\n  \"sender\": 1,");
+        assert_eq!(truncated.status, StatusCode::BAD_REQUEST);
+        assert_eq!(truncated.body.error, "malformed JSON: EOF while parsing a value");
+        assert_eq!((truncated.body.line, truncated.body.column), (Some(2), Some(14)));
+        assert_eq!(truncated.body.field, None);
+
+        let trailing = rejection(r#"{"sender": 1, "txs": []} x"#);
+        assert_eq!(trailing.status, StatusCode::BAD_REQUEST);
+        assert_eq!(trailing.body.error, "malformed JSON: trailing characters");
+        assert_eq!((trailing.body.line, trailing.body.column), (Some(1), Some(26)));
+    }
+
+    #[tokio::test]
+    async fn test_extractor_answers_with_the_error_body() {
+        let handler = |ValidJson(submit): ValidJson<Submit>| async move { submit.txs.len().to_string() };
+        let app: Router = Router::new().route("/tx", post(handler));
+        let request = |content_type: Option<&str>, body: &str| {
+            let mut request = Request::builder().method("POST").uri("/tx");
+            if let Some(content_type) = content_type {
+                request = request.header(header::CONTENT_TYPE, content_type);
+            }
+            request.body(Body::from(body.to_string())).unwrap()
+        };
+        let json_of = |response: Response| async move {
+            let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+            serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()
+        };
+
+        let body = r#"{"sender": 1, "txs": [{"nonce": 1, "kind": "stake"}]}"#;
+        let response = app.clone().oneshot(request(Some("application/json"), body)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            json_of(response).await,
+            serde_json::json!({
+                "error": "invalid field txs[0].kind: unknown variant `stake`, expected `transfer` or `bond`",
+                "field": "txs[0].kind",
+                "expected": "\"transfer\" or \"bond\"",
+                "allowed": ["transfer", "bond"],
+            })
+        );
+
+        let response = app.clone().oneshot(request(None, body)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(
+            json_of(response).await["error"],
+            "expected a request body with Content-Type: application/json"
+        );
+
+        let valid = r#"{"sender": 1, "txs": [{"nonce": 1, "kind": "bond"}]}"#;
+        let response = app.oneshot(request(Some("application/vnd.api+json; charset=utf-8"), valid)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+}
@@ -0,0 +1,145 @@
+// Conditional GETs for the heavily polled read endpoints. Each response
+// carries a weak ETag for the version of the state it was built from, and a
+// request whose `If-None-Match` names the current version gets 304 without
+// the body being built. The replicated state only changes when a block is
+// finalized, so the finalized block id versions `/finalized`, `/params` and
+// `/staking/validators`. `/status` has no single version behind it and is
+// tagged by a hash of its body. `Cache-Control` lets a reverse proxy serve
+// the same response for a second before revalidating.
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use consensus::BlockId;
+use serde::Serialize;
+
+const CACHE_CONTROL: &str = "public, max-age=1, must-revalidate";
+
+// Weak ETag of the finalized block a response reflects
+pub fn finalized_etag(route: &str, finalized: Option<BlockId>) -> String {
+    let version = finalized.map(|id| id.to_string()).unwrap_or_else(|| "genesis".to_string());
+    format!("W/\"{}-{}\"", route, version)
+}
+
+// Weak ETag of a serialized body
+pub fn content_etag(route: &str, body: &[u8]) -> String {
+    format!("W/\"{}-{}\"", route, &blake3::hash(body).to_hex()[..32])
+}
+
+// Weak comparison: `If-None-Match: *` or any listed tag, with or without
+// its `W/` prefix, matches
+pub fn not_modified(headers: &HeaderMap, etag: &str) -> bool {
+    let bare = etag.trim_start_matches("W/");
+    headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|tag| tag.trim().trim_start_matches("W/"))
+        .any(|tag| tag == "*" || tag == bare)
+}
+
+// 304 when the request already holds `etag`, otherwise the body built now
+pub fn respond<T: Serialize>(headers: &HeaderMap, etag: String, body: impl FnOnce() -> T) -> Response {
+    let response = if not_modified(headers, &etag) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        Json(body()).into_response()
+    };
+    tag(response, &etag)
+}
+
+// For bodies tagged by their content, which are built either way
+pub fn respond_by_content<T: Serialize>(headers: &HeaderMap, route: &str, body: T) -> Response {
+    let bytes = serde_json::to_vec(&body).unwrap_or_default();
+    let etag = content_etag(route, &bytes);
+    let response = if not_modified(headers, &etag) {
+        StatusCode::NOT_MODIFIED.into_response()
+    } else {
+        ([(header::CONTENT_TYPE, "application/json")], bytes).into_response()
+    };
+    tag(response, &etag)
+}
+
+fn tag(mut response: Response, etag: &str) -> Response {
+    let headers = response.headers_mut();
+    if let Ok(etag) = HeaderValue::from_str(etag) {
+        headers.insert(header::ETAG, etag);
+    }
+    headers.insert(header::CACHE_CONTROL, HeaderValue::from_static(CACHE_CONTROL));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::extract::Request;
+    use axum::routing::get;
+    use axum::Router;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use std::sync::Arc;
+    use tower::ServiceExt;
+
+    fn get_request(path: &str, if_none_match: Option<&str>) -> Request {
+        let mut request = Request::builder().uri(path);
+        if let Some(tag) = if_none_match {
+            request = request.header(header::IF_NONE_MATCH, tag);
+        }
+        request.body(Body::empty()).unwrap()
+    }
+
+    #[test]
+    fn test_if_none_match_compares_weakly() {
+        let etag = finalized_etag("params", None);
+        assert_eq!(etag, "W/\"params-genesis\"");
+        let matches = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::IF_NONE_MATCH, HeaderValue::from_str(value).unwrap());
+            not_modified(&headers, &etag)
+        };
+        assert!(matches("W/\"params-genesis\""));
+        assert!(matches("\"params-genesis\""));
+        assert!(matches("\"other\", W/\"params-genesis\""));
+        assert!(matches("*"));
+        assert!(!matches("W/\"finalized-genesis\""));
+        assert!(!matches("params-genesis"));
+        assert!(!not_modified(&HeaderMap::new(), &etag));
+    }
+
+    #[tokio::test]
+    async fn test_matching_etag_gets_304_without_a_body() {
+        let built = Arc::new(AtomicU32::new(0));
+        let counter = built.clone();
+        let app: Router = Router::new()
+            .route(
+                "/params",
+                get(move |headers: HeaderMap| async move {
+                    respond(&headers, finalized_etag("params", None), || counter.fetch_add(1, Ordering::SeqCst))
+                }),
+            )
+            .route("/status", get(|headers: HeaderMap| async move { respond_by_content(&headers, "status", [1, 2]) }));
+
+        let response = app.clone().oneshot(get_request("/params", None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()[header::CACHE_CONTROL], CACHE_CONTROL);
+        let etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+        assert_eq!(etag, "W/\"params-genesis\"");
+
+        let response = app.clone().oneshot(get_request("/params", Some(&etag))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+        assert_eq!(response.headers()[header::ETAG], etag.as_str());
+        assert!(axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap().is_empty());
+        assert_eq!(built.load(Ordering::SeqCst), 1);
+
+        // A stale tag gets the full body
+        let response = app.clone().oneshot(get_request("/params", Some("W/\"params-old\""))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(built.load(Ordering::SeqCst), 2);
+
+        // Tagged by content: the same body keeps its tag
+        let response = app.clone().oneshot(get_request("/status", None)).await.unwrap();
+        let etag = response.headers()[header::ETAG].to_str().unwrap().to_string();
+        assert_eq!(etag, content_etag("status", b"[1,2]"));
+        let response = app.oneshot(get_request("/status", Some(&etag))).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+    }
+}
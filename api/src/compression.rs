@@ -0,0 +1,192 @@
+// Response compression, through tower-http's `CompressionLayer`. Any of the
+// enabled algorithms may be chosen; the request's `Accept-Encoding` q-values
+// pick between them. Only bodies of a known size of at least `min_bytes` are
+// compressed: bodies of unknown size are streams (and upgrades carry none), so
+// they pass through as they are and keep flushing incrementally.
+use axum::body::HttpBody;
+use axum::http::Response;
+use std::fmt;
+use std::str::FromStr;
+use tower_http::compression::predicate::{And, NotForContentType, Predicate, SizeAbove};
+use tower_http::compression::CompressionLayer;
+
+pub const DEFAULT_MIN_BYTES: u16 = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Encoding {
+    Br,
+    Gzip,
+    Zstd,
+}
+
+impl Encoding {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Encoding::Br => "br",
+            Encoding::Gzip => "gzip",
+            Encoding::Zstd => "zstd",
+        }
+    }
+}
+
+impl fmt::Display for Encoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.name())
+    }
+}
+
+impl FromStr for Encoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "br" => Ok(Encoding::Br),
+            "gzip" => Ok(Encoding::Gzip),
+            "zstd" => Ok(Encoding::Zstd),
+            _ => Err(format!("unknown compression algorithm '{}' (use br, gzip or zstd)", s)),
+        }
+    }
+}
+
+// Enabled algorithms; none disables compression
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressionConfig {
+    pub algorithms: Vec<Encoding>,
+    pub min_bytes: u16,
+}
+
+impl Default for CompressionConfig {
+    fn default() -> Self {
+        Self { algorithms: vec![Encoding::Br, Encoding::Zstd, Encoding::Gzip], min_bytes: DEFAULT_MIN_BYTES }
+    }
+}
+
+impl CompressionConfig {
+    pub fn disabled() -> Self {
+        Self { algorithms: Vec::new(), ..Self::default() }
+    }
+
+    pub fn with_algorithms(mut self, algorithms: Vec<Encoding>) -> Self {
+        self.algorithms = algorithms;
+        self
+    }
+
+    pub fn with_min_bytes(mut self, min_bytes: u16) -> Self {
+        self.min_bytes = min_bytes;
+        self
+    }
+
+    // Router layer; with no algorithms enabled every response is identity
+    pub fn layer(&self) -> CompressionLayer<ResponsePredicate> {
+        let enabled = |encoding| self.algorithms.contains(&encoding);
+        CompressionLayer::new()
+            .br(enabled(Encoding::Br))
+            .gzip(enabled(Encoding::Gzip))
+            .zstd(enabled(Encoding::Zstd))
+            .compress_when(
+                SizeAbove::new(self.min_bytes)
+                    .and(KnownSize)
+                    .and(NotForContentType::GRPC)
+                    .and(NotForContentType::IMAGES)
+                    .and(NotForContentType::SSE),
+            )
+    }
+}
+
+pub type ResponsePredicate =
+    And<And<And<And<SizeAbove, KnownSize>, NotForContentType>, NotForContentType>, NotForContentType>;
+
+// `SizeAbove` compresses bodies of unknown size; this leaves them to stream
+#[derive(Debug, Clone, Copy)]
+pub struct KnownSize;
+
+impl Predicate for KnownSize {
+    fn should_compress<B: HttpBody>(&self, response: &Response<B>) -> bool {
+        response.body().size_hint().exact().is_some()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::{to_bytes, Body, Bytes};
+    use axum::http::{header, Request};
+    use axum::routing::get;
+    use axum::Router;
+    use hyper::body::Frame;
+    use std::convert::Infallible;
+    use std::pin::Pin;
+    use std::task::{Context, Poll};
+    use tower::ServiceExt;
+
+    // 64 chunks of 64 bytes, with no size hint
+    struct Chunks(usize);
+
+    impl HttpBody for Chunks {
+        type Data = Bytes;
+        type Error = Infallible;
+
+        fn poll_frame(mut self: Pin<&mut Self>, _: &mut Context<'_>) -> Poll<Option<Result<Frame<Bytes>, Infallible>>> {
+            if self.0 == 0 {
+                return Poll::Ready(None);
+            }
+            self.0 -= 1;
+            Poll::Ready(Some(Ok(Frame::data(Bytes::from(vec![b'a'; 64])))))
+        }
+    }
+
+    fn app(config: &CompressionConfig) -> Router {
+        let stream = || async { Body::new(Chunks(64)) };
+        Router::new()
+            .route("/large", get(|| async { "a".repeat(4096) }))
+            .route("/small", get(|| async { "a".repeat(16) }))
+            .route("/stream", get(stream))
+            .layer(config.layer())
+    }
+
+    async fn fetch(config: &CompressionConfig, path: &str, accept: &str) -> (Option<String>, usize) {
+        let request = Request::get(path).header(header::ACCEPT_ENCODING, accept).body(Body::empty()).unwrap();
+        let response = app(config).oneshot(request).await.unwrap();
+        let encoding = response.headers().get(header::CONTENT_ENCODING).map(|v| v.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (encoding, body.len())
+    }
+
+    #[tokio::test]
+    async fn test_large_bodies_use_the_accepted_algorithm() {
+        let config = CompressionConfig::default();
+        for name in ["br", "gzip", "zstd"] {
+            let (encoding, len) = fetch(&config, "/large", name).await;
+            assert_eq!(encoding.as_deref(), Some(name));
+            assert!(len < 4096);
+        }
+        let (encoding, _) = fetch(&config, "/large", "gzip;q=0.5, br;q=1").await;
+        assert_eq!(encoding.as_deref(), Some("br"));
+    }
+
+    #[tokio::test]
+    async fn test_disabled_algorithms_are_not_used() {
+        let gzip_only = CompressionConfig::default().with_algorithms(vec![Encoding::Gzip]);
+        assert_eq!(fetch(&gzip_only, "/large", "br, gzip;q=0.5").await.0.as_deref(), Some("gzip"));
+        assert_eq!(fetch(&gzip_only, "/large", "br").await, (None, 4096));
+        assert_eq!(fetch(&CompressionConfig::disabled(), "/large", "br, gzip").await, (None, 4096));
+    }
+
+    #[tokio::test]
+    async fn test_small_bodies_and_streams_pass_through() {
+        let config = CompressionConfig::default();
+        assert_eq!(fetch(&config, "/small", "br, gzip").await, (None, 16));
+        assert_eq!(fetch(&config.clone().with_min_bytes(8), "/small", "gzip").await.0.as_deref(), Some("gzip"));
+        assert_eq!(fetch(&config, "/stream", "br, gzip").await, (None, 4096));
+    }
+
+    #[test]
+    fn test_parse_encodings() {
+        assert_eq!("br".parse::<Encoding>(), Ok(Encoding::Br));
+        assert_eq!("zstd".parse::<Encoding>().map(|e| e.to_string()), Ok("zstd".to_string()));
+        assert_eq!(
+            "deflate".parse::<Encoding>(),
+            Err("unknown compression algorithm 'deflate' (use br, gzip or zstd)".to_string())
+        );
+    }
+}
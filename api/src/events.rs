@@ -0,0 +1,373 @@
+// The node's event stream (finalized blocks, health failures, equivocations,
+// alerts, intake decisions and leader demotions, as sent to webhooks) numbered with a sequence and kept for
+// replay. Subscribers that reconnect pass the last sequence they saw plus
+// one as `from_seq` and get everything since, then live events, so delivery
+// is at-least-once. With a data directory the log is appended to
+// `events.jsonl` there and reloaded on start; old events are dropped by
+// count and age, and the file is compacted once it holds twice the
+// retained count. A gap in sequence numbers means the events in it expired.
+use crate::webhooks::WebhookEvent;
+use crate::{now_ms, AppState};
+use axum::extract::ws::{Message, WebSocket, WebSocketUpgrade};
+use axum::extract::{Query, State};
+use axum::response::{Json, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use tokio::sync::broadcast;
+
+const LOG_FILE: &str = "events.jsonl";
+const DEFAULT_LIST_LIMIT: usize = 100;
+const MAX_LIST_LIMIT: usize = 1000;
+// Live events buffered per subscriber before it must catch up from the log
+const LIVE_BUFFER: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EventLogConfig {
+    pub retain_events: usize,
+    // None keeps events regardless of age
+    pub retain_secs: Option<u64>,
+}
+
+impl Default for EventLogConfig {
+    fn default() -> Self {
+        Self { retain_events: 10_000, retain_secs: None }
+    }
+}
+
+impl EventLogConfig {
+    pub fn with_retain_events(mut self, events: usize) -> Self {
+        self.retain_events = events.max(1);
+        self
+    }
+
+    pub fn with_retain_secs(mut self, secs: u64) -> Self {
+        self.retain_secs = Some(secs);
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Event {
+    pub seq: u64,
+    pub event: WebhookEvent,
+    // Unix milliseconds
+    pub at: u64,
+    pub data: serde_json::Value,
+}
+
+struct Log {
+    config: EventLogConfig,
+    events: VecDeque<Event>,
+    next_seq: u64,
+    file: Option<(PathBuf, File)>,
+    // Events in the file, expired ones included
+    lines: usize,
+    // Rough heap bytes of `events`
+    bytes: usize,
+}
+
+#[derive(Clone)]
+pub struct EventLog {
+    log: Arc<Mutex<Log>>,
+    live: broadcast::Sender<Event>,
+}
+
+impl EventLog {
+    // In memory only without a directory
+    pub fn open(dir: Option<&Path>, config: EventLogConfig) -> Result<Self, String> {
+        let mut log = Log { config, events: VecDeque::new(), next_seq: 0, file: None, lines: 0, bytes: 0 };
+        if let Some(dir) = dir {
+            let path = dir.join(LOG_FILE);
+            if path.exists() {
+                let file = File::open(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+                // A torn last line from a crash is skipped
+                let lines = BufReader::new(file).lines().map_while(Result::ok);
+                for event in lines.filter_map(|line| serde_json::from_str(&line).ok()) {
+                    log.push(event);
+                    log.lines += 1;
+                }
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+            log.file = Some((path, file));
+        }
+        log.expire(now_ms());
+        Ok(Self { log: Arc::new(Mutex::new(log)), live: broadcast::channel(LIVE_BUFFER).0 })
+    }
+
+    pub fn append<T: Serialize>(&self, event: WebhookEvent, data: &T) -> Event {
+        let mut log = self.log.lock().unwrap();
+        let data = serde_json::to_value(data).expect("event serializes");
+        let event = Event { seq: log.next_seq, event, at: now_ms(), data };
+        log.push(event.clone());
+        log.persist(&event);
+        log.expire(event.at);
+        // No receivers is fine
+        let _ = self.live.send(event.clone());
+        event
+    }
+
+    // Retained events from `from_seq` on, oldest first
+    pub fn since(&self, from_seq: u64, limit: usize) -> Vec<Event> {
+        let log = self.log.lock().unwrap();
+        log.events.iter().filter(|e| e.seq >= from_seq).take(limit).cloned().collect()
+    }
+
+    pub fn next_seq(&self) -> u64 {
+        self.log.lock().unwrap().next_seq
+    }
+
+    pub fn first_seq(&self) -> Option<u64> {
+        self.log.lock().unwrap().events.front().map(|e| e.seq)
+    }
+
+    // Rough heap bytes held, for memory accounting
+    pub fn heap_bytes(&self) -> usize {
+        self.log.lock().unwrap().bytes
+    }
+
+    // Drops the oldest events from memory until at least `bytes` are freed;
+    // the file keeps them until its next compaction. Returns the bytes freed.
+    pub fn evict(&self, bytes: usize) -> usize {
+        let mut log = self.log.lock().unwrap();
+        let before = log.bytes;
+        while before - log.bytes < bytes && log.pop_front() {}
+        before - log.bytes
+    }
+
+    fn subscribe(&self) -> broadcast::Receiver<Event> {
+        self.live.subscribe()
+    }
+}
+
+impl Log {
+    fn push(&mut self, event: Event) {
+        self.next_seq = self.next_seq.max(event.seq + 1);
+        self.bytes += event_bytes(&event);
+        self.events.push_back(event);
+    }
+
+    fn pop_front(&mut self) -> bool {
+        let Some(event) = self.events.pop_front() else {
+            return false;
+        };
+        self.bytes -= event_bytes(&event);
+        true
+    }
+
+    fn persist(&mut self, event: &Event) {
+        let Some((path, file)) = &mut self.file else {
+            return;
+        };
+        let line = serde_json::to_string(event).expect("event serializes") + "\n";
+        match file.write_all(line.as_bytes()) {
+            Ok(()) => self.lines += 1,
+            Err(e) => eprintln!("Cannot append to {}: {}", path.display(), e),
+        }
+    }
+
+    fn expire(&mut self, now: u64) {
+        let oldest = self.config.retain_secs.map_or(0, |secs| now.saturating_sub(secs * 1000));
+        while self.events.len() > self.config.retain_events || self.events.front().is_some_and(|e| e.at < oldest) {
+            self.pop_front();
+        }
+        if self.lines > 2 * self.config.retain_events {
+            if let Err(e) = self.compact() {
+                eprintln!("Cannot compact the event log: {}", e);
+            }
+        }
+    }
+
+    // Rewrites the file with only the retained events
+    fn compact(&mut self) -> std::io::Result<()> {
+        let Some((path, _)) = &self.file else {
+            return Ok(());
+        };
+        let path = path.clone();
+        let temp = path.with_extension("jsonl.tmp");
+        let mut out = File::create(&temp)?;
+        for event in &self.events {
+            writeln!(out, "{}", serde_json::to_string(event).expect("event serializes"))?;
+        }
+        out.sync_all()?;
+        std::fs::rename(&temp, &path)?;
+        self.file = Some((path.clone(), OpenOptions::new().append(true).open(&path)?));
+        self.lines = self.events.len();
+        Ok(())
+    }
+}
+
+fn event_bytes(event: &Event) -> usize {
+    size_of::<Event>() + event.data.to_string().len()
+}
+
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    pub from_seq: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct EventsResponse {
+    // Oldest retained event; anything before it has expired
+    pub first_seq: Option<u64>,
+    // Sequence the next event will get
+    pub next_seq: u64,
+    pub events: Vec<Event>,
+}
+
+pub(crate) async fn list_events(State(state): State<AppState>, Query(query): Query<EventsQuery>) -> Json<EventsResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIST_LIMIT).min(MAX_LIST_LIMIT);
+    Json(EventsResponse {
+        first_seq: state.events.first_seq(),
+        next_seq: state.events.next_seq(),
+        events: state.events.since(query.from_seq.unwrap_or(0), limit),
+    })
+}
+
+// Replays from `from_seq` (or starts with the next event) and then streams
+// live events, each as a JSON text message
+pub(crate) async fn subscribe(
+    State(state): State<AppState>,
+    Query(query): Query<EventsQuery>,
+    upgrade: WebSocketUpgrade,
+) -> Response {
+    upgrade.on_upgrade(move |socket| stream(socket, state.events, query.from_seq))
+}
+
+async fn stream(mut socket: WebSocket, events: EventLog, from_seq: Option<u64>) {
+    // Subscribed before reading the backlog so nothing falls between them
+    let mut live = events.subscribe();
+    let mut next = from_seq.unwrap_or_else(|| events.next_seq());
+    loop {
+        let backlog = events.since(next, MAX_LIST_LIMIT);
+        if backlog.is_empty() {
+            break;
+        }
+        for event in backlog {
+            next = event.seq + 1;
+            if send(&mut socket, &event).await.is_err() {
+                return;
+            }
+        }
+    }
+    loop {
+        tokio::select! {
+            received = live.recv() => match received {
+                Ok(event) if event.seq < next => {}
+                Ok(event) => {
+                    next = event.seq + 1;
+                    if send(&mut socket, &event).await.is_err() {
+                        return;
+                    }
+                }
+                // Too slow for the live buffer: catch up from the log
+                Err(broadcast::error::RecvError::Lagged(_)) => {
+                    for event in events.since(next, usize::MAX) {
+                        next = event.seq + 1;
+                        if send(&mut socket, &event).await.is_err() {
+                            return;
+                        }
+                    }
+                }
+                Err(broadcast::error::RecvError::Closed) => return,
+            },
+            // Anything from the client but a close is ignored
+            message = socket.recv() => match message {
+                Some(Ok(Message::Close(_))) | Some(Err(_)) | None => return,
+                Some(Ok(_)) => {}
+            },
+        }
+    }
+}
+
+async fn send(socket: &mut WebSocket, event: &Event) -> Result<(), axum::Error> {
+    socket.send(Message::Text(serde_json::to_string(event).expect("event serializes"))).await
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_dir(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("mcn-events-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn seqs(events: &[Event]) -> Vec<u64> {
+        events.iter().map(|e| e.seq).collect()
+    }
+
+    #[test]
+    fn test_log_is_reloaded_past_a_torn_line() {
+        let dir = temp_dir("reload");
+        let log = EventLog::open(Some(&dir), EventLogConfig::default()).unwrap();
+        for height in 0..3 {
+            log.append(WebhookEvent::Finalized, &serde_json::json!({ "height": height }));
+        }
+        drop(log);
+        let mut file = OpenOptions::new().append(true).open(dir.join(LOG_FILE)).unwrap();
+        file.write_all(b"{\"seq\":3,\"event\":\"finali").unwrap();
+
+        let log = EventLog::open(Some(&dir), EventLogConfig::default()).unwrap();
+        assert_eq!(seqs(&log.since(0, 10)), [0, 1, 2]);
+        assert_eq!(log.since(1, 1)[0].data["height"], 1);
+        assert_eq!(log.append(WebhookEvent::Alert, &"next").seq, 3);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_retention_expires_and_compacts() {
+        let dir = temp_dir("retain");
+        // An event older than the age limit is dropped on open, but its
+        // sequence number is not reused
+        let old = Event { seq: 7, event: WebhookEvent::Finalized, at: 1_000, data: serde_json::Value::Null };
+        std::fs::write(dir.join(LOG_FILE), serde_json::to_string(&old).unwrap() + "\n").unwrap();
+        let config = EventLogConfig::default().with_retain_events(2).with_retain_secs(3600);
+        let log = EventLog::open(Some(&dir), config).unwrap();
+        assert_eq!((log.first_seq(), log.next_seq()), (None, 8));
+
+        for _ in 0..5 {
+            log.append(WebhookEvent::Intake, &"decided");
+        }
+        assert_eq!(seqs(&log.since(0, 10)), [11, 12]);
+        assert_eq!(log.first_seq(), Some(11));
+        // Compacted once the file held more than twice the retained count
+        let lines = std::fs::read_to_string(dir.join(LOG_FILE)).unwrap().lines().count();
+        assert!(lines <= 4, "{} lines", lines);
+        drop(log);
+        assert_eq!(seqs(&EventLog::open(Some(&dir), config).unwrap().since(0, 10)), [11, 12]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn test_unusable_directory_and_eviction() {
+        let dir = temp_dir("unusable");
+        let not_a_dir = dir.join("file");
+        std::fs::write(&not_a_dir, b"").unwrap();
+        let err = EventLog::open(Some(&not_a_dir), EventLogConfig::default()).err().unwrap();
+        assert!(err.starts_with("cannot open "), "{}", err);
+        std::fs::remove_dir_all(&dir).unwrap();
+
+        let log = EventLog::open(None, EventLogConfig::default()).unwrap();
+        for _ in 0..4 {
+            log.append(WebhookEvent::TxDropped, &"expired");
+        }
+        let held = log.heap_bytes();
+        let freed = log.evict(1);
+        assert!(freed > 0 && log.heap_bytes() == held - freed);
+        assert_eq!(log.first_seq(), Some(1));
+        assert_eq!(log.evict(usize::MAX), held - freed);
+        assert_eq!((log.first_seq(), log.next_seq(), log.heap_bytes()), (None, 4, 0));
+    }
+}
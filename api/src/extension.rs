@@ -0,0 +1,27 @@
+// Additions to the public API from downstream crates: routes served on the
+// same port and layers wrapped around the whole API, both given the node's
+// state. Registered with `ApiServerBuilder::extension`.
+use crate::AppState;
+use axum::Router;
+
+pub trait ApiExtension: Send + Sync + 'static {
+    // Merged into the API behind the self-test gate; a path the API already
+    // serves panics at `build`
+    fn routes(&self, _state: &AppState) -> Router<AppState> {
+        Router::new()
+    }
+
+    // Wraps every public route, /selftest and /readyz included, inside the
+    // global limits and CORS. Extensions are applied in registration order,
+    // so the last one registered runs first
+    fn layer(&self, router: Router<AppState>, _state: &AppState) -> Router<AppState> {
+        router
+    }
+}
+
+// A plain router is an extension with routes only
+impl ApiExtension for Router<AppState> {
+    fn routes(&self, _state: &AppState) -> Router<AppState> {
+        self.clone()
+    }
+}
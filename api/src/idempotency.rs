@@ -0,0 +1,363 @@
+// `Idempotency-Key` support for `POST /propose` and `POST /tx`. The first
+// request with a key runs and its response is kept for the window, keyed by
+// route and key along with a hash of the body; a retry with the same key
+// and body gets that response back with `Idempotent-Replayed: true` instead
+// of proposing or submitting again. The same key with another body is
+// refused with 422, and a retry while the first is still running with 409.
+// Responses with a 5xx status are not kept, and neither is anything for a
+// request that timed out or whose client went away, so those retries run
+// again. With a data directory the kept responses are appended to
+// `idempotency.jsonl` there and reloaded on start, so retries across a
+// restart are caught too.
+use crate::{now_ms, ErrorResponse};
+use axum::body::{to_bytes, Body};
+use axum::extract::{Request, State};
+use axum::http::{header, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs::{File, OpenOptions};
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+const LOG_FILE: &str = "idempotency.jsonl";
+const MAX_KEY_LEN: usize = 255;
+// Bodies are buffered to hash them
+const MAX_BODY_BYTES: usize = 16 << 20;
+pub const DEFAULT_WINDOW: Duration = Duration::from_secs(24 * 60 * 60);
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct Stored {
+    route: String,
+    key: String,
+    body_hash: String,
+    status: u16,
+    content_type: Option<String>,
+    body: String,
+    // Unix milliseconds
+    at: u64,
+}
+
+enum Entry {
+    InFlight { body_hash: String },
+    Done(Stored),
+}
+
+struct Cache {
+    window_ms: u64,
+    entries: HashMap<(String, String), Entry>,
+    file: Option<(PathBuf, File)>,
+    // Responses in the file, expired ones included
+    lines: usize,
+}
+
+#[derive(Clone)]
+pub struct IdempotencyCache(Arc<Mutex<Cache>>);
+
+impl IdempotencyCache {
+    // In memory only without a directory
+    pub fn open(dir: Option<&Path>, window: Duration) -> Result<Self, String> {
+        let mut cache = Cache { window_ms: window.as_millis() as u64, entries: HashMap::new(), file: None, lines: 0 };
+        if let Some(dir) = dir {
+            let path = dir.join(LOG_FILE);
+            if path.exists() {
+                let file = File::open(&path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+                // A torn last line from a crash is skipped
+                let lines = BufReader::new(file).lines().map_while(Result::ok);
+                for stored in lines.filter_map(|line| serde_json::from_str::<Stored>(&line).ok()) {
+                    cache.entries.insert((stored.route.clone(), stored.key.clone()), Entry::Done(stored));
+                    cache.lines += 1;
+                }
+            }
+            let file = OpenOptions::new()
+                .create(true)
+                .append(true)
+                .open(&path)
+                .map_err(|e| format!("cannot open {}: {}", path.display(), e))?;
+            cache.file = Some((path, file));
+        }
+        cache.expire(now_ms());
+        Ok(Self(Arc::new(Mutex::new(cache))))
+    }
+}
+
+impl Cache {
+    fn expire(&mut self, now: u64) {
+        let oldest = now.saturating_sub(self.window_ms);
+        self.entries.retain(|_, entry| !matches!(entry, Entry::Done(stored) if stored.at < oldest));
+        if self.lines > 2 * self.entries.len() + 1024 {
+            if let Err(e) = self.compact() {
+                eprintln!("Cannot compact the idempotency log: {}", e);
+            }
+        }
+    }
+
+    fn persist(&mut self, stored: &Stored) {
+        let Some((path, file)) = &mut self.file else {
+            return;
+        };
+        let line = serde_json::to_string(stored).expect("response serializes") + "\n";
+        match file.write_all(line.as_bytes()) {
+            Ok(()) => self.lines += 1,
+            Err(e) => eprintln!("Cannot append to {}: {}", path.display(), e),
+        }
+    }
+
+    // Rewrites the file with only the unexpired responses
+    fn compact(&mut self) -> std::io::Result<()> {
+        let Some((path, _)) = &self.file else {
+            return Ok(());
+        };
+        let path = path.clone();
+        let temp = path.with_extension("jsonl.tmp");
+        let mut out = File::create(&temp)?;
+        let mut lines = 0;
+        for entry in self.entries.values() {
+            if let Entry::Done(stored) = entry {
+                writeln!(out, "{}", serde_json::to_string(stored).expect("response serializes"))?;
+                lines += 1;
+            }
+        }
+        out.sync_all()?;
+        std::fs::rename(&temp, &path)?;
+        self.file = Some((path.clone(), OpenOptions::new().append(true).open(&path)?));
+        self.lines = lines;
+        Ok(())
+    }
+}
+
+// Clears the key's in-flight entry if the request ends without a response
+// being kept, e.g. when the route times out and drops it
+struct InFlightGuard {
+    cache: IdempotencyCache,
+    id: (String, String),
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        let Ok(mut cache) = self.cache.0.lock() else {
+            return;
+        };
+        if matches!(cache.entries.get(&self.id), Some(Entry::InFlight { .. })) {
+            cache.entries.remove(&self.id);
+        }
+    }
+}
+
+fn refuse(status: StatusCode, error: &str) -> Response {
+    (status, Json(ErrorResponse { error: error.to_string() })).into_response()
+}
+
+// Route middleware; requests without the header pass straight through
+pub async fn guard(State(cache): State<IdempotencyCache>, request: Request, next: Next) -> Response {
+    let Some(key) = request.headers().get("idempotency-key") else {
+        return next.run(request).await;
+    };
+    let key = match key.to_str() {
+        Ok(key) if !key.is_empty() && key.len() <= MAX_KEY_LEN => key.to_string(),
+        _ => return refuse(StatusCode::BAD_REQUEST, "Idempotency-Key must be 1 to 255 visible ASCII characters"),
+    };
+    let route = request.uri().path().to_string();
+    let (parts, body) = request.into_parts();
+    let Ok(bytes) = to_bytes(body, MAX_BODY_BYTES).await else {
+        return refuse(StatusCode::PAYLOAD_TOO_LARGE, "request body too large");
+    };
+    let body_hash = blake3::hash(&bytes).to_hex().to_string();
+
+    let id = (route.clone(), key.clone());
+    {
+        let mut cache = cache.0.lock().unwrap();
+        cache.expire(now_ms());
+        match cache.entries.get(&id) {
+            Some(Entry::InFlight { body_hash: hash } | Entry::Done(Stored { body_hash: hash, .. })) if *hash != body_hash => {
+                return refuse(StatusCode::UNPROCESSABLE_ENTITY, "Idempotency-Key was already used with a different request body");
+            }
+            Some(Entry::InFlight { .. }) => {
+                return refuse(StatusCode::CONFLICT, "a request with this Idempotency-Key is still in progress");
+            }
+            Some(Entry::Done(stored)) => return replay(stored),
+            None => {
+                cache.entries.insert(id.clone(), Entry::InFlight { body_hash: body_hash.clone() });
+            }
+        }
+    }
+
+    let in_flight = InFlightGuard { cache: cache.clone(), id };
+
+    let response = next.run(Request::from_parts(parts, Body::from(bytes))).await;
+    let (parts, body) = response.into_parts();
+    let body = to_bytes(body, usize::MAX).await.unwrap_or_default();
+    let content_type = parts.headers.get(header::CONTENT_TYPE).and_then(|v| v.to_str().ok()).map(str::to_string);
+    // Responses that are not kept are cleared by the guard
+    let text = String::from_utf8(body.to_vec()).ok().filter(|_| !parts.status.is_server_error());
+    if let Some(text) = text {
+        let stored = Stored { route, key, body_hash, status: parts.status.as_u16(), content_type, body: text, at: now_ms() };
+        let mut cache = cache.0.lock().unwrap();
+        cache.persist(&stored);
+        cache.entries.insert(in_flight.id.clone(), Entry::Done(stored));
+    }
+    drop(in_flight);
+    Response::from_parts(parts, Body::from(body))
+}
+
+fn replay(stored: &Stored) -> Response {
+    let status = StatusCode::from_u16(stored.status).unwrap_or(StatusCode::OK);
+    let mut response = (status, stored.body.clone()).into_response();
+    let headers = response.headers_mut();
+    if let Some(content_type) = stored.content_type.as_deref().and_then(|v| HeaderValue::from_str(v).ok()) {
+        headers.insert(header::CONTENT_TYPE, content_type);
+    }
+    headers.insert("idempotent-replayed", HeaderValue::from_static("true"));
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::limits::{LimitsConfig, RouteLimits};
+    use axum::middleware;
+    use axum::routing::{post, MethodRouter};
+    use axum::Router;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tokio::sync::{mpsc, Notify};
+    use tower::ServiceExt;
+
+    fn post_request(key: Option<&str>, body: &str) -> Request {
+        let mut request = Request::builder().method("POST").uri("/tx");
+        if let Some(key) = key {
+            request = request.header("idempotency-key", key);
+        }
+        request.body(Body::from(body.to_string())).unwrap()
+    }
+
+    async fn text(response: Response) -> String {
+        String::from_utf8(to_bytes(response.into_body(), usize::MAX).await.unwrap().to_vec()).unwrap()
+    }
+
+    // `/tx` behind the guard, as the API serves it
+    fn app(cache: &IdempotencyCache, handler: MethodRouter) -> Router {
+        let limits = LimitsConfig::default()
+            .with_route("/tx", RouteLimits { timeout: Duration::from_millis(50), max_in_flight: 8 })
+            .live();
+        let guarded = handler.route_layer(middleware::from_fn_with_state(cache.clone(), guard));
+        Router::new().route("/tx", limits.route("/tx", guarded))
+    }
+
+    // Answers 201 with the number of requests it has run
+    fn counting() -> (Arc<AtomicU32>, MethodRouter) {
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let handler = post(move || {
+            let counter = counter.clone();
+            async move { (StatusCode::CREATED, format!("run {}", counter.fetch_add(1, Ordering::SeqCst) + 1)) }
+        });
+        (calls, handler)
+    }
+
+    #[tokio::test]
+    async fn test_retry_gets_the_first_response() {
+        let cache = IdempotencyCache::open(None, DEFAULT_WINDOW).unwrap();
+        let (calls, handler) = counting();
+        let app = app(&cache, handler);
+
+        let first = app.clone().oneshot(post_request(Some("k1"), "{}")).await.unwrap();
+        assert_eq!(first.status(), StatusCode::CREATED);
+        assert!(first.headers().get("idempotent-replayed").is_none());
+        assert_eq!(text(first).await, "run 1");
+
+        let retry = app.clone().oneshot(post_request(Some("k1"), "{}")).await.unwrap();
+        assert_eq!(retry.status(), StatusCode::CREATED);
+        assert_eq!(retry.headers()["idempotent-replayed"], "true");
+        assert_eq!(text(retry).await, "run 1");
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+
+        // Without a key, or with another one, the request runs again
+        assert_eq!(text(app.clone().oneshot(post_request(None, "{}")).await.unwrap()).await, "run 2");
+        assert_eq!(text(app.clone().oneshot(post_request(Some("k2"), "{}")).await.unwrap()).await, "run 3");
+
+        let too_long = "k".repeat(MAX_KEY_LEN + 1);
+        let response = app.oneshot(post_request(Some(&too_long), "{}")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(calls.load(Ordering::SeqCst), 3);
+    }
+
+    #[tokio::test]
+    async fn test_same_key_with_another_body_is_refused() {
+        let cache = IdempotencyCache::open(None, DEFAULT_WINDOW).unwrap();
+        let (calls, handler) = counting();
+        let app = app(&cache, handler);
+
+        app.clone().oneshot(post_request(Some("k1"), r#"{"nonce":1}"#)).await.unwrap();
+        let response = app.oneshot(post_request(Some("k1"), r#"{"nonce":2}"#)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            text(response).await,
+            r#"{"error":"Idempotency-Key was already used with a different request body"}"#
+        );
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+    }
+
+    #[tokio::test]
+    async fn test_retry_while_in_flight_is_refused() {
+        let cache = IdempotencyCache::open(None, DEFAULT_WINDOW).unwrap();
+        let (entered_tx, mut entered) = mpsc::unbounded_channel();
+        let release = Arc::new(Notify::new());
+        let notify = release.clone();
+        let handler = post(move || {
+            let (entered_tx, notify) = (entered_tx.clone(), notify.clone());
+            async move {
+                let released = notify.notified();
+                entered_tx.send(()).unwrap();
+                released.await;
+                "done"
+            }
+        });
+        let app = app(&cache, handler);
+
+        let first = tokio::spawn(app.clone().oneshot(post_request(Some("k1"), "{}")));
+        entered.recv().await.unwrap();
+        let response = app.clone().oneshot(post_request(Some("k1"), "{}")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::CONFLICT);
+        assert_eq!(text(response).await, r#"{"error":"a request with this Idempotency-Key is still in progress"}"#);
+        // A different body is still a 422, not a 409
+        let response = app.clone().oneshot(post_request(Some("k1"), "[]")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNPROCESSABLE_ENTITY);
+
+        release.notify_waiters();
+        assert_eq!(first.await.unwrap().unwrap().status(), StatusCode::OK);
+        let retry = app.oneshot(post_request(Some("k1"), "{}")).await.unwrap();
+        assert_eq!(retry.headers()["idempotent-replayed"], "true");
+    }
+
+    #[tokio::test]
+    async fn test_retry_after_a_timeout_runs_again() {
+        let cache = IdempotencyCache::open(None, DEFAULT_WINDOW).unwrap();
+        // The first request outlives the route's 50ms timeout
+        let calls = Arc::new(AtomicU32::new(0));
+        let counter = calls.clone();
+        let handler = post(move || {
+            let counter = counter.clone();
+            async move {
+                if counter.fetch_add(1, Ordering::SeqCst) == 0 {
+                    tokio::time::sleep(Duration::from_secs(10)).await;
+                }
+                (StatusCode::CREATED, "submitted")
+            }
+        });
+        let app = app(&cache, handler);
+
+        let response = app.clone().oneshot(post_request(Some("k1"), "{}")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert!(cache.0.lock().unwrap().entries.is_empty());
+
+        let retry = app.clone().oneshot(post_request(Some("k1"), "{}")).await.unwrap();
+        assert_eq!(retry.status(), StatusCode::CREATED);
+        assert!(retry.headers().get("idempotent-replayed").is_none());
+        let replayed = app.oneshot(post_request(Some("k1"), "{}")).await.unwrap();
+        assert_eq!(replayed.headers()["idempotent-replayed"], "true");
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,234 @@
+// Provisional intake for proposals and votes. `POST /intake/...` queues the
+// request and answers 202 with a tracking id at once; a single worker then
+// runs the checks off the request path, applies what passes exactly as the
+// synchronous endpoint would, and records the outcome. Outcomes are served at
+// `GET /intake/{id}` and published as `intake` events. The queue is bounded
+// and a full one is refused with 503; outcomes are kept in memory for the
+// last RETAINED submissions and do not survive a restart.
+use crate::payload::ProposalPayload;
+use crate::webhooks::WebhookEvent;
+use crate::{
+    apply_combined_vote, apply_proposal, apply_vote, not_active, now_ms, AppState, CombinedVoteRequest, ErrorResponse,
+    ValidJson, VoteRequest,
+};
+use axum::extract::{Path, State};
+use axum::http::{header, StatusCode};
+use axum::response::{IntoResponse, Json, Response};
+use consensus::{Diagnostic, Severity};
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::sync::mpsc;
+
+const CAPACITY: usize = 1024;
+const RETAINED: usize = 10_000;
+
+pub enum IntakeRequest {
+    Proposal(Vec<u8>),
+    Vote(VoteRequest),
+    CombinedVote(CombinedVoteRequest),
+}
+
+impl IntakeRequest {
+    fn kind(&self) -> IntakeKind {
+        match self {
+            IntakeRequest::Proposal(_) => IntakeKind::Proposal,
+            IntakeRequest::Vote(_) => IntakeKind::Vote,
+            IntakeRequest::CombinedVote(_) => IntakeKind::CombinedVote,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntakeKind {
+    Proposal,
+    Vote,
+    CombinedVote,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum IntakeState {
+    Pending,
+    Accepted,
+    Rejected,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct IntakeStatus {
+    pub id: u64,
+    pub kind: IntakeKind,
+    pub state: IntakeState,
+    // Once decided: the status and body the synchronous endpoint would have
+    // answered with
+    pub status: Option<u16>,
+    pub response: Option<serde_json::Value>,
+    pub error: Option<String>,
+    // Every failed check of a rejected proposal
+    pub diagnostics: Vec<Diagnostic>,
+    // Unix milliseconds
+    pub submitted_at: u64,
+    pub decided_at: Option<u64>,
+}
+
+struct Statuses {
+    next_id: u64,
+    by_id: BTreeMap<u64, IntakeStatus>,
+}
+
+#[derive(Clone)]
+pub struct IntakeQueue {
+    sender: mpsc::Sender<(u64, IntakeRequest)>,
+    // Held by the worker while it runs, and taken over by its restart
+    receiver: Arc<tokio::sync::Mutex<mpsc::Receiver<(u64, IntakeRequest)>>>,
+    statuses: Arc<Mutex<Statuses>>,
+}
+
+impl Default for IntakeQueue {
+    fn default() -> Self {
+        let (sender, receiver) = mpsc::channel(CAPACITY);
+        Self {
+            sender,
+            receiver: Arc::new(tokio::sync::Mutex::new(receiver)),
+            statuses: Arc::new(Mutex::new(Statuses { next_id: 1, by_id: BTreeMap::new() })),
+        }
+    }
+}
+
+impl IntakeQueue {
+    // None when the queue is full
+    pub fn submit(&self, request: IntakeRequest) -> Option<IntakeStatus> {
+        // Locked across the send so the worker cannot decide an id before
+        // it is recorded as pending
+        let mut statuses = self.statuses.lock().unwrap();
+        let id = statuses.next_id;
+        let kind = request.kind();
+        self.sender.try_send((id, request)).ok()?;
+        statuses.next_id += 1;
+        let status = IntakeStatus {
+            id,
+            kind,
+            state: IntakeState::Pending,
+            status: None,
+            response: None,
+            error: None,
+            diagnostics: Vec::new(),
+            submitted_at: now_ms(),
+            decided_at: None,
+        };
+        statuses.by_id.insert(id, status.clone());
+        while statuses.by_id.len() > RETAINED {
+            statuses.by_id.pop_first();
+        }
+        Some(status)
+    }
+
+    pub fn status(&self, id: u64) -> Option<IntakeStatus> {
+        self.statuses.lock().unwrap().by_id.get(&id).cloned()
+    }
+
+    // Requests queued and not yet taken up by the worker
+    pub fn depth(&self) -> usize {
+        CAPACITY - self.sender.capacity()
+    }
+
+    fn decide(&self, id: u64, outcome: Outcome) -> Option<IntakeStatus> {
+        let mut statuses = self.statuses.lock().unwrap();
+        let status = statuses.by_id.get_mut(&id)?;
+        let (state, code) = match &outcome {
+            Ok(_) => (IntakeState::Accepted, StatusCode::OK),
+            Err((code, _, _)) => (IntakeState::Rejected, *code),
+        };
+        status.state = state;
+        status.status = Some(code.as_u16());
+        status.decided_at = Some(now_ms());
+        match outcome {
+            Ok(response) => status.response = Some(response),
+            Err((_, error, diagnostics)) => {
+                status.error = Some(error);
+                status.diagnostics = diagnostics;
+            }
+        }
+        Some(status.clone())
+    }
+}
+
+type Outcome = Result<serde_json::Value, (StatusCode, String, Vec<Diagnostic>)>;
+
+// Takes queued requests one at a time, in submission order
+pub(crate) async fn run(state: AppState) {
+    let mut receiver = state.intake.receiver.clone().lock_owned().await;
+    while let Some((id, request)) = receiver.recv().await {
+        let worker_state = state.clone();
+        let outcome = tokio::task::spawn_blocking(move || process(&worker_state, request))
+            .await
+            .unwrap_or_else(|e| Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string(), Vec::new())));
+        if let Some(status) = state.intake.decide(id, outcome) {
+            state.publish(WebhookEvent::Intake, &status);
+        }
+    }
+}
+
+fn process(state: &AppState, request: IntakeRequest) -> Outcome {
+    match request {
+        IntakeRequest::Proposal(payload) => {
+            let report = state.consensus.validate_proposal(&payload);
+            if !report.valid {
+                let errors: Vec<Diagnostic> =
+                    report.diagnostics.into_iter().filter(|d| d.severity == Severity::Error).collect();
+                let error = errors.iter().map(|d| d.message.as_str()).collect::<Vec<_>>().join("; ");
+                // As `/propose` answers
+                let status = if errors.iter().any(|d| d.check == "size") {
+                    StatusCode::PAYLOAD_TOO_LARGE
+                } else {
+                    StatusCode::UNPROCESSABLE_ENTITY
+                };
+                return Err((status, error, errors));
+            }
+            respond(apply_proposal(state, payload))
+        }
+        IntakeRequest::Vote(vote) => respond(apply_vote(state, vote)),
+        IntakeRequest::CombinedVote(vote) => respond(apply_combined_vote(state, vote)),
+    }
+}
+
+fn respond<T: Serialize>(result: Result<T, (StatusCode, Json<ErrorResponse>)>) -> Outcome {
+    result
+        .map(|response| serde_json::to_value(response).expect("response serializes"))
+        .map_err(|(status, Json(e))| (status, e.error, Vec::new()))
+}
+
+// 202 with the pending status and where to poll it, or 503 when the queue
+// is full
+fn enqueue(state: &AppState, request: IntakeRequest) -> Response {
+    if let Err(status) = state.require_active() {
+        return not_active(status).into_response();
+    }
+    match state.intake.submit(request) {
+        Some(status) => {
+            let location = format!("/intake/{}", status.id);
+            (StatusCode::ACCEPTED, [(header::LOCATION, location)], Json(status)).into_response()
+        }
+        None => {
+            let error = "intake queue is full".to_string();
+            (StatusCode::SERVICE_UNAVAILABLE, [(header::RETRY_AFTER, "1")], Json(ErrorResponse { error })).into_response()
+        }
+    }
+}
+
+pub(crate) async fn propose(State(state): State<AppState>, ProposalPayload(payload): ProposalPayload) -> Response {
+    enqueue(&state, IntakeRequest::Proposal(payload))
+}
+
+pub(crate) async fn vote(State(state): State<AppState>, ValidJson(vote): ValidJson<VoteRequest>) -> Response {
+    enqueue(&state, IntakeRequest::Vote(vote))
+}
+
+pub(crate) async fn vote_combined(State(state): State<AppState>, ValidJson(vote): ValidJson<CombinedVoteRequest>) -> Response {
+    enqueue(&state, IntakeRequest::CombinedVote(vote))
+}
+
+pub(crate) async fn get_status(State(state): State<AppState>, Path(id): Path<u64>) -> Result<Json<IntakeStatus>, StatusCode> {
+    state.intake.status(id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
@@ -0,0 +1,176 @@
+// JSON response profiles. `snake_case` serves responses as the handlers
+// write them. `compat` is for tooling built against camelCase field names
+// and heights as strings: every object key is camelCased, and numeric
+// `height` and `*_height` fields become decimal strings. A request picks a
+// profile with the `X-Json-Profile` header or the `json_profile` query
+// parameter, the header winning; without either the node's default applies.
+// Only `application/json` responses are rewritten; request bodies, event
+// streams and WebSocket messages keep snake_case. ETags of compat responses
+// carry a `-compat` suffix, so a cached representation is only revalidated
+// in the profile it was served in.
+use crate::ErrorResponse;
+use axum::body::{to_bytes, Body};
+use axum::extract::Request;
+use axum::http::{header, HeaderMap, HeaderValue, StatusCode};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Json, Response};
+use serde_json::{Map, Value};
+use std::fmt;
+use std::str::FromStr;
+
+const HEADER: &str = "x-json-profile";
+const QUERY_PARAM: &str = "json_profile";
+// Closes the ETag of a compat response
+const COMPAT_TAG: &str = "-compat\"";
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum JsonProfile {
+    #[default]
+    SnakeCase,
+    Compat,
+}
+
+impl fmt::Display for JsonProfile {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JsonProfile::SnakeCase => write!(f, "snake_case"),
+            JsonProfile::Compat => write!(f, "compat"),
+        }
+    }
+}
+
+// "snake_case", or "compat" (also "camelCase")
+impl FromStr for JsonProfile {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "snake_case" => Ok(JsonProfile::SnakeCase),
+            "compat" | "camelCase" => Ok(JsonProfile::Compat),
+            _ => Err(format!("unknown JSON profile '{}' (use snake_case or compat)", s)),
+        }
+    }
+}
+
+impl JsonProfile {
+    // The profile a request asks for, or None when it names none
+    fn requested(request: &Request) -> Result<Option<Self>, String> {
+        if let Some(value) = request.headers().get(HEADER) {
+            return value.to_str().map_err(|_| "X-Json-Profile is not ASCII".to_string())?.parse().map(Some);
+        }
+        let query = request.uri().query().unwrap_or_default();
+        let requested = query.split('&').find_map(|pair| pair.strip_prefix(QUERY_PARAM)?.strip_prefix('='));
+        requested.map(str::parse).transpose()
+    }
+
+    pub fn apply(&self, value: Value) -> Value {
+        match self {
+            JsonProfile::SnakeCase => value,
+            JsonProfile::Compat => compat(value),
+        }
+    }
+}
+
+fn compat(value: Value) -> Value {
+    match value {
+        Value::Object(object) => {
+            let object: Map<String, Value> = object
+                .into_iter()
+                .map(|(key, value)| {
+                    let value = match value {
+                        Value::Number(height) if is_height(&key) && height.is_u64() => Value::String(height.to_string()),
+                        value => compat(value),
+                    };
+                    (camel_case(&key), value)
+                })
+                .collect();
+            Value::Object(object)
+        }
+        Value::Array(items) => Value::Array(items.into_iter().map(compat).collect()),
+        value => value,
+    }
+}
+
+fn is_height(key: &str) -> bool {
+    key == "height" || key.ends_with("_height")
+}
+
+fn camel_case(key: &str) -> String {
+    let mut out = String::with_capacity(key.len());
+    let mut upper = false;
+    for c in key.chars() {
+        if c == '_' && !out.is_empty() {
+            upper = true;
+        } else if upper {
+            out.extend(c.to_uppercase());
+            upper = false;
+        } else {
+            out.push(c);
+        }
+    }
+    out
+}
+
+// Keeps only the compat tags of `If-None-Match`, without their suffix
+fn revalidate_compat(headers: &mut HeaderMap) {
+    let tags: Vec<String> = headers
+        .get_all(header::IF_NONE_MATCH)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(str::trim)
+        .filter_map(|tag| match tag {
+            "*" => Some(tag.to_string()),
+            _ => tag.strip_suffix(COMPAT_TAG).map(|tag| format!("{}\"", tag)),
+        })
+        .collect();
+    headers.remove(header::IF_NONE_MATCH);
+    if tags.is_empty() {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(&tags.join(", ")) {
+        headers.insert(header::IF_NONE_MATCH, value);
+    }
+}
+
+fn tag_compat(headers: &mut HeaderMap) {
+    let etag = headers.get(header::ETAG).and_then(|v| v.to_str().ok()).and_then(|v| v.strip_suffix('"'));
+    if let Some(Ok(etag)) = etag.map(|etag| HeaderValue::from_str(&format!("{}{}", etag, COMPAT_TAG))) {
+        headers.insert(header::ETAG, etag);
+    }
+}
+
+// Router middleware; snake_case responses pass straight through
+pub async fn negotiate(default: JsonProfile, request: Request, next: Next) -> Response {
+    let profile = match JsonProfile::requested(&request) {
+        Ok(requested) => requested.unwrap_or(default),
+        Err(error) => return (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })).into_response(),
+    };
+    let mut request = request;
+    if profile == JsonProfile::Compat {
+        revalidate_compat(request.headers_mut());
+    }
+    let mut response = next.run(request).await;
+    response.headers_mut().append(header::VARY, HeaderValue::from_static(HEADER));
+    if profile == JsonProfile::Compat {
+        tag_compat(response.headers_mut());
+    }
+    let is_json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if profile == JsonProfile::SnakeCase || !is_json {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = to_bytes(body, usize::MAX).await.unwrap_or_default();
+    let Ok(value) = serde_json::from_slice::<Value>(&body) else {
+        return Response::from_parts(parts, Body::from(body));
+    };
+    let body = serde_json::to_vec(&profile.apply(value)).unwrap_or_default();
+    parts.headers.remove(header::CONTENT_LENGTH);
+    parts.headers.insert(HEADER, HeaderValue::from_static("compat"));
+    Response::from_parts(parts, Body::from(body))
+}
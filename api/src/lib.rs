@@ -1,32 +1,355 @@
+pub mod admin;
+pub mod alerts;
+pub mod body;
+pub mod caching;
+pub mod compression;
+pub mod events;
+pub mod extension;
+pub mod idempotency;
+pub mod intake;
+pub mod json_profile;
+pub mod limits;
+pub mod listen;
+pub mod payload;
+pub mod reload;
+pub mod selftest;
+pub mod server;
+pub mod systemd;
+pub mod tasks;
+pub mod webhooks;
+
+pub use admin::{AdminConfig, AdminToken};
+pub use alerts::{Alert, AlertCondition, AlertManager, AlertRule};
+pub use body::{BodyError, BodyRejection, ValidJson};
+pub use compression::{CompressionConfig, Encoding};
+pub use events::{Event, EventLog, EventLogConfig};
+pub use extension::ApiExtension;
+pub use idempotency::IdempotencyCache;
+pub use intake::{IntakeKind, IntakeQueue, IntakeRequest, IntakeState, IntakeStatus};
+pub use json_profile::JsonProfile;
+pub use limits::{Lane, LimitsConfig, LiveLimits, RouteLimits};
+pub use reload::{ConfigLoader, RuntimeConfig};
+pub use listen::ListenAddr;
+pub use payload::{PayloadEncoding, ProposalPayload};
+pub use selftest::{SelfTestReport, SelfTestResult};
+pub use server::{ApiServer, ApiServerBuilder};
+pub use tasks::{TaskManager, TaskState, TaskStatus};
+pub use webhooks::{WebhookConfig, WebhookEvent, Webhooks};
+
 use axum::{
-    extract::{Query, State},
-    http::StatusCode,
-    response::Json,
-    routing::{get, post},
-    Router,
+    extract::{Path, Query, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware,
+    response::{IntoResponse, Json, Response},
+    routing::{get, post, MethodRouter},
+};
+use consensus::{
+    BeaconEntry, BeaconInfo, Block, BlockId, BlockSummary, CertifiedHeader, Checkpoint, ConsensusState, Demotion, FileLease,
+    LaneDepth, LeaderPolicy, MemoryAccountant, PendingTx, MemoryUsage, MempoolError, MetricsSnapshot,
+    ProposalError, ProposalReport, Pruning, SearchResult, SparseMerkleProof, StakeEntry, StakeStatus, TimeoutCertificate, Transaction, Vote, VoteError,
+    VotePhase, Round, Severity, ValidatorId, WhatIf,
 };
-use consensus::{ConsensusState, VotePhase};
+use light_client::{BeaconAttestation, LightError, ProofBuilder};
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
-use trng::Trng;
-use tower_http::cors::CorsLayer;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use trng::{
+    DrandConfig, DrandStatus, DriftMonitor, HardwareConfig, HardwareStatus, HealthHistory, HealthPolicy, HealthSample,
+    MasterSeed, PoolConfig, PoolStatus, SourceCorrelation, SourceStatus, Trng,
+};
+
+const CHECKPOINT_INTERVAL: u64 = 10;
+const DEFAULT_BLOCKS_LIMIT: usize = 20;
+const MAX_BLOCKS_LIMIT: usize = 100;
+const SCRUB_INTERVAL: Duration = Duration::from_secs(600);
+// A day of health history at one sample every 10 seconds
+const HEALTH_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+const HEALTH_HISTORY_LEN: usize = 8640;
+const FINALIZED_POLL_INTERVAL: Duration = Duration::from_millis(250);
+// As often as the collector runs between the watermarks
+const POOL_POLL_INTERVAL: Duration = Duration::from_millis(100);
+const ALERT_INTERVAL: Duration = Duration::from_secs(5);
+const STATUS_INTERVAL: Duration = Duration::from_secs(5);
+const MEMORY_INTERVAL: Duration = Duration::from_secs(1);
+const MEMPOOL_EXPIRY_INTERVAL: Duration = Duration::from_secs(5);
+const DEFAULT_MEMPOOL_LIMIT: usize = 100;
+const MAX_MEMPOOL_LIMIT: usize = 1000;
+const DEFAULT_HEADERS_LIMIT: usize = 100;
+const MAX_HEADERS_LIMIT: usize = 1000;
+
+// How the node takes part in consensus. A standby validator only proposes
+// and votes while it holds the lease; an observer never does, and serves the
+// read API over the chain in its data directory
+#[derive(Default)]
+pub enum NodeRole {
+    #[default]
+    Validator,
+    Standby(FileLease),
+    Observer,
+}
+
+impl NodeRole {
+    pub fn name(&self) -> &'static str {
+        match self {
+            NodeRole::Validator => "validator",
+            NodeRole::Standby(_) => "standby",
+            NodeRole::Observer => "observer",
+        }
+    }
+}
+
+// Which validators `/vote`, `/vote/combined`, `/vote/timeout` and their
+// intake routes may vote as. Other validators' votes arrive signed over the
+// network, so by default the API only casts the node's own
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VotePolicy {
+    Local(ValidatorId),
+    // Any validator, for single-process clusters and load tests; an
+    // operator's override
+    Any,
+}
+
+impl Default for VotePolicy {
+    // The node signs as validator 0
+    fn default() -> Self {
+        VotePolicy::Local(ValidatorId(0))
+    }
+}
+
+// Where the node's randomness comes from: the master seed its keys are
+// derived from, hardware generators and the drand beacon feeding the TRNG
+// pool, and the pool's size and watermarks. Not Debug, so the seed cannot
+// end up in logs
+#[derive(Clone, Default)]
+pub struct EntropyConfig {
+    pub seed: Option<MasterSeed>,
+    pub hardware: Vec<HardwareConfig>,
+    pub drand: Option<DrandConfig>,
+    pub pool: PoolConfig,
+}
+
+impl EntropyConfig {
+    pub fn with_seed(mut self, seed: MasterSeed) -> Self {
+        self.seed = Some(seed);
+        self
+    }
+
+    pub fn with_hardware(mut self, hardware: Vec<HardwareConfig>) -> Self {
+        self.hardware = hardware;
+        self
+    }
+
+    pub fn with_drand(mut self, drand: DrandConfig) -> Self {
+        self.drand = Some(drand);
+        self
+    }
+
+    pub fn with_pool(mut self, pool: PoolConfig) -> Self {
+        self.pool = pool;
+        self
+    }
+}
+
+// Where finalized blocks, the event log and idempotent responses are
+// persisted, if anywhere, how much of the chain is kept in memory and
+// served, and how long events and idempotent responses are kept
+#[derive(Debug, Clone, Default)]
+pub struct StorageConfig {
+    pub data_dir: Option<PathBuf>,
+    pub pruning: Pruning,
+    pub events: EventLogConfig,
+    // How long `Idempotency-Key` responses are kept, a day if unset
+    pub idempotency_window: Option<Duration>,
+}
+
+impl StorageConfig {
+    pub fn with_data_dir(mut self, data_dir: PathBuf) -> Self {
+        self.data_dir = Some(data_dir);
+        self
+    }
+
+    pub fn with_pruning(mut self, pruning: Pruning) -> Self {
+        self.pruning = pruning;
+        self
+    }
+
+    pub fn with_event_log(mut self, events: EventLogConfig) -> Self {
+        self.events = events;
+        self
+    }
+
+    pub fn with_idempotency_window(mut self, window: Duration) -> Self {
+        self.idempotency_window = Some(window);
+        self
+    }
+}
+
+// What the node watches and whom it tells: the TRNG health policy, the
+// alert rules and the webhooks registered at startup, plus where to reload
+// them and the other runtime settings from
+#[derive(Debug, Clone, Default)]
+pub struct MonitoringConfig {
+    pub health_policy: HealthPolicy,
+    pub alert_rules: Vec<AlertRule>,
+    pub webhooks: Vec<WebhookConfig>,
+    pub config_loader: Option<ConfigLoader>,
+}
+
+impl MonitoringConfig {
+    pub fn with_health_policy(mut self, health_policy: HealthPolicy) -> Self {
+        self.health_policy = health_policy;
+        self
+    }
+
+    pub fn with_alert_rules(mut self, alert_rules: Vec<AlertRule>) -> Self {
+        self.alert_rules = alert_rules;
+        self
+    }
+
+    pub fn with_webhooks(mut self, webhooks: Vec<WebhookConfig>) -> Self {
+        self.webhooks = webhooks;
+        self
+    }
+
+    pub fn with_config_loader(mut self, loader: ConfigLoader) -> Self {
+        self.config_loader = Some(loader);
+        self
+    }
+}
 
 #[derive(Clone)]
 pub struct AppState {
     pub consensus: ConsensusState,
     pub trng: Trng,
+    // False while a hot standby waits for the signing lease, and always for
+    // an observer
+    pub active: Arc<AtomicBool>,
+    pub observer: bool,
+    pub vote_policy: VotePolicy,
+    pub selftest: Arc<SelfTestReport>,
+    pub health_history: Arc<Mutex<HealthHistory>>,
+    pub webhooks: Webhooks,
+    pub events: EventLog,
+    pub alerts: AlertManager,
+    pub limits: LiveLimits,
+    // None when the node has no config to reload
+    pub config_loader: Option<ConfigLoader>,
+    // Unix time in milliseconds the proposer loop last went round
+    pub proposer_heartbeat: Arc<AtomicU64>,
+    // Set to true to stop the listeners
+    pub shutdown: Arc<watch::Sender<bool>>,
+    pub tasks: TaskManager,
+    pub memory: MemoryAccountant,
+    pub idempotency: IdempotencyCache,
+    pub intake: IntakeQueue,
+}
+
+impl AppState {
+    // For embedders serving their own consensus and TRNG: an active
+    // validator with in-memory events and idempotent responses, default
+    // limits, no memory budgets, and no webhooks, alerts or config to
+    // reload. Nothing is self-tested; the embedder vouches for its parts
+    pub fn new(consensus: ConsensusState, trng: Trng) -> Self {
+        let drift = DriftMonitor::new(trng.health_policy().sample_size, 1024);
+        let state = Self {
+            consensus,
+            trng,
+            active: Arc::new(AtomicBool::new(true)),
+            observer: false,
+            vote_policy: VotePolicy::default(),
+            selftest: Arc::new(SelfTestReport { passed: true, results: Vec::new(), restart_tests: Vec::new(), finished_at: now_ms() }),
+            health_history: Arc::new(Mutex::new(HealthHistory::new(HEALTH_HISTORY_LEN, drift))),
+            webhooks: Webhooks::new(Vec::new()).expect("HTTP client builds"),
+            events: EventLog::open(None, EventLogConfig::default()).expect("in-memory log opens"),
+            alerts: AlertManager::default(),
+            limits: LimitsConfig::default().live(),
+            config_loader: None,
+            proposer_heartbeat: Arc::new(AtomicU64::new(now_ms())),
+            shutdown: Arc::new(watch::channel(false).0),
+            tasks: TaskManager::default(),
+            memory: MemoryAccountant::default(),
+            idempotency: IdempotencyCache::open(None, idempotency::DEFAULT_WINDOW).expect("in-memory cache opens"),
+            intake: IntakeQueue::default(),
+        };
+        state.track_memory();
+        state
+    }
+
+    // Registers the structures that grow with load with the memory accountant
+    fn track_memory(&self) {
+        let (usage, evict) = (self.consensus.clone(), self.consensus.clone());
+        self.memory.track_evictable("mempool", move || usage.mempool_bytes(), move |bytes| evict.evict_mempool(bytes));
+        let (usage, evict) = (self.consensus.clone(), self.consensus.clone());
+        self.memory.track_evictable("votes", move || usage.vote_bytes(), move |bytes| evict.evict_votes(bytes));
+        let (usage, evict) = (self.events.clone(), self.events.clone());
+        self.memory.track_evictable("event_log", move || usage.heap_bytes(), move |bytes| evict.evict(bytes));
+        let usage = self.trng.clone();
+        self.memory.track("entropy_pool", move || usage.pool_bytes());
+    }
+
+    // Observers are refused for good with 403, a standby for now with 503
+    fn require_active(&self) -> Result<(), StatusCode> {
+        if self.observer {
+            Err(StatusCode::FORBIDDEN)
+        } else if self.active.load(Ordering::Relaxed) {
+            Ok(())
+        } else {
+            Err(StatusCode::SERVICE_UNAVAILABLE)
+        }
+    }
+
+    // 403 for a vote as another validator than the policy allows
+    fn require_voter(&self, validator_id: ValidatorId) -> Result<(), (StatusCode, Json<ErrorResponse>)> {
+        match self.vote_policy {
+            VotePolicy::Local(local) if local != validator_id => {
+                let error = format!("node only votes as validator {}, not {}", local, validator_id);
+                Err((StatusCode::FORBIDDEN, Json(ErrorResponse { error })))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    // Logs the event for subscribers and sends it to webhooks
+    fn publish<T: Serialize>(&self, event: WebhookEvent, data: &T) {
+        self.events.append(event, data);
+        self.webhooks.emit(event, data);
+    }
 }
 
 #[derive(Debug, Deserialize)]
 pub struct ProposeRequest {
     pub payload: String,
+    #[serde(default)]
+    pub payload_encoding: PayloadEncoding,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct VoteRequest {
-    pub proposal_id: String,
-    pub validator_id: usize,
-    pub phase: String,
+    pub proposal_id: BlockId,
+    // Height and round of the proposal, as returned by /propose
+    pub height: u64,
+    pub round: Round,
+    pub validator_id: ValidatorId,
+    pub phase: VotePhase,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct CombinedVoteRequest {
+    pub proposal_id: BlockId,
+    pub height: u64,
+    pub round: Round,
+    pub validator_id: ValidatorId,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TimeoutVoteRequest {
+    pub height: u64,
+    pub round: Round,
+    pub validator_id: ValidatorId,
 }
 
 #[derive(Debug, Deserialize)]
@@ -36,7 +359,85 @@ pub struct RngQuery {
 
 #[derive(Debug, Serialize)]
 pub struct ProposeResponse {
-    pub proposal_id: String,
+    pub proposal_id: BlockId,
+    // Votes for the proposal must name its height and round
+    pub height: u64,
+    pub round: Round,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SearchQuery {
+    pub q: String,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct WhatIfQuery {
+    // Comma-separated validator ids, e.g. "1,2"
+    pub validators: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HeadersQuery {
+    #[serde(default)]
+    pub from: u64,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HeadersResponse {
+    pub finalized_height: Option<u64>,
+    pub headers: Vec<CertifiedHeader>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct BlocksQuery {
+    // Only blocks below this height, for paging back from the last response
+    pub before: Option<u64>,
+    pub limit: Option<usize>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HistoryQuery {
+    // e.g. "15m" or "1h"
+    pub window: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct HealthHistoryResponse {
+    pub interval_secs: u64,
+    pub samples: Vec<HealthSample>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReadyResponse {
+    pub ready: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ErrorResponse {
+    pub error: String,
+}
+
+#[derive(Debug, Serialize)]
+pub struct FinalizedEvent {
+    pub height: u64,
+    pub block_id: BlockId,
+    pub round: Round,
+    pub proposer: ValidatorId,
+    pub timestamp: u64,
+    pub signers: Vec<ValidatorId>,
+}
+
+// Two votes by one validator for different proposals in the same height,
+// round and phase
+#[derive(Debug, Serialize)]
+pub struct EquivocationEvent {
+    pub validator_id: ValidatorId,
+    pub height: u64,
+    pub round: Round,
+    pub phase: VotePhase,
+    pub first: BlockId,
+    pub second: BlockId,
 }
 
 #[derive(Debug, Serialize)]
@@ -45,9 +446,181 @@ pub struct VoteResponse {
     pub finalized: bool,
 }
 
+#[derive(Debug, Serialize)]
+pub struct TimeoutVoteResponse {
+    // True when this vote completed the timeout certificate
+    pub advanced: bool,
+    pub round: Round,
+    pub certificate: Option<TimeoutCertificate>,
+}
+
 #[derive(Debug, Serialize)]
 pub struct FinalizedResponse {
-    pub finalized_block: Option<String>,
+    pub finalized_block: Option<BlockId>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatusResponse {
+    // "validator" or "observer"
+    pub role: &'static str,
+    pub active: bool,
+    pub finalized_block: Option<BlockId>,
+    pub finalized_height: Option<u64>,
+    // Round of the next proposal at the undecided height
+    pub round: Round,
+    // e.g. "archive" or "default:100000", and the lowest finalized height
+    // still served
+    pub pruning: String,
+    pub retained_from: Option<u64>,
+    pub validators: Vec<ValidatorId>,
+    pub metrics: MetricsSnapshot,
+    // Background tasks, in the order they were started
+    pub tasks: Vec<TaskStatus>,
+    pub memory: Vec<MemoryUsage>,
+    // Intake requests not yet taken up by the worker
+    pub intake_queued: usize,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StakingValidator {
+    pub validator_id: ValidatorId,
+    pub status: StakeStatus,
+    pub voting_power: u64,
+    #[serde(flatten)]
+    pub stake: StakeEntry,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StakingResponse {
+    pub epoch: u64,
+    pub total_power: u64,
+    pub validators: Vec<StakingValidator>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct SearchResponse {
+    pub query: String,
+    pub results: Vec<SearchResult>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidatorBlocksResponse {
+    pub validator_id: ValidatorId,
+    // Newest first
+    pub blocks: Vec<BlockSummary>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct BalanceResponse {
+    pub account: ValidatorId,
+    pub balance: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeadersResponse {
+    // The undecided height and its current round
+    pub height: u64,
+    pub round: Round,
+    pub leader: ValidatorId,
+    // Leaders of the height's rounds in order, demoted validators last
+    pub rotation: Vec<ValidatorId>,
+    pub policy: LeaderPolicy,
+    pub validators: Vec<LeaderStats>,
+    // Oldest first, with the turns each was judged on
+    pub demotions: Vec<Demotion>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct LeaderStats {
+    pub validator_id: ValidatorId,
+    pub turns: u64,
+    pub finalized: u64,
+    // Over the recent window; null before any turn
+    pub success_percent: Option<u32>,
+    // First height back in place, while demoted
+    pub demoted_until: Option<u64>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ValidatorKeyResponse {
+    pub validator_id: ValidatorId,
+    pub public_key: String, // hex
+    // Height of the rotation that registered the key; null for genesis keys
+    pub since_height: Option<u64>,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct MempoolQuery {
+    pub limit: Option<usize>,
+    // `next_cursor` of the previous page
+    pub cursor: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MempoolResponse {
+    pub pending: usize,
+    // Highest lane first
+    pub lanes: Vec<LaneDepth>,
+    // Ordered by hash
+    pub txs: Vec<PendingTx>,
+    // Pass as `cursor` for the next page; null on the last one
+    pub next_cursor: Option<String>,
+}
+
+// A transaction that left the mempool without being proposed, because it
+// outlived the TTL ("expired") or an operator removed it ("evicted")
+#[derive(Debug, Serialize)]
+pub struct TxDroppedEvent {
+    pub hash: String,
+    pub sender: String,
+    pub nonce: u64,
+    pub reason: &'static str,
+}
+
+impl TxDroppedEvent {
+    fn new(tx: &Transaction, reason: &'static str) -> Self {
+        Self { hash: tx.hash(), sender: tx.sender.clone(), nonce: tx.nonce, reason }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct NonceResponse {
+    pub account: String,
+    pub next_nonce: u64,
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StateQuery {
+    #[serde(default)]
+    pub proof: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StateResponse {
+    pub key: String,
+    // Finalized block whose `state_root` commits to this state
+    pub height: Option<u64>,
+    pub state_root: String, // hex
+    // The stored JSON as text, so the proof can be checked against its bytes;
+    // null for an absent key
+    pub value: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub proof: Option<SparseMerkleProof>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct TxResponse {
+    pub accepted: bool,
+    pub tx_hash: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct MempoolProposeResponse {
+    pub proposal_id: Option<BlockId>,
+    pub height: Option<u64>,
+    pub round: Option<Round>,
+    pub pending: usize,
 }
 
 #[derive(Debug, Serialize)]
@@ -59,101 +632,987 @@ pub struct RngResponse {
 pub struct HealthResponse {
     pub healthy: bool,
     pub metrics: HashMap<String, f64>,
+    pub pool: PoolStatus,
 }
 
-pub async fn start_server(port: u16) {
-    let validators = vec![0, 1, 2, 3];
-    let app_state = AppState {
-        consensus: ConsensusState::new(validators),
-        trng: Trng::new(),
+// Runs the node's background tasks on `state`. Registered after what each
+// depends on; the closures clone their state afresh for every restart
+fn spawn_tasks(state: &AppState, role: NodeRole) {
+    let role_name = role.name();
+    let tasks = &state.tasks;
+    let memory = state.memory.clone();
+    tasks.spawn("memory_accountant", move || account_memory(memory.clone()));
+    let app_state = state.clone();
+    tasks.spawn("health_sampler", move || sample_health(app_state.clone()));
+    let app_state = state.clone();
+    tasks.spawn("pool_watcher", move || watch_pool(app_state.clone()));
+    if let NodeRole::Standby(lease) = role {
+        let active = state.active.clone();
+        tasks.spawn("lease_holder", move || hold_lease(lease.clone(), active.clone()));
+    }
+    let app_state = state.clone();
+    tasks.spawn("proposer", move || run_proposer(app_state.clone()));
+    let app_state = state.clone();
+    tasks.spawn("mempool_expirer", move || expire_mempool(app_state.clone()));
+    let app_state = state.clone();
+    tasks.spawn("intake_worker", move || intake::run(app_state.clone()));
+    let app_state = state.clone();
+    tasks.spawn("finalized_announcer", move || announce_finalized(app_state.clone()));
+    let app_state = state.clone();
+    tasks.spawn("alert_evaluator", move || evaluate_alerts(app_state.clone()));
+    let app_state = state.clone();
+    tasks.spawn("config_reloader", move || reload_on_sighup(app_state.clone()));
+    if let Some(notifier) = systemd::Notifier::from_env().map(Arc::new) {
+        let app_state = state.clone();
+        tasks.spawn("systemd_notifier", move || notify_systemd(app_state.clone(), notifier.clone(), role_name));
+    }
+}
+
+// The public API, refused after a failed self-test. Each route gets its own
+// timeout and in-flight cap
+fn routes(state: &AppState) -> Vec<(&'static str, MethodRouter<AppState>)> {
+    // Retries of these with the same Idempotency-Key get the first response
+    let idempotent = |handler: MethodRouter<AppState>| {
+        handler.route_layer(middleware::from_fn_with_state(state.idempotency.clone(), idempotency::guard))
     };
+    vec![
+        ("/finalized", get(get_finalized)),
+        ("/headers", get(get_headers)),
+        ("/blocks/:id", get(get_block)),
+        ("/checkpoints/latest", get(get_latest_checkpoint)),
+        ("/beacon/info", get(get_beacon_info)),
+        ("/beacon/latest", get(get_latest_beacon)),
+        ("/beacon/:round", get(get_beacon_round)),
+        ("/beacon/:round/attestation", get(get_beacon_attestation)),
+        ("/status", get(get_status)),
+        ("/metrics", get(get_metrics)),
+        ("/staking/validators", get(get_staking_validators)),
+        ("/validators/:id/blocks", get(get_validator_blocks)),
+        ("/validators/:id/key", get(get_validator_key)),
+        ("/leaders", get(get_leaders)),
+        ("/search", get(search)),
+        ("/events", get(events::list_events)),
+        ("/events/ws", get(events::subscribe)),
+        ("/proposals/:id/what-if", get(get_what_if)),
+        ("/accounts/:id/balance", get(get_balance)),
+        ("/accounts/:id/nonce", get(get_nonce)),
+        ("/state/*key", get(get_state)),
+        ("/tx", idempotent(post(submit_tx))),
+        ("/mempool", get(get_mempool)),
+        ("/params", get(get_params)),
+        ("/propose/mempool", post(propose_from_mempool)),
+        ("/propose", idempotent(post(propose))),
+        ("/propose/validate", post(validate_proposal)),
+        ("/vote", post(vote)),
+        ("/vote/combined", post(vote_combined)),
+        ("/vote/timeout", post(vote_timeout)),
+        ("/intake/propose", post(intake::propose)),
+        ("/intake/vote", post(intake::vote)),
+        ("/intake/vote/combined", post(intake::vote_combined)),
+        ("/intake/:id", get(intake::get_status)),
+        ("/rng", get(get_rng)),
+        ("/health", get(health_check)),
+        ("/health/sources", get(get_sources)),
+        ("/health/sources/correlation", get(get_source_correlations)),
+        ("/health/sources/hardware", get(get_hardware_sources)),
+        ("/health/sources/drand", get(get_drand_source)),
+        ("/health/history", get(get_health_history)),
+        ("/alerts", get(get_alerts)),
+    ]
+}
 
-    let app = Router::new()
-        .route("/finalized", get(get_finalized))
-        .route("/propose", post(propose))
-        .route("/vote", post(vote))
-        .route("/rng", get(get_rng))
-        .route("/health", get(health_check))
-        .layer(CorsLayer::permissive())
-        .with_state(app_state);
+// Tries to acquire or renew the lease every third of its TTL
+async fn hold_lease(lease: FileLease, active: Arc<AtomicBool>) {
+    let mut interval = tokio::time::interval(lease.ttl() / 3);
+    loop {
+        interval.tick().await;
+        let held = match lease.try_acquire() {
+            Ok(acquired) => acquired || lease.is_held(),
+            Err(e) => {
+                eprintln!("Lease renewal failed: {}", e);
+                false
+            }
+        };
+        if active.swap(held, Ordering::Relaxed) != held {
+            println!("{}", if held { "Acquired signing lease, now active" } else { "Lost signing lease, now standby" });
+        }
+    }
+}
 
-    let listener = tokio::net::TcpListener::bind(format!("0.0.0.0:{}", port))
-        .await
-        .unwrap();
-    
-    println!("Server running on http://0.0.0.0:{}", port);
-    axum::serve(listener, app).await.unwrap();
+// Proposes a block of pending transactions every `block_time_ms`, re-read
+// each round so governance changes apply without a restart.
+async fn run_proposer(state: AppState) {
+    loop {
+        let block_time = Duration::from_millis(state.consensus.params().block_time_ms);
+        tokio::time::sleep(block_time).await;
+        state.proposer_heartbeat.store(now_ms(), Ordering::Relaxed);
+        if state.active.load(Ordering::Relaxed) {
+            state.consensus.propose_from_mempool();
+        }
+    }
+}
+
+// Drops transactions that outlived the mempool TTL every
+// MEMPOOL_EXPIRY_INTERVAL, with an event for each
+async fn expire_mempool(state: AppState) {
+    let mut interval = tokio::time::interval(MEMPOOL_EXPIRY_INTERVAL);
+    loop {
+        interval.tick().await;
+        for tx in state.consensus.expire_mempool() {
+            state.publish(WebhookEvent::TxDropped, &TxDroppedEvent::new(&tx, "expired"));
+        }
+    }
+}
+
+// Measures the accounted subsystems every MEMORY_INTERVAL, evicting from
+// those over budget
+async fn account_memory(memory: MemoryAccountant) {
+    let mut interval = tokio::time::interval(MEMORY_INTERVAL);
+    loop {
+        interval.tick().await;
+        let freed = memory.enforce();
+        if freed > 0 {
+            eprintln!("Memory budgets exceeded; evicted {} bytes", freed);
+        }
+    }
+}
+
+// Records a health sample every HEALTH_SAMPLE_INTERVAL, warning on drift
+// and notifying webhooks when the generator turns unhealthy
+async fn sample_health(state: AppState) {
+    let mut interval = tokio::time::interval(HEALTH_SAMPLE_INTERVAL);
+    let mut was_healthy = true;
+    loop {
+        interval.tick().await;
+        let mut history = state.health_history.lock().unwrap();
+        let sample = history.record(&state.trng);
+        if let Some(drift) = sample.drift {
+            eprintln!("TRNG bit bias drifting {:?} (z = {:.3})", drift, sample.bias_z);
+        }
+        if was_healthy && !sample.healthy {
+            state.publish(WebhookEvent::HealthFailure, sample);
+        }
+        was_healthy = sample.healthy;
+    }
+}
+
+// Publishes each drop of the entropy pool below its low watermark
+async fn watch_pool(state: AppState) {
+    let mut interval = tokio::time::interval(POOL_POLL_INTERVAL);
+    let mut crossings = state.trng.pool_status().low_crossings;
+    loop {
+        interval.tick().await;
+        let pool = state.trng.pool_status();
+        if pool.low_crossings > crossings {
+            eprintln!("Entropy pool below its low watermark: {} of {} bytes", pool.fill, pool.config.capacity);
+            state.publish(WebhookEvent::EntropyLow, &pool);
+        }
+        crossings = pool.low_crossings;
+    }
+}
+
+// Notifies webhooks of each finalized block, polling the head
+async fn announce_finalized(state: AppState) {
+    let mut interval = tokio::time::interval(FINALIZED_POLL_INTERVAL);
+    let mut next_height = state.consensus.finalized_height().map_or(0, |h| h + 1);
+    loop {
+        interval.tick().await;
+        let Some(head) = state.consensus.finalized_height() else {
+            continue;
+        };
+        if head < next_height {
+            continue;
+        }
+        for block in state.consensus.iter_finalized(next_height) {
+            let signers = state.consensus.certificate(&block.id).map(|qc| qc.signers).unwrap_or_default();
+            state.publish(WebhookEvent::Finalized, &FinalizedEvent {
+                height: block.height,
+                block_id: block.id,
+                round: block.round,
+                proposer: block.proposer,
+                timestamp: block.timestamp,
+                signers,
+            });
+        }
+        let demotions: Vec<Demotion> = state.consensus.state_machine()
+            .leaders
+            .demotions()
+            .filter(|d| (next_height..=head).contains(&d.at_height))
+            .cloned()
+            .collect();
+        for demotion in &demotions {
+            eprintln!(
+                "Leader {} demoted until height {}: {}% of its last {} turns finalized",
+                demotion.validator_id, demotion.until_height, demotion.success_percent, demotion.evidence.len()
+            );
+            state.publish(WebhookEvent::LeaderDemoted, demotion);
+        }
+        next_height = head + 1;
+    }
+}
+
+// Checks the alert rules every ALERT_INTERVAL and sends alerts that fired or
+// resolved to webhooks
+async fn evaluate_alerts(state: AppState) {
+    let mut interval = tokio::time::interval(ALERT_INTERVAL);
+    let mut finalized = state.consensus.finalized_height();
+    let mut advanced_at = std::time::Instant::now();
+    loop {
+        interval.tick().await;
+        let height = state.consensus.finalized_height();
+        if height != finalized {
+            finalized = height;
+            advanced_at = std::time::Instant::now();
+        }
+        let observations = alerts::Observations {
+            entropy: state.health_history.lock().unwrap().latest().map(|s| s.health.shannon_entropy),
+            stalled_for: advanced_at.elapsed(),
+        };
+        for alert in state.alerts.evaluate(observations, now_ms()) {
+            let verb = if alert.state == alerts::AlertState::Firing { "fired" } else { "resolved" };
+            eprintln!("Alert {} {}: {}", alert.rule, verb, alert.message);
+            state.publish(WebhookEvent::Alert, &alert);
+        }
+    }
+}
+
+// Reloads the runtime config on each SIGHUP; an invalid config is logged
+// and the running one kept
+async fn reload_on_sighup(state: AppState) {
+    let Ok(mut hangups) = tokio::signal::unix::signal(tokio::signal::unix::SignalKind::hangup()) else {
+        eprintln!("Cannot listen for SIGHUP, reload only through the admin API");
+        return;
+    };
+    while hangups.recv().await.is_some() {
+        if let Err(e) = reload::reload(&state) {
+            eprintln!("Config reload rejected: {}", e);
+        }
+    }
+}
+
+// Tells systemd the node is ready once the generator has collected entropy,
+// then keeps STATUS= current and, under `WatchdogSec=`, sends WATCHDOG=1
+// only while the proposer loop and the entropy collector both progress
+async fn notify_systemd(state: AppState, notifier: Arc<systemd::Notifier>, role: &'static str) {
+    let status = |state: &AppState| {
+        let height = state.consensus.finalized_height().map_or("none".to_string(), |h| h.to_string());
+        let selftest = if state.selftest.passed { "" } else { ", self-test failed" };
+        format!("STATUS=Running as {}, finalized height {}{}", role, height, selftest)
+    };
+    while state.trng.collection_rounds() == 0 {
+        tokio::time::sleep(Duration::from_millis(100)).await;
+    }
+    notifier.notify(&format!("READY=1\n{}", status(&state)));
+
+    let watchdog = systemd::watchdog_interval();
+    let mut interval = tokio::time::interval(watchdog.unwrap_or(STATUS_INTERVAL).min(STATUS_INTERVAL));
+    // The first tick is immediate
+    interval.tick().await;
+    let mut rounds = state.trng.collection_rounds();
+    loop {
+        interval.tick().await;
+        let mut message = status(&state);
+        if watchdog.is_some() {
+            let collected = state.trng.collection_rounds();
+            let block_time = state.consensus.params().block_time_ms;
+            let proposer_idle = now_ms().saturating_sub(state.proposer_heartbeat.load(Ordering::Relaxed));
+            if collected == rounds {
+                eprintln!("Entropy collector stalled, withholding watchdog keepalive");
+            } else if proposer_idle > 2 * block_time + 1000 {
+                eprintln!("Proposer loop stalled for {}ms, withholding watchdog keepalive", proposer_idle);
+            } else {
+                message.push_str("\nWATCHDOG=1");
+            }
+            rounds = collected;
+        }
+        notifier.notify(&message);
+    }
+}
+
+pub(crate) fn now_ms() -> u64 {
+    std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+// Accepts plain seconds or a number with an s/m/h suffix, e.g. "60s", "2m"
+pub fn parse_duration(s: &str) -> Result<Duration, String> {
+    let (number, unit) = match s.find(|c: char| !c.is_ascii_digit()) {
+        Some(i) => s.split_at(i),
+        None => (s, "s"),
+    };
+    let value: u64 = number.parse().map_err(|_| format!("invalid duration '{}'", s))?;
+    let secs = match unit {
+        "s" => value,
+        "m" => value * 60,
+        "h" => value * 3600,
+        _ => return Err(format!("invalid duration unit '{}' (use s, m or h)", unit)),
+    };
+    Ok(Duration::from_secs(secs))
+}
+
+fn not_active(status: StatusCode) -> (StatusCode, Json<ErrorResponse>) {
+    let error = if status == StatusCode::FORBIDDEN { "node is an observer" } else { "node is on standby" };
+    (status, Json(ErrorResponse { error: error.into() }))
+}
+
+fn proposal_rejected(e: ProposalError) -> (StatusCode, Json<ErrorResponse>) {
+    let status = match e {
+        ProposalError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+        ProposalError::TooManyTxs { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+    };
+    (status, Json(ErrorResponse { error: e.to_string() }))
+}
+
+// A conflicting vote is evidence of equivocation, reported to webhooks
+fn report_equivocation(state: &AppState, e: &VoteError, validator_id: ValidatorId, second: &BlockId) {
+    if let VoteError::AlreadyVoted { height, round, phase, proposal_id } = e {
+        state.publish(WebhookEvent::Equivocation, &EquivocationEvent {
+            validator_id,
+            height: *height,
+            round: *round,
+            phase: phase.clone(),
+            first: *proposal_id,
+            second: *second,
+        });
+    }
+}
+
+// Votes outside the round being decided, or conflicting with the
+// validator's earlier vote in it, are refused with 409
+fn vote_rejected(e: VoteError) -> (StatusCode, Json<ErrorResponse>) {
+    let status = match e {
+        VoteError::UnknownValidator(_) | VoteError::UnknownProposal(_) => StatusCode::NOT_FOUND,
+        VoteError::Unjustified(_) | VoteError::StateRootMismatch { .. } => StatusCode::UNPROCESSABLE_ENTITY,
+        VoteError::WrongHeight { .. }
+        | VoteError::WrongRound { .. }
+        | VoteError::ProposalMismatch { .. }
+        | VoteError::AlreadyVoted { .. } => StatusCode::CONFLICT,
+    };
+    (status, Json(ErrorResponse { error: e.to_string() }))
 }
 
 async fn get_finalized(
     State(state): State<AppState>,
-) -> Json<FinalizedResponse> {
+    headers: HeaderMap,
+) -> Response {
     let finalized_block = state.consensus.finalize();
-    
-    Json(FinalizedResponse {
+
+    caching::respond(&headers, caching::finalized_etag("finalized", finalized_block), || FinalizedResponse {
         finalized_block,
     })
 }
 
+async fn get_headers(State(state): State<AppState>, Query(query): Query<HeadersQuery>) -> Json<HeadersResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_HEADERS_LIMIT).min(MAX_HEADERS_LIMIT);
+
+    Json(HeadersResponse {
+        finalized_height: state.consensus.finalized_height(),
+        headers: state.consensus.certified_headers(query.from, limit),
+    })
+}
+
+async fn get_block(State(state): State<AppState>, Path(id): Path<BlockId>) -> Result<Json<Block>, StatusCode> {
+    state.consensus.get_block(&id).map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn get_latest_checkpoint(
+    State(state): State<AppState>,
+) -> Result<Json<Checkpoint>, StatusCode> {
+    state.consensus.latest_checkpoint()
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn get_beacon_info(
+    State(state): State<AppState>,
+) -> Result<Json<BeaconInfo>, StatusCode> {
+    state.consensus.beacon()
+        .map(|beacon| Json(beacon.info()))
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn get_latest_beacon(
+    State(state): State<AppState>,
+) -> Result<Json<BeaconEntry>, StatusCode> {
+    state.consensus.beacon()
+        .and_then(|beacon| beacon.latest())
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn get_beacon_round(
+    State(state): State<AppState>,
+    Path(round): Path<u64>,
+) -> Result<Json<BeaconEntry>, StatusCode> {
+    state.consensus.beacon()
+        .and_then(|beacon| beacon.entry(round))
+        .map(Json)
+        .ok_or(StatusCode::NOT_FOUND)
+}
+
+// Built by the light client's proof builder, for systems that verify it
+// offline against a trusted validator set commitment
+async fn get_beacon_attestation(
+    State(state): State<AppState>,
+    Path(height): Path<u64>,
+) -> Result<Json<BeaconAttestation>, (StatusCode, Json<ErrorResponse>)> {
+    ProofBuilder::new(&state.consensus).beacon_attestation(height).map(Json).map_err(|e| {
+        let status = match e {
+            LightError::Uncertified { .. } => StatusCode::CONFLICT,
+            _ => StatusCode::NOT_FOUND,
+        };
+        (status, Json(ErrorResponse { error: e.to_string() }))
+    })
+}
+
+async fn get_status(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    caching::respond_by_content(&headers, "status", StatusResponse {
+        role: if state.observer { "observer" } else { "validator" },
+        active: state.active.load(Ordering::Relaxed),
+        finalized_block: state.consensus.finalize(),
+        finalized_height: state.consensus.finalized_height(),
+        round: state.consensus.current_round(),
+        pruning: state.consensus.pruning().to_string(),
+        retained_from: state.consensus.retained_from(),
+        validators: state.consensus.get_validators().to_vec(),
+        metrics: state.consensus.metrics(),
+        tasks: state.tasks.statuses(),
+        memory: state.memory.snapshot(),
+        intake_queued: state.intake.depth(),
+    })
+}
+
+// Prometheus text exposition format
+async fn get_metrics(
+    State(state): State<AppState>,
+) -> impl IntoResponse {
+    (
+        [(header::CONTENT_TYPE, "text/plain; version=0.0.4")],
+        state.consensus.render_metrics()
+            + &state.memory.render_prometheus()
+            + &state.trng.pool_status().render_prometheus(),
+    )
+}
+
+async fn get_staking_validators(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    // The version is read first, so a tag is never newer than its body
+    let etag = caching::finalized_etag("staking-validators", state.consensus.finalize());
+    caching::respond(&headers, etag, || {
+        let state_machine = state.consensus.state_machine();
+        let staking = &state_machine.staking;
+
+        StakingResponse {
+            epoch: staking.epoch(),
+            total_power: staking.total_power(),
+            validators: staking
+                .entries()
+                .map(|(validator_id, entry)| StakingValidator {
+                    validator_id,
+                    status: entry.status(),
+                    voting_power: entry.bonded,
+                    stake: entry.clone(),
+                })
+                .collect(),
+        }
+    })
+}
+
+async fn get_validator_blocks(
+    State(state): State<AppState>,
+    Path(validator_id): Path<ValidatorId>,
+    Query(params): Query<BlocksQuery>,
+) -> Result<Json<ValidatorBlocksResponse>, StatusCode> {
+    let limit = params.limit.unwrap_or(DEFAULT_BLOCKS_LIMIT).min(MAX_BLOCKS_LIMIT);
+    let blocks = state.consensus
+        .blocks_proposed_by(validator_id, params.before, limit)
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(ValidatorBlocksResponse { validator_id, blocks }))
+}
+
+async fn get_leaders(State(state): State<AppState>) -> Json<LeadersResponse> {
+    let height = state.consensus.finalized_height().map_or(0, |h| h + 1);
+    let round = state.consensus.current_round();
+    let state_machine = state.consensus.state_machine();
+    let leaders = &state_machine.leaders;
+    let demoted: HashMap<ValidatorId, u64> = leaders.demoted(height).collect();
+
+    Json(LeadersResponse {
+        height,
+        round,
+        leader: leaders.leader(height, round),
+        rotation: leaders.rotation(height),
+        policy: leaders.policy(),
+        validators: state
+            .consensus
+            .get_validators()
+            .iter()
+            .map(|&validator_id| {
+                let record = leaders.record(validator_id);
+                LeaderStats {
+                    validator_id,
+                    turns: record.map_or(0, |r| r.turns),
+                    finalized: record.map_or(0, |r| r.finalized),
+                    success_percent: record.and_then(|r| r.success_percent()),
+                    demoted_until: demoted.get(&validator_id).copied(),
+                }
+            })
+            .collect(),
+        demotions: leaders.demotions().cloned().collect(),
+    })
+}
+
+async fn get_validator_key(
+    State(state): State<AppState>,
+    Path(validator_id): Path<ValidatorId>,
+) -> Result<Json<ValidatorKeyResponse>, StatusCode> {
+    let entry = state.consensus.validator_key(validator_id).ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(ValidatorKeyResponse {
+        validator_id,
+        public_key: hex::encode(entry.key.as_bytes()),
+        since_height: entry.since_height,
+    }))
+}
+
+// Would the proposal finalize if these validators voted for it now?
+async fn get_what_if(
+    State(state): State<AppState>,
+    Path(proposal_id): Path<BlockId>,
+    Query(query): Query<WhatIfQuery>,
+) -> Result<Json<WhatIf>, (StatusCode, Json<ErrorResponse>)> {
+    let rejected = |status, error: String| (status, Json(ErrorResponse { error }));
+    let voters = query
+        .validators
+        .as_deref()
+        .unwrap_or_default()
+        .split(',')
+        .map(str::trim)
+        .filter(|id| !id.is_empty())
+        .map(|id| id.parse().map_err(|_| rejected(StatusCode::BAD_REQUEST, format!("invalid validator id '{}'", id))))
+        .collect::<Result<Vec<ValidatorId>, _>>()?;
+    state
+        .consensus
+        .what_if(&proposal_id, &voters)
+        .map(Json)
+        .map_err(|e| rejected(StatusCode::NOT_FOUND, e.to_string()))
+}
+
+async fn search(
+    State(state): State<AppState>,
+    Query(params): Query<SearchQuery>,
+) -> Json<SearchResponse> {
+    let results = state.consensus.search(&params.q);
+
+    Json(SearchResponse { query: params.q, results })
+}
+
+async fn get_balance(
+    State(state): State<AppState>,
+    Path(account): Path<ValidatorId>,
+) -> Json<BalanceResponse> {
+    let balance = state.consensus.state_machine().rewards.balance(account);
+
+    Json(BalanceResponse { account, balance })
+}
+
+async fn get_nonce(
+    State(state): State<AppState>,
+    Path(account): Path<String>,
+) -> Json<NonceResponse> {
+    let next_nonce = state.consensus.state_machine().next_nonce(&account);
+
+    Json(NonceResponse { account, next_nonce })
+}
+
+// Without `proof=true` an absent key is a 404; with it, the proof of absence
+async fn get_state(
+    State(state): State<AppState>,
+    Path(key): Path<String>,
+    Query(query): Query<StateQuery>,
+) -> Result<Json<StateResponse>, StatusCode> {
+    let read = state.consensus.read_state(&key);
+    if read.value.is_none() && !query.proof {
+        return Err(StatusCode::NOT_FOUND);
+    }
+
+    Ok(Json(StateResponse {
+        key,
+        height: read.height,
+        state_root: read.state_root,
+        value: read.value.map(|v| String::from_utf8_lossy(&v).into_owned()),
+        proof: query.proof.then_some(read.proof),
+    }))
+}
+
+async fn get_mempool(State(state): State<AppState>, Query(query): Query<MempoolQuery>) -> Json<MempoolResponse> {
+    let limit = query.limit.unwrap_or(DEFAULT_MEMPOOL_LIMIT).min(MAX_MEMPOOL_LIMIT);
+    let lanes = state.consensus.mempool_lanes();
+    // One extra to learn whether another page follows
+    let mut txs = state.consensus.pending_transactions(query.cursor.as_deref(), limit + 1);
+    let next_cursor = if txs.len() > limit {
+        txs.truncate(limit);
+        txs.last().map(|p| p.hash.clone())
+    } else {
+        None
+    };
+
+    Json(MempoolResponse { pending: lanes.iter().map(|l| l.pending).sum(), lanes, txs, next_cursor })
+}
+
+// An observer never proposes, so transactions would sit in its mempool
+async fn submit_tx(
+    State(state): State<AppState>,
+    ValidJson(tx): ValidJson<Transaction>,
+) -> (StatusCode, Json<TxResponse>) {
+    if state.observer {
+        return (StatusCode::FORBIDDEN, Json(TxResponse {
+            accepted: false,
+            tx_hash: None,
+            error: Some("node is an observer".to_string()),
+        }));
+    }
+    match state.consensus.submit_transaction(tx) {
+        Ok(hash) => (StatusCode::ACCEPTED, Json(TxResponse {
+            accepted: true,
+            tx_hash: Some(hash),
+            error: None,
+        })),
+        Err(e) => {
+            let status = match e {
+                MempoolError::TooLarge { .. } => StatusCode::PAYLOAD_TOO_LARGE,
+                _ => StatusCode::CONFLICT,
+            };
+            (status, Json(TxResponse {
+                accepted: false,
+                tx_hash: None,
+                error: Some(e.to_string()),
+            }))
+        }
+    }
+}
+
+async fn propose_from_mempool(
+    State(state): State<AppState>,
+) -> Result<Json<MempoolProposeResponse>, StatusCode> {
+    state.require_active()?;
+
+    let proposal_id = state.consensus.propose_from_mempool();
+    let position = proposal_id.as_ref().and_then(|id| state.consensus.proposal_round(id));
+    Ok(Json(MempoolProposeResponse {
+        proposal_id,
+        height: position.map(|(height, _)| height),
+        round: position.map(|(_, round)| round),
+        pending: state.consensus.mempool_len(),
+    }))
+}
+
+async fn get_params(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+) -> Response {
+    let etag = caching::finalized_etag("params", state.consensus.finalize());
+    caching::respond(&headers, etag, || state.consensus.params())
+}
+
 async fn propose(
     State(state): State<AppState>,
-    Json(payload): Json<ProposeRequest>,
-) -> Result<Json<ProposeResponse>, StatusCode> {
-    let proposal_id = state.consensus.propose(payload.payload.into_bytes());
+    ProposalPayload(payload): ProposalPayload,
+) -> Result<Json<ProposeResponse>, (StatusCode, Json<ErrorResponse>)> {
+    apply_proposal(&state, payload).map(Json)
+}
+
+// What `/propose` does once the payload is decoded, shared with the intake
+// worker
+fn apply_proposal(state: &AppState, payload: Vec<u8>) -> Result<ProposeResponse, (StatusCode, Json<ErrorResponse>)> {
+    state.require_active().map_err(not_active)?;
+    let proposal_id = state.consensus
+        .propose(payload)
+        .map_err(proposal_rejected)?;
+    let (height, round) = state.consensus.proposal_round(&proposal_id).unwrap_or_default();
     
-    Ok(Json(ProposeResponse {
+    Ok(ProposeResponse {
         proposal_id,
-    }))
+        height,
+        round,
+    })
 }
 
-async fn vote(
+// Runs `/propose`'s checks without proposing; problems are reported in the
+// body, so the status is 200 whenever the request itself was understood
+async fn validate_proposal(
     State(state): State<AppState>,
-    Json(vote_req): Json<VoteRequest>,
-) -> Json<VoteResponse> {
-    let phase = match vote_req.phase.as_str() {
-        "precommit" => VotePhase::Precommit,
-        "commit" => VotePhase::Commit,
-        _ => {
-            return Json(VoteResponse {
-                success: false,
-                finalized: false,
-            });
-        }
+    payload: Result<ProposalPayload, BodyRejection>,
+) -> Json<ProposalReport> {
+    let mut report = match &payload {
+        Ok(ProposalPayload(payload)) => state.consensus.validate_proposal(payload),
+        Err(_) => state.consensus.validate_proposal(&[]),
     };
+    if let Err(rejection) = payload {
+        report.push("payload", Severity::Error, rejection.body.error);
+    }
+    if let Err(status) = state.require_active() {
+        let (_, Json(error)) = not_active(status);
+        report.push("role", Severity::Error, error.error);
+    }
+    Json(report)
+}
+
+async fn vote(
+    State(state): State<AppState>,
+    ValidJson(vote_req): ValidJson<VoteRequest>,
+) -> Result<Json<VoteResponse>, (StatusCode, Json<ErrorResponse>)> {
+    apply_vote(&state, vote_req).map(Json)
+}
+
+fn apply_vote(state: &AppState, vote_req: VoteRequest) -> Result<VoteResponse, (StatusCode, Json<ErrorResponse>)> {
+    state.require_active().map_err(not_active)?;
+    state.require_voter(vote_req.validator_id)?;
+
+    let success = state.consensus
+        .vote(Vote {
+            proposal_id: vote_req.proposal_id,
+            height: vote_req.height,
+            round: vote_req.round,
+            validator_id: vote_req.validator_id,
+            phase: vote_req.phase,
+        })
+        .map_err(|e| {
+            report_equivocation(state, &e, vote_req.validator_id, &vote_req.proposal_id);
+            vote_rejected(e)
+        })?;
+    let finalized = state.consensus.finalize().is_some();
+    
+    Ok(VoteResponse {
+        success,
+        finalized,
+    })
+}
+
+async fn vote_combined(
+    State(state): State<AppState>,
+    ValidJson(vote_req): ValidJson<CombinedVoteRequest>,
+) -> Result<Json<VoteResponse>, (StatusCode, Json<ErrorResponse>)> {
+    apply_combined_vote(&state, vote_req).map(Json)
+}
+
+fn apply_combined_vote(
+    state: &AppState,
+    vote_req: CombinedVoteRequest,
+) -> Result<VoteResponse, (StatusCode, Json<ErrorResponse>)> {
+    state.require_active().map_err(not_active)?;
+    state.require_voter(vote_req.validator_id)?;
 
-    let success = state.consensus.vote(vote_req.proposal_id, vote_req.validator_id, phase);
+    let success = state.consensus
+        .vote_combined(vote_req.proposal_id, vote_req.height, vote_req.round, vote_req.validator_id)
+        .map_err(|e| {
+            report_equivocation(state, &e, vote_req.validator_id, &vote_req.proposal_id);
+            vote_rejected(e)
+        })?;
     let finalized = state.consensus.finalize().is_some();
     
-    Json(VoteResponse {
+    Ok(VoteResponse {
         success,
         finalized,
     })
 }
 
+async fn vote_timeout(
+    State(state): State<AppState>,
+    ValidJson(vote_req): ValidJson<TimeoutVoteRequest>,
+) -> Result<Json<TimeoutVoteResponse>, (StatusCode, Json<ErrorResponse>)> {
+    state.require_active().map_err(not_active)?;
+    state.require_voter(vote_req.validator_id)?;
+
+    let advanced = state.consensus
+        .vote_timeout(vote_req.height, vote_req.round, vote_req.validator_id)
+        .is_some();
+
+    Ok(Json(TimeoutVoteResponse {
+        advanced,
+        round: state.consensus.current_round(),
+        certificate: state.consensus.timeout_certificate(),
+    }))
+}
+
+// Large draws run off the async workers, so they cannot stall other
+// requests and the route's timeout still fires. Refused until the entropy
+// pool first reaches its high watermark
 async fn get_rng(
     State(state): State<AppState>,
     Query(params): Query<RngQuery>,
-) -> Json<RngResponse> {
+) -> Result<Json<RngResponse>, Response> {
+    if !state.trng.pool_status().warm {
+        let error = "entropy pool is warming up".to_string();
+        let retry_after = [(header::RETRY_AFTER, "1")];
+        return Err((StatusCode::SERVICE_UNAVAILABLE, retry_after, Json(ErrorResponse { error })).into_response());
+    }
     let len = params.len.unwrap_or(32);
-    let random_bytes = state.trng.rand_bytes(len);
-    
-    Json(RngResponse {
-        random_bytes: hex::encode(random_bytes),
-    })
+    let random_bytes = tokio::task::spawn_blocking(move || hex::encode(state.trng.rand_bytes(len)))
+        .await
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR.into_response())?;
+
+    Ok(Json(RngResponse { random_bytes }))
+}
+
+async fn get_selftest(State(state): State<AppState>) -> Json<SelfTestReport> {
+    Json(state.selftest.as_ref().clone())
+}
+
+async fn readyz(State(state): State<AppState>) -> (StatusCode, Json<ReadyResponse>) {
+    let ready = state.selftest.passed;
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, Json(ReadyResponse { ready }))
+}
+
+async fn get_health_history(
+    State(state): State<AppState>,
+    Query(params): Query<HistoryQuery>,
+) -> Result<Json<HealthHistoryResponse>, (StatusCode, Json<ErrorResponse>)> {
+    let window = params.window.as_deref().unwrap_or("1h");
+    let window = parse_duration(window).map_err(|error| (StatusCode::BAD_REQUEST, Json(ErrorResponse { error })))?;
+    let now = now_ms();
+    let samples = state.health_history.lock().unwrap().since(now.saturating_sub(window.as_millis() as u64));
+    Ok(Json(HealthHistoryResponse {
+        interval_secs: HEALTH_SAMPLE_INTERVAL.as_secs(),
+        samples,
+    }))
+}
+
+async fn get_alerts(State(state): State<AppState>) -> Json<Vec<Alert>> {
+    Json(state.alerts.active())
+}
+
+async fn get_sources(State(state): State<AppState>) -> Json<Vec<SourceStatus>> {
+    Json(state.trng.sources())
+}
+
+async fn get_hardware_sources(State(state): State<AppState>) -> Json<Vec<HardwareStatus>> {
+    Json(state.trng.hardware_sources())
+}
+
+async fn get_drand_source(State(state): State<AppState>) -> Result<Json<DrandStatus>, StatusCode> {
+    state.trng.drand_source().map(Json).ok_or(StatusCode::NOT_FOUND)
+}
+
+async fn get_source_correlations(State(state): State<AppState>) -> Json<Vec<SourceCorrelation>> {
+    Json(state.trng.source_correlations())
 }
 
 async fn health_check(
     State(state): State<AppState>,
 ) -> Json<HealthResponse> {
-    let health = state.trng.health_check(8192);
+    let policy = state.trng.health_policy();
+    let health = state.trng.health_check(policy.sample_size);
     
     let mut metrics = HashMap::new();
     metrics.insert("monobit_deviation".to_string(), health.monobit_deviation);
     metrics.insert("runs_deviation".to_string(), health.runs_deviation);
     metrics.insert("shannon_entropy".to_string(), health.shannon_entropy);
+    metrics.insert("longest_run_p".to_string(), health.longest_run_p);
+    metrics.insert("block_frequency_p".to_string(), health.block_frequency_p);
+    metrics.insert("autocorrelation_z".to_string(), health.autocorrelation_z);
+    metrics.insert("autocorrelation_lag".to_string(), health.autocorrelation_lag as f64);
+    metrics.insert("arithmetic_mean".to_string(), health.arithmetic_mean);
+    metrics.insert("monte_carlo_pi".to_string(), health.monte_carlo_pi);
+    metrics.insert("serial_correlation".to_string(), health.serial_correlation);
+    metrics.insert("compression_ratio".to_string(), health.compression_ratio);
     
     Json(HealthResponse {
-        healthy: health.is_healthy(),
+        healthy: policy.is_healthy(&health),
         metrics,
+        pool: state.trng.pool_status(),
     })
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::Router;
+    use tower::ServiceExt;
+
+    fn app(vote_policy: VotePolicy) -> (AppState, Router) {
+        let consensus = ConsensusState::new((0..4).map(ValidatorId).collect());
+        let mut state = AppState::new(consensus, Trng::from_seed(b"votes"));
+        state.vote_policy = vote_policy;
+        let router = Router::new()
+            .route("/vote/combined", post(vote_combined))
+            .route("/vote/timeout", post(vote_timeout))
+            .with_state(state.clone());
+        (state, router)
+    }
+
+    async fn post_json(app: &Router, path: &str, body: serde_json::Value) -> (StatusCode, serde_json::Value) {
+        let request = axum::extract::Request::builder()
+            .method("POST")
+            .uri(path)
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.clone().oneshot(request).await.unwrap();
+        let status = response.status();
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, serde_json::from_slice(&bytes).unwrap())
+    }
+
+    fn combined(proposal_id: BlockId, validator: u32) -> serde_json::Value {
+        serde_json::json!({ "proposal_id": proposal_id, "height": 0, "round": 0, "validator_id": validator })
+    }
+
+    #[tokio::test]
+    async fn test_any_voter_node_finalizes_over_http() {
+        let (state, app) = app(VotePolicy::Any);
+        let proposal_id = state.consensus.propose(b"block".to_vec()).unwrap();
+        // Three of four validators make a quorum
+        for validator in 0..3 {
+            let (status, body) = post_json(&app, "/vote/combined", combined(proposal_id, validator)).await;
+            assert_eq!(status, StatusCode::OK, "{}", body);
+            assert_eq!(body["finalized"], validator == 2);
+        }
+        assert_eq!(state.consensus.finalized_height(), Some(0));
+    }
+
+    #[tokio::test]
+    async fn test_default_policy_refuses_other_validators() {
+        assert_eq!(VotePolicy::default(), VotePolicy::Local(ValidatorId(0)));
+        let (state, app) = app(VotePolicy::default());
+        let proposal_id = state.consensus.propose(b"block".to_vec()).unwrap();
+
+        let (status, body) = post_json(&app, "/vote/combined", combined(proposal_id, 1)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body, serde_json::json!({ "error": "node only votes as validator 0, not 1" }));
+        let (status, _) = post_json(&app, "/vote/combined", combined(proposal_id, 0)).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let timeout = |validator: u32| serde_json::json!({ "height": 0, "round": 0, "validator_id": validator });
+        let (status, body) = post_json(&app, "/vote/timeout", timeout(2)).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body["error"], "node only votes as validator 0, not 2");
+        let (status, body) = post_json(&app, "/vote/timeout", timeout(0)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body["advanced"], false);
+    }
+
+    #[tokio::test]
+    async fn test_any_policy_overrides_the_local_identity() {
+        let (_, app) = app(VotePolicy::Any);
+        for validator in 0..3 {
+            let vote = serde_json::json!({ "height": 0, "round": 0, "validator_id": validator });
+            let (status, body) = post_json(&app, "/vote/timeout", vote).await;
+            assert_eq!(status, StatusCode::OK, "{}", body);
+        }
+        // Three of four timeout votes move the height to the next round
+        let vote = serde_json::json!({ "height": 0, "round": 0, "validator_id": 3 });
+        let (_, body) = post_json(&app, "/vote/timeout", vote).await;
+        assert_eq!(body["round"], 1);
+    }
+}
@@ -0,0 +1,432 @@
+// Overload protection: every route has a request timeout and a cap on
+// requests in flight, and the whole API shares a global cap. A request over
+// a cap is rejected at once with 503 and `Retry-After` rather than queued;
+// the handler never ran, so clients may retry it safely. A request that
+// times out gets 504, since its handler may already have had an effect.
+//
+// Requests are also sorted into lanes so randomness traffic cannot starve
+// consensus. Votes and proposals have in-flight capacity of their own outside
+// the global cap, and the RNG lane may only spend a fixed amount of handler
+// time per second before it is turned away with 503 as well.
+//
+// The global cap, the default timeout and the RNG budget can be changed
+// while serving through `LiveLimits::update`.
+use crate::ErrorResponse;
+use axum::extract::Request;
+use axum::http::{header, StatusCode};
+use axum::middleware::{self, Next};
+use axum::response::{IntoResponse, Json, Response};
+use axum::routing::MethodRouter;
+use axum::Router;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+use tokio::sync::Semaphore;
+
+// Seconds clients are asked to wait before retrying an overloaded route
+const RETRY_AFTER_SECS: u64 = 1;
+
+#[derive(Debug, Clone, Copy)]
+pub struct RouteLimits {
+    pub timeout: Duration,
+    pub max_in_flight: usize,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Lane {
+    // Votes and proposals: reserved capacity, never behind other traffic
+    Consensus,
+    // Randomness draws and health checks: CPU-heavy, time-budgeted
+    Rng,
+    Default,
+}
+
+#[derive(Debug, Clone)]
+pub struct LimitsConfig {
+    pub global_max_in_flight: usize,
+    // Routes without an entry in `routes`
+    pub default: RouteLimits,
+    pub routes: HashMap<&'static str, RouteLimits>,
+    pub lanes: HashMap<&'static str, Lane>,
+    // In-flight cap of the consensus lane, separate from the global one
+    pub consensus_max_in_flight: usize,
+    // Handler time the RNG lane may use per second
+    pub rng_budget: Duration,
+    // Bytes each memory-accounted subsystem may hold, by name: "mempool",
+    // "votes", "event_log" or "entropy_pool"
+    pub memory_budgets: BTreeMap<String, usize>,
+    // How long a transaction may stay in the mempool
+    pub mempool_ttl: Duration,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        let default = RouteLimits { timeout: Duration::from_secs(10), max_in_flight: 256 };
+        Self {
+            global_max_in_flight: 1024,
+            default,
+            routes: HashMap::from([
+                ("/rng", RouteLimits { max_in_flight: 64, ..default }),
+                ("/propose", RouteLimits { max_in_flight: 16, ..default }),
+                ("/propose/mempool", RouteLimits { max_in_flight: 16, ..default }),
+                ("/health", RouteLimits { timeout: Duration::from_secs(30), max_in_flight: 8 }),
+            ]),
+            lanes: HashMap::from([
+                ("/propose", Lane::Consensus),
+                ("/propose/mempool", Lane::Consensus),
+                ("/vote", Lane::Consensus),
+                ("/vote/combined", Lane::Consensus),
+                ("/vote/timeout", Lane::Consensus),
+                ("/rng", Lane::Rng),
+                ("/health", Lane::Rng),
+            ]),
+            consensus_max_in_flight: 256,
+            rng_budget: Duration::from_millis(500),
+            memory_budgets: BTreeMap::new(),
+            mempool_ttl: consensus::mempool::DEFAULT_TX_TTL,
+        }
+    }
+}
+
+impl LimitsConfig {
+    pub fn with_route(mut self, path: &'static str, limits: RouteLimits) -> Self {
+        self.routes.insert(path, limits);
+        self
+    }
+
+    pub fn with_global_max_in_flight(mut self, max: usize) -> Self {
+        self.global_max_in_flight = max;
+        self
+    }
+
+    pub fn with_default_timeout(mut self, timeout: Duration) -> Self {
+        self.default.timeout = timeout;
+        self
+    }
+
+    pub fn with_rng_budget(mut self, budget: Duration) -> Self {
+        self.rng_budget = budget;
+        self
+    }
+
+    pub fn with_memory_budget(mut self, subsystem: &str, bytes: usize) -> Self {
+        self.memory_budgets.insert(subsystem.to_string(), bytes);
+        self
+    }
+
+    pub fn with_mempool_ttl(mut self, ttl: Duration) -> Self {
+        self.mempool_ttl = ttl;
+        self
+    }
+
+    pub fn lane(&self, path: &str) -> Lane {
+        self.lanes.get(path).copied().unwrap_or(Lane::Default)
+    }
+
+    pub fn for_route(&self, path: &str) -> RouteLimits {
+        self.routes.get(path).copied().unwrap_or(self.default)
+    }
+
+    // The limits as enforced while serving
+    pub fn live(&self) -> LiveLimits {
+        LiveLimits {
+            shared: Arc::new(Shared {
+                global: RwLock::new(Arc::new(Semaphore::new(self.global_max_in_flight.max(1)))),
+                consensus: Arc::new(Semaphore::new(self.consensus_max_in_flight.max(1))),
+                rng_budget: TimeBudget::new(),
+                config: RwLock::new(self.clone()),
+            }),
+        }
+    }
+}
+
+// The limits the middleware enforces, shared by every route built from them
+#[derive(Clone)]
+pub struct LiveLimits {
+    shared: Arc<Shared>,
+}
+
+struct Shared {
+    // Replaced rather than resized when the cap changes; requests already in
+    // flight finish on the old one
+    global: RwLock<Arc<Semaphore>>,
+    consensus: Arc<Semaphore>,
+    rng_budget: TimeBudget,
+    config: RwLock<LimitsConfig>,
+}
+
+impl LiveLimits {
+    pub fn config(&self) -> LimitsConfig {
+        self.shared.config.read().unwrap().clone()
+    }
+
+    // Applies a new global cap, default timeout and RNG budget, returning
+    // what changed as "name: old -> new"
+    pub fn update(&self, global_max_in_flight: usize, default_timeout: Duration, rng_budget: Duration) -> Vec<String> {
+        let mut config = self.shared.config.write().unwrap();
+        let mut changes = Vec::new();
+        if config.global_max_in_flight != global_max_in_flight {
+            changes.push(format!("max_in_flight: {} -> {}", config.global_max_in_flight, global_max_in_flight));
+            config.global_max_in_flight = global_max_in_flight;
+            *self.shared.global.write().unwrap() = Arc::new(Semaphore::new(global_max_in_flight.max(1)));
+        }
+        if config.default.timeout != default_timeout {
+            changes.push(format!("request_timeout: {:?} -> {:?}", config.default.timeout, default_timeout));
+            config.default.timeout = default_timeout;
+        }
+        if config.rng_budget != rng_budget {
+            changes.push(format!("rng_budget: {:?} -> {:?}", config.rng_budget, rng_budget));
+            config.rng_budget = rng_budget;
+        }
+        changes
+    }
+
+    // Wraps one route's handlers in its own timeout and in-flight cap; routes
+    // without limits of their own follow changes to the default timeout
+    pub fn route<S: Clone + Send + Sync + 'static>(&self, path: &str, handler: MethodRouter<S>) -> MethodRouter<S> {
+        let config = self.config();
+        let own = config.routes.get(path).map(|limits| limits.timeout);
+        let permits = Arc::new(Semaphore::new(config.for_route(path).max_in_flight.max(1)));
+        let shared = self.shared.clone();
+        handler.layer(middleware::from_fn(move |request: Request, next: Next| {
+            let timeout = own.unwrap_or_else(|| shared.config.read().unwrap().default.timeout);
+            enforce(permits.clone(), Some(timeout), request, next)
+        }))
+    }
+
+    // Applies the lane caps to everything in `router`: consensus requests
+    // count against their own cap, everything else against the global one
+    pub fn global<S: Clone + Send + Sync + 'static>(&self, router: Router<S>) -> Router<S> {
+        let shared = self.shared.clone();
+        router.layer(middleware::from_fn(move |request: Request, next: Next| {
+            let shared = shared.clone();
+            async move { shared.enforce(request, next).await }
+        }))
+    }
+}
+
+impl Shared {
+    async fn enforce(&self, request: Request, next: Next) -> Response {
+        // Lanes are looked up by the request path, which matches the route
+        // for every path without parameters
+        let (lane, rng_budget) = {
+            let config = self.config.read().unwrap();
+            (config.lane(request.uri().path()), config.rng_budget)
+        };
+        let global = self.global.read().unwrap().clone();
+        match lane {
+            Lane::Consensus => enforce(self.consensus.clone(), None, request, next).await,
+            Lane::Default => enforce(global, None, request, next).await,
+            Lane::Rng => {
+                if self.rng_budget.exhausted(rng_budget) {
+                    return overloaded();
+                }
+                let started = Instant::now();
+                let response = enforce(global, None, request, next).await;
+                self.rng_budget.charge(started.elapsed());
+                response
+            }
+        }
+    }
+}
+
+// Handler time charged in the current one-second window
+struct TimeBudget {
+    // Start of the current window and the time charged to it
+    window: Mutex<(Instant, Duration)>,
+}
+
+impl TimeBudget {
+    fn new() -> Self {
+        Self { window: Mutex::new((Instant::now(), Duration::ZERO)) }
+    }
+
+    fn exhausted(&self, per_second: Duration) -> bool {
+        let mut window = self.window.lock().unwrap();
+        if window.0.elapsed() >= Duration::from_secs(1) {
+            *window = (Instant::now(), Duration::ZERO);
+        }
+        window.1 >= per_second
+    }
+
+    fn charge(&self, elapsed: Duration) {
+        self.window.lock().unwrap().1 += elapsed;
+    }
+}
+
+async fn enforce(permits: Arc<Semaphore>, timeout: Option<Duration>, request: Request, next: Next) -> Response {
+    let Ok(_permit) = permits.try_acquire() else {
+        return overloaded();
+    };
+    let Some(timeout) = timeout else {
+        return next.run(request).await;
+    };
+    match tokio::time::timeout(timeout, next.run(request)).await {
+        Ok(response) => response,
+        Err(_) => (
+            StatusCode::GATEWAY_TIMEOUT,
+            Json(ErrorResponse { error: format!("request timed out after {:?}", timeout) }),
+        )
+            .into_response(),
+    }
+}
+
+fn overloaded() -> Response {
+    (
+        StatusCode::SERVICE_UNAVAILABLE,
+        [(header::RETRY_AFTER, RETRY_AFTER_SECS.to_string())],
+        Json(ErrorResponse { error: "too many requests in flight, retry later".to_string() }),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::routing::get;
+    use tokio::sync::{mpsc, Notify};
+    use tower::ServiceExt;
+
+    fn get_request(path: &str) -> Request {
+        Request::builder().uri(path).body(Body::empty()).unwrap()
+    }
+
+    async fn error_of(response: Response) -> String {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        serde_json::from_slice::<serde_json::Value>(&bytes).unwrap()["error"].as_str().unwrap().to_string()
+    }
+
+    // A handler that reports each request it starts on `entered` and holds
+    // it until `release` is notified
+    struct Held {
+        entered: mpsc::UnboundedReceiver<()>,
+        release: Arc<Notify>,
+    }
+
+    fn held<S: Clone + Send + Sync + 'static>() -> (Held, MethodRouter<S>) {
+        let (tx, entered) = mpsc::unbounded_channel();
+        let release = Arc::new(Notify::new());
+        let notify = release.clone();
+        let handler = get(move || {
+            let (tx, notify) = (tx.clone(), notify.clone());
+            async move {
+                let released = notify.notified();
+                tx.send(()).unwrap();
+                released.await;
+                "done"
+            }
+        });
+        (Held { entered, release }, handler)
+    }
+
+    #[tokio::test]
+    async fn test_route_over_its_cap_answers_503_with_retry_after() {
+        let limits = LimitsConfig::default()
+            .with_route("/held", RouteLimits { timeout: Duration::from_secs(10), max_in_flight: 1 })
+            .live();
+        let (mut held, handler) = held();
+        let app: Router = Router::new().route("/held", limits.route("/held", handler));
+
+        let first = tokio::spawn(app.clone().oneshot(get_request("/held")));
+        held.entered.recv().await.unwrap();
+        let response = app.clone().oneshot(get_request("/held")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        assert_eq!(error_of(response).await, "too many requests in flight, retry later");
+
+        // The permit is returned once the first request completes
+        held.release.notify_waiters();
+        assert_eq!(first.await.unwrap().unwrap().status(), StatusCode::OK);
+        let second = tokio::spawn(app.oneshot(get_request("/held")));
+        held.entered.recv().await.unwrap();
+        held.release.notify_waiters();
+        assert_eq!(second.await.unwrap().unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_slow_route_answers_504() {
+        let limits = LimitsConfig::default().with_default_timeout(Duration::from_millis(20)).live();
+        let slow = get(|| async {
+            tokio::time::sleep(Duration::from_secs(10)).await;
+            "late"
+        });
+        let app: Router = Router::new()
+            .route("/slow", limits.route("/slow", slow))
+            .route("/fast", limits.route("/fast", get(|| async { "ok" })));
+
+        let response = app.clone().oneshot(get_request("/slow")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::GATEWAY_TIMEOUT);
+        assert_eq!(error_of(response).await, "request timed out after 20ms");
+        assert_eq!(app.clone().oneshot(get_request("/fast")).await.unwrap().status(), StatusCode::OK);
+
+        // Routes without limits of their own follow a changed default timeout
+        limits.update(1024, Duration::from_millis(5), Duration::from_millis(500));
+        let response = app.oneshot(get_request("/slow")).await.unwrap();
+        assert_eq!(error_of(response).await, "request timed out after 5ms");
+    }
+
+    #[tokio::test]
+    async fn test_consensus_lane_is_not_behind_the_global_cap() {
+        let mut config = LimitsConfig::default().with_global_max_in_flight(1);
+        config.consensus_max_in_flight = 1;
+        let limits = config.live();
+        let (mut busy, busy_handler) = held();
+        let (mut vote, vote_handler) = held();
+        let app: Router = limits.global(
+            Router::new()
+                .route("/busy", limits.route("/busy", busy_handler))
+                .route("/other", limits.route("/other", get(|| async { "ok" })))
+                .route("/rng", limits.route("/rng", get(|| async { "ok" })))
+                .route("/vote", limits.route("/vote", vote_handler)),
+        );
+
+        // One default-lane request fills the global cap for the default and
+        // RNG lanes alike
+        let first = tokio::spawn(app.clone().oneshot(get_request("/busy")));
+        busy.entered.recv().await.unwrap();
+        for path in ["/other", "/rng"] {
+            let response = app.clone().oneshot(get_request(path)).await.unwrap();
+            assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE, "{}", path);
+            assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        }
+
+        // Votes still get in, up to the consensus lane's own cap
+        let voting = tokio::spawn(app.clone().oneshot(get_request("/vote")));
+        vote.entered.recv().await.unwrap();
+        let response = app.clone().oneshot(get_request("/vote")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+
+        busy.release.notify_waiters();
+        vote.release.notify_waiters();
+        assert_eq!(first.await.unwrap().unwrap().status(), StatusCode::OK);
+        assert_eq!(voting.await.unwrap().unwrap().status(), StatusCode::OK);
+        assert_eq!(app.oneshot(get_request("/other")).await.unwrap().status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_rng_lane_answers_503_once_its_budget_is_spent() {
+        let limits = LimitsConfig::default().with_rng_budget(Duration::from_millis(10)).live();
+        let draw = get(|| async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+            "random"
+        });
+        let app: Router = limits.global(
+            Router::new()
+                .route("/rng", limits.route("/rng", draw))
+                .route("/vote", limits.route("/vote", get(|| async { "ok" }))),
+        );
+
+        // The first draw overruns the budget; the next one in the same
+        // second is turned away, while other lanes are unaffected
+        assert_eq!(app.clone().oneshot(get_request("/rng")).await.unwrap().status(), StatusCode::OK);
+        let response = app.clone().oneshot(get_request("/rng")).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+        assert_eq!(app.clone().oneshot(get_request("/vote")).await.unwrap().status(), StatusCode::OK);
+
+        // The budget is renewed with the next window
+        tokio::time::sleep(Duration::from_millis(1000)).await;
+        assert_eq!(app.oneshot(get_request("/rng")).await.unwrap().status(), StatusCode::OK);
+    }
+}
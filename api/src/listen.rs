@@ -0,0 +1,99 @@
+// Where a router is served: a TCP address, or `unix:PATH` for a Unix domain
+// socket that only local processes with filesystem access can reach. Sockets
+// are created owner and group read-write, so access is granted by adding a
+// sidecar's user to the node's group.
+use axum::Router;
+use hyper_util::rt::TokioIo;
+use hyper_util::service::TowerToHyperService;
+use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::os::unix::fs::PermissionsExt;
+use std::path::PathBuf;
+use std::str::FromStr;
+
+const SOCKET_MODE: u32 = 0o660;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ListenAddr {
+    Tcp(SocketAddr),
+    Unix(PathBuf),
+}
+
+impl ListenAddr {
+    // Unix sockets count as local too
+    pub fn is_local(&self) -> bool {
+        match self {
+            ListenAddr::Tcp(addr) => addr.ip().is_loopback(),
+            ListenAddr::Unix(_) => true,
+        }
+    }
+}
+
+impl fmt::Display for ListenAddr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ListenAddr::Tcp(addr) => write!(f, "http://{}", addr),
+            ListenAddr::Unix(path) => write!(f, "unix:{}", path.display()),
+        }
+    }
+}
+
+impl FromStr for ListenAddr {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.strip_prefix("unix:") {
+            Some("") => Err("unix: needs a socket path".to_string()),
+            Some(path) => Ok(ListenAddr::Unix(PathBuf::from(path))),
+            None => s
+                .parse()
+                .map(ListenAddr::Tcp)
+                .map_err(|_| format!("invalid listen address '{}' (expected HOST:PORT or unix:PATH)", s)),
+        }
+    }
+}
+
+// Serves `app` until `shutdown` resolves. TCP listeners then let open
+// requests finish; Unix sockets only stop accepting. A stale socket file
+// from an earlier run is replaced.
+pub async fn serve(
+    addr: &ListenAddr,
+    app: Router,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> std::io::Result<()> {
+    match addr {
+        ListenAddr::Tcp(addr) => {
+            let listener = tokio::net::TcpListener::bind(addr).await?;
+            axum::serve(listener, app).with_graceful_shutdown(shutdown).await
+        }
+        ListenAddr::Unix(path) => {
+            let _ = std::fs::remove_file(path);
+            let listener = tokio::net::UnixListener::bind(path)?;
+            std::fs::set_permissions(path, std::fs::Permissions::from_mode(SOCKET_MODE))?;
+            tokio::pin!(shutdown);
+            loop {
+                let stream = tokio::select! {
+                    accepted = listener.accept() => match accepted {
+                        Ok((stream, _)) => stream,
+                        Err(e) => {
+                            eprintln!("Accept on {} failed: {}", path.display(), e);
+                            continue;
+                        }
+                    },
+                    _ = &mut shutdown => break,
+                };
+                let service = TowerToHyperService::new(app.clone());
+                tokio::spawn(async move {
+                    let connection = hyper::server::conn::http1::Builder::new()
+                        .serve_connection(TokioIo::new(stream), service);
+                    if let Err(e) = connection.await {
+                        eprintln!("Unix socket connection failed: {}", e);
+                    }
+                });
+            }
+            let _ = std::fs::remove_file(path);
+            Ok(())
+        }
+    }
+}
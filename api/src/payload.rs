@@ -0,0 +1,60 @@
+// Proposal payloads are raw bytes. JSON bodies carry them as a string in the
+// request's `payload_encoding` (UTF-8 by default), so binary payloads need no
+// double encoding; `application/octet-stream` bodies are taken verbatim.
+use crate::{BodyRejection, ProposeRequest, ValidJson};
+use axum::async_trait;
+use axum::body::Bytes;
+use axum::extract::{FromRequest, Request};
+use axum::http::{header, StatusCode};
+use base64::Engine;
+use serde::Deserialize;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PayloadEncoding {
+    #[default]
+    Utf8,
+    Hex,
+    Base64,
+}
+
+impl PayloadEncoding {
+    pub fn decode(&self, payload: String) -> Result<Vec<u8>, String> {
+        match self {
+            PayloadEncoding::Utf8 => Ok(payload.into_bytes()),
+            PayloadEncoding::Hex => hex::decode(payload.trim()).map_err(|e| format!("payload is not valid hex: {}", e)),
+            PayloadEncoding::Base64 => base64::engine::general_purpose::STANDARD
+                .decode(payload.trim())
+                .map_err(|e| format!("payload is not valid base64: {}", e)),
+        }
+    }
+}
+
+// The decoded payload of a `POST /propose` body
+pub struct ProposalPayload(pub Vec<u8>);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequest<S> for ProposalPayload {
+    type Rejection = BodyRejection;
+
+    async fn from_request(request: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let octet_stream = request
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .is_some_and(|value| value.starts_with("application/octet-stream"));
+
+        if octet_stream {
+            let body = Bytes::from_request(request, state)
+                .await
+                .map_err(|e| BodyRejection::new(e.status(), e.body_text()))?;
+            return Ok(ProposalPayload(body.to_vec()));
+        }
+
+        let ValidJson(body) = ValidJson::<ProposeRequest>::from_request(request, state).await?;
+        body.payload_encoding
+            .decode(body.payload)
+            .map(ProposalPayload)
+            .map_err(|e| BodyRejection::new(StatusCode::BAD_REQUEST, e))
+    }
+}
@@ -0,0 +1,206 @@
+// Settings that can change without a restart, reloaded on SIGHUP or
+// `POST /admin/reload`. The node supplies a loader that re-reads its config
+// files; the new settings are validated as a whole and applied only if all
+// of them are valid, and each change is logged. Everything else, such as
+// listeners, storage and the node's role, needs a restart.
+use crate::alerts::AlertRule;
+use crate::webhooks::WebhookConfig;
+use crate::AppState;
+use serde::Serialize;
+use std::fmt;
+use std::sync::Arc;
+use std::time::Duration;
+use trng::{HealthPolicy, PoolConfig};
+
+#[derive(Debug, Clone)]
+pub struct RuntimeConfig {
+    pub max_in_flight: usize,
+    pub request_timeout: Duration,
+    pub rng_budget: Duration,
+    pub health_policy: HealthPolicy,
+    pub pool: PoolConfig,
+    pub alert_rules: Vec<AlertRule>,
+    pub webhooks: Vec<WebhookConfig>,
+}
+
+// Re-reads the node's config, e.g. from the files it was started with
+#[derive(Clone)]
+pub struct ConfigLoader(Arc<dyn Fn() -> Result<RuntimeConfig, String> + Send + Sync>);
+
+impl ConfigLoader {
+    pub fn new(load: impl Fn() -> Result<RuntimeConfig, String> + Send + Sync + 'static) -> Self {
+        Self(Arc::new(load))
+    }
+}
+
+impl fmt::Debug for ConfigLoader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ConfigLoader")
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct ReloadResponse {
+    // "name: old -> new" for settings, "added"/"removed" for webhooks and rules
+    pub changes: Vec<String>,
+}
+
+// Loads, validates and applies the config, returning what changed
+pub(crate) fn reload(state: &AppState) -> Result<Vec<String>, String> {
+    let loader = state.config_loader.as_ref().ok_or("no config to reload")?;
+    let config = (loader.0)()?;
+    validate(state, &config)?;
+
+    let mut changes = state
+        .limits
+        .update(config.max_in_flight, config.request_timeout, config.rng_budget);
+    let policy = state.trng.health_policy();
+    let policy_changes = field_changes("health_policy", &*policy, &config.health_policy);
+    if !policy_changes.is_empty() {
+        state.trng.set_health_policy(config.health_policy);
+        changes.extend(policy_changes);
+    }
+    let pool_changes = field_changes("pool", &state.trng.pool_config(), &config.pool);
+    if !pool_changes.is_empty() {
+        state.trng.set_pool_config(config.pool)?;
+        changes.extend(pool_changes);
+    }
+    changes.extend(state.alerts.set_rules(config.alert_rules)?);
+    changes.extend(state.webhooks.replace_configured(config.webhooks)?);
+
+    if changes.is_empty() {
+        println!("Config reloaded, nothing changed");
+    }
+    for change in &changes {
+        println!("Config reloaded: {}", change);
+    }
+    Ok(changes)
+}
+
+fn validate(state: &AppState, config: &RuntimeConfig) -> Result<(), String> {
+    if config.max_in_flight == 0 {
+        return Err("max_in_flight must be at least 1".to_string());
+    }
+    if config.request_timeout.is_zero() {
+        return Err("request timeout must be positive".to_string());
+    }
+    // The drift monitor and health history are sized for the sample
+    if config.health_policy.sample_size != state.trng.health_policy().sample_size {
+        return Err("health policy sample_size cannot change without a restart".to_string());
+    }
+    config.pool.validate()?;
+    for config in &config.webhooks {
+        config.validate()?;
+    }
+    crate::alerts::AlertManager::new(config.alert_rules.clone()).map(|_| ())
+}
+
+// "prefix.field: old -> new" for each top-level field that differs
+fn field_changes<T: Serialize>(prefix: &str, old: &T, new: &T) -> Vec<String> {
+    let (serde_json::Value::Object(old), serde_json::Value::Object(new)) =
+        (serde_json::to_value(old).unwrap_or_default(), serde_json::to_value(new).unwrap_or_default())
+    else {
+        return Vec::new();
+    };
+    new.iter()
+        .filter(|(field, value)| old.get(*field) != Some(value))
+        .map(|(field, value)| format!("{}.{}: {} -> {}", prefix, field, old.get(field).cloned().unwrap_or_default(), value))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::alerts::AlertCondition;
+    use consensus::{ConsensusState, ValidatorId};
+    use trng::Trng;
+
+    fn state() -> AppState {
+        AppState::new(ConsensusState::new((0..4).map(ValidatorId).collect()), Trng::from_seed(b"reload"))
+    }
+
+    // The config the state runs with now
+    fn current(state: &AppState) -> RuntimeConfig {
+        let limits = state.limits.config();
+        RuntimeConfig {
+            max_in_flight: limits.global_max_in_flight,
+            request_timeout: limits.default.timeout,
+            rng_budget: limits.rng_budget,
+            health_policy: (*state.trng.health_policy()).clone(),
+            pool: state.trng.pool_config(),
+            alert_rules: Vec::new(),
+            webhooks: Vec::new(),
+        }
+    }
+
+    fn reload_with(state: &mut AppState, config: RuntimeConfig) -> Result<Vec<String>, String> {
+        state.config_loader = Some(ConfigLoader::new(move || Ok(config.clone())));
+        reload(state)
+    }
+
+    fn low_entropy() -> AlertRule {
+        AlertRule { name: "low".to_string(), condition: AlertCondition::EntropyBelow { threshold: 7.5 } }
+    }
+
+    #[test]
+    fn test_reload_needs_a_loadable_config() {
+        let mut state = state();
+        assert_eq!(reload(&state).unwrap_err(), "no config to reload");
+        state.config_loader = Some(ConfigLoader::new(|| Err("config.toml: bad line 3".to_string())));
+        assert_eq!(reload(&state).unwrap_err(), "config.toml: bad line 3");
+    }
+
+    #[test]
+    fn test_invalid_config_applies_nothing() {
+        let mut state = state();
+        let base = current(&state);
+        let invalid: Vec<(RuntimeConfig, &str)> = vec![
+            (RuntimeConfig { max_in_flight: 0, ..base.clone() }, "max_in_flight must be at least 1"),
+            (RuntimeConfig { request_timeout: Duration::ZERO, ..base.clone() }, "request timeout must be positive"),
+            (
+                RuntimeConfig {
+                    health_policy: HealthPolicy::for_sample_size(base.health_policy.sample_size * 2),
+                    ..base.clone()
+                },
+                "health policy sample_size cannot change without a restart",
+            ),
+            (
+                RuntimeConfig { alert_rules: vec![low_entropy(), low_entropy()], ..base.clone() },
+                "alert rule 'low' is defined twice",
+            ),
+        ];
+        for (config, error) in invalid {
+            // Valid settings next to the invalid one are not applied either
+            let config = RuntimeConfig { rng_budget: Duration::from_millis(7), ..config };
+            assert_eq!(reload_with(&mut state, config).unwrap_err(), error);
+        }
+        let url = "https://127.0.0.1/hook".to_string();
+        let webhooks = vec![WebhookConfig { url, secret: "s".to_string(), events: Vec::new() }];
+        let config = RuntimeConfig { webhooks, max_in_flight: 8, ..base.clone() };
+        assert_eq!(reload_with(&mut state, config).unwrap_err(), "webhook url 'https://127.0.0.1/hook' must use http");
+
+        let limits = state.limits.config();
+        assert_eq!((limits.global_max_in_flight, limits.rng_budget), (base.max_in_flight, base.rng_budget));
+        assert!(state.webhooks.list().is_empty());
+    }
+
+    #[test]
+    fn test_reload_reports_what_changed() {
+        let mut state = state();
+        let base = current(&state);
+        assert!(reload_with(&mut state, base.clone()).unwrap().is_empty());
+
+        let mut health_policy = base.health_policy.clone();
+        health_policy.min_shannon_entropy = 7.25;
+        let alert_rules = vec![low_entropy()];
+        let config = RuntimeConfig { max_in_flight: 8, health_policy, alert_rules, ..base.clone() };
+        let changes = reload_with(&mut state, config).unwrap();
+        assert_eq!(changes[0], "max_in_flight: 1024 -> 8");
+        let entropy = &changes[1];
+        assert!(entropy.starts_with("health_policy.min_shannon_entropy: "), "{}", entropy);
+        assert!(entropy.ends_with(" -> 7.25"), "{}", entropy);
+        assert_eq!(changes[2..], ["alert rule low added"]);
+        assert_eq!(state.limits.config().global_max_in_flight, 8);
+        assert_eq!(state.trng.health_policy().min_shannon_entropy, 7.25);
+    }
+}
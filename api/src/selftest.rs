@@ -0,0 +1,152 @@
+// Power-on self-test: known-answer tests for the primitives the node relies
+// on and the SP 800-90B restart tests of each entropy source, run once at
+// boot. A node whose self-test failed stays up so operators
+// can inspect `/selftest`, but reports not ready and refuses to serve.
+use consensus::{BlockId, BlockStore, FileStore, WriteBatch};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier};
+use serde::Serialize;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use trng::{EntropySource, RestartTestResult, Trng};
+
+// BLAKE3 of "abc", from the reference implementation's test vectors
+const BLAKE3_ABC: &str = "6437b3ac38465133ffb63b75273a8db548c558465d79db03fd359c6cd5bd9d85";
+
+// First draw from the generator seeded with DRBG_SEED, and the first draw
+// after reseeding it with DRBG_RESEED
+const DRBG_SEED: &[u8] = b"mini-consensus self-test seed";
+const DRBG_RESEED: &[u8] = b"mini-consensus self-test reseed";
+const DRBG_GENERATE: &str = "16f65ce62953dd4ee98cbb5d1eb1383005073364440568564a6effd50c7f42c2";
+const DRBG_AFTER_RESEED: &str = "447fd8305d020deb49402c3dfbeda0809c714ffa38aff9bedee6306f4cdc9ddd";
+
+// RFC 8032 section 7.1, test 1 (empty message)
+const ED25519_SECRET: &str = "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60";
+const ED25519_PUBLIC: &str = "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a";
+const ED25519_SIGNATURE: &str = "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b";
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestResult {
+    pub name: &'static str,
+    pub passed: bool,
+    // Why the test failed
+    pub detail: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SelfTestReport {
+    pub passed: bool,
+    pub results: Vec<SelfTestResult>,
+    pub restart_tests: Vec<RestartTestResult>,
+    // Unix time in milliseconds
+    pub finished_at: u64,
+}
+
+// `node_key` gets a sign/verify round trip; the storage test writes and reads
+// back a probe batch in `data_dir`, or the temporary directory without one.
+// The restart tests restart the sources of `trng`.
+pub async fn run(node_key: &SigningKey, data_dir: Option<&Path>, trng: &Trng) -> SelfTestReport {
+    let mut restart_tests = Vec::new();
+    for source in EntropySource::ALL {
+        restart_tests.push(trng.restart_test(source).await);
+    }
+    let mut outcomes = vec![
+        ("blake3", blake3_kat()),
+        ("drbg", drbg_kat()),
+        ("ed25519", ed25519_kat(node_key)),
+        ("storage", storage_round_trip(data_dir).await),
+    ];
+    outcomes.extend(restart_tests.iter().map(|test| (restart_test_name(test.source), restart_outcome(test))));
+    let results: Vec<SelfTestResult> = outcomes
+        .into_iter()
+        .map(|(name, outcome)| SelfTestResult { name, passed: outcome.is_ok(), detail: outcome.err() })
+        .collect();
+
+    SelfTestReport {
+        passed: results.iter().all(|r| r.passed),
+        results,
+        restart_tests,
+        finished_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0),
+    }
+}
+
+fn expect_hex(what: &str, actual: &[u8], expected: &str) -> Result<(), String> {
+    let actual = hex::encode(actual);
+    if actual == expected {
+        Ok(())
+    } else {
+        Err(format!("{}: expected {}, got {}", what, expected, actual))
+    }
+}
+
+fn restart_test_name(source: EntropySource) -> &'static str {
+    match source {
+        EntropySource::Os => "restart_os",
+        EntropySource::Timing => "restart_timing",
+        EntropySource::Io => "restart_io",
+    }
+}
+
+fn restart_outcome(test: &RestartTestResult) -> Result<(), String> {
+    if test.passed {
+        return Ok(());
+    }
+    Err(format!(
+        "most common value repeats {} times in a row and {} in a column, cutoff {} for {:.3} bits per sample",
+        test.max_row_count, test.max_column_count, test.cutoff, test.min_entropy
+    ))
+}
+
+fn blake3_kat() -> Result<(), String> {
+    expect_hex("hash of \"abc\"", blake3::hash(b"abc").as_bytes(), BLAKE3_ABC)
+}
+
+fn drbg_kat() -> Result<(), String> {
+    let trng = Trng::from_seed(DRBG_SEED);
+    expect_hex("generate", &trng.rand_bytes(32), DRBG_GENERATE)?;
+    trng.reseed_with(DRBG_RESEED);
+    expect_hex("generate after reseed", &trng.rand_bytes(32), DRBG_AFTER_RESEED)
+}
+
+fn ed25519_kat(node_key: &SigningKey) -> Result<(), String> {
+    let secret: [u8; 32] = hex::decode(ED25519_SECRET).unwrap().try_into().unwrap();
+    let key = SigningKey::from_bytes(&secret);
+    expect_hex("public key", key.verifying_key().as_bytes(), ED25519_PUBLIC)?;
+    let signature = key.sign(b"");
+    expect_hex("signature", &signature.to_bytes(), ED25519_SIGNATURE)?;
+
+    let message = b"mini-consensus self-test";
+    let signature = node_key.sign(message);
+    let verifying = node_key.verifying_key();
+    verifying
+        .verify(message, &signature)
+        .map_err(|e| format!("node key round trip: {}", e))?;
+    let forged = Signature::from_bytes(&[0u8; 64]);
+    if verifying.verify(message, &forged).is_ok() {
+        return Err("node key accepted a forged signature".to_string());
+    }
+    Ok(())
+}
+
+async fn storage_round_trip(data_dir: Option<&Path>) -> Result<(), String> {
+    let dir = data_dir.map(Path::to_path_buf).unwrap_or_else(std::env::temp_dir);
+    let path = dir.join(format!("selftest-{}.jsonl", std::process::id()));
+    let _ = std::fs::remove_file(&path);
+    let store = FileStore::open(&path);
+
+    let probe = BlockId::from(blake3::hash(b"selftest-probe"));
+    let outcome = async {
+        store.commit(WriteBatch { head: Some(probe), ..Default::default() }).await?;
+        store.load().await
+    }
+    .await;
+    let _ = std::fs::remove_file(&path);
+
+    match outcome {
+        Ok(snapshot) if snapshot.head.as_ref() == Some(&probe) => Ok(()),
+        Ok(snapshot) => Err(format!("read back head {:?}, wrote {}", snapshot.head, probe)),
+        Err(e) => Err(format!("{} in {}", e, dir.display())),
+    }
+}
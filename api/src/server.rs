@@ -0,0 +1,398 @@
+// Assembles a node's API server: the `AppState` it serves, built from the
+// node configs or supplied by an embedder, the background tasks running on
+// it, and the router, which can be served here or mounted in another axum
+// app.
+use crate::{
+    admin, idempotency, json_profile, listen, now_ms, routes, selftest, spawn_tasks, AdminConfig, AlertManager, ApiExtension, AppState, CompressionConfig, EntropyConfig,
+    ErrorResponse, EventLog, IdempotencyCache, IntakeQueue, JsonProfile, LimitsConfig, ListenAddr, MonitoringConfig, NodeRole, StorageConfig, TaskManager,
+    ValidatorId, VotePolicy, Webhooks, CHECKPOINT_INTERVAL, HEALTH_HISTORY_LEN, SCRUB_INTERVAL,
+};
+use axum::{
+    extract::Request,
+    http::StatusCode,
+    middleware::{self, Next},
+    response::{IntoResponse, Json, Response},
+    routing::get,
+    Router,
+};
+use consensus::{
+    Beacon, BlockStore, Checkpointer, ConsensusState, FileStore, MemoryAccountant, RewardSchedule, Scrubber, StateMachine,
+    StoreWriter, GENESIS_STAKE,
+};
+use ed25519_dalek::SigningKey;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+use tower_http::cors::CorsLayer;
+use trng::{DriftMonitor, HealthHistory, KeyPurpose, MasterSeed, Trng};
+
+pub struct ApiServer {
+    state: AppState,
+    router: Router,
+    listen: Vec<ListenAddr>,
+    admin: Option<AdminConfig>,
+}
+
+impl ApiServer {
+    pub fn builder() -> ApiServerBuilder {
+        ApiServerBuilder::default()
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    // The public API with its state applied, for mounting in another app
+    pub fn router(&self) -> Router {
+        self.router.clone()
+    }
+
+    // Serves the router on every bound address, and the admin API on its
+    // own listener when configured, until `AppState::shutdown` is set or a
+    // listener fails. Then stops the background tasks and flushes the store.
+    pub async fn serve(self) -> Result<(), String> {
+        let state = self.state;
+        let mut servers = Vec::new();
+        if let Some(admin) = self.admin {
+            let app = admin::routes(&admin, &state.limits).with_state(state.clone());
+            println!("Admin API on {}", admin.listen);
+            servers.push(tokio::spawn(serve_until_shutdown(admin.listen, app, state.shutdown.clone())));
+        }
+        for addr in self.listen {
+            println!("Server running on {}", addr);
+            servers.push(tokio::spawn(serve_until_shutdown(addr, self.router.clone(), state.shutdown.clone())));
+        }
+        let mut result = Ok(());
+        for server in servers {
+            if let Ok(Err(e)) = server.await {
+                if result.is_ok() {
+                    result = Err(e);
+                }
+            }
+        }
+        state.tasks.shutdown().await;
+        state.consensus.flush_store().await;
+        println!("Shut down");
+        result
+    }
+}
+
+// Without `state`, the state is built from the entropy, storage, limits and
+// monitoring configs and the reward schedule as `node server` does; with it, those are ignored and
+// the embedder's consensus, TRNG and storage are served as they are. The
+// role applies either way.
+#[derive(Default)]
+pub struct ApiServerBuilder {
+    state: Option<AppState>,
+    extensions: Vec<Box<dyn ApiExtension>>,
+    listen: Vec<ListenAddr>,
+    role: NodeRole,
+    entropy: EntropyConfig,
+    storage: StorageConfig,
+    limits: LimitsConfig,
+    admin: Option<AdminConfig>,
+    monitoring: MonitoringConfig,
+    json_profile: JsonProfile,
+    compression: CompressionConfig,
+    vote_policy: VotePolicy,
+    rewards: RewardSchedule,
+}
+
+impl ApiServerBuilder {
+    pub fn state(mut self, state: AppState) -> Self {
+        self.state = Some(state);
+        self
+    }
+
+    pub fn extension(mut self, extension: impl ApiExtension) -> Self {
+        self.extensions.push(Box::new(extension));
+        self
+    }
+
+    // Extra routes served next to the API, with the same state, limits and
+    // self-test gate
+    pub fn router_extension(self, routes: Router<AppState>) -> Self {
+        self.extension(routes)
+    }
+
+    // May be called once per address to listen on
+    pub fn bind(mut self, addr: ListenAddr) -> Self {
+        self.listen.push(addr);
+        self
+    }
+
+    pub fn role(mut self, role: NodeRole) -> Self {
+        self.role = role;
+        self
+    }
+
+    pub fn entropy(mut self, entropy: EntropyConfig) -> Self {
+        self.entropy = entropy;
+        self
+    }
+
+    pub fn storage(mut self, storage: StorageConfig) -> Self {
+        self.storage = storage;
+        self
+    }
+
+    pub fn limits(mut self, limits: LimitsConfig) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    // Validator 0 only unless set otherwise
+    pub fn vote_policy(mut self, vote_policy: VotePolicy) -> Self {
+        self.vote_policy = vote_policy;
+        self
+    }
+
+    // Genesis reward schedule; every validator must run the same one
+    pub fn reward_schedule(mut self, rewards: RewardSchedule) -> Self {
+        self.rewards = rewards;
+        self
+    }
+
+    pub fn admin(mut self, admin: AdminConfig) -> Self {
+        self.admin = Some(admin);
+        self
+    }
+
+    pub fn monitoring(mut self, monitoring: MonitoringConfig) -> Self {
+        self.monitoring = monitoring;
+        self
+    }
+
+    // Profile of JSON responses to requests that do not pick one
+    pub fn json_profile(mut self, profile: JsonProfile) -> Self {
+        self.json_profile = profile;
+        self
+    }
+
+    // br, zstd and gzip for responses of 1KiB and up unless set otherwise
+    pub fn compression(mut self, compression: CompressionConfig) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    // Starts the background tasks; nothing is served until `serve`. Fails,
+    // with nothing left running, when the state cannot be built from the
+    // configs
+    pub async fn build(self) -> Result<ApiServer, String> {
+        let mut state = match self.state {
+            Some(state) => state,
+            None => build_state(self.entropy, self.storage, self.limits, self.monitoring, self.rewards).await?,
+        };
+        state.active.store(matches!(self.role, NodeRole::Validator), Ordering::Relaxed);
+        state.observer = matches!(self.role, NodeRole::Observer);
+        state.vote_policy = self.vote_policy;
+        println!("Running as {}", self.role.name());
+        spawn_tasks(&state, self.role);
+
+        let router = router(&state, self.extensions, self.json_profile, self.compression);
+        Ok(ApiServer { state, router, listen: self.listen, admin: self.admin })
+    }
+
+    pub async fn serve(self) -> Result<(), String> {
+        self.build().await?.serve().await
+    }
+}
+
+// Without a master seed the node runs with a fresh, ephemeral identity.
+// With a data directory, finalized blocks are persisted there and reloaded
+// on the next start.
+async fn build_state(
+    entropy: EntropyConfig,
+    storage: StorageConfig,
+    limits: LimitsConfig,
+    monitoring: MonitoringConfig,
+    rewards: RewardSchedule,
+) -> Result<AppState, String> {
+    let tasks = TaskManager::default();
+    let state = new_state(tasks.clone(), entropy, storage, limits, monitoring, rewards).await;
+    // Tasks started before the failure stop with it
+    if state.is_err() {
+        tasks.shutdown().await;
+    }
+    state
+}
+
+async fn new_state(
+    tasks: TaskManager,
+    entropy: EntropyConfig,
+    storage: StorageConfig,
+    limits: LimitsConfig,
+    monitoring: MonitoringConfig,
+    rewards: RewardSchedule,
+) -> Result<AppState, String> {
+    let validators: Vec<ValidatorId> = (0..4).map(ValidatorId).collect();
+    entropy.pool.validate()?;
+    let trng = Trng::without_collector()
+        .with_health_policy(monitoring.health_policy)
+        .with_pool_config(entropy.pool);
+    tasks.spawn("entropy_collector", {
+        let trng = trng.clone();
+        move || {
+            let trng = trng.clone();
+            async move { trng.collect_entropy().await }
+        }
+    });
+    for device in entropy.hardware {
+        trng.add_hardware_source(device)?;
+    }
+    if let Some(config) = entropy.drand {
+        trng.add_drand_source(config)?;
+    }
+    let webhooks = Webhooks::new(monitoring.webhooks)?;
+    let alerts = AlertManager::new(monitoring.alert_rules)?;
+
+    let seed = entropy.seed.unwrap_or_else(|| MasterSeed::generate(&trng));
+    let node_key = SigningKey::from_bytes(&seed.derive(KeyPurpose::ConsensusSigning, 0));
+
+    let selftest = selftest::run(&node_key, storage.data_dir.as_deref(), &trng).await;
+    for failed in selftest.results.iter().filter(|r| !r.passed) {
+        eprintln!("Self-test {} failed: {}", failed.name, failed.detail.as_deref().unwrap_or("no detail"));
+    }
+    if !selftest.passed {
+        eprintln!("Self-test failed, refusing to serve until restarted; see /selftest");
+    }
+
+    // Every validator's key derives from the same seed; this node signs as
+    // validator 0
+    let validator_keys: Vec<_> = validators
+        .iter()
+        .map(|&v| (v, SigningKey::from_bytes(&seed.derive(KeyPurpose::ConsensusSigning, v.0)).verifying_key()))
+        .collect();
+    let consensus = ConsensusState::new(validators.clone())
+        .with_state_machine(StateMachine::genesis(&validators, GENESIS_STAKE).with_reward_schedule(rewards))
+        .with_validator_keys(validator_keys)
+        .with_mempool_ttl(limits.mempool_ttl);
+    let epoch_length = consensus.state_machine().staking.epoch_length();
+    let beacon = Beacon::new(validators, seed.derive(KeyPurpose::BeaconVrf, 0), epoch_length);
+    let mut consensus = consensus
+        .with_checkpointer(Checkpointer::new(CHECKPOINT_INTERVAL, node_key))
+        .with_beacon(beacon)
+        .with_pruning(storage.pruning);
+    if let Some(dir) = &storage.data_dir {
+        let scrubber = Arc::new(Scrubber::new(open_store(&mut consensus, dir.clone()).await?, consensus.clone()));
+        tasks.spawn("scrubber", move || {
+            let scrubber = scrubber.clone();
+            async move { scrubber.run(SCRUB_INTERVAL).await }
+        });
+    }
+    let events = EventLog::open(storage.data_dir.as_deref(), storage.events)?;
+    let window = storage.idempotency_window.unwrap_or(idempotency::DEFAULT_WINDOW);
+    let idempotency = IdempotencyCache::open(storage.data_dir.as_deref(), window)?;
+
+    let health_history = HealthHistory::new(HEALTH_HISTORY_LEN, DriftMonitor::new(trng.health_policy().sample_size, 1024));
+    let state = AppState {
+        consensus,
+        trng,
+        active: Arc::new(AtomicBool::new(true)),
+        observer: false,
+        vote_policy: VotePolicy::default(),
+        selftest: Arc::new(selftest),
+        health_history: Arc::new(Mutex::new(health_history)),
+        webhooks,
+        events,
+        alerts,
+        limits: limits.live(),
+        config_loader: monitoring.config_loader,
+        proposer_heartbeat: Arc::new(AtomicU64::new(now_ms())),
+        shutdown: Arc::new(watch::channel(false).0),
+        tasks,
+        memory: MemoryAccountant::new(limits.memory_budgets.clone()),
+        idempotency,
+        intake: IntakeQueue::default(),
+    };
+    state.track_memory();
+    for name in state.memory.unknown_budgets() {
+        eprintln!("Ignoring the memory budget for unknown subsystem {}", name);
+    }
+    Ok(state)
+}
+
+// Restores the chain from the store in `dir`, which then receives every
+// finalization
+async fn open_store(consensus: &mut ConsensusState, dir: PathBuf) -> Result<Arc<FileStore>, String> {
+    std::fs::create_dir_all(&dir).map_err(|e| format!("Cannot create data directory {}: {}", dir.display(), e))?;
+    let store = Arc::new(FileStore::in_dir(&dir));
+    let snapshot = store
+        .load()
+        .await
+        .map_err(|e| format!("Cannot load {}: {}", store.path().display(), e))?;
+    println!("Restored {} finalized blocks from {}", snapshot.blocks.len(), store.path().display());
+    *consensus = consensus.clone().restore(snapshot).with_store(StoreWriter::spawn(store.clone()));
+    Ok(store)
+}
+
+fn router(
+    state: &AppState,
+    extensions: Vec<Box<dyn ApiExtension>>,
+    json_profile: JsonProfile,
+    compression: CompressionConfig,
+) -> Router {
+    let ready = state.selftest.passed;
+    let limits = state.limits.clone();
+    let app = routes(state)
+        .into_iter()
+        .fold(Router::new(), |router, (path, handler)| router.route(path, limits.route(path, handler)));
+    let app = extensions
+        .iter()
+        .fold(app, |app, extension| app.merge(extension.routes(state)))
+        .route_layer(middleware::from_fn(move |request: Request, next: Next| refuse_unless_ready(ready, request, next)))
+        .route("/selftest", limits.route("/selftest", get(crate::get_selftest)))
+        .route("/readyz", limits.route("/readyz", get(crate::readyz)));
+    let app = extensions.iter().fold(app, |app, extension| extension.layer(app, state));
+    limits
+        .global(app)
+        .layer(middleware::from_fn(move |request: Request, next: Next| json_profile::negotiate(json_profile, request, next)))
+        .layer(compression.layer())
+        .layer(CorsLayer::permissive())
+        .with_state(state.clone())
+}
+
+// Everything but the self-test report and readiness probe is refused after
+// a failed self-test
+async fn refuse_unless_ready(ready: bool, request: Request, next: Next) -> Response {
+    if ready {
+        return next.run(request).await;
+    }
+    let error = "node failed its startup self-test, see /selftest".to_string();
+    (StatusCode::SERVICE_UNAVAILABLE, Json(ErrorResponse { error })).into_response()
+}
+
+// A listener that fails stops the others with it
+async fn serve_until_shutdown(addr: ListenAddr, app: Router, shutdown: Arc<watch::Sender<bool>>) -> Result<(), String> {
+    let mut stop = shutdown.subscribe();
+    let signal = async move {
+        let _ = stop.wait_for(|&stop| stop).await;
+    };
+    listen::serve(&addr, app, signal).await.map_err(|e| {
+        shutdown.send_replace(true);
+        format!("Cannot serve on {}: {}", addr, e)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use trng::PoolConfig;
+
+    #[tokio::test]
+    async fn test_unusable_configs_are_errors() {
+        let pool = PoolConfig { capacity: 0, ..PoolConfig::default() };
+        let built = ApiServer::builder().entropy(EntropyConfig::default().with_pool(pool)).build().await;
+        let err = built.err().unwrap();
+        assert!(err.starts_with("pool capacity must be at least"), "{}", err);
+
+        // A data directory that is a file
+        let file = std::env::temp_dir().join(format!("mcn-server-{}", std::process::id()));
+        std::fs::write(&file, b"").unwrap();
+        let built = ApiServer::builder().storage(StorageConfig::default().with_data_dir(file.clone())).build().await;
+        let err = built.err().unwrap();
+        assert!(err.starts_with("Cannot create data directory"), "{}", err);
+        std::fs::remove_file(&file).unwrap();
+    }
+}
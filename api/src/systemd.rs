@@ -0,0 +1,57 @@
+// systemd integration for `Type=notify` units, speaking the sd_notify
+// datagram protocol directly. Nothing is sent unless systemd set
+// NOTIFY_SOCKET. READY=1 goes out once the generator has finished its first
+// collection round, and STATUS= lines report the finalized height. With
+// `WatchdogSec=` set, WATCHDOG=1 is sent at half the watchdog interval, but
+// only while both the proposer loop and the entropy collector are making
+// progress; otherwise systemd's watchdog restarts the node.
+use std::os::unix::net::{SocketAddr, UnixDatagram};
+use std::time::Duration;
+
+pub struct Notifier {
+    socket: UnixDatagram,
+    addr: SocketAddr,
+}
+
+impl Notifier {
+    // None when not started by systemd with notifications enabled
+    pub fn from_env() -> Option<Self> {
+        let path = std::env::var_os("NOTIFY_SOCKET")?;
+        let path = path.to_str()?;
+        let addr = match path.strip_prefix('@') {
+            Some(name) => abstract_addr(name)?,
+            None => SocketAddr::from_pathname(path).ok()?,
+        };
+        let socket = UnixDatagram::unbound().ok()?;
+        Some(Self { socket, addr })
+    }
+
+    // Newline-separated assignments, e.g. "READY=1\nSTATUS=Serving"
+    pub fn notify(&self, state: &str) {
+        if let Err(e) = self.socket.send_to_addr(state.as_bytes(), &self.addr) {
+            eprintln!("Cannot notify systemd: {}", e);
+        }
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn abstract_addr(name: &str) -> Option<SocketAddr> {
+    use std::os::linux::net::SocketAddrExt;
+    SocketAddr::from_abstract_name(name.as_bytes()).ok()
+}
+
+#[cfg(not(target_os = "linux"))]
+fn abstract_addr(_name: &str) -> Option<SocketAddr> {
+    None
+}
+
+// Half of WATCHDOG_USEC, if the watchdog is enabled for this process
+pub fn watchdog_interval() -> Option<Duration> {
+    if let Some(pid) = std::env::var("WATCHDOG_PID").ok().and_then(|pid| pid.parse::<u32>().ok()) {
+        if pid != std::process::id() {
+            return None;
+        }
+    }
+    let usec: u64 = std::env::var("WATCHDOG_USEC").ok()?.parse().ok()?;
+    (usec > 0).then(|| Duration::from_micros(usec / 2))
+}
@@ -0,0 +1,220 @@
+// Supervision for the node's background tasks. Each task runs in its own
+// tokio task under a supervisor: one that panics is restarted after a
+// backoff (1s, doubling up to 60s, back to 1s once a run lasted a minute),
+// one that returns is left finished, and the status of each is served in
+// `/status`. Tasks are registered dependencies first, e.g. the entropy
+// collector before the health sampler reading from it, and `shutdown` stops
+// them in the reverse order.
+use crate::now_ms;
+use serde::Serialize;
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+const FIRST_BACKOFF: Duration = Duration::from_secs(1);
+const MAX_BACKOFF: Duration = Duration::from_secs(60);
+// A run this long resets the backoff
+const STABLE_RUN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TaskState {
+    Running,
+    // Panicked, waiting out the backoff
+    Restarting,
+    // Returned on its own
+    Finished,
+    Stopped,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct TaskStatus {
+    pub name: &'static str,
+    pub state: TaskState,
+    pub restarts: u32,
+    pub last_panic: Option<String>,
+    // Unix milliseconds the current run started
+    pub started_at: u64,
+}
+
+struct Supervised {
+    status: Arc<Mutex<TaskStatus>>,
+    stop: watch::Sender<bool>,
+    supervisor: JoinHandle<()>,
+}
+
+#[derive(Clone, Default)]
+pub struct TaskManager {
+    tasks: Arc<Mutex<Vec<Supervised>>>,
+}
+
+impl TaskManager {
+    // `task` is called again for every restart; must be called from within
+    // a tokio runtime
+    pub fn spawn<F, Fut>(&self, name: &'static str, task: F)
+    where
+        F: Fn() -> Fut + Send + 'static,
+        Fut: Future<Output = ()> + Send + 'static,
+    {
+        let status = Arc::new(Mutex::new(TaskStatus {
+            name,
+            state: TaskState::Running,
+            restarts: 0,
+            last_panic: None,
+            started_at: now_ms(),
+        }));
+        let (stop, stopped) = watch::channel(false);
+        let supervisor = tokio::spawn(supervise(task, status.clone(), stopped));
+        self.tasks.lock().unwrap().push(Supervised { status, stop, supervisor });
+    }
+
+    // In registration order
+    pub fn statuses(&self) -> Vec<TaskStatus> {
+        self.tasks.lock().unwrap().iter().map(|task| task.status.lock().unwrap().clone()).collect()
+    }
+
+    // Stops the tasks last registered first, waiting for each to end
+    pub async fn shutdown(&self) {
+        let tasks = std::mem::take(&mut *self.tasks.lock().unwrap());
+        for task in tasks.into_iter().rev() {
+            let _ = task.stop.send(true);
+            let _ = task.supervisor.await;
+        }
+    }
+}
+
+async fn supervise<F, Fut>(task: F, status: Arc<Mutex<TaskStatus>>, mut stopped: watch::Receiver<bool>)
+where
+    F: Fn() -> Fut,
+    Fut: Future<Output = ()> + Send + 'static,
+{
+    let set = |state| status.lock().unwrap().state = state;
+    let mut backoff = FIRST_BACKOFF;
+    loop {
+        let started = Instant::now();
+        let mut run = tokio::spawn(task());
+        let outcome = tokio::select! {
+            outcome = &mut run => outcome,
+            _ = stopped.changed() => {
+                run.abort();
+                let _ = run.await;
+                return set(TaskState::Stopped);
+            }
+        };
+        let Err(error) = outcome else {
+            return set(TaskState::Finished);
+        };
+        let message = match error.try_into_panic() {
+            Ok(panic) => panic
+                .downcast_ref::<&str>()
+                .map(|s| s.to_string())
+                .or_else(|| panic.downcast_ref::<String>().cloned())
+                .unwrap_or_else(|| "non-string panic".to_string()),
+            Err(error) => error.to_string(),
+        };
+        if started.elapsed() >= STABLE_RUN {
+            backoff = FIRST_BACKOFF;
+        }
+        let name = {
+            let mut status = status.lock().unwrap();
+            status.state = TaskState::Restarting;
+            status.restarts += 1;
+            status.last_panic = Some(message.clone());
+            status.name
+        };
+        eprintln!("Task {} panicked: {}; restarting in {}s", name, message, backoff.as_secs());
+        tokio::select! {
+            _ = tokio::time::sleep(backoff) => {}
+            _ = stopped.changed() => return set(TaskState::Stopped),
+        }
+        backoff = (backoff * 2).min(MAX_BACKOFF);
+        let mut status = status.lock().unwrap();
+        status.state = TaskState::Running;
+        status.started_at = now_ms();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicU32, Ordering};
+    use tokio::time::Instant as TokioInstant;
+
+    fn status(tasks: &TaskManager, name: &str) -> TaskStatus {
+        tasks.statuses().into_iter().find(|status| status.name == name).unwrap()
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_panicking_task_restarts_with_doubling_backoff() {
+        let tasks = TaskManager::default();
+        let runs = Arc::new(Mutex::new(Vec::new()));
+        let log = runs.clone();
+        tasks.spawn("flaky", move || {
+            log.lock().unwrap().push(TokioInstant::now());
+            async { panic!("boom") }
+        });
+
+        // Runs at 0s, 1s and 3s; the next is due at 7s
+        tokio::time::sleep(Duration::from_millis(3500)).await;
+        let runs = runs.lock().unwrap().clone();
+        let gaps: Vec<_> = runs.windows(2).map(|pair| pair[1] - pair[0]).collect();
+        assert_eq!(gaps, vec![Duration::from_secs(1), Duration::from_secs(2)]);
+
+        let status = status(&tasks, "flaky");
+        assert_eq!(status.state, TaskState::Restarting);
+        assert_eq!(status.restarts, 3);
+        assert_eq!(status.last_panic.as_deref(), Some("boom"));
+        tasks.shutdown().await;
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_returning_task_is_finished_and_not_restarted() {
+        let tasks = TaskManager::default();
+        let runs = Arc::new(AtomicU32::new(0));
+        let counter = runs.clone();
+        tasks.spawn("once", move || {
+            counter.fetch_add(1, Ordering::SeqCst);
+            async {}
+        });
+
+        tokio::time::sleep(MAX_BACKOFF * 2).await;
+        assert_eq!(runs.load(Ordering::SeqCst), 1);
+        let status = status(&tasks, "once");
+        assert_eq!(status.state, TaskState::Finished);
+        assert_eq!(status.restarts, 0);
+        assert_eq!(status.last_panic, None);
+    }
+
+    // Records its task's name when the run is dropped
+    struct Stopped(&'static str, Arc<Mutex<Vec<&'static str>>>);
+
+    impl Drop for Stopped {
+        fn drop(&mut self) {
+            self.1.lock().unwrap().push(self.0);
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_shutdown_stops_last_registered_first() {
+        let tasks = TaskManager::default();
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        for name in ["entropy", "health", "webhooks"] {
+            let stopped = stopped.clone();
+            tasks.spawn(name, move || {
+                let guard = Stopped(name, stopped.clone());
+                async move {
+                    let _guard = guard;
+                    std::future::pending::<()>().await
+                }
+            });
+        }
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(tasks.statuses().iter().all(|status| status.state == TaskState::Running));
+
+        tasks.shutdown().await;
+        assert_eq!(*stopped.lock().unwrap(), vec!["webhooks", "health", "entropy"]);
+        assert!(tasks.statuses().is_empty());
+    }
+}
@@ -0,0 +1,388 @@
+// Outbound notifications for operators. Each webhook gets a JSON POST per
+// event it subscribed to, signed with HMAC-SHA256 under its secret and sent
+// as `X-Webhook-Signature: sha256=<hex>` over the exact body. Failed
+// deliveries are retried with exponential backoff, and every attempt is kept
+// in a bounded delivery log for the admin API. Deliveries go over plain HTTP,
+// e.g. to a relay on the same host. A config reload replaces the webhooks
+// from the config file and leaves those registered through the admin API.
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+type HmacSha256 = Hmac<Sha256>;
+
+const MAX_ATTEMPTS: u32 = 5;
+const FIRST_RETRY: Duration = Duration::from_secs(1);
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(5);
+const DELIVERY_LOG_LEN: usize = 256;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WebhookEvent {
+    Finalized,
+    // A health sample failed the policy after passing
+    HealthFailure,
+    // A validator voted for two proposals in the same height, round and phase
+    Equivocation,
+    // An alert rule fired or resolved
+    Alert,
+    // A proposal or vote submitted through the intake queue was decided
+    Intake,
+    // A chronically failing leader was moved to the back of the schedule
+    LeaderDemoted,
+    // A pending transaction expired or was removed from the mempool
+    TxDropped,
+    // The entropy pool dropped below its low watermark
+    EntropyLow,
+}
+
+impl WebhookEvent {
+    pub const ALL: [WebhookEvent; 8] = [
+        WebhookEvent::Finalized,
+        WebhookEvent::HealthFailure,
+        WebhookEvent::Equivocation,
+        WebhookEvent::Alert,
+        WebhookEvent::Intake,
+        WebhookEvent::LeaderDemoted,
+        WebhookEvent::TxDropped,
+        WebhookEvent::EntropyLow,
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            WebhookEvent::Finalized => "finalized",
+            WebhookEvent::HealthFailure => "health_failure",
+            WebhookEvent::Equivocation => "equivocation",
+            WebhookEvent::Alert => "alert",
+            WebhookEvent::Intake => "intake",
+            WebhookEvent::LeaderDemoted => "leader_demoted",
+            WebhookEvent::TxDropped => "tx_dropped",
+            WebhookEvent::EntropyLow => "entropy_low",
+        }
+    }
+}
+
+fn all_events() -> Vec<WebhookEvent> {
+    WebhookEvent::ALL.to_vec()
+}
+
+#[derive(Debug, Clone, PartialEq, Deserialize)]
+pub struct WebhookConfig {
+    // http://HOST[:PORT]/PATH
+    pub url: String,
+    pub secret: String,
+    // Every event when left out
+    #[serde(default = "all_events")]
+    pub events: Vec<WebhookEvent>,
+}
+
+impl WebhookConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        let url = reqwest::Url::parse(&self.url).map_err(|e| format!("invalid webhook url '{}': {}", self.url, e))?;
+        if url.scheme() != "http" {
+            return Err(format!("webhook url '{}' must use http", self.url));
+        }
+        if self.secret.is_empty() {
+            return Err("webhook secret is empty".to_string());
+        }
+        Ok(())
+    }
+}
+
+// A registered webhook as listed by the admin API; the secret is never shown
+#[derive(Debug, Clone, Serialize)]
+pub struct WebhookInfo {
+    pub id: u64,
+    pub url: String,
+    pub events: Vec<WebhookEvent>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Delivery {
+    pub webhook_id: u64,
+    pub event_id: u64,
+    pub event: WebhookEvent,
+    // 1-based
+    pub attempt: u32,
+    // Unix time in milliseconds
+    pub at: u64,
+    pub status: Option<u16>,
+    pub error: Option<String>,
+    pub delivered: bool,
+}
+
+#[derive(Serialize)]
+struct Envelope<'a, T: Serialize> {
+    id: u64,
+    event: WebhookEvent,
+    at: u64,
+    data: &'a T,
+}
+
+struct Hook {
+    id: u64,
+    config: WebhookConfig,
+    // From the config file rather than the admin API
+    configured: bool,
+}
+
+#[derive(Default)]
+struct Registry {
+    next_id: u64,
+    next_event_id: u64,
+    hooks: Vec<Hook>,
+    deliveries: VecDeque<Delivery>,
+}
+
+impl Registry {
+    fn add(&mut self, config: WebhookConfig, configured: bool) -> WebhookInfo {
+        self.next_id += 1;
+        let info = WebhookInfo { id: self.next_id, url: config.url.clone(), events: config.events.clone() };
+        self.hooks.push(Hook { id: self.next_id, config, configured });
+        info
+    }
+}
+
+#[derive(Clone)]
+pub struct Webhooks {
+    registry: Arc<Mutex<Registry>>,
+    client: reqwest::Client,
+}
+
+impl Webhooks {
+    pub fn new(configs: Vec<WebhookConfig>) -> Result<Self, String> {
+        let webhooks = Self {
+            registry: Arc::new(Mutex::new(Registry::default())),
+            client: reqwest::Client::builder()
+                .timeout(DELIVERY_TIMEOUT)
+                .build()
+                .map_err(|e| e.to_string())?,
+        };
+        webhooks.replace_configured(configs)?;
+        Ok(webhooks)
+    }
+
+    pub fn register(&self, config: WebhookConfig) -> Result<WebhookInfo, String> {
+        config.validate()?;
+        Ok(self.registry.lock().unwrap().add(config, false))
+    }
+
+    // Swaps the config file's webhooks for `configs`, keeping the ids of
+    // those that did not change, and describes what was added and removed
+    pub fn replace_configured(&self, configs: Vec<WebhookConfig>) -> Result<Vec<String>, String> {
+        for config in &configs {
+            config.validate()?;
+        }
+        let mut registry = self.registry.lock().unwrap();
+        let mut changes = Vec::new();
+        registry.hooks.retain(|hook| {
+            let keep = !hook.configured || configs.contains(&hook.config);
+            if !keep {
+                changes.push(format!("webhook {} removed", hook.config.url));
+            }
+            keep
+        });
+        for config in configs {
+            if !registry.hooks.iter().any(|hook| hook.configured && hook.config == config) {
+                changes.push(format!("webhook {} added", config.url));
+                registry.add(config, true);
+            }
+        }
+        Ok(changes)
+    }
+
+    pub fn remove(&self, id: u64) -> bool {
+        let mut registry = self.registry.lock().unwrap();
+        let before = registry.hooks.len();
+        registry.hooks.retain(|hook| hook.id != id);
+        registry.hooks.len() < before
+    }
+
+    pub fn list(&self) -> Vec<WebhookInfo> {
+        self.registry
+            .lock()
+            .unwrap()
+            .hooks
+            .iter()
+            .map(|hook| WebhookInfo { id: hook.id, url: hook.config.url.clone(), events: hook.config.events.clone() })
+            .collect()
+    }
+
+    // Oldest first
+    pub fn deliveries(&self) -> Vec<Delivery> {
+        self.registry.lock().unwrap().deliveries.iter().cloned().collect()
+    }
+
+    // Queues `data` for every webhook subscribed to `event` and returns at
+    // once; must be called from within a tokio runtime
+    pub fn emit<T: Serialize>(&self, event: WebhookEvent, data: &T) {
+        let (event_id, targets) = {
+            let mut registry = self.registry.lock().unwrap();
+            let targets: Vec<(u64, WebhookConfig)> = registry
+                .hooks
+                .iter()
+                .filter(|hook| hook.config.events.contains(&event))
+                .map(|hook| (hook.id, hook.config.clone()))
+                .collect();
+            if targets.is_empty() {
+                return;
+            }
+            registry.next_event_id += 1;
+            (registry.next_event_id, targets)
+        };
+        let body = serde_json::to_vec(&Envelope { id: event_id, event, at: now_ms(), data }).expect("event serializes");
+        for (webhook_id, config) in targets {
+            tokio::spawn(self.clone().deliver(webhook_id, config, event_id, event, body.clone()));
+        }
+    }
+
+    async fn deliver(self, webhook_id: u64, config: WebhookConfig, event_id: u64, event: WebhookEvent, body: Vec<u8>) {
+        let signature = sign(config.secret.as_bytes(), &body);
+        let mut backoff = FIRST_RETRY;
+        for attempt in 1..=MAX_ATTEMPTS {
+            let response = self
+                .client
+                .post(&config.url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header("X-Webhook-Event", event.name())
+                .header("X-Webhook-Signature", &signature)
+                .body(body.clone())
+                .send()
+                .await;
+            let (status, error) = match response {
+                Ok(response) if response.status().is_success() => (Some(response.status().as_u16()), None),
+                Ok(response) => (Some(response.status().as_u16()), Some(format!("answered {}", response.status()))),
+                Err(e) => (None, Some(e.to_string())),
+            };
+            let delivered = error.is_none();
+            self.log(Delivery { webhook_id, event_id, event, attempt, at: now_ms(), status, error, delivered });
+            if delivered {
+                return;
+            }
+            if attempt < MAX_ATTEMPTS {
+                tokio::time::sleep(backoff).await;
+                backoff *= 2;
+            }
+        }
+        eprintln!("Webhook {} gave up on event {} after {} attempts", config.url, event_id, MAX_ATTEMPTS);
+    }
+
+    fn log(&self, delivery: Delivery) {
+        let mut registry = self.registry.lock().unwrap();
+        if registry.deliveries.len() == DELIVERY_LOG_LEN {
+            registry.deliveries.pop_front();
+        }
+        registry.deliveries.push_back(delivery);
+    }
+}
+
+// What receivers compare `X-Webhook-Signature` against
+pub fn sign(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = HmacSha256::new_from_slice(secret).expect("HMAC accepts keys of any length");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::{HeaderMap, StatusCode};
+    use axum::routing::post;
+    use axum::Router;
+    use std::sync::atomic::{AtomicU32, Ordering};
+
+    fn hook(url: &str, secret: &str) -> WebhookConfig {
+        WebhookConfig { url: url.to_string(), secret: secret.to_string(), events: vec![WebhookEvent::Finalized] }
+    }
+
+    #[test]
+    fn test_invalid_webhooks_are_rejected() {
+        let webhooks = Webhooks::new(Vec::new()).unwrap();
+        let err = webhooks.register(hook("not a url", "s")).unwrap_err();
+        assert!(err.starts_with("invalid webhook url 'not a url'"), "{}", err);
+        let err = webhooks.register(hook("https://127.0.0.1/hook", "s")).unwrap_err();
+        assert_eq!(err, "webhook url 'https://127.0.0.1/hook' must use http");
+        let err = webhooks.register(hook("http://127.0.0.1/hook", "")).unwrap_err();
+        assert_eq!(err, "webhook secret is empty");
+        assert!(Webhooks::new(vec![hook("ftp://127.0.0.1/hook", "s")]).is_err());
+
+        // One invalid entry leaves the configured webhooks as they were
+        let webhooks = Webhooks::new(vec![hook("http://127.0.0.1/a", "s")]).unwrap();
+        assert!(webhooks.replace_configured(vec![hook("http://127.0.0.1/b", "s"), hook("http://x", "")]).is_err());
+        assert_eq!(webhooks.list()[0].url, "http://127.0.0.1/a");
+        assert_eq!(webhooks.list().len(), 1);
+        assert!(!webhooks.remove(99));
+    }
+
+    #[test]
+    fn test_reload_replaces_only_configured_webhooks() {
+        let webhooks = Webhooks::new(vec![hook("http://127.0.0.1/a", "s"), hook("http://127.0.0.1/b", "s")]).unwrap();
+        let registered = webhooks.register(hook("http://127.0.0.1/api", "s")).unwrap();
+
+        let changes = webhooks
+            .replace_configured(vec![hook("http://127.0.0.1/b", "s"), hook("http://127.0.0.1/c", "s")])
+            .unwrap();
+        assert_eq!(changes, vec!["webhook http://127.0.0.1/a removed", "webhook http://127.0.0.1/c added"]);
+        let listed: Vec<(u64, String)> = webhooks.list().into_iter().map(|info| (info.id, info.url)).collect();
+        assert_eq!(
+            listed,
+            vec![
+                (2, "http://127.0.0.1/b".to_string()),
+                (registered.id, "http://127.0.0.1/api".to_string()),
+                (4, "http://127.0.0.1/c".to_string()),
+            ]
+        );
+        assert!(webhooks.remove(registered.id));
+        assert_eq!(webhooks.list().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_failed_delivery_is_retried_and_logged() {
+        // Answers 500 to the first POST and checks the signature of the rest
+        let calls = Arc::new(AtomicU32::new(0));
+        let seen = calls.clone();
+        let receiver = Router::new().route(
+            "/hook",
+            post(move |headers: HeaderMap, body: axum::body::Bytes| async move {
+                if seen.fetch_add(1, Ordering::SeqCst) == 0 {
+                    return StatusCode::INTERNAL_SERVER_ERROR;
+                }
+                assert_eq!(headers["x-webhook-event"], "finalized");
+                assert_eq!(headers["x-webhook-signature"], sign(b"secret", &body).as_str());
+                StatusCode::OK
+            }),
+        );
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/hook", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, receiver).await.unwrap() });
+
+        let webhooks = Webhooks::new(vec![hook(&url, "secret")]).unwrap();
+        // Not subscribed: nothing is sent
+        webhooks.emit(WebhookEvent::Alert, &"ignored");
+        webhooks.emit(WebhookEvent::Finalized, &serde_json::json!({ "height": 1 }));
+        for _ in 0..100 {
+            if webhooks.deliveries().len() == 2 {
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+
+        let deliveries = webhooks.deliveries();
+        assert_eq!(deliveries.len(), 2);
+        assert_eq!((deliveries[0].attempt, deliveries[0].status, deliveries[0].delivered), (1, Some(500), false));
+        assert_eq!(deliveries[0].error.as_deref(), Some("answered 500 Internal Server Error"));
+        assert_eq!((deliveries[1].attempt, deliveries[1].status, deliveries[1].delivered), (2, Some(200), true));
+        assert_eq!(deliveries[1].event_id, 1);
+        assert_eq!(calls.load(Ordering::SeqCst), 2);
+    }
+}
@@ -0,0 +1,170 @@
+// Debug bundles for bug reports: a single tar.gz with the node's flags and
+// config files (secrets redacted), storage stats, the tail of its log file
+// and, from a running node's API, its status, self-test, recent health
+// samples, alerts and validator set. The node has no peer table of its own,
+// so the validator set stands in for one. On a panic the server writes a
+// crash bundle with the panic message and backtrace instead of the live
+// parts, since the node's state may be half-updated by then.
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{json, Value};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+const LOG_TAIL_LINES: usize = 2000;
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+// Keys whose values never leave the host
+const SECRET_KEYS: [&str; 4] = ["secret", "token", "password", "seed"];
+
+#[derive(Default)]
+pub struct Bundle {
+    files: Vec<(String, Vec<u8>)>,
+}
+
+impl Bundle {
+    pub fn add(&mut self, name: &str, contents: impl Into<Vec<u8>>) {
+        self.files.push((name.to_string(), contents.into()));
+    }
+
+    pub fn add_json(&mut self, name: &str, value: &Value) {
+        self.add(name, serde_json::to_vec_pretty(value).expect("JSON values serialize"));
+    }
+
+    // Files go under a `debug-bundle/` directory in the archive
+    pub fn write(&self, path: &Path) -> std::io::Result<()> {
+        let file = std::fs::File::create(path)?;
+        let mut archive = tar::Builder::new(GzEncoder::new(file, Compression::default()));
+        let mtime = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        for (name, contents) in &self.files {
+            let mut header = tar::Header::new_gnu();
+            header.set_size(contents.len() as u64);
+            header.set_mode(0o644);
+            header.set_mtime(mtime);
+            header.set_cksum();
+            archive.append_data(&mut header, format!("debug-bundle/{}", name), contents.as_slice())?;
+        }
+        archive.into_inner()?.finish()?;
+        Ok(())
+    }
+}
+
+// Replaces the value of every key naming a secret, at any depth
+pub fn redact(value: Value) -> Value {
+    match value {
+        Value::Object(map) => Value::Object(
+            map.into_iter()
+                .map(|(key, value)| {
+                    let lower = key.to_ascii_lowercase();
+                    if SECRET_KEYS.iter().any(|secret| lower.contains(secret)) {
+                        (key, Value::String("[redacted]".to_string()))
+                    } else {
+                        (key, redact(value))
+                    }
+                })
+                .collect(),
+        ),
+        Value::Array(items) => Value::Array(items.into_iter().map(redact).collect()),
+        other => other,
+    }
+}
+
+// A config file's contents, redacted, or why it could not be read
+pub fn config_file(path: &Path) -> Value {
+    match std::fs::read_to_string(path).map_err(|e| e.to_string()).and_then(|text| {
+        serde_json::from_str::<Value>(&text).map_err(|e| e.to_string())
+    }) {
+        Ok(value) => redact(value),
+        Err(e) => json!({ "error": e }),
+    }
+}
+
+// Size of every file in the data directory
+pub fn storage_stats(data_dir: Option<&Path>) -> Value {
+    let Some(dir) = data_dir else {
+        return json!({ "data_dir": null });
+    };
+    let entries = match std::fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) => return json!({ "data_dir": dir, "error": e.to_string() }),
+    };
+    let mut files: Vec<(String, u64)> = entries
+        .filter_map(Result::ok)
+        .filter_map(|entry| Some((entry.file_name().to_string_lossy().into_owned(), entry.metadata().ok()?.len())))
+        .collect();
+    files.sort();
+    let total: u64 = files.iter().map(|(_, size)| size).sum();
+    json!({
+        "data_dir": dir,
+        "total_bytes": total,
+        "files": files.into_iter().map(|(name, bytes)| json!({ "name": name, "bytes": bytes })).collect::<Vec<_>>(),
+    })
+}
+
+// The last LOG_TAIL_LINES lines of a log file
+pub fn log_tail(path: &Path) -> String {
+    match std::fs::read_to_string(path) {
+        Ok(text) => {
+            let lines: Vec<&str> = text.lines().collect();
+            let mut tail = lines[lines.len().saturating_sub(LOG_TAIL_LINES)..].join("\n");
+            tail.push('\n');
+            tail
+        }
+        Err(e) => format!("cannot read {}: {}\n", path.display(), e),
+    }
+}
+
+// Adds what a running node reports about itself; endpoints that fail are
+// recorded as errors so a sick node still yields a bundle
+pub async fn add_live(bundle: &mut Bundle, url: &str) {
+    let client = reqwest::Client::builder().timeout(FETCH_TIMEOUT).build().expect("client builds");
+    let endpoints = [
+        ("status.json", "/status"),
+        ("selftest.json", "/selftest"),
+        ("health-history.json", "/health/history?window=1h"),
+        ("alerts.json", "/alerts"),
+        ("validators.json", "/staking/validators"),
+    ];
+    for (name, path) in endpoints {
+        let url = format!("{}{}", url.trim_end_matches('/'), path);
+        let value = match client.get(&url).send().await {
+            Ok(response) => match response.json::<Value>().await {
+                Ok(value) => redact(value),
+                Err(e) => json!({ "url": url, "error": e.to_string() }),
+            },
+            Err(e) => json!({ "url": url, "error": e.to_string() }),
+        };
+        bundle.add_json(name, &value);
+    }
+}
+
+// Writes a crash bundle into `dir` on any panic, after the default hook has
+// printed the message
+pub fn install_panic_hook(config: Value, data_dir: Option<PathBuf>, log_file: Option<PathBuf>, dir: PathBuf) {
+    let previous = std::panic::take_hook();
+    std::panic::set_hook(Box::new(move |info| {
+        previous(info);
+        let mut bundle = Bundle::default();
+        let thread = std::thread::current();
+        bundle.add(
+            "panic.txt",
+            format!(
+                "thread '{}' {}\n\n{}\n",
+                thread.name().unwrap_or("<unnamed>"),
+                info,
+                std::backtrace::Backtrace::force_capture()
+            ),
+        );
+        bundle.add_json("config.json", &config);
+        bundle.add_json("storage.json", &storage_stats(data_dir.as_deref()));
+        if let Some(path) = &log_file {
+            bundle.add("node.log", log_tail(path));
+        }
+        let at = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_millis());
+        let path = dir.join(format!("crash-{}.tar.gz", at));
+        match bundle.write(&path) {
+            Ok(()) => eprintln!("Crash bundle written to {}", path.display()),
+            Err(e) => eprintln!("Cannot write crash bundle {}: {}", path.display(), e),
+        }
+    }));
+}
+
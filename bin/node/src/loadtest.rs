@@ -0,0 +1,196 @@
+// Drives proposals and votes against a running node at a fixed rate and
+// reports acceptance and finalization latency percentiles. A node that
+// refuses the votes (403) would refuse every one, so the test stops there.
+use clap::ValueEnum;
+use consensus::ValidatorId;
+use mini_consensus_client::{Client, ClientError, ConsensusClient, RetryPolicy};
+use serde::Serialize;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use tokio::task::JoinSet;
+
+#[derive(Clone, Copy, ValueEnum)]
+pub enum ReportFormat {
+    Text,
+    Json,
+    Csv,
+}
+
+pub struct LoadTest {
+    pub url: String,
+    pub tps: u32,
+    pub duration: Duration,
+    pub validators: u32,
+}
+
+#[derive(Default)]
+struct Sample {
+    accepted: Option<Duration>,
+    finalized: Option<Duration>,
+    error: bool,
+    // Why the node refused a vote
+    refused: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Percentiles {
+    pub p50_ms: f64,
+    pub p90_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct Report {
+    pub target_tps: u32,
+    pub achieved_tps: f64,
+    pub duration_secs: f64,
+    pub sent: usize,
+    pub accepted: usize,
+    pub finalized: usize,
+    pub errors: usize,
+    pub acceptance_latency: Percentiles,
+    pub finalization_latency: Percentiles,
+}
+
+impl Report {
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Json => serde_json::to_string_pretty(self).unwrap(),
+            ReportFormat::Csv => {
+                let a = &self.acceptance_latency;
+                let f = &self.finalization_latency;
+                format!(
+                    "target_tps,achieved_tps,duration_secs,sent,accepted,finalized,errors,\
+                     accept_p50_ms,accept_p90_ms,accept_p99_ms,accept_max_ms,\
+                     final_p50_ms,final_p90_ms,final_p99_ms,final_max_ms\n\
+                     {},{:.2},{:.2},{},{},{},{},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3},{:.3}\n",
+                    self.target_tps, self.achieved_tps, self.duration_secs,
+                    self.sent, self.accepted, self.finalized, self.errors,
+                    a.p50_ms, a.p90_ms, a.p99_ms, a.max_ms,
+                    f.p50_ms, f.p90_ms, f.p99_ms, f.max_ms,
+                )
+            }
+            ReportFormat::Text => {
+                let line = |name: &str, p: &Percentiles| {
+                    format!(
+                        "{:<22} p50 {:>9.3} ms  p90 {:>9.3} ms  p99 {:>9.3} ms  max {:>9.3} ms\n",
+                        name, p.p50_ms, p.p90_ms, p.p99_ms, p.max_ms
+                    )
+                };
+                format!(
+                    "Load test: {} tps target, {:.2} tps achieved over {:.1}s\n\
+                     Sent: {}  Accepted: {}  Finalized: {}  Errors: {}\n{}{}",
+                    self.target_tps, self.achieved_tps, self.duration_secs,
+                    self.sent, self.accepted, self.finalized, self.errors,
+                    line("Acceptance latency", &self.acceptance_latency),
+                    line("Finalization latency", &self.finalization_latency),
+                )
+            }
+        }
+    }
+}
+
+impl LoadTest {
+    pub async fn run(&self) -> Result<Report, String> {
+        // Retries would hide the latency being measured
+        let client = Arc::new(Client::new(self.url.as_str()).with_retry(RetryPolicy::none()));
+        let mut ticker = tokio::time::interval(Duration::from_secs_f64(1.0 / self.tps.max(1) as f64));
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+
+        let mut tasks = JoinSet::new();
+        let mut samples = Vec::new();
+        let started = Instant::now();
+        let mut seq = 0u64;
+        while started.elapsed() < self.duration {
+            ticker.tick().await;
+            while let Some(sample) = tasks.try_join_next() {
+                samples.push(checked(sample)?);
+            }
+            seq += 1;
+            let payload = format!("loadtest-{}-{}-{}", std::process::id(), started.elapsed().as_nanos(), seq);
+            tasks.spawn(submit(client.clone(), payload, self.validators));
+        }
+
+        while let Some(sample) = tasks.join_next().await {
+            samples.push(checked(sample)?);
+        }
+        let elapsed = started.elapsed().as_secs_f64();
+
+        let mut accepted: Vec<Duration> = samples.iter().filter_map(|s| s.accepted).collect();
+        let mut finalized: Vec<Duration> = samples.iter().filter_map(|s| s.finalized).collect();
+        Ok(Report {
+            target_tps: self.tps,
+            achieved_tps: accepted.len() as f64 / elapsed,
+            duration_secs: elapsed,
+            sent: samples.len(),
+            accepted: accepted.len(),
+            finalized: finalized.len(),
+            errors: samples.iter().filter(|s| s.error).count(),
+            acceptance_latency: percentiles(&mut accepted),
+            finalization_latency: percentiles(&mut finalized),
+        })
+    }
+}
+
+// A finished sample, or why the test cannot go on
+fn checked(sample: Result<Sample, tokio::task::JoinError>) -> Result<Sample, String> {
+    let sample = sample.unwrap_or(Sample { error: true, ..Default::default() });
+    match sample.refused {
+        Some(refusal) => Err(refusal),
+        None => Ok(sample),
+    }
+}
+
+// One proposal followed by a combined vote from every validator
+async fn submit(client: Arc<Client>, payload: String, validators: u32) -> Sample {
+    let mut sample = Sample::default();
+    let start = Instant::now();
+
+    let Ok(proposal) = client.propose(&payload).await else {
+        sample.error = true;
+        return sample;
+    };
+    sample.accepted = Some(start.elapsed());
+
+    for validator_id in 0..validators {
+        match client.vote_combined(&proposal, ValidatorId(validator_id)).await {
+            Ok(vote) if vote.success => {
+                sample.finalized = Some(start.elapsed());
+                break;
+            }
+            Ok(_) => {}
+            // Another proposal won the height first
+            Err(ClientError::Status { status: 409, .. }) => break,
+            Err(ClientError::Status { status: 403, body }) => {
+                sample.refused = Some(format!(
+                    "node refused the vote of validator {}: {}; restart it with --allow-any-voter or pass --validators 1",
+                    validator_id, body
+                ));
+                break;
+            }
+            Err(_) => {
+                sample.error = true;
+                break;
+            }
+        }
+    }
+    sample
+}
+
+fn percentiles(latencies: &mut [Duration]) -> Percentiles {
+    latencies.sort();
+    let at = |q: f64| {
+        if latencies.is_empty() {
+            return 0.0;
+        }
+        let i = ((q * latencies.len() as f64).ceil() as usize).clamp(1, latencies.len()) - 1;
+        latencies[i].as_secs_f64() * 1000.0
+    };
+    Percentiles {
+        p50_ms: at(0.5),
+        p90_ms: at(0.9),
+        p99_ms: at(0.99),
+        max_ms: at(1.0),
+    }
+}
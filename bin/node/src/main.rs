@@ -1,5 +1,26 @@
-use clap::{Parser, Subcommand};
-use api::start_server;
+mod bundle;
+mod loadtest;
+mod rngd;
+mod sealing;
+
+use base64::Engine;
+use clap::{Parser, Subcommand, ValueEnum};
+use api::alerts::AlertRule;
+use api::webhooks::WebhookConfig;
+use api::{
+    AdminConfig, AdminToken, ApiServer, ApiServerBuilder, CompressionConfig, ConfigLoader, EntropyConfig, Encoding, EventLogConfig, JsonProfile, LimitsConfig, ListenAddr, MonitoringConfig, NodeRole,
+    RuntimeConfig, StorageConfig, VotePolicy,
+};
+use consensus::archive::{self, ArchiveEntry};
+use consensus::{BlockStore, FileLease, FileStore, LocalSigner, Pruning, RemoteSignerServer, RewardSchedule, Signer};
+use loadtest::{LoadTest, ReportFormat};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use trng::{
+    Charset, DrandConfig, DriftMonitor, HealthPolicy, HealthTest, MasterSeed, PoolConfig, SecretPolicy, SourceComparison,
+    Trng,
+};
 
 #[derive(Parser)]
 #[command(name = "mini-consensus-node")]
@@ -10,6 +31,420 @@ struct Cli {
     
     #[arg(long, default_value_t = 8080)]
     port: u16,
+
+    /// HOST:PORT or unix:PATH to serve the API on, repeatable; defaults to 0.0.0.0:PORT
+    #[arg(long)]
+    listen: Vec<ListenAddr>,
+
+    /// Master seed file the node's keys are derived from, plain or sealed with `keys seal`
+    #[arg(long)]
+    seed_file: Option<PathBuf>,
+
+    /// validator, or observer to serve the read API without ever proposing or voting
+    #[arg(long, value_enum, default_value_t = Role::Validator)]
+    role: Role,
+
+    /// Let the API vote as any validator, not just this node's own (validator 0), for single-node clusters and load tests
+    #[arg(long)]
+    allow_any_voter: bool,
+
+    /// JSON file with hardware generators to feed the pool: [{"name", "kind": "hwrng" | "serial", "path", "max_bytes_per_sec", "credit_bits_per_byte", "baud", "framing", "init"}]
+    #[arg(long)]
+    hardware_sources: Option<PathBuf>,
+
+    /// Mix the public drand beacon (quicknet) into the pool, credited zero bits
+    #[arg(long)]
+    drand: bool,
+
+    /// JSON file configuring the drand source, implies --drand: {"urls", "chain_hash", "public_key", "poll_secs", "credit_bits"}
+    #[arg(long)]
+    drand_config: Option<PathBuf>,
+
+    /// Shared lease file; run as hot standby and only sign while holding it
+    #[arg(long)]
+    lease_file: Option<PathBuf>,
+
+    /// Seconds a lease stays valid without renewal
+    #[arg(long, default_value_t = 10)]
+    lease_ttl_secs: u64,
+
+    /// Directory finalized blocks are persisted to and restored from
+    #[arg(long)]
+    data_dir: Option<PathBuf>,
+
+    /// Finalized blocks kept in memory and served: archive, default (the last 100000 heights), default:N or minimal
+    #[arg(long, default_value = "default")]
+    pruning: Pruning,
+
+    /// How long responses to requests with an Idempotency-Key are kept for retries
+    #[arg(long, default_value = "24h", value_parser = api::parse_duration)]
+    idempotency_window: std::time::Duration,
+
+    /// Events kept for replay at /events and /events/ws
+    #[arg(long, default_value_t = 10_000)]
+    event_retention: usize,
+
+    /// Also drop events older than this, e.g. "24h"
+    #[arg(long, value_parser = api::parse_duration)]
+    event_max_age: Option<std::time::Duration>,
+
+    /// Requests served at once across the API before answering 503
+    #[arg(long, default_value_t = 1024)]
+    max_in_flight: usize,
+
+    /// Timeout for routes without their own, in milliseconds
+    #[arg(long, default_value_t = 10000)]
+    request_timeout_ms: u64,
+
+    /// Milliseconds per second the /rng and /health lane may spend serving requests
+    #[arg(long, default_value_t = 500)]
+    rng_budget_ms: u64,
+
+    /// Memory budget of a subsystem as NAME=SIZE, repeatable; NAME is mempool, votes, event_log or entropy_pool and SIZE e.g. 64MiB
+    #[arg(long, value_parser = parse_memory_budget)]
+    memory_budget: Vec<(String, usize)>,
+
+    /// Reward credited to the proposer of each finalized height
+    #[arg(long, default_value_t = RewardSchedule::default().proposer)]
+    proposer_reward: u64,
+
+    /// Reward credited to each commit voter in a finalized height's certificate
+    #[arg(long, default_value_t = RewardSchedule::default().voter)]
+    voter_reward: u64,
+
+    /// Halve both rewards every this many heights; flat without it
+    #[arg(long)]
+    reward_halving_interval: Option<u64>,
+
+    /// How long a transaction may wait in the mempool before it is dropped, e.g. "30m"
+    #[arg(long, default_value = "1h", value_parser = api::parse_duration)]
+    mempool_ttl: std::time::Duration,
+
+    /// JSON file with health thresholds, required tests and sample size
+    #[arg(long)]
+    health_policy: Option<PathBuf>,
+
+    /// Bytes the entropy pool keeps
+    #[arg(long, default_value_t = PoolConfig::default().capacity)]
+    pool_capacity: usize,
+
+    /// Entropy pool fill, in bytes, below which collection speeds up and an entropy_low event is sent
+    #[arg(long, default_value_t = PoolConfig::default().low_watermark)]
+    pool_low_watermark: usize,
+
+    /// Entropy pool fill, in bytes, from which collection slows down; /rng answers 503 until the pool first reaches it
+    #[arg(long, default_value_t = PoolConfig::default().high_watermark)]
+    pool_high_watermark: usize,
+
+    /// JSON file with alert rules: [{"name", "condition": "entropy_below" | "finalization_stalled", "threshold" | "seconds"}]
+    #[arg(long)]
+    alert_rules: Option<PathBuf>,
+
+    /// JSON file with webhooks to notify: [{"url", "secret", "events"}]
+    #[arg(long)]
+    webhooks: Option<PathBuf>,
+
+    /// JSON file overriding any of max_in_flight, request_timeout_ms, rng_budget_ms, health_policy, pool, alert_rules and webhooks; re-read with the files above on SIGHUP
+    #[arg(long)]
+    config: Option<PathBuf>,
+
+    /// Loopback HOST:PORT or unix:PATH to serve the admin API on; disabled without it
+    #[arg(long)]
+    admin_listen: Option<ListenAddr>,
+
+    /// File holding the bearer token for /admin endpoints, required over TCP
+    #[arg(long, requires = "admin_listen")]
+    admin_token_file: Option<PathBuf>,
+
+    /// File the node's output is redirected to, included in debug bundles
+    #[arg(long)]
+    log_file: Option<PathBuf>,
+
+    /// JSON response profile for requests without X-Json-Profile or ?json_profile=: snake_case, or compat for camelCase keys and heights as strings
+    #[arg(long, default_value = "snake_case")]
+    json_profile: JsonProfile,
+
+    /// Response compression algorithms to offer, comma-separated: br, zstd, gzip
+    #[arg(long, value_delimiter = ',', default_value = "br,zstd,gzip")]
+    compression: Vec<Encoding>,
+
+    /// Serve every response uncompressed
+    #[arg(long, conflicts_with = "compression")]
+    no_compression: bool,
+
+    /// Smallest response body compressed, in bytes
+    #[arg(long, default_value_t = api::compression::DEFAULT_MIN_BYTES)]
+    compression_min_bytes: u16,
+
+    /// Do not write a debug bundle to the data directory (or the temp directory) on a panic
+    #[arg(long)]
+    no_crash_bundle: bool,
+}
+
+impl Cli {
+    fn runtime_sources(&self) -> RuntimeSources {
+        RuntimeSources {
+            max_in_flight: self.max_in_flight,
+            request_timeout_ms: self.request_timeout_ms,
+            rng_budget_ms: self.rng_budget_ms,
+            health_policy: self.health_policy.clone(),
+            pool: PoolConfig {
+                capacity: self.pool_capacity,
+                low_watermark: self.pool_low_watermark,
+                high_watermark: self.pool_high_watermark,
+            },
+            alert_rules: self.alert_rules.clone(),
+            webhooks: self.webhooks.clone(),
+            config: self.config.clone(),
+        }
+    }
+
+    fn runtime(&self) -> RuntimeConfig {
+        self.runtime_sources().load().unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        })
+    }
+
+    fn limits(&self) -> LimitsConfig {
+        let runtime = self.runtime();
+        let limits = LimitsConfig::default()
+            .with_global_max_in_flight(runtime.max_in_flight)
+            .with_default_timeout(runtime.request_timeout)
+            .with_rng_budget(runtime.rng_budget)
+            .with_mempool_ttl(self.mempool_ttl);
+        self.memory_budget.iter().fold(limits, |limits, (name, bytes)| limits.with_memory_budget(name, *bytes))
+    }
+
+    fn entropy(&self) -> EntropyConfig {
+        let hardware = self.hardware_sources.as_ref().map(|path| load_json(path, "hardware sources")).unwrap_or_default();
+        let mut entropy = EntropyConfig::default().with_hardware(hardware).with_pool(self.runtime().pool);
+        if let Some(path) = &self.drand_config {
+            entropy = entropy.with_drand(load_json(path, "drand config"));
+        } else if self.drand {
+            entropy = entropy.with_drand(DrandConfig::default());
+        }
+        match load_seed(self.seed_file.as_ref()) {
+            Some(seed) => entropy.with_seed(seed),
+            None => entropy,
+        }
+    }
+
+    fn storage(&self) -> StorageConfig {
+        let mut events = EventLogConfig::default().with_retain_events(self.event_retention);
+        if let Some(age) = self.event_max_age {
+            events = events.with_retain_secs(age.as_secs());
+        }
+        let storage = StorageConfig::default()
+            .with_pruning(self.pruning)
+            .with_event_log(events)
+            .with_idempotency_window(self.idempotency_window);
+        match &self.data_dir {
+            Some(dir) => storage.with_data_dir(dir.clone()),
+            None => storage,
+        }
+    }
+
+    fn rewards(&self) -> RewardSchedule {
+        RewardSchedule {
+            proposer: self.proposer_reward,
+            voter: self.voter_reward,
+            halving_interval: self.reward_halving_interval,
+        }
+    }
+
+    fn compression(&self) -> CompressionConfig {
+        if self.no_compression {
+            return CompressionConfig::disabled();
+        }
+        CompressionConfig::default()
+            .with_algorithms(self.compression.clone())
+            .with_min_bytes(self.compression_min_bytes)
+    }
+
+    fn listen(&self) -> Vec<ListenAddr> {
+        if self.listen.is_empty() {
+            vec![ListenAddr::Tcp(([0, 0, 0, 0], self.port).into())]
+        } else {
+            self.listen.clone()
+        }
+    }
+
+    fn monitoring(&self) -> MonitoringConfig {
+        let runtime = self.runtime();
+        let sources = self.runtime_sources();
+        MonitoringConfig::default()
+            .with_health_policy(runtime.health_policy)
+            .with_alert_rules(runtime.alert_rules)
+            .with_webhooks(runtime.webhooks)
+            .with_config_loader(ConfigLoader::new(move || sources.load()))
+    }
+
+    fn server(&self) -> ApiServerBuilder {
+        let server = ApiServer::builder()
+            .role(self.role())
+            .vote_policy(if self.allow_any_voter { VotePolicy::Any } else { VotePolicy::default() })
+            .entropy(self.entropy())
+            .storage(self.storage())
+            .limits(self.limits())
+            .reward_schedule(self.rewards())
+            .monitoring(self.monitoring())
+            .json_profile(self.json_profile)
+            .compression(self.compression());
+        let server = match self.admin() {
+            Some(admin) => server.admin(admin),
+            None => server,
+        };
+        self.listen().into_iter().fold(server, ApiServerBuilder::bind)
+    }
+
+    fn admin(&self) -> Option<AdminConfig> {
+        let listen = self.admin_listen.clone()?;
+        let mut admin = AdminConfig::new(listen).unwrap_or_else(|e| {
+            eprintln!("{}", e);
+            std::process::exit(1);
+        });
+        if let Some(path) = &self.admin_token_file {
+            let token = std::fs::read_to_string(path).unwrap_or_else(|e| {
+                eprintln!("Cannot read admin token {}: {}", path.display(), e);
+                std::process::exit(1);
+            });
+            let token = token.trim();
+            if token.is_empty() {
+                eprintln!("Admin token file {} is empty", path.display());
+                std::process::exit(1);
+            }
+            admin = admin.with_token(AdminToken::new(token));
+        }
+        Some(admin)
+    }
+
+    // Flags and config file contents for debug bundles, secrets redacted;
+    // key files are named but never read
+    fn bundle_config(&self) -> serde_json::Value {
+        let file = |path: &Option<PathBuf>| path.as_deref().map(bundle::config_file);
+        serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "flags": {
+                "port": self.port,
+                "listen": self.listen().iter().map(|addr| addr.to_string()).collect::<Vec<_>>(),
+                "role": self.role.to_possible_value().map(|v| v.get_name().to_string()),
+                "drand": self.drand,
+                "lease_file": self.lease_file,
+                "lease_ttl_secs": self.lease_ttl_secs,
+                "data_dir": self.data_dir,
+                "pruning": self.pruning.to_string(),
+                "idempotency_window_secs": self.idempotency_window.as_secs(),
+                "event_retention": self.event_retention,
+                "event_max_age_secs": self.event_max_age.map(|age| age.as_secs()),
+                "max_in_flight": self.max_in_flight,
+                "request_timeout_ms": self.request_timeout_ms,
+                "rng_budget_ms": self.rng_budget_ms,
+                "proposer_reward": self.proposer_reward,
+                "voter_reward": self.voter_reward,
+                "reward_halving_interval": self.reward_halving_interval,
+                "pool_capacity": self.pool_capacity,
+                "pool_low_watermark": self.pool_low_watermark,
+                "pool_high_watermark": self.pool_high_watermark,
+                "admin_listen": self.admin_listen.as_ref().map(|addr| addr.to_string()),
+            },
+            "files": {
+                "config": file(&self.config),
+                "health_policy": file(&self.health_policy),
+                "alert_rules": file(&self.alert_rules),
+                "webhooks": file(&self.webhooks),
+                "hardware_sources": file(&self.hardware_sources),
+                "drand_config": file(&self.drand_config),
+            },
+        })
+    }
+
+    fn install_crash_bundle(&self) {
+        if self.no_crash_bundle {
+            return;
+        }
+        let dir = self.data_dir.clone().unwrap_or_else(std::env::temp_dir);
+        bundle::install_panic_hook(self.bundle_config(), self.data_dir.clone(), self.log_file.clone(), dir);
+    }
+
+    fn role(&self) -> NodeRole {
+        match (self.role, &self.lease_file) {
+            (Role::Observer, Some(_)) => {
+                eprintln!("An observer never signs, so it cannot hold a lease");
+                std::process::exit(1);
+            }
+            (Role::Observer, None) => NodeRole::Observer,
+            (Role::Validator, None) => NodeRole::Validator,
+            (Role::Validator, Some(path)) => {
+                let holder = format!(
+                    "{}-{}",
+                    std::env::var("HOSTNAME").unwrap_or_else(|_| "node".to_string()),
+                    std::process::id()
+                );
+                NodeRole::Standby(FileLease::new(path, holder, std::time::Duration::from_secs(self.lease_ttl_secs)))
+            }
+        }
+    }
+}
+
+// The flags and files the reloadable settings come from, kept so a reload
+// reads them again
+#[derive(Clone)]
+struct RuntimeSources {
+    max_in_flight: usize,
+    request_timeout_ms: u64,
+    rng_budget_ms: u64,
+    health_policy: Option<PathBuf>,
+    pool: PoolConfig,
+    alert_rules: Option<PathBuf>,
+    webhooks: Option<PathBuf>,
+    config: Option<PathBuf>,
+}
+
+// `--config`: settings given here win over the flags and files
+#[derive(Default, serde::Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ConfigFile {
+    max_in_flight: Option<usize>,
+    request_timeout_ms: Option<u64>,
+    rng_budget_ms: Option<u64>,
+    health_policy: Option<HealthPolicy>,
+    pool: Option<PoolConfig>,
+    alert_rules: Option<Vec<AlertRule>>,
+    webhooks: Option<Vec<WebhookConfig>>,
+}
+
+impl RuntimeSources {
+    fn load(&self) -> Result<RuntimeConfig, String> {
+        let file: ConfigFile = read_optional(&self.config, "config")?;
+        let health_policy = match file.health_policy {
+            Some(policy) => policy,
+            None => read_optional(&self.health_policy, "health policy")?,
+        };
+        let alert_rules = match file.alert_rules {
+            Some(rules) => rules,
+            None => read_optional(&self.alert_rules, "alert rules")?,
+        };
+        let webhooks = match file.webhooks {
+            Some(webhooks) => webhooks,
+            None => read_optional(&self.webhooks, "webhooks")?,
+        };
+        Ok(RuntimeConfig {
+            max_in_flight: file.max_in_flight.unwrap_or(self.max_in_flight),
+            request_timeout: std::time::Duration::from_millis(file.request_timeout_ms.unwrap_or(self.request_timeout_ms)),
+            rng_budget: std::time::Duration::from_millis(file.rng_budget_ms.unwrap_or(self.rng_budget_ms)),
+            health_policy,
+            pool: file.pool.unwrap_or(self.pool),
+            alert_rules,
+            webhooks,
+        })
+    }
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum Role {
+    Validator,
+    Observer,
 }
 
 #[derive(Subcommand)]
@@ -20,9 +455,393 @@ enum Commands {
     Rng {
         #[arg(default_value_t = 32)]
         len: usize,
+        /// Output format
+        #[arg(long, value_enum, default_value_t = OutputFormat::Hex)]
+        format: OutputFormat,
+        /// Write to FILE instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+        /// Number of values to generate
+        #[arg(long, default_value_t = 1)]
+        count: usize,
+        /// Words per passphrase (passphrase format only)
+        #[arg(long, default_value_t = 6)]
+        words: usize,
+    },
+    /// Generate a password or API key
+    Secret {
+        #[arg(long, default_value_t = 32)]
+        length: usize,
+        /// digits, hex, alpha, alnum or alnum-symbols
+        #[arg(long, default_value = "alnum-symbols")]
+        charset: Charset,
+        /// Refuse policies yielding fewer bits of entropy
+        #[arg(long)]
+        entropy_min: Option<f64>,
+    },
+    /// Split or recombine node keys with Shamir's Secret Sharing
+    Keys {
+        #[command(subcommand)]
+        action: KeysCommand,
+    },
+    /// Run a remote signer holding the validator key
+    Signer {
+        /// Address to accept node connections on
+        #[arg(long, default_value = "127.0.0.1:7400")]
+        listen: std::net::SocketAddr,
+        /// Hex ed25519 secret key file, plain or sealed with `keys seal`
+        #[arg(long, required_unless_present = "kms", conflicts_with = "kms")]
+        key_file: Option<PathBuf>,
+        /// Sign with a KMS key instead: aws:ARN, aws:KEY_ID with AWS_REGION, or gcp:KEY_VERSION_NAME (needs the aws-kms or gcp-kms feature)
+        #[arg(long)]
+        kms: Option<String>,
+        /// Print KMS signing latency as JSON this often
+        #[arg(long, default_value = "60s", value_parser = api::parse_duration)]
+        latency_interval: std::time::Duration,
+        /// File with the secret shared with the node for request authentication
+        #[arg(long)]
+        auth_key_file: PathBuf,
+        /// Double-sign guard file; copy it along with the key when failing over
+        #[arg(long)]
+        guard_file: PathBuf,
+    },
+    /// Collect config, storage stats, logs and a running node's status and health into a tar.gz for bug reports
+    DebugBundle {
+        /// Base URL of the node's API [default: http://127.0.0.1:PORT]
+        #[arg(long)]
+        url: Option<String>,
+        /// Archive file to write
+        #[arg(long, default_value = "debug-bundle.tar.gz")]
+        out: PathBuf,
+    },
+    /// Drive proposals and votes against a node at a fixed rate
+    Loadtest {
+        /// Base URL of the node's API
+        #[arg(long, default_value = "http://127.0.0.1:8080")]
+        url: String,
+        /// Proposals submitted per second
+        #[arg(long, default_value_t = 100)]
+        tps: u32,
+        /// Test length, e.g. 60s or 5m
+        #[arg(long, default_value = "60s", value_parser = api::parse_duration)]
+        duration: std::time::Duration,
+        /// Validators casting votes on each proposal; more than one needs a node started with --allow-any-voter
+        #[arg(long, default_value_t = 4)]
+        validators: u32,
+        /// Report format
+        #[arg(long, value_enum, default_value_t = ReportFormat::Text)]
+        report: ReportFormat,
+        /// Write the report to FILE instead of stdout
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Write finalized blocks from a data directory to a self-verifying archive
+    Export {
+        /// Data directory of a stopped node
+        #[arg(long)]
+        data_dir: PathBuf,
+        /// First height to include
+        #[arg(long, default_value_t = 0)]
+        from: u64,
+        /// Last height to include; defaults to the stored head
+        #[arg(long)]
+        to: Option<u64>,
+        /// Archive file to write
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Verify an archive and append its blocks to a data directory
+    Import {
+        /// Data directory of a stopped node
+        #[arg(long)]
+        data_dir: PathBuf,
+        /// Archive file to read
+        #[arg(long = "in")]
+        input: PathBuf,
+    },
+    /// Feed TRNG output into the kernel entropy pool via RNDADDENTROPY (Linux, needs CAP_SYS_ADMIN)
+    Rngd {
+        /// Bytes written to the pool per second
+        #[arg(long, default_value_t = 512)]
+        rate: usize,
+        /// Bytes per write
+        #[arg(long, default_value_t = 64)]
+        chunk: usize,
+        /// Entropy credited per byte written, 0 to 8 bits
+        #[arg(long, default_value_t = 4.0)]
+        credit_bits: f64,
+        /// How often the health battery gates the feed, e.g. 60s
+        #[arg(long, default_value = "60s", value_parser = api::parse_duration)]
+        health_interval: std::time::Duration,
+        /// How often stats are printed as JSON, e.g. 5m
+        #[arg(long, default_value = "60s", value_parser = api::parse_duration)]
+        stats_interval: std::time::Duration,
+    },
+    /// Sample the TRNG for hours, flagging health failures and drift
+    SoakRng {
+        /// How long to run
+        #[arg(long, default_value_t = 24.0, value_parser = parse_hours)]
+        hours: f64,
+        /// Sliding window the health battery runs over, in bytes
+        #[arg(long, default_value_t = 65536)]
+        window: usize,
+        /// Fresh bytes drawn per step
+        #[arg(long, default_value_t = 8192)]
+        step: usize,
+        /// Pause between steps in milliseconds
+        #[arg(long, default_value_t = 1000)]
+        interval_ms: u64,
+        /// Tests that must pass: monobit, runs, entropy, chi2, nist, longest_run, block_frequency, autocorrelation [default: the policy's]
+        #[arg(long, value_delimiter = ',')]
+        tests: Option<Vec<HealthTest>>,
+        /// Append one JSON record per step to FILE
+        #[arg(long)]
+        out: Option<PathBuf>,
     },
     /// Run TRNG health checks
-    HealthCheck,
+    HealthCheck {
+        /// Print results as JSON
+        #[arg(long)]
+        json: bool,
+        /// Number of bytes to sample
+        #[arg(long, default_value_t = 65536)]
+        sample_size: usize,
+        /// Tests that must pass: monobit, runs, entropy, chi2, nist, longest_run, block_frequency, autocorrelation [default: the policy's]
+        #[arg(long, value_delimiter = ',')]
+        tests: Option<Vec<HealthTest>>,
+        /// Also run the tests on each source's raw samples and compare them with the conditioned output
+        #[arg(long)]
+        compare_sources: bool,
+    },
+}
+
+#[derive(Subcommand)]
+enum KeysCommand {
+    /// Generate a new master seed for deriving node keys
+    GenerateSeed {
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Split a key file into shares, printed one per line
+    Split {
+        /// Key file to split
+        #[arg(long = "in")]
+        input: PathBuf,
+        /// Shares needed to recover the key
+        #[arg(long)]
+        threshold: u8,
+        /// Shares to produce
+        #[arg(long)]
+        shares: u8,
+    },
+    /// Recover a key from shares
+    Combine {
+        /// Shares as printed by `keys split`
+        #[arg(required = true)]
+        shares: Vec<trng::sss::Share>,
+        /// Write the key to FILE instead of printing it as hex
+        #[arg(long)]
+        out: Option<PathBuf>,
+    },
+    /// Seal a seed or key file to this machine's TPM (needs the tpm feature)
+    Seal {
+        #[arg(long = "in")]
+        input: PathBuf,
+        #[arg(long)]
+        out: PathBuf,
+    },
+    /// Recover a sealed file's contents, on the machine that sealed it
+    Unseal {
+        #[arg(long = "in")]
+        input: PathBuf,
+        #[arg(long)]
+        out: PathBuf,
+    },
+}
+
+#[derive(Clone, Copy, ValueEnum)]
+enum OutputFormat {
+    Hex,
+    Base64,
+    Binary,
+    Uuid,
+    Passphrase,
+}
+
+// Exports of a megabyte or more are generated on all cores
+fn random_bytes(trng: &Trng, len: usize) -> Vec<u8> {
+    if len >= trng::PARALLEL_CHUNK {
+        trng.rand_bytes_parallel(len)
+    } else {
+        trng.rand_bytes(len)
+    }
+}
+
+fn render_value(trng: &Trng, format: OutputFormat, len: usize, words: usize) -> Vec<u8> {
+    let text = match format {
+        OutputFormat::Binary => return random_bytes(trng, len),
+        OutputFormat::Hex => hex::encode(random_bytes(trng, len)),
+        OutputFormat::Base64 => base64::engine::general_purpose::STANDARD.encode(random_bytes(trng, len)),
+        OutputFormat::Uuid => trng.uuid_v4(),
+        OutputFormat::Passphrase => trng.passphrase(words, "-"),
+    };
+    format!("{}\n", text).into_bytes()
+}
+
+async fn run_keys(action: KeysCommand) {
+    let result = match action {
+        KeysCommand::GenerateSeed { out } => {
+            let trng = Trng::new();
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            MasterSeed::generate(&trng)
+                .save(&out)
+                .map_err(|e| format!("cannot write {}: {}", out.display(), e))
+        }
+        KeysCommand::Split { input, threshold, shares } => {
+            let trng = Trng::new();
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            std::fs::read(&input)
+                .map_err(|e| format!("cannot read {}: {}", input.display(), e))
+                .and_then(|key| trng::sss::split(&trng, &key, threshold, shares).map_err(|e| e.to_string()))
+                .map(|shares| {
+                    for share in shares {
+                        println!("{}", share);
+                    }
+                })
+        }
+        KeysCommand::Combine { shares, out } => {
+            trng::sss::combine(&shares)
+                .map_err(|e| e.to_string())
+                .and_then(|key| match out {
+                    Some(path) => std::fs::write(&path, key)
+                        .map_err(|e| format!("cannot write {}: {}", path.display(), e)),
+                    None => {
+                        println!("{}", hex::encode(key));
+                        Ok(())
+                    }
+                })
+        }
+        KeysCommand::Seal { input, out } => sealing::seal_file(&input, &out),
+        KeysCommand::Unseal { input, out } => sealing::unseal_file(&input, &out),
+    };
+
+    if let Err(e) = result {
+        eprintln!("keys: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run_export(data_dir: PathBuf, from: u64, to: Option<u64>, out: PathBuf) -> Result<(), String> {
+    let snapshot = FileStore::in_dir(&data_dir).load().await.map_err(|e| e.to_string())?;
+    let entries = archive::entries_from_snapshot(snapshot, from, to);
+    let Some(last) = entries.last() else {
+        return Err(format!("no stored blocks from height {}", from));
+    };
+    let summary = format!("Exported heights {}..={} to {}", from, last.block.height, out.display());
+
+    let file = std::fs::File::create(&out).map_err(|e| format!("cannot create {}: {}", out.display(), e))?;
+    archive::write_archive(std::io::BufWriter::new(file), &entries).map_err(|e| e.to_string())?;
+    println!("{}", summary);
+    Ok(())
+}
+
+// The archive must start at genesis for an empty store, or continue the
+// stored chain exactly
+async fn run_import(data_dir: PathBuf, input: PathBuf) -> Result<(), String> {
+    let file = std::fs::File::open(&input).map_err(|e| format!("cannot open {}: {}", input.display(), e))?;
+    let (header, entries) = archive::read_archive(std::io::BufReader::new(file)).map_err(|e| e.to_string())?;
+
+    std::fs::create_dir_all(&data_dir).map_err(|e| format!("cannot create {}: {}", data_dir.display(), e))?;
+    let store = FileStore::in_dir(&data_dir);
+    let stored = store.load().await.map_err(|e| e.to_string())?;
+    let next_height = stored.blocks.last().map(|b| b.height + 1).unwrap_or(0);
+    let first: &ArchiveEntry = &entries[0];
+    if header.from != next_height || first.block.parent_id != stored.head {
+        return Err(format!(
+            "archive starts at height {}, but the store continues at height {}",
+            header.from, next_height
+        ));
+    }
+
+    store.commit(archive::into_batch(entries)).await.map_err(|e| e.to_string())?;
+    println!("Imported heights {}..={} into {}", header.from, header.to, store.path().display());
+    Ok(())
+}
+
+// A positive, finite number of hours that fits a Duration
+fn parse_hours(s: &str) -> Result<f64, String> {
+    let invalid = || format!("expected a positive number of hours, got {}", s);
+    let hours: f64 = s.parse().map_err(|_| invalid())?;
+    if hours <= 0.0 || std::time::Duration::try_from_secs_f64(hours * 3600.0).is_err() {
+        return Err(invalid());
+    }
+    Ok(hours)
+}
+
+fn parse_memory_budget(s: &str) -> Result<(String, usize), String> {
+    let (name, size) = s.split_once('=').ok_or("expected NAME=SIZE, e.g. mempool=64MiB")?;
+    Ok((name.to_string(), consensus::memory::parse_size(size)?))
+}
+
+fn read_json<T: serde::de::DeserializeOwned>(path: &PathBuf, what: &str) -> Result<T, String> {
+    std::fs::read_to_string(path)
+        .map_err(|e| e.to_string())
+        .and_then(|text| serde_json::from_str(&text).map_err(|e| e.to_string()))
+        .map_err(|e| format!("Cannot load {} {}: {}", what, path.display(), e))
+}
+
+// The default when no file is given
+fn read_optional<T: serde::de::DeserializeOwned + Default>(path: &Option<PathBuf>, what: &str) -> Result<T, String> {
+    path.as_ref().map_or_else(|| Ok(T::default()), |path| read_json(path, what))
+}
+
+fn load_json<T: serde::de::DeserializeOwned>(path: &PathBuf, what: &str) -> T {
+    read_json(path, what).unwrap_or_else(|e| {
+        eprintln!("{}", e);
+        std::process::exit(1);
+    })
+}
+
+fn load_seed(path: Option<&PathBuf>) -> Option<MasterSeed> {
+    let path = path?;
+    match sealing::read_secret(path).and_then(|text| MasterSeed::from_hex(&text).map_err(|e| e.to_string())) {
+        Ok(seed) => Some(seed),
+        Err(e) => {
+            eprintln!("Cannot load seed file {}: {}", path.display(), e);
+            std::process::exit(1);
+        }
+    }
+}
+
+fn local_signer(key_file: &Path, guard_file: &Path) -> Result<Arc<dyn Signer>, String> {
+    let text = sealing::read_secret(key_file)?;
+    let key = LocalSigner::key_from_hex(&text).ok_or_else(|| format!("{} is not a hex ed25519 key", key_file.display()))?;
+    Ok(Arc::new(LocalSigner::with_guard_file(key, guard_file).map_err(|e| e.to_string())?))
+}
+
+// Also prints the KMS round trip's latency every `interval`
+#[cfg(any(feature = "aws-kms", feature = "gcp-kms"))]
+fn kms_signer(spec: &str, guard_file: &Path, interval: std::time::Duration) -> Result<Arc<dyn Signer>, String> {
+    let key: consensus::KmsKey = spec.parse()?;
+    let signer = consensus::KmsSigner::connect(key)
+        .and_then(|signer| signer.with_guard_file(guard_file))
+        .map_err(|e| e.to_string())?;
+    println!("Signing with KMS key {}, public key {}", signer.key(), hex::encode(signer.public_key().as_bytes()));
+    let signer = Arc::new(signer);
+    let reported = Arc::downgrade(&signer);
+    std::thread::spawn(move || loop {
+        std::thread::sleep(interval);
+        let Some(signer) = reported.upgrade() else { break };
+        println!("{}", serde_json::to_string(&signer.latency()).expect("latency serializes"));
+    });
+    Ok(signer)
+}
+
+#[cfg(not(any(feature = "aws-kms", feature = "gcp-kms")))]
+fn kms_signer(spec: &str, _guard_file: &Path, _interval: std::time::Duration) -> Result<Arc<dyn Signer>, String> {
+    Err(format!("cannot use KMS key {}: this build has no KMS support (enable the aws-kms or gcp-kms feature)", spec))
 }
 
 #[tokio::main]
@@ -31,44 +850,305 @@ async fn main() {
 
     match cli.command {
         Some(Commands::Server) => {
-            println!("Starting mini-consensus node on port {}", cli.port);
-            start_server(cli.port).await;
+            println!("Starting mini-consensus node");
+            cli.install_crash_bundle();
+            if let Err(e) = cli.server().serve().await {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
         }
-        Some(Commands::Rng { len }) => {
-            let trng = trng::Trng::new();
+        Some(Commands::Rng { len, format, out, count, words }) => {
+            let trng = Trng::new();
             tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
             
-            let random_bytes = trng.rand_bytes(len);
-            println!("{}", hex::encode(random_bytes));
+            let output: Vec<u8> = (0..count)
+                .flat_map(|_| render_value(&trng, format, len, words))
+                .collect();
+
+            let written = match out {
+                Some(path) => std::fs::write(&path, &output),
+                None => std::io::stdout().write_all(&output),
+            };
+            if let Err(e) = written {
+                eprintln!("Failed to write random output: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Secret { length, charset, entropy_min }) => {
+            let trng = Trng::new();
+            tokio::time::sleep(tokio::time::Duration::from_millis(100)).await;
+
+            let policy = SecretPolicy {
+                length,
+                charset,
+                min_entropy_bits: entropy_min,
+            };
+            match trng.generate_secret(&policy) {
+                Ok(secret) => {
+                    println!("{}", secret.value);
+                    eprintln!("Entropy: {:.1} bits", secret.entropy_bits);
+                }
+                Err(e) => {
+                    eprintln!("Cannot generate secret: {}", e);
+                    std::process::exit(1);
+                }
+            }
+        }
+        Some(Commands::Keys { action }) => run_keys(action).await,
+        Some(Commands::Signer { listen, key_file, kms, latency_interval, auth_key_file, guard_file }) => {
+            let signer = match (key_file, kms) {
+                (Some(key_file), _) => local_signer(&key_file, &guard_file),
+                (None, kms) => kms_signer(kms.as_deref().unwrap_or_default(), &guard_file, latency_interval),
+            }
+            .unwrap_or_else(|e| {
+                eprintln!("Cannot load signer: {}", e);
+                std::process::exit(1);
+            });
+            let auth_key = std::fs::read_to_string(&auth_key_file).unwrap_or_else(|e| {
+                eprintln!("Cannot read {}: {}", auth_key_file.display(), e);
+                std::process::exit(1);
+            });
+            let listener = std::net::TcpListener::bind(listen).unwrap_or_else(|e| {
+                eprintln!("Cannot listen on {}: {}", listen, e);
+                std::process::exit(1);
+            });
+
+            println!("Remote signer listening on {}", listen);
+            let server = RemoteSignerServer::new(signer, auth_key.trim().as_bytes().to_vec());
+            tokio::task::spawn_blocking(move || server.serve(listener)).await.unwrap();
+        }
+        Some(Commands::DebugBundle { ref url, ref out }) => {
+            let url = url.clone().unwrap_or_else(|| format!("http://127.0.0.1:{}", cli.port));
+            let mut debug = bundle::Bundle::default();
+            debug.add_json("config.json", &cli.bundle_config());
+            debug.add_json("storage.json", &bundle::storage_stats(cli.data_dir.as_deref()));
+            if let Some(path) = &cli.log_file {
+                debug.add("node.log", bundle::log_tail(path));
+            }
+            bundle::add_live(&mut debug, &url).await;
+            if let Err(e) = debug.write(out) {
+                eprintln!("Cannot write {}: {}", out.display(), e);
+                std::process::exit(1);
+            }
+            println!("Debug bundle written to {}", out.display());
         }
-        Some(Commands::HealthCheck) => {
-            let trng = trng::Trng::new();
+        Some(Commands::Loadtest { url, tps, duration, validators, report, out }) => {
+            let test = LoadTest { url, tps, duration, validators };
+            let rendered = match test.run().await {
+                Ok(results) => results.render(report),
+                Err(e) => {
+                    eprintln!("Load test stopped: {}", e);
+                    std::process::exit(1);
+                }
+            };
+
+            let written = match out {
+                Some(path) => std::fs::write(&path, rendered),
+                None => std::io::stdout().write_all(rendered.as_bytes()),
+            };
+            if let Err(e) = written {
+                eprintln!("Failed to write load test report: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Export { data_dir, from, to, out }) => {
+            if let Err(e) = run_export(data_dir, from, to, out).await {
+                eprintln!("export: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Import { data_dir, input }) => {
+            if let Err(e) = run_import(data_dir, input).await {
+                eprintln!("import: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::Rngd { rate, chunk, credit_bits, health_interval, stats_interval }) => {
+            let trng = Trng::new().with_health_policy(cli.runtime().health_policy);
+            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+            let daemon = rngd::Rngd { rate, chunk, credit_bits, health_interval, stats_interval };
+            if let Err(e) = daemon.run(&trng).await {
+                eprintln!("rngd: {}", e);
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::SoakRng { hours, window, step, interval_ms, tests, out }) => {
+            let trng = Trng::new();
+            tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
+
+            let mut series = out.map(|path| {
+                std::fs::OpenOptions::new().create(true).append(true).open(&path).unwrap_or_else(|e| {
+                    eprintln!("Cannot open {}: {}", path.display(), e);
+                    std::process::exit(1);
+                })
+            });
+
+            let deadline = std::time::Instant::now() + std::time::Duration::from_secs_f64(hours * 3600.0);
+            let policy = match &cli.health_policy {
+                Some(path) => load_json(path, "health policy"),
+                None => HealthPolicy::for_sample_size(window),
+            };
+            let policy = match tests {
+                Some(tests) => policy.with_tests(tests),
+                None => policy,
+            };
+            let mut monitor = DriftMonitor::new(window, step);
+            let mut ticker = tokio::time::interval(tokio::time::Duration::from_millis(interval_ms.max(1)));
+            let (mut failures, mut drifts, mut steps) = (0u64, 0u64, 0u64);
+
+            while std::time::Instant::now() < deadline {
+                ticker.tick().await;
+                let sample = monitor.observe(&trng);
+                let healthy = policy.is_healthy(&sample.health);
+                steps += 1;
+
+                if !healthy {
+                    failures += 1;
+                    eprintln!("step {}: health battery failed: {:?}", sample.step, sample.health);
+                }
+                if let Some(drift) = sample.drift {
+                    drifts += 1;
+                    eprintln!("step {}: bit bias drifting {:?} (z = {:.3})", sample.step, drift, sample.bias_z);
+                }
+                if let Some(file) = series.as_mut() {
+                    let timestamp = std::time::SystemTime::now()
+                        .duration_since(std::time::UNIX_EPOCH)
+                        .map(|d| d.as_secs())
+                        .unwrap_or(0);
+                    let mut record = serde_json::to_value(&sample).unwrap();
+                    record["timestamp"] = timestamp.into();
+                    record["healthy"] = healthy.into();
+                    if let Err(e) = writeln!(file, "{}", record) {
+                        eprintln!("Failed to write soak record: {}", e);
+                        std::process::exit(1);
+                    }
+                }
+            }
+
+            println!("Soak test: {} steps, {} health failures, {} drift alarms", steps, failures, drifts);
+            if failures > 0 || drifts > 0 {
+                std::process::exit(1);
+            }
+        }
+        Some(Commands::HealthCheck { json, sample_size, tests, compare_sources }) => {
+            let trng = Trng::new();
             tokio::time::sleep(tokio::time::Duration::from_millis(500)).await;
             
-            let health = trng.health_check(65536); // 64KB sample
-            
-            println!("TRNG Health Check Results ({} bytes sample):", health.sample_size);
-            println!("=============================================");
-            println!("Monobit Test Deviation: {:.6} (should be < 0.01)", health.monobit_deviation);
-            println!("Runs Test Deviation: {:.6} (should be < 0.1)", health.runs_deviation);
-            println!("Shannon Entropy: {:.6} bits/byte (should be > 7.5)", health.shannon_entropy);
-            println!("Overall Healthy: {}", health.is_healthy());
-            
-            // Negative control demonstration
-            println!("\nNegative Control (Constant Pattern):");
-            println!("====================================");
-            let constant_data = vec![0x55u8; 8192];
-            let monobit_dev = trng.monobit_test(&constant_data);
-            let runs_dev = trng.runs_test(&constant_data);
-            let entropy = trng.approximate_entropy(&constant_data);
-            println!("Monobit Deviation: {:.6}", monobit_dev);
-            println!("Runs Deviation: {:.6}", runs_dev);
-            println!("Shannon Entropy: {:.6}", entropy);
+            let policy = match &cli.health_policy {
+                Some(path) => load_json(path, "health policy"),
+                None => HealthPolicy::for_sample_size(sample_size),
+            };
+            let policy = match tests {
+                Some(tests) => policy.with_tests(tests),
+                None => policy,
+            };
+            if compare_sources {
+                let report = trng.compare_sources(sample_size, &policy).await;
+                if json {
+                    println!("{}", serde_json::to_string(&report).unwrap());
+                } else {
+                    print_comparison(&report);
+                }
+                // Raw sources are expected to fail; only the output counts
+                if !report.rows.last().is_some_and(|row| row.healthy) {
+                    std::process::exit(1);
+                }
+                return;
+            }
+            let health = trng.health_check(sample_size);
+            let healthy = policy.is_healthy(&health);
+
+            if json {
+                let results: serde_json::Map<String, serde_json::Value> = policy
+                    .tests
+                    .iter()
+                    .map(|&test| {
+                        (test.to_string(), serde_json::json!({
+                            "value": health.value(test),
+                            "threshold": policy.threshold(test),
+                            "passed": policy.passes(&health, test),
+                        }))
+                    })
+                    .collect();
+                println!("{}", serde_json::json!({
+                    "healthy": healthy,
+                    "sample_size": health.sample_size,
+                    "tests": results,
+                }));
+            } else {
+                println!("TRNG Health Check Results ({} bytes sample):", health.sample_size);
+                println!("=============================================");
+                for &test in &policy.tests {
+                    let line = match test {
+                        HealthTest::Monobit => format!("Monobit Test Deviation: {:.6}", health.monobit_deviation),
+                        HealthTest::Runs => format!("Runs Test Deviation: {:.6}", health.runs_deviation),
+                        HealthTest::Entropy => format!("Shannon Entropy: {:.6} bits/byte", health.shannon_entropy),
+                        HealthTest::Chi2 => format!("Chi-Square Statistic: {:.3}", health.chi_square),
+                        HealthTest::Nist => format!("NIST Frequency p-value: {:.6}", health.nist_frequency_p),
+                        HealthTest::LongestRun => format!("Longest Run of Ones p-value: {:.6}", health.longest_run_p),
+                        HealthTest::BlockFrequency => format!("Block Frequency p-value: {:.6}", health.block_frequency_p),
+                        HealthTest::Autocorrelation => format!(
+                            "Autocorrelation |z|: {:.3} at lag {}",
+                            health.autocorrelation_z, health.autocorrelation_lag
+                        ),
+                    };
+                    println!("{} (should be {})", line, policy.describe(test));
+                }
+                println!("Overall Healthy: {}", healthy);
+                
+                // Negative control demonstration
+                println!("\nNegative Control (Constant Pattern):");
+                println!("====================================");
+                let constant_data = vec![0x55u8; 8192];
+                let monobit_dev = trng.monobit_test(&constant_data);
+                let runs_dev = trng.runs_test(&constant_data);
+                let entropy = trng.approximate_entropy(&constant_data);
+                println!("Monobit Deviation: {:.6}", monobit_dev);
+                println!("Runs Deviation: {:.6}", runs_dev);
+                println!("Shannon Entropy: {:.6}", entropy);
+            }
+
+            if !healthy {
+                std::process::exit(1);
+            }
         }
         None => {
             // Default to server mode
-            println!("Starting mini-consensus node on port {}", cli.port);
-            start_server(cli.port).await;
+            println!("Starting mini-consensus node");
+            cli.install_crash_bundle();
+            if let Err(e) = cli.server().serve().await {
+                eprintln!("{}", e);
+                std::process::exit(1);
+            }
         }
     }
-}
\ No newline at end of file
+}
+
+// `health-check --compare-sources` as a table, one row per raw source and
+// the conditioned output last
+fn print_comparison(report: &SourceComparison) {
+    let tests = |tests: &[HealthTest]| match tests {
+        [] => "-".to_string(),
+        tests => tests.iter().map(|test| test.to_string()).collect::<Vec<_>>().join(","),
+    };
+    println!("Raw sources vs conditioned output ({} bytes each):", report.sample_size);
+    println!(
+        "{:<12} {:<8} {:>12} {:>8} {:>8} {:<32} Masked by conditioning",
+        "Output", "Healthy", "Min-entropy", "Claimed", "Shannon", "Failed"
+    );
+    for row in &report.rows {
+        let claimed = row.claimed_min_entropy.map_or("-".to_string(), |bits| format!("{:.3}", bits));
+        println!(
+            "{:<12} {:<8} {:>12.3} {:>8} {:>8.3} {:<32} {}",
+            row.output,
+            if row.healthy { "yes" } else { "no" },
+            row.min_entropy,
+            claimed,
+            row.health.shannon_entropy,
+            tests(&row.failed),
+            tests(&row.masked),
+        );
+    }
+    println!("Min-entropy, claimed and Shannon are in bits per byte");
+}
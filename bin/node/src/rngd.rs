@@ -0,0 +1,129 @@
+// `node rngd`: feeds conditioned TRNG output into the kernel's entropy pool
+// through the RNDADDENTROPY ioctl on /dev/random, like rngd does for
+// hardware generators, so a host with poor entropy gets a userspace source.
+// Crediting entropy needs CAP_SYS_ADMIN. Part of the pool comes from the
+// kernel's own getrandom, so by default each byte is credited with less than
+// its eight bits. Feeding pauses while the generator fails its health checks.
+use serde::Serialize;
+use std::os::fd::AsRawFd;
+use std::time::{Duration, Instant};
+use trng::{HealthPolicy, Trng};
+
+// _IOW('R', 0x03, int[2]); the direction bits sit higher on these targets
+#[cfg(any(target_arch = "powerpc", target_arch = "powerpc64", target_arch = "mips", target_arch = "mips64", target_arch = "sparc64"))]
+const RNDADDENTROPY: u64 = 0x8008_5203;
+#[cfg(not(any(target_arch = "powerpc", target_arch = "powerpc64", target_arch = "mips", target_arch = "mips64", target_arch = "sparc64")))]
+const RNDADDENTROPY: u64 = 0x4008_5203;
+
+const ENTROPY_AVAIL: &str = "/proc/sys/kernel/random/entropy_avail";
+
+pub struct Rngd {
+    // Bytes written per second
+    pub rate: usize,
+    // Bytes per ioctl
+    pub chunk: usize,
+    // Entropy credited per byte, 0 to 8 bits
+    pub credit_bits: f64,
+    pub health_interval: Duration,
+    pub stats_interval: Duration,
+}
+
+#[derive(Debug, Default, Serialize)]
+pub struct RngdStats {
+    pub bytes_written: u64,
+    pub bits_credited: u64,
+    pub writes: u64,
+    pub failed_writes: u64,
+    // Writes skipped while the generator was unhealthy
+    pub skipped_unhealthy: u64,
+    // The kernel's estimate at the last report, where it exposes one
+    pub entropy_avail: Option<u64>,
+}
+
+impl Rngd {
+    // Runs until interrupted, printing stats every `stats_interval` and once
+    // more on exit
+    pub async fn run(&self, trng: &Trng) -> Result<RngdStats, String> {
+        if !(0.0..=8.0).contains(&self.credit_bits) {
+            return Err(format!("credit of {} bits per byte is outside 0..=8", self.credit_bits));
+        }
+        let chunk = self.chunk.clamp(1, self.rate.max(1));
+        let random = std::fs::OpenOptions::new()
+            .write(true)
+            .open("/dev/random")
+            .map_err(|e| format!("cannot open /dev/random: {}", e))?;
+        let period = Duration::from_secs_f64(chunk as f64 / self.rate.max(1) as f64);
+        let policy = trng.health_policy();
+        let mut stats = RngdStats::default();
+        let mut healthy = check_health(trng, &policy);
+        let mut checked = Instant::now();
+        let mut reported = Instant::now();
+        let mut interval = tokio::time::interval(period);
+        let interrupted = tokio::signal::ctrl_c();
+        tokio::pin!(interrupted);
+
+        loop {
+            tokio::select! {
+                _ = &mut interrupted => break,
+                _ = interval.tick() => {}
+            }
+            if checked.elapsed() >= self.health_interval {
+                let was_healthy = healthy;
+                healthy = check_health(trng, &policy);
+                if was_healthy != healthy {
+                    eprintln!("{}", if healthy { "TRNG healthy again, resuming" } else { "TRNG unhealthy, pausing feed" });
+                }
+                checked = Instant::now();
+            }
+            if !healthy {
+                stats.skipped_unhealthy += 1;
+            } else {
+                let bits = (chunk as f64 * self.credit_bits) as u64;
+                match add_entropy(random.as_raw_fd(), &trng.rand_bytes(chunk), bits as i32) {
+                    Ok(()) => {
+                        stats.writes += 1;
+                        stats.bytes_written += chunk as u64;
+                        stats.bits_credited += bits;
+                    }
+                    Err(e) => {
+                        stats.failed_writes += 1;
+                        if stats.failed_writes == 1 {
+                            eprintln!("RNDADDENTROPY failed: {} (needs CAP_SYS_ADMIN)", e);
+                        }
+                    }
+                }
+            }
+            if reported.elapsed() >= self.stats_interval {
+                report(&mut stats);
+                reported = Instant::now();
+            }
+        }
+        report(&mut stats);
+        Ok(stats)
+    }
+}
+
+fn check_health(trng: &Trng, policy: &HealthPolicy) -> bool {
+    policy.is_healthy(&trng.health_check(policy.sample_size))
+}
+
+fn report(stats: &mut RngdStats) {
+    stats.entropy_avail = std::fs::read_to_string(ENTROPY_AVAIL).ok().and_then(|s| s.trim().parse().ok());
+    println!("{}", serde_json::to_string(stats).expect("stats serialize"));
+}
+
+// struct rand_pool_info { int entropy_count; int buf_size; __u32 buf[]; }
+fn add_entropy(fd: i32, bytes: &[u8], bits: i32) -> std::io::Result<()> {
+    let mut info = Vec::with_capacity(8 + bytes.len());
+    info.extend_from_slice(&bits.to_ne_bytes());
+    info.extend_from_slice(&(bytes.len() as i32).to_ne_bytes());
+    info.extend_from_slice(bytes);
+    // SAFETY: `info` is a complete rand_pool_info whose buf_size matches
+    // the bytes that follow the header, and it outlives the call
+    let result = unsafe { libc::ioctl(fd, RNDADDENTROPY as _, info.as_ptr()) };
+    if result < 0 {
+        Err(std::io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
@@ -0,0 +1,65 @@
+// Secret files sealed to this machine's TPM: `keys seal` replaces a seed or
+// signer key file's contents with a blob only the same TPM can unseal, and
+// everywhere the node reads those files it unseals them transparently.
+// Needs the `tpm` feature; without it sealed files are refused by name.
+use std::io::Write;
+use std::path::Path;
+
+// The first line of every sealed file
+const SEALED_PREFIX: &str = "tpm2-sealed";
+
+// A secret file's contents, unsealed first if needed
+pub fn read_secret(path: &Path) -> Result<String, String> {
+    let text = std::fs::read_to_string(path).map_err(|e| format!("cannot read {}: {}", path.display(), e))?;
+    if !text.starts_with(SEALED_PREFIX) {
+        return Ok(text);
+    }
+    let secret = unseal(&text).map_err(|e| format!("cannot unseal {}: {}", path.display(), e))?;
+    String::from_utf8(secret).map_err(|_| format!("{} unseals to binary data", path.display()))
+}
+
+pub fn seal_file(input: &Path, out: &Path) -> Result<(), String> {
+    let secret = std::fs::read(input).map_err(|e| format!("cannot read {}: {}", input.display(), e))?;
+    let sealed = seal(&secret)?;
+    write_private(out, sealed.as_bytes())
+}
+
+pub fn unseal_file(input: &Path, out: &Path) -> Result<(), String> {
+    let text = std::fs::read_to_string(input).map_err(|e| format!("cannot read {}: {}", input.display(), e))?;
+    write_private(out, &unseal(&text)?)
+}
+
+#[cfg(feature = "tpm")]
+fn seal(secret: &[u8]) -> Result<String, String> {
+    let mut tpm = trng::tpm::Tpm::open().map_err(|e| e.to_string())?;
+    Ok(tpm.seal(secret).map_err(|e| e.to_string())?.encode())
+}
+
+#[cfg(feature = "tpm")]
+fn unseal(text: &str) -> Result<Vec<u8>, String> {
+    let blob = trng::tpm::SealedBlob::decode(text).map_err(|e| e.to_string())?;
+    let mut tpm = trng::tpm::Tpm::open().map_err(|e| e.to_string())?;
+    tpm.unseal(&blob).map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "tpm"))]
+fn seal(_secret: &[u8]) -> Result<String, String> {
+    Err("this build has no TPM support (enable the tpm feature)".to_string())
+}
+
+#[cfg(not(feature = "tpm"))]
+fn unseal(_text: &str) -> Result<Vec<u8>, String> {
+    Err("the file is sealed to a TPM, but this build has no TPM support (enable the tpm feature)".to_string())
+}
+
+fn write_private(path: &Path, contents: &[u8]) -> Result<(), String> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+
+    options
+        .open(path)
+        .and_then(|mut file| file.write_all(contents))
+        .map_err(|e| format!("cannot write {}: {}", path.display(), e))
+}
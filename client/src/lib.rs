@@ -0,0 +1,444 @@
+// Typed async client for the node's HTTP API.
+pub mod mock;
+
+pub use mock::MockCluster;
+
+use consensus::{BlockId, Checkpoint, Round, SparseMerkleProof, Transaction, ValidatorId, VotePhase};
+use reqwest::{Method, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::mpsc;
+
+#[derive(Debug)]
+pub enum ClientError {
+    Http(reqwest::Error),
+    Status { status: u16, body: String },
+    Decode(String),
+    InvalidProof(String),
+}
+
+impl fmt::Display for ClientError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ClientError::Http(e) => write!(f, "request failed: {}", e),
+            ClientError::Status { status, body } => write!(f, "server returned {}: {}", status, body),
+            ClientError::Decode(e) => write!(f, "cannot decode response: {}", e),
+            ClientError::InvalidProof(e) => write!(f, "invalid finality proof: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for ClientError {}
+
+impl From<reqwest::Error> for ClientError {
+    fn from(e: reqwest::Error) -> Self {
+        ClientError::Http(e)
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct RetryPolicy {
+    pub max_retries: u32,
+    pub initial_backoff: Duration,
+    pub max_backoff: Duration,
+}
+
+impl Default for RetryPolicy {
+    fn default() -> Self {
+        Self {
+            max_retries: 3,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_secs(2),
+        }
+    }
+}
+
+impl RetryPolicy {
+    pub fn none() -> Self {
+        Self { max_retries: 0, ..Self::default() }
+    }
+
+    // Exponential: initial, 2x, 4x, ... capped at max_backoff
+    pub fn backoff(&self, attempt: u32) -> Duration {
+        self.initial_backoff
+            .saturating_mul(1u32 << attempt.min(16))
+            .min(self.max_backoff)
+    }
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ProposeResponse {
+    pub proposal_id: BlockId,
+    // Votes for the proposal name its height and round
+    pub height: u64,
+    pub round: Round,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct VoteResponse {
+    // True when this vote completed the quorum
+    pub success: bool,
+    pub finalized: bool,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TxResponse {
+    pub accepted: bool,
+    pub tx_hash: Option<String>,
+    pub error: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct Status {
+    pub active: bool,
+    pub finalized_block: Option<BlockId>,
+    pub finalized_height: Option<u64>,
+    pub validators: Vec<ValidatorId>,
+    pub metrics: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct ValidatorKey {
+    pub validator_id: ValidatorId,
+    pub public_key: String, // hex
+    // None for genesis keys
+    pub since_height: Option<u64>,
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct StateProof {
+    pub key: String,
+    pub height: Option<u64>,
+    pub state_root: String, // hex
+    // JSON text; None when the proof shows the key is absent
+    pub value: Option<String>,
+    pub proof: SparseMerkleProof,
+}
+
+#[derive(Deserialize)]
+struct FinalizedResponse {
+    finalized_block: Option<BlockId>,
+}
+
+#[derive(Deserialize)]
+struct RngResponse {
+    random_bytes: String,
+}
+
+#[derive(Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    base_url: String,
+    auth_token: Option<String>,
+    retry: RetryPolicy,
+    // Checkpoint signers accepted as finality proofs; without any, no
+    // checkpoint is accepted
+    trusted_signers: Vec<String>,
+}
+
+impl Client {
+    pub fn new(base_url: impl Into<String>) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            auth_token: None,
+            retry: RetryPolicy::default(),
+            trusted_signers: Vec::new(),
+        }
+    }
+
+    // Sent as a bearer token on every request
+    pub fn with_auth_token(mut self, token: impl Into<String>) -> Self {
+        self.auth_token = Some(token.into());
+        self
+    }
+
+    pub fn with_retry(mut self, retry: RetryPolicy) -> Self {
+        self.retry = retry;
+        self
+    }
+
+    // Hex ed25519 public key of a node whose checkpoints are trusted
+    pub fn with_trusted_signer(mut self, public_key: impl Into<String>) -> Self {
+        self.trusted_signers.push(public_key.into().to_lowercase());
+        self
+    }
+
+    pub async fn status(&self) -> Result<Status, ClientError> {
+        self.get("/status").await
+    }
+
+    // Latest checkpoint, checked as a finality proof: the signature must
+    // verify and the signer must be trusted
+    pub async fn verified_checkpoint(&self) -> Result<Option<Checkpoint>, ClientError> {
+        let checkpoint: Checkpoint = match self.get("/checkpoints/latest").await {
+            Ok(checkpoint) => checkpoint,
+            Err(ClientError::Status { status: 404, .. }) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        self.verify_checkpoint(&checkpoint)?;
+        Ok(Some(checkpoint))
+    }
+
+    pub fn verify_checkpoint(&self, checkpoint: &Checkpoint) -> Result<(), ClientError> {
+        // Anyone can sign a checkpoint; only a trusted key makes it a proof
+        if self.trusted_signers.is_empty() {
+            return Err(ClientError::InvalidProof("no trusted checkpoint signers configured".into()));
+        }
+        if !checkpoint.verify() {
+            return Err(ClientError::InvalidProof("bad checkpoint signature".into()));
+        }
+        if checkpoint.qc.block_id != checkpoint.block_id || checkpoint.qc.height != checkpoint.height {
+            return Err(ClientError::InvalidProof("certificate does not match checkpoint".into()));
+        }
+        if !self.trusted_signers.contains(&checkpoint.signer.to_lowercase()) {
+            return Err(ClientError::InvalidProof(format!("untrusted signer {}", checkpoint.signer)));
+        }
+        Ok(())
+    }
+
+    // The validator's current key from the node's registry, e.g. to pass to
+    // `with_trusted_signer`; None for an unknown validator
+    pub async fn validator_key(&self, validator: ValidatorId) -> Result<Option<ValidatorKey>, ClientError> {
+        match self.get(&format!("/validators/{}/key", validator)).await {
+            Ok(key) => Ok(Some(key)),
+            Err(ClientError::Status { status: 404, .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    // A state value and its Merkle proof, checked against the state root the
+    // node reports. That root is only as trustworthy as the node; compare it
+    // with one from a certified block to rely on the value.
+    pub async fn state_proof(&self, key: &str) -> Result<StateProof, ClientError> {
+        let state: StateProof = self.get(&format!("/state/{}?proof=true", encode_path(key))).await?;
+        let root = hex::decode(&state.state_root)
+            .ok()
+            .and_then(|root| <[u8; 32]>::try_from(root).ok())
+            .ok_or_else(|| ClientError::InvalidProof("malformed state root".into()))?;
+        if state.key != key || !state.proof.verify(&root, key, state.value.as_deref().map(str::as_bytes)) {
+            return Err(ClientError::InvalidProof(format!("state proof for {} does not match the root", key)));
+        }
+        Ok(state)
+    }
+
+    // Proposes a binary payload, sent hex-encoded
+    pub async fn propose_bytes(&self, payload: &[u8]) -> Result<ProposeResponse, ClientError> {
+        let body = serde_json::json!({ "payload": hex::encode(payload), "payload_encoding": "hex" });
+        self.post("/propose", &body).await
+    }
+
+    // Polls the finalized head and yields each new head once. The task
+    // stops when the receiver is dropped.
+    pub fn subscribe_finalized(&self, poll_interval: Duration) -> mpsc::Receiver<BlockId> {
+        let (tx, rx) = mpsc::channel(64);
+        let client = self.clone();
+        tokio::spawn(async move {
+            let mut last = None;
+            let mut ticker = tokio::time::interval(poll_interval);
+            loop {
+                ticker.tick().await;
+                if tx.is_closed() {
+                    return;
+                }
+                let Ok(Some(head)) = client.finalized().await else {
+                    continue;
+                };
+                if last.as_ref() != Some(&head) {
+                    last = Some(head);
+                    if tx.send(head).await.is_err() {
+                        return;
+                    }
+                }
+            }
+        });
+        rx
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, ClientError> {
+        self.send(Method::GET, path, None::<&()>).await
+    }
+
+    async fn post<B: Serialize, T: DeserializeOwned>(&self, path: &str, body: &B) -> Result<T, ClientError> {
+        self.send(Method::POST, path, Some(body)).await
+    }
+
+    // GETs retry on connection errors and 5xx. POSTs only retry when the
+    // request provably had no effect: a refused connection, or a 503 from a
+    // standby node that rejects before touching consensus state.
+    async fn send<B: Serialize, T: DeserializeOwned>(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<&B>,
+    ) -> Result<T, ClientError> {
+        let idempotent = method == Method::GET;
+        let mut attempt = 0;
+        loop {
+            let result = self.request(method.clone(), path, body).send().await;
+            let retryable = match &result {
+                Ok(response) => {
+                    let status = response.status();
+                    status == StatusCode::SERVICE_UNAVAILABLE || (idempotent && status.is_server_error())
+                }
+                Err(e) => e.is_connect() || (idempotent && e.is_timeout()),
+            };
+            if retryable && attempt < self.retry.max_retries {
+                tokio::time::sleep(self.retry.backoff(attempt)).await;
+                attempt += 1;
+                continue;
+            }
+
+            let response = result?;
+            let status = response.status();
+            if !status.is_success() {
+                let body = response.text().await.unwrap_or_default();
+                return Err(ClientError::Status { status: status.as_u16(), body });
+            }
+            return response.json().await.map_err(|e| ClientError::Decode(e.to_string()));
+        }
+    }
+
+    fn request<B: Serialize>(&self, method: Method, path: &str, body: Option<&B>) -> RequestBuilder {
+        let mut request = self.http.request(method, format!("{}{}", self.base_url, path));
+        if let Some(token) = &self.auth_token {
+            request = request.bearer_auth(token);
+        }
+        if let Some(body) = body {
+            request = request.json(body);
+        }
+        request
+    }
+}
+
+// Operations shared by the HTTP client and `MockCluster`, so applications can
+// be written against either.
+pub trait ConsensusClient: Send + Sync {
+    fn propose(&self, payload: &str) -> impl Future<Output = Result<ProposeResponse, ClientError>> + Send;
+
+    // Votes outside the proposal's round fail with status 409
+    fn vote(
+        &self,
+        proposal: &ProposeResponse,
+        validator_id: ValidatorId,
+        phase: VotePhase,
+    ) -> impl Future<Output = Result<VoteResponse, ClientError>> + Send;
+
+    fn vote_combined(
+        &self,
+        proposal: &ProposeResponse,
+        validator_id: ValidatorId,
+    ) -> impl Future<Output = Result<VoteResponse, ClientError>> + Send;
+
+    fn submit_transaction(&self, tx: &Transaction) -> impl Future<Output = Result<TxResponse, ClientError>> + Send;
+
+    fn finalized(&self) -> impl Future<Output = Result<Option<BlockId>, ClientError>> + Send;
+
+    fn get_random(&self, len: usize) -> impl Future<Output = Result<Vec<u8>, ClientError>> + Send;
+}
+
+impl ConsensusClient for Client {
+    async fn propose(&self, payload: &str) -> Result<ProposeResponse, ClientError> {
+        self.post("/propose", &serde_json::json!({ "payload": payload })).await
+    }
+
+    async fn vote(
+        &self,
+        proposal: &ProposeResponse,
+        validator_id: ValidatorId,
+        phase: VotePhase,
+    ) -> Result<VoteResponse, ClientError> {
+        let body = serde_json::json!({
+            "proposal_id": proposal.proposal_id,
+            "height": proposal.height,
+            "round": proposal.round,
+            "validator_id": validator_id,
+            "phase": phase,
+        });
+        self.post("/vote", &body).await
+    }
+
+    async fn vote_combined(&self, proposal: &ProposeResponse, validator_id: ValidatorId) -> Result<VoteResponse, ClientError> {
+        let body = serde_json::json!({
+            "proposal_id": proposal.proposal_id,
+            "height": proposal.height,
+            "round": proposal.round,
+            "validator_id": validator_id,
+        });
+        self.post("/vote/combined", &body).await
+    }
+
+    async fn submit_transaction(&self, tx: &Transaction) -> Result<TxResponse, ClientError> {
+        self.post("/tx", tx).await
+    }
+
+    async fn finalized(&self) -> Result<Option<BlockId>, ClientError> {
+        let response: FinalizedResponse = self.get("/finalized").await?;
+        Ok(response.finalized_block)
+    }
+
+    async fn get_random(&self, len: usize) -> Result<Vec<u8>, ClientError> {
+        let response: RngResponse = self.get(&format!("/rng?len={}", len)).await?;
+        hex::decode(response.random_bytes).map_err(|e| ClientError::Decode(e.to_string()))
+    }
+}
+
+// Percent-encodes all but unreserved characters and `/`, for a key served
+// under a wildcard route
+fn encode_path(path: &str) -> String {
+    path.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => (b as char).to_string(),
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus::{Checkpointer, ConsensusState};
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn test_backoff_is_capped_exponential() {
+        let retry = RetryPolicy {
+            max_retries: 5,
+            initial_backoff: Duration::from_millis(100),
+            max_backoff: Duration::from_millis(500),
+        };
+        let delays: Vec<u128> = (0..5).map(|a| retry.backoff(a).as_millis()).collect();
+        assert_eq!(delays, vec![100, 200, 400, 500, 500]);
+
+        let client = Client::new("http://localhost:8080/").with_trusted_signer("AB");
+        assert_eq!(client.base_url, "http://localhost:8080");
+        assert_eq!(client.trusted_signers, vec!["ab".to_string()]);
+        assert_eq!(encode_path("nonce/a b?#%é"), "nonce/a%20b%3F%23%25%C3%A9");
+    }
+
+    #[test]
+    fn test_checkpoints_need_a_trusted_signer() {
+        let key = SigningKey::from_bytes(&[7; 32]);
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect())
+            .with_checkpointer(Checkpointer::new(1, key.clone()));
+        let id = state.propose(b"a".to_vec()).unwrap();
+        for validator in 0..3 {
+            state.vote_combined(id, 0, Round(0), ValidatorId(validator)).unwrap();
+        }
+        let checkpoint = state.latest_checkpoint().unwrap();
+
+        let client = Client::new("http://localhost:8080");
+        assert!(matches!(client.verify_checkpoint(&checkpoint), Err(ClientError::InvalidProof(_))));
+        let client = client.with_trusted_signer("ab");
+        assert!(matches!(client.verify_checkpoint(&checkpoint), Err(ClientError::InvalidProof(_))));
+        let client = client.with_trusted_signer(hex::encode(key.verifying_key().as_bytes()));
+        client.verify_checkpoint(&checkpoint).unwrap();
+
+        let mut forged = checkpoint.clone();
+        forged.height += 1;
+        assert!(matches!(client.verify_checkpoint(&forged), Err(ClientError::InvalidProof(_))));
+    }
+}
@@ -0,0 +1,150 @@
+// In-process stand-in for a node: the consensus core and a seeded Trng
+// behind the same `ConsensusClient` trait, for fast deterministic
+// integration tests without sockets.
+use crate::{ClientError, ConsensusClient, ProposeResponse, TxResponse, VoteResponse};
+use consensus::{BlockId, ConsensusState, ProposalError, Transaction, ValidatorId, Vote, VoteError, VotePhase};
+use trng::Trng;
+
+#[derive(Clone)]
+pub struct MockCluster {
+    consensus: ConsensusState,
+    trng: Trng,
+}
+
+impl MockCluster {
+    // Validators 0..validators; the same seed yields the same random bytes
+    pub fn new(validators: u32, seed: u64) -> Self {
+        Self {
+            consensus: ConsensusState::new((0..validators).map(ValidatorId).collect()),
+            trng: Trng::from_seed(&seed.to_le_bytes()),
+        }
+    }
+
+    pub fn consensus(&self) -> &ConsensusState {
+        &self.consensus
+    }
+
+    // Proposes `payload` and casts combined votes from every validator
+    // until it finalizes; returns the block id.
+    pub fn finalize(&self, payload: &[u8]) -> Result<BlockId, ProposalError> {
+        let id = self.consensus.propose(payload.to_vec())?;
+        let (height, round) = self.consensus.proposal_round(&id).unwrap_or_default();
+        for &validator in self.validators() {
+            if self.consensus.vote_combined(id, height, round, validator) == Ok(true) {
+                break;
+            }
+        }
+        Ok(id)
+    }
+
+    fn validators(&self) -> &[ValidatorId] {
+        self.consensus.get_validators()
+    }
+
+    fn vote_response(&self, outcome: Result<bool, VoteError>) -> Result<VoteResponse, ClientError> {
+        let success = outcome.map_err(|e| {
+            let status = match e {
+                VoteError::UnknownValidator(_) | VoteError::UnknownProposal(_) => 404,
+                VoteError::Unjustified(_) | VoteError::StateRootMismatch { .. } => 422,
+                _ => 409,
+            };
+            ClientError::Status { status, body: e.to_string() }
+        })?;
+        Ok(VoteResponse {
+            success,
+            finalized: self.consensus.finalize().is_some(),
+        })
+    }
+}
+
+impl ConsensusClient for MockCluster {
+    async fn propose(&self, payload: &str) -> Result<ProposeResponse, ClientError> {
+        // Same status codes the API answers with
+        let proposal_id = self.consensus.propose(payload.as_bytes().to_vec()).map_err(|e| {
+            let status = match e {
+                ProposalError::TooLarge { .. } => 413,
+                ProposalError::TooManyTxs { .. } => 422,
+            };
+            ClientError::Status { status, body: e.to_string() }
+        })?;
+        let (height, round) = self.consensus.proposal_round(&proposal_id).unwrap_or_default();
+        Ok(ProposeResponse { proposal_id, height, round })
+    }
+
+    async fn vote(
+        &self,
+        proposal: &ProposeResponse,
+        validator_id: ValidatorId,
+        phase: VotePhase,
+    ) -> Result<VoteResponse, ClientError> {
+        self.vote_response(self.consensus.vote(Vote {
+            proposal_id: proposal.proposal_id,
+            height: proposal.height,
+            round: proposal.round,
+            validator_id,
+            phase,
+        }))
+    }
+
+    async fn vote_combined(&self, proposal: &ProposeResponse, validator_id: ValidatorId) -> Result<VoteResponse, ClientError> {
+        let outcome = self.consensus.vote_combined(
+            proposal.proposal_id,
+            proposal.height,
+            proposal.round,
+            validator_id,
+        );
+        self.vote_response(outcome)
+    }
+
+    async fn submit_transaction(&self, tx: &Transaction) -> Result<TxResponse, ClientError> {
+        Ok(match self.consensus.submit_transaction(tx.clone()) {
+            Ok(hash) => TxResponse { accepted: true, tx_hash: Some(hash), error: None },
+            Err(e) => TxResponse { accepted: false, tx_hash: None, error: Some(e.to_string()) },
+        })
+    }
+
+    async fn finalized(&self) -> Result<Option<BlockId>, ClientError> {
+        Ok(self.consensus.finalize())
+    }
+
+    async fn get_random(&self, len: usize) -> Result<Vec<u8>, ClientError> {
+        Ok(self.trng.rand_bytes(len))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // Application code written against the trait
+    async fn commit_and_draw<C: ConsensusClient>(client: &C) -> (Option<BlockId>, Vec<u8>) {
+        let proposal = client.propose("app-state").await.unwrap();
+        for validator in 0..3 {
+            client.vote_combined(&proposal, ValidatorId(validator)).await.unwrap();
+        }
+        // The height is decided; a late vote is out of round
+        assert!(matches!(
+            client.vote_combined(&proposal, ValidatorId(3)).await,
+            Err(ClientError::Status { status: 409, .. })
+        ));
+        (client.finalized().await.unwrap(), client.get_random(16).await.unwrap())
+    }
+
+    #[tokio::test]
+    async fn test_mock_cluster_is_deterministic() {
+        let a = MockCluster::new(4, 7);
+        let b = MockCluster::new(4, 7);
+
+        let (head, random) = commit_and_draw(&a).await;
+        assert!(head.is_some());
+        assert_eq!(random, commit_and_draw(&b).await.1);
+        assert_ne!(random, MockCluster::new(4, 8).get_random(16).await.unwrap());
+
+        let tx = Transaction { sender: "app".into(), nonce: 1, data: String::new(), priority: 0 };
+        assert!(a.submit_transaction(&tx).await.unwrap().accepted);
+        assert!(!a.submit_transaction(&tx).await.unwrap().accepted);
+
+        let id = a.finalize(b"direct").unwrap();
+        assert_eq!(a.finalized().await.unwrap(), Some(id));
+    }
+}
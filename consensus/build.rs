@@ -0,0 +1,10 @@
+// Generates the protobuf messages in proto/ with the pure-Rust parser, so
+// building needs no protoc.
+fn main() {
+    protobuf_codegen::Codegen::new()
+        .pure()
+        .include("proto")
+        .input("proto/consensus.proto")
+        .cargo_out_dir("proto")
+        .run_from_script();
+}
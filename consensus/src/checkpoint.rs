@@ -0,0 +1,199 @@
+use crate::{BlockId, Chain, QuorumCertificate, ValidatorId};
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::sync::RwLock;
+
+const CHECKPOINT_DOMAIN: &[u8] = b"mini-consensus/checkpoint/v1";
+
+// Tamper-evidence anchor for external publication: everything needed to
+// check that the node attested to this chain prefix and validator set.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Checkpoint {
+    pub height: u64,
+    pub block_id: BlockId,
+    pub state_hash: String,
+    pub validator_set_hash: String,
+    pub qc: QuorumCertificate,
+    pub signer: String,    // hex ed25519 public key
+    pub signature: String, // hex ed25519 signature
+}
+
+impl Checkpoint {
+    fn signing_bytes(
+        height: u64,
+        block_id: &BlockId,
+        state_hash: &str,
+        validator_set_hash: &str,
+        qc: &QuorumCertificate,
+    ) -> Vec<u8> {
+        let mut bytes = CHECKPOINT_DOMAIN.to_vec();
+        bytes.extend_from_slice(&height.to_le_bytes());
+        bytes.extend_from_slice(block_id.to_hex().as_bytes());
+        bytes.extend_from_slice(state_hash.as_bytes());
+        bytes.extend_from_slice(validator_set_hash.as_bytes());
+        for signer in &qc.signers {
+            bytes.extend_from_slice(&(signer.0 as u64).to_le_bytes());
+        }
+        bytes
+    }
+
+    pub fn verify(&self) -> bool {
+        let Some(key) = hex::decode(&self.signer).ok()
+            .and_then(|b| <[u8; 32]>::try_from(b).ok())
+            .and_then(|b| VerifyingKey::from_bytes(&b).ok()) else {
+            return false;
+        };
+        let Some(signature) = hex::decode(&self.signature).ok()
+            .and_then(|b| Signature::from_slice(&b).ok()) else {
+            return false;
+        };
+
+        let message = Self::signing_bytes(
+            self.height,
+            &self.block_id,
+            &self.state_hash,
+            &self.validator_set_hash,
+            &self.qc,
+        );
+        key.verify(&message, &signature).is_ok()
+    }
+}
+
+pub fn validator_set_hash(validators: &[ValidatorId]) -> String {
+    let mut hasher = blake3::Hasher::new();
+    for validator in validators {
+        hasher.update(&(validator.0 as u64).to_le_bytes());
+    }
+    hasher.finalize().to_hex().to_string()
+}
+
+// Signs a checkpoint every `interval` finalized heights
+pub struct Checkpointer {
+    interval: u64,
+    key: SigningKey,
+    latest: RwLock<Option<Checkpoint>>,
+}
+
+impl Checkpointer {
+    pub fn new(interval: u64, key: SigningKey) -> Self {
+        Self {
+            interval: interval.max(1),
+            key,
+            latest: RwLock::new(None),
+        }
+    }
+
+    pub fn latest(&self) -> Option<Checkpoint> {
+        self.latest.read().unwrap().clone()
+    }
+
+    pub(crate) fn observe(&self, chain: &Chain, validators: &[ValidatorId]) {
+        let Some(head) = chain.finalized_height() else {
+            return;
+        };
+        let height = head - head % self.interval;
+        if self.latest().is_some_and(|c| c.height >= height) {
+            return;
+        }
+
+        let Some(block_id) = chain.finalized_at(height).cloned() else {
+            return;
+        };
+        let Some(qc) = chain.certificate(&block_id).cloned() else {
+            return;
+        };
+        let Some(state_hash) = chain.state_hash(height) else {
+            return;
+        };
+        let validator_set_hash = validator_set_hash(validators);
+
+        let message = Checkpoint::signing_bytes(height, &block_id, &state_hash, &validator_set_hash, &qc);
+        let signature = self.key.sign(&message);
+
+        *self.latest.write().unwrap() = Some(Checkpoint {
+            height,
+            block_id,
+            state_hash,
+            validator_set_hash,
+            qc,
+            signer: hex::encode(self.key.verifying_key().as_bytes()),
+            signature: hex::encode(signature.to_bytes()),
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ConsensusState;
+
+    #[test]
+    fn test_checkpoint_every_k_heights() {
+        let key = SigningKey::from_bytes(&[7u8; 32]);
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect()).with_checkpointer(Checkpointer::new(2, key));
+
+        let mut finalized = Vec::new();
+        for i in 0..4u8 {
+            let id = state.propose(vec![i]).unwrap();
+            let (height, round) = state.proposal_round(&id).unwrap();
+            for validator in 0..3 {
+                state.vote_combined(id, height, round, ValidatorId(validator)).unwrap();
+            }
+            finalized.push(id);
+        }
+
+        // Heights 0..=3 finalized; the latest multiple of 2 is height 2
+        let checkpoint = state.latest_checkpoint().unwrap();
+        assert_eq!(checkpoint.height, 2);
+        assert_eq!(checkpoint.block_id, finalized[2]);
+        assert_eq!(checkpoint.qc.signers, (0..3).map(ValidatorId).collect::<Vec<_>>());
+        assert!(checkpoint.verify());
+
+        let mut tampered = checkpoint.clone();
+        tampered.state_hash = "00".repeat(32);
+        assert!(!tampered.verify());
+    }
+
+    // consensus/vectors/checkpoint.json. `qc_json` is the certificate's JSON
+    // encoding byte for byte; keys, signing bytes and signatures are hex
+    #[derive(Deserialize)]
+    struct CheckpointVectors {
+        secret_key: String,
+        cases: Vec<CheckpointCase>,
+    }
+
+    #[derive(Deserialize)]
+    struct CheckpointCase {
+        validators: Vec<ValidatorId>,
+        validator_set_hash: String,
+        qc_json: String,
+        signing_bytes: String,
+        checkpoint: Checkpoint,
+    }
+
+    #[test]
+    fn test_checkpoints_match_golden_vectors() {
+        let vectors: CheckpointVectors = serde_json::from_str(include_str!("../vectors/checkpoint.json")).unwrap();
+        let key = SigningKey::from_bytes(&hex::decode(&vectors.secret_key).unwrap().try_into().unwrap());
+        for case in vectors.cases {
+            let checkpoint = case.checkpoint;
+            let qc: QuorumCertificate = serde_json::from_str(&case.qc_json).unwrap();
+            assert_eq!(qc, checkpoint.qc);
+            assert_eq!(serde_json::to_string(&qc).unwrap(), case.qc_json);
+            assert_eq!(validator_set_hash(&case.validators), case.validator_set_hash);
+            assert_eq!(checkpoint.validator_set_hash, case.validator_set_hash);
+
+            let bytes = Checkpoint::signing_bytes(
+                checkpoint.height,
+                &checkpoint.block_id,
+                &checkpoint.state_hash,
+                &checkpoint.validator_set_hash,
+                &checkpoint.qc,
+            );
+            assert_eq!(hex::encode(&bytes), case.signing_bytes);
+            assert_eq!(hex::encode(key.verifying_key().as_bytes()), checkpoint.signer);
+            assert_eq!(hex::encode(key.sign(&bytes).to_bytes()), checkpoint.signature);
+            assert!(checkpoint.verify());
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use crate::Bytes;
+use serde::Serialize;
+
+// Payloads smaller than this aren't worth the zstd frame overhead
+pub const COMPRESSION_THRESHOLD: usize = 256;
+const ZSTD_LEVEL: i32 = 3;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StoredPayload {
+    Raw(Bytes),
+    Zstd { data: Bytes, original_len: usize },
+}
+
+impl StoredPayload {
+    pub fn encode(payload: Bytes) -> Self {
+        Self::encode_with_threshold(payload, COMPRESSION_THRESHOLD)
+    }
+
+    pub fn encode_with_threshold(payload: Bytes, threshold: usize) -> Self {
+        if payload.len() < threshold {
+            return StoredPayload::Raw(payload);
+        }
+
+        match zstd::bulk::compress(&payload, ZSTD_LEVEL) {
+            // Incompressible data stays raw
+            Ok(data) if data.len() < payload.len() => StoredPayload::Zstd {
+                data,
+                original_len: payload.len(),
+            },
+            _ => StoredPayload::Raw(payload),
+        }
+    }
+
+    pub fn decode(&self) -> Bytes {
+        match self {
+            StoredPayload::Raw(data) => data.clone(),
+            StoredPayload::Zstd { data, original_len } => zstd::bulk::decompress(data, *original_len)
+                .expect("stored zstd payload is corrupt"),
+        }
+    }
+
+    pub fn original_len(&self) -> usize {
+        match self {
+            StoredPayload::Raw(data) => data.len(),
+            StoredPayload::Zstd { original_len, .. } => *original_len,
+        }
+    }
+
+    pub fn stored_len(&self) -> usize {
+        match self {
+            StoredPayload::Raw(data) => data.len(),
+            StoredPayload::Zstd { data, .. } => data.len(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct CompressionStats {
+    pub payloads: u64,
+    pub compressed_payloads: u64,
+    pub original_bytes: u64,
+    pub stored_bytes: u64,
+}
+
+impl CompressionStats {
+    pub fn record(&mut self, payload: &StoredPayload) {
+        self.payloads += 1;
+        if matches!(payload, StoredPayload::Zstd { .. }) {
+            self.compressed_payloads += 1;
+        }
+        self.original_bytes += payload.original_len() as u64;
+        self.stored_bytes += payload.stored_len() as u64;
+    }
+
+    // original / stored; 1.0 when nothing was compressed
+    pub fn ratio(&self) -> f64 {
+        if self.stored_bytes == 0 {
+            return 1.0;
+        }
+        self.original_bytes as f64 / self.stored_bytes as f64
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold_and_round_trip() {
+        let small = StoredPayload::encode(b"tiny".to_vec());
+        assert_eq!(small, StoredPayload::Raw(b"tiny".to_vec()));
+
+        let large = vec![b'x'; 4096];
+        let stored = StoredPayload::encode(large.clone());
+        assert!(matches!(stored, StoredPayload::Zstd { .. }));
+        assert!(stored.stored_len() < large.len());
+        assert_eq!(stored.decode(), large);
+
+        let mut stats = CompressionStats::default();
+        stats.record(&small);
+        stats.record(&stored);
+        assert_eq!(stats.compressed_payloads, 1);
+        assert!(stats.ratio() > 1.0);
+    }
+}
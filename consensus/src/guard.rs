@@ -0,0 +1,186 @@
+// Anti-slash guard: the highest (height, round, phase) a key has signed.
+// Persisted before every signature is released, so a crash-restore or a
+// failover to another host holding the same file can't equivocate.
+use crate::{BlockId, Round, SignerError, Vote, VotePhase};
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct HighWaterMark {
+    pub height: u64,
+    pub round: Round,
+    // 1 = precommit, 2 = commit
+    pub step: u8,
+    pub proposal_id: BlockId,
+}
+
+impl HighWaterMark {
+    fn position(&self) -> (u64, Round, u8) {
+        (self.height, self.round, self.step)
+    }
+}
+
+// On-disk format; carries the key it belongs to so a guard file can't be
+// paired with the wrong validator key after being copied between hosts.
+#[derive(Debug, Serialize, Deserialize)]
+struct GuardFile {
+    public_key: String,
+    mark: Option<HighWaterMark>,
+}
+
+fn step(phase: &VotePhase) -> u8 {
+    match phase {
+        VotePhase::Precommit => 1,
+        VotePhase::Commit => 2,
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DoubleSignGuard {
+    mark: Option<HighWaterMark>,
+    file: Option<(PathBuf, String)>,
+}
+
+impl DoubleSignGuard {
+    // Loads the guard file, creating it if missing
+    pub fn open(path: &Path, public_key: &VerifyingKey) -> Result<Self, SignerError> {
+        let public_key = hex::encode(public_key.as_bytes());
+
+        let mut guard = Self {
+            mark: None,
+            file: Some((path.to_path_buf(), public_key)),
+        };
+        guard.reload()?;
+        guard.persist()?;
+        Ok(guard)
+    }
+
+    fn reload(&mut self) -> Result<(), SignerError> {
+        let Some((path, public_key)) = &self.file else {
+            return Ok(());
+        };
+
+        match std::fs::read_to_string(path) {
+            Ok(text) => {
+                let file: GuardFile = serde_json::from_str(&text)
+                    .map_err(|e| SignerError::Io(format!("corrupt guard file {}: {}", path.display(), e)))?;
+                if file.public_key != *public_key {
+                    return Err(SignerError::Io(format!("guard file {} belongs to another key", path.display())));
+                }
+                self.mark = file.mark;
+                Ok(())
+            }
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(SignerError::Io(e.to_string())),
+        }
+    }
+
+    pub fn high_water_mark(&self) -> Option<&HighWaterMark> {
+        self.mark.as_ref()
+    }
+
+    pub fn check_and_record(&mut self, vote: &Vote) -> Result<(), SignerError> {
+        // Another process sharing the file (a failover peer) may have signed since
+        self.reload()?;
+
+        let (height, round) = (vote.height, vote.round);
+        let next = HighWaterMark {
+            height,
+            round,
+            step: step(&vote.phase),
+            proposal_id: vote.proposal_id,
+        };
+
+        if let Some(mark) = &self.mark {
+            if next.position() < mark.position() {
+                return Err(SignerError::Regression {
+                    height,
+                    round,
+                    last_height: mark.height,
+                    last_round: mark.round,
+                });
+            }
+            if next.position() == mark.position() {
+                if next.proposal_id != mark.proposal_id {
+                    return Err(SignerError::DoubleSign {
+                        height,
+                        phase: vote.phase.clone(),
+                        signed: mark.proposal_id,
+                    });
+                }
+                // Identical re-sign, nothing new to record
+                return Ok(());
+            }
+        }
+
+        let previous = self.mark.replace(next);
+        if let Err(e) = self.persist() {
+            self.mark = previous;
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    // Write to a temp file, fsync, then rename over the old one
+    fn persist(&self) -> Result<(), SignerError> {
+        let Some((path, public_key)) = &self.file else {
+            return Ok(());
+        };
+        let io = |e: std::io::Error| SignerError::Io(e.to_string());
+
+        let contents = serde_json::to_string_pretty(&GuardFile {
+            public_key: public_key.clone(),
+            mark: self.mark.clone(),
+        })
+        .map_err(|e| SignerError::Io(e.to_string()))?;
+
+        let tmp = path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp).map_err(io)?;
+        file.write_all(contents.as_bytes()).map_err(io)?;
+        file.sync_all().map_err(io)?;
+        std::fs::rename(&tmp, path).map_err(io)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ValidatorId;
+    use ed25519_dalek::SigningKey;
+
+    fn vote(proposal: &str, height: u64, round: u64, phase: VotePhase) -> Vote {
+        let proposal_id = BlockId::from(blake3::hash(proposal.as_bytes()));
+        Vote { proposal_id, height, round: Round(round), validator_id: ValidatorId(0), phase }
+    }
+
+    #[test]
+    fn test_guard_survives_restart() {
+        let key = SigningKey::from_bytes(&[4u8; 32]).verifying_key();
+        let path = std::env::temp_dir().join(format!("mcn-guard-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+
+        let mut guard = DoubleSignGuard::open(&path, &key).unwrap();
+        guard.check_and_record(&vote("a", 3, 1, VotePhase::Precommit)).unwrap();
+        guard.check_and_record(&vote("a", 3, 1, VotePhase::Commit)).unwrap();
+        drop(guard);
+
+        // "Restarted" guard still knows about height 3 round 1
+        let mut guard = DoubleSignGuard::open(&path, &key).unwrap();
+        assert!(guard.check_and_record(&vote("a", 3, 1, VotePhase::Commit)).is_ok());
+        assert!(matches!(
+            guard.check_and_record(&vote("b", 3, 1, VotePhase::Commit)),
+            Err(SignerError::DoubleSign { .. })
+        ));
+        assert!(matches!(
+            guard.check_and_record(&vote("b", 3, 0, VotePhase::Precommit)),
+            Err(SignerError::Regression { .. })
+        ));
+        assert!(guard.check_and_record(&vote("b", 3, 2, VotePhase::Precommit)).is_ok());
+
+        let other = SigningKey::from_bytes(&[5u8; 32]).verifying_key();
+        assert!(DoubleSignGuard::open(&path, &other).is_err());
+        std::fs::remove_file(&path).unwrap();
+    }
+}
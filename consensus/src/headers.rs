@@ -0,0 +1,78 @@
+// Finalized headers for light clients: a block without its payload, with the
+// certificate that finalized it. Blocks whose payload changes voting power
+// (staking transactions) keep their payload, so a client can follow the
+// stake behind certificates and recompute those blocks' ids.
+use crate::{
+    block_id, BlockId, Chain, ConsensusState, Header, QuorumCertificate, Round, StakingTx, TimeoutCertificate,
+    ValidatorId,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CertifiedHeader {
+    pub id: BlockId,
+    pub parent_id: Option<BlockId>,
+    pub height: u64,
+    pub round: Round,
+    pub proposer: ValidatorId,
+    pub timestamp: u64,
+    #[serde(default)]
+    pub timeout_certificate: Option<TimeoutCertificate>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_root: Option<String>,
+    // Hex, for staking transactions only
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub payload: Option<String>,
+    // Absent for blocks finalized through a descendant's certificate
+    pub qc: Option<QuorumCertificate>,
+}
+
+impl CertifiedHeader {
+    // The id recomputed from the carried payload; None without one
+    pub fn computed_id(&self) -> Option<BlockId> {
+        let payload = hex::decode(self.payload.as_ref()?).ok()?;
+        Some(block_id(&Header {
+            parent_id: self.parent_id.as_ref(),
+            payload: &payload,
+            height: self.height,
+            round: self.round,
+            proposer: self.proposer,
+            timestamp: self.timestamp,
+            timeout_certificate: self.timeout_certificate.as_ref(),
+            state_root: self.state_root.as_deref(),
+        }))
+    }
+}
+
+impl Chain {
+    fn certified_headers(&self, from_height: u64, limit: usize) -> Vec<CertifiedHeader> {
+        self.finalized_chain
+            .iter()
+            .skip(from_height as usize)
+            .filter_map(|id| self.blocks.get(id))
+            .take(limit)
+            .map(|block| {
+                let payload = self.payloads.get(&block.payload_hash);
+                CertifiedHeader {
+                    id: block.id,
+                    parent_id: block.parent_id,
+                    height: block.height,
+                    round: block.round,
+                    proposer: block.proposer,
+                    timestamp: block.timestamp,
+                    timeout_certificate: block.timeout_certificate.clone(),
+                    state_root: block.state_root.clone(),
+                    payload: payload.filter(|p| StakingTx::decode(p).is_some()).map(hex::encode),
+                    qc: self.certificates.get(&block.id).cloned(),
+                }
+            })
+            .collect()
+    }
+}
+
+impl ConsensusState {
+    // Up to `limit` finalized headers from `from_height`, oldest first
+    pub fn certified_headers(&self, from_height: u64, limit: usize) -> Vec<CertifiedHeader> {
+        self.chain.read().unwrap().certified_headers(from_height, limit)
+    }
+}
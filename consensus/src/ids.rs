@@ -0,0 +1,126 @@
+// Typed identifiers, so a height cannot be passed as a round or a validator
+// index as anything else. Block ids are BLAKE3 hashes, written as 64
+// lowercase hex digits in JSON, URLs and logs; validator ids and rounds are
+// plain numbers on the wire.
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BlockId(pub [u8; 32]);
+
+impl BlockId {
+    pub fn as_bytes(&self) -> &[u8; 32] {
+        &self.0
+    }
+
+    pub fn to_hex(&self) -> String {
+        hex::encode(self.0)
+    }
+}
+
+impl From<blake3::Hash> for BlockId {
+    fn from(hash: blake3::Hash) -> Self {
+        Self(*hash.as_bytes())
+    }
+}
+
+impl fmt::Display for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.to_hex())
+    }
+}
+
+impl fmt::Debug for BlockId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BlockId({})", self)
+    }
+}
+
+impl FromStr for BlockId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let mut bytes = [0u8; 32];
+        hex::decode_to_slice(s, &mut bytes).map_err(|_| format!("invalid block id {:?}: expected 64 hex digits", s))?;
+        Ok(Self(bytes))
+    }
+}
+
+impl Serialize for BlockId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for BlockId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = <std::borrow::Cow<str>>::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct ValidatorId(pub u32);
+
+impl fmt::Display for ValidatorId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for ValidatorId {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        s.parse().map(Self).map_err(|_| format!("invalid validator id {:?}", s))
+    }
+}
+
+// Proposal attempt at a height, starting at 0
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Round(pub u64);
+
+impl Round {
+    // None after the last round
+    pub fn next(self) -> Option<Self> {
+        self.0.checked_add(1).map(Self)
+    }
+}
+
+impl fmt::Display for Round {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl FromStr for Round {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        s.parse().map(Self).map_err(|_| format!("invalid round {:?}", s))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_ids_round_trip_through_text_and_json() {
+        let id = BlockId::from(blake3::hash(b"block"));
+        assert_eq!(id.to_string().len(), 64);
+        assert_eq!(id.to_string().parse::<BlockId>(), Ok(id));
+        assert_eq!(serde_json::to_string(&id).unwrap(), format!("\"{}\"", id));
+        assert_eq!(serde_json::from_str::<BlockId>(&format!("\"{}\"", id)).unwrap(), id);
+        assert!("abc".parse::<BlockId>().is_err());
+        assert!(serde_json::from_str::<BlockId>("\"not hex\"").is_err());
+
+        assert_eq!(serde_json::to_string(&(ValidatorId(3), Round(7))).unwrap(), "[3,7]");
+        assert_eq!("3".parse::<ValidatorId>(), Ok(ValidatorId(3)));
+        assert!("-1".parse::<ValidatorId>().is_err());
+        assert_eq!("7".parse::<Round>().map(Round::next), Ok(Some(Round(8))));
+    }
+}
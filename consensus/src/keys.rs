@@ -0,0 +1,166 @@
+// Validator public keys by id, part of the replicated state: set at genesis
+// and changed only by finalized key rotations, so every node that finalizes
+// the same chain resolves an id to the same key. A rotation is a block
+// payload signed by the validator's current key, so nobody else can replace
+// it, and a key can belong to one validator only.
+use crate::ValidatorId;
+use ed25519_dalek::{Signature, Signer, SigningKey, Verifier, VerifyingKey};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+const ROTATION_DOMAIN: &[u8] = b"mini-consensus/key-rotation/v1";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum KeyTx {
+    RotateKey(KeyRotation),
+}
+
+// Payload `{"type":"rotate_key","validator_id":1,"public_key":"<hex>","signature":"<hex>"}`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct KeyRotation {
+    pub validator_id: ValidatorId,
+    pub public_key: String, // hex ed25519 public key
+    pub signature: String,  // hex, by the current key over the id and new key
+}
+
+impl KeyRotation {
+    pub fn sign(validator_id: ValidatorId, new_key: &VerifyingKey, current: &SigningKey) -> Self {
+        let signature = current.sign(&Self::signing_bytes(validator_id, new_key));
+        Self {
+            validator_id,
+            public_key: hex::encode(new_key.as_bytes()),
+            signature: hex::encode(signature.to_bytes()),
+        }
+    }
+
+    fn signing_bytes(validator_id: ValidatorId, new_key: &VerifyingKey) -> Vec<u8> {
+        let mut bytes = ROTATION_DOMAIN.to_vec();
+        bytes.extend_from_slice(&(validator_id.0 as u64).to_le_bytes());
+        bytes.extend_from_slice(new_key.as_bytes());
+        bytes
+    }
+
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(&KeyTx::RotateKey(self.clone())).unwrap()
+    }
+
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        match serde_json::from_slice(payload).ok()? {
+            KeyTx::RotateKey(rotation) => Some(rotation),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KeyError {
+    UnknownValidator(ValidatorId),
+    InvalidKey,
+    BadSignature,
+    KeyInUse(ValidatorId),
+}
+
+impl fmt::Display for KeyError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            KeyError::UnknownValidator(id) => write!(f, "validator {} has no registered key", id),
+            KeyError::InvalidKey => write!(f, "public key is not a valid ed25519 key"),
+            KeyError::BadSignature => write!(f, "rotation is not signed by the validator's current key"),
+            KeyError::KeyInUse(id) => write!(f, "key is already registered to validator {}", id),
+        }
+    }
+}
+
+impl std::error::Error for KeyError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KeyEntry {
+    pub key: VerifyingKey,
+    // Height of the rotation that registered it; None for genesis keys
+    pub since_height: Option<u64>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct KeyRegistry {
+    keys: BTreeMap<ValidatorId, KeyEntry>,
+}
+
+impl KeyRegistry {
+    pub fn genesis(keys: impl IntoIterator<Item = (ValidatorId, VerifyingKey)>) -> Self {
+        Self {
+            keys: keys.into_iter().map(|(id, key)| (id, KeyEntry { key, since_height: None })).collect(),
+        }
+    }
+
+    pub fn get(&self, validator: ValidatorId) -> Option<KeyEntry> {
+        self.keys.get(&validator).copied()
+    }
+
+    pub fn key(&self, validator: ValidatorId) -> Option<VerifyingKey> {
+        self.get(validator).map(|entry| entry.key)
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (ValidatorId, KeyEntry)> + '_ {
+        self.keys.iter().map(|(&id, &entry)| (id, entry))
+    }
+
+    pub fn apply(&mut self, rotation: &KeyRotation, height: u64) -> Result<(), KeyError> {
+        let current = self.key(rotation.validator_id).ok_or(KeyError::UnknownValidator(rotation.validator_id))?;
+        let new_key = hex::decode(&rotation.public_key)
+            .ok()
+            .and_then(|b| <[u8; 32]>::try_from(b).ok())
+            .and_then(|b| VerifyingKey::from_bytes(&b).ok())
+            .ok_or(KeyError::InvalidKey)?;
+        let signature = hex::decode(&rotation.signature)
+            .ok()
+            .and_then(|b| Signature::from_slice(&b).ok())
+            .ok_or(KeyError::BadSignature)?;
+        current
+            .verify(&KeyRotation::signing_bytes(rotation.validator_id, &new_key), &signature)
+            .map_err(|_| KeyError::BadSignature)?;
+        if let Some((owner, _)) = self.entries().find(|(id, entry)| entry.key == new_key && *id != rotation.validator_id) {
+            return Err(KeyError::KeyInUse(owner));
+        }
+
+        self.keys.insert(rotation.validator_id, KeyEntry { key: new_key, since_height: Some(height) });
+        Ok(())
+    }
+
+    pub(crate) fn on_finalized(&mut self, height: u64, payload: &[u8]) {
+        if let Some(rotation) = KeyRotation::decode(payload) {
+            // Invalid rotations are finalized but have no effect
+            let _ = self.apply(&rotation, height);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(seed: u8) -> SigningKey {
+        SigningKey::from_bytes(&[seed; 32])
+    }
+
+    #[test]
+    fn test_only_the_current_key_rotates() {
+        let mut registry = KeyRegistry::genesis((0..3).map(|v| (ValidatorId(v), key(v as u8).verifying_key())));
+
+        let rotation = KeyRotation::sign(ValidatorId(1), &key(9).verifying_key(), &key(1));
+        registry.on_finalized(7, &rotation.encode());
+        assert_eq!(registry.get(ValidatorId(1)), Some(KeyEntry { key: key(9).verifying_key(), since_height: Some(7) }));
+
+        // The old key can no longer rotate it, and nobody else's ever could
+        let stale = KeyRotation::sign(ValidatorId(1), &key(10).verifying_key(), &key(1));
+        assert_eq!(registry.apply(&stale, 8), Err(KeyError::BadSignature));
+        let forged = KeyRotation::sign(ValidatorId(2), &key(10).verifying_key(), &key(0));
+        assert_eq!(registry.apply(&forged, 8), Err(KeyError::BadSignature));
+
+        let taken = KeyRotation::sign(ValidatorId(2), &key(0).verifying_key(), &key(2));
+        assert_eq!(registry.apply(&taken, 8), Err(KeyError::KeyInUse(ValidatorId(0))));
+        let unknown = KeyRotation::sign(ValidatorId(5), &key(10).verifying_key(), &key(5));
+        assert_eq!(registry.apply(&unknown, 8), Err(KeyError::UnknownValidator(ValidatorId(5))));
+        assert_eq!(registry.key(ValidatorId(2)), Some(key(2).verifying_key()));
+    }
+}
@@ -0,0 +1,624 @@
+// Vote signing with the validator key held in a cloud KMS: AWS KMS behind the
+// `aws-kms` feature and GCP Cloud KMS behind `gcp-kms`, both with Ed25519
+// keys that never leave the provider's HSMs. The public key is fetched once
+// and cached, and every signature the KMS returns is verified against it.
+// The double-sign guard stays on this host, as with a local key. Requests
+// run on the signer's own runtime, so `sign_vote` blocks like the remote
+// signer does from sync and async callers alike, and their latency is
+// tracked so operators can see what each signature costs a round.
+use crate::guard::DoubleSignGuard;
+use crate::{SignedVote, Signer, SignerError, Vote};
+use base64::Engine;
+use ed25519_dalek::{Signature, VerifyingKey};
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::VecDeque;
+use std::fmt;
+use std::future::Future;
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+const KMS_TIMEOUT: Duration = Duration::from_secs(5);
+// Signatures the latency percentiles cover
+const LATENCY_WINDOW: usize = 1024;
+// DER SubjectPublicKeyInfo header of an Ed25519 key; the key follows
+const ED25519_SPKI_PREFIX: [u8; 12] = [0x30, 0x2a, 0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+// `aws:ARN`, or `aws:KEY_ID` with the region from AWS_REGION;
+// `gcp:projects/P/locations/L/keyRings/R/cryptoKeys/K/cryptoKeyVersions/V`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum KmsKey {
+    #[cfg(feature = "aws-kms")]
+    Aws { region: String, key_id: String },
+    #[cfg(feature = "gcp-kms")]
+    Gcp { version: String },
+}
+
+impl FromStr for KmsKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, String> {
+        let (provider, key) = s
+            .split_once(':')
+            .ok_or_else(|| format!("KMS key '{}' must be aws:KEY or gcp:KEY_VERSION", s))?;
+        match provider {
+            #[cfg(feature = "aws-kms")]
+            "aws" => aws::parse_key(key),
+            #[cfg(feature = "gcp-kms")]
+            "gcp" => gcp::parse_key(key),
+            _ if provider == "aws" || provider == "gcp" => {
+                Err(format!("this build has no {} KMS support (enable the {}-kms feature)", provider, provider))
+            }
+            _ => Err(format!("unknown KMS provider '{}'", provider)),
+        }
+    }
+}
+
+impl fmt::Display for KmsKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            #[cfg(feature = "aws-kms")]
+            KmsKey::Aws { region, key_id } => write!(f, "aws:{} in {}", key_id, region),
+            #[cfg(feature = "gcp-kms")]
+            KmsKey::Gcp { version } => write!(f, "gcp:{}", version),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize)]
+pub struct KmsLatency {
+    pub signatures: u64,
+    pub failures: u64,
+    pub last_ms: f64,
+    pub mean_ms: f64,
+    // Over the last LATENCY_WINDOW signatures
+    pub p50_ms: f64,
+    pub p99_ms: f64,
+    pub max_ms: f64,
+}
+
+#[derive(Default)]
+struct LatencyTracker {
+    signatures: u64,
+    failures: u64,
+    total: Duration,
+    last: Duration,
+    max: Duration,
+    recent: VecDeque<Duration>,
+}
+
+impl LatencyTracker {
+    fn record(&mut self, elapsed: Duration, ok: bool) {
+        if !ok {
+            self.failures += 1;
+            return;
+        }
+        self.signatures += 1;
+        self.total += elapsed;
+        self.last = elapsed;
+        self.max = self.max.max(elapsed);
+        if self.recent.len() == LATENCY_WINDOW {
+            self.recent.pop_front();
+        }
+        self.recent.push_back(elapsed);
+    }
+
+    fn snapshot(&self) -> KmsLatency {
+        let ms = |d: Duration| d.as_secs_f64() * 1000.0;
+        let mut sorted: Vec<Duration> = self.recent.iter().copied().collect();
+        sorted.sort();
+        let percentile = |p: f64| sorted.get(((sorted.len() as f64 - 1.0) * p).round() as usize).copied().map_or(0.0, ms);
+        KmsLatency {
+            signatures: self.signatures,
+            failures: self.failures,
+            last_ms: ms(self.last),
+            mean_ms: if self.signatures == 0 { 0.0 } else { ms(self.total) / self.signatures as f64 },
+            p50_ms: percentile(0.5),
+            p99_ms: percentile(0.99),
+            max_ms: ms(self.max),
+        }
+    }
+}
+
+enum Backend {
+    #[cfg(feature = "aws-kms")]
+    Aws(aws::Kms),
+    #[cfg(feature = "gcp-kms")]
+    Gcp(gcp::Kms),
+}
+
+impl Backend {
+    async fn public_key(&self, http: &reqwest::Client) -> Result<VerifyingKey, SignerError> {
+        match self {
+            #[cfg(feature = "aws-kms")]
+            Backend::Aws(kms) => kms.public_key(http).await,
+            #[cfg(feature = "gcp-kms")]
+            Backend::Gcp(kms) => kms.public_key(http).await,
+        }
+    }
+
+    async fn sign(&self, http: &reqwest::Client, message: &[u8]) -> Result<Vec<u8>, SignerError> {
+        match self {
+            #[cfg(feature = "aws-kms")]
+            Backend::Aws(kms) => kms.sign(http, message).await,
+            #[cfg(feature = "gcp-kms")]
+            Backend::Gcp(kms) => kms.sign(http, message).await,
+        }
+    }
+}
+
+pub struct KmsSigner {
+    key: KmsKey,
+    backend: Arc<Backend>,
+    http: reqwest::Client,
+    // Taken on drop so the runtime shuts down without blocking
+    runtime: Option<tokio::runtime::Runtime>,
+    public_key: VerifyingKey,
+    guard: Mutex<DoubleSignGuard>,
+    latency: Mutex<LatencyTracker>,
+}
+
+impl KmsSigner {
+    // Fetches and caches the key's public half; guard state lives in memory
+    // only until `with_guard_file`
+    pub fn connect(key: KmsKey) -> Result<Self, SignerError> {
+        let backend = Arc::new(match &key {
+            #[cfg(feature = "aws-kms")]
+            KmsKey::Aws { region, key_id } => Backend::Aws(aws::Kms::new(region, key_id)),
+            #[cfg(feature = "gcp-kms")]
+            KmsKey::Gcp { version } => Backend::Gcp(gcp::Kms::new(version)),
+        });
+        let http = reqwest::Client::builder().timeout(KMS_TIMEOUT).build().map_err(remote)?;
+        let runtime = tokio::runtime::Builder::new_multi_thread()
+            .worker_threads(1)
+            .thread_name("kms-signer")
+            .enable_all()
+            .build()
+            .map_err(|e| SignerError::Io(e.to_string()))?;
+
+        let (fetch_backend, fetch_http) = (backend.clone(), http.clone());
+        let public_key = match run(&runtime, async move { fetch_backend.public_key(&fetch_http).await }) {
+            Ok(public_key) => public_key,
+            Err(e) => {
+                runtime.shutdown_background();
+                return Err(e);
+            }
+        };
+        Ok(Self {
+            key,
+            backend,
+            http,
+            runtime: Some(runtime),
+            public_key,
+            guard: Mutex::new(DoubleSignGuard::default()),
+            latency: Mutex::new(LatencyTracker::default()),
+        })
+    }
+
+    pub fn with_guard_file(mut self, path: &Path) -> Result<Self, SignerError> {
+        self.guard = Mutex::new(DoubleSignGuard::open(path, &self.public_key)?);
+        Ok(self)
+    }
+
+    pub fn key(&self) -> &KmsKey {
+        &self.key
+    }
+
+    pub fn latency(&self) -> KmsLatency {
+        self.latency.lock().unwrap().snapshot()
+    }
+}
+
+impl Signer for KmsSigner {
+    fn public_key(&self) -> VerifyingKey {
+        self.public_key
+    }
+
+    fn sign_vote(&self, vote: Vote) -> Result<SignedVote, SignerError> {
+        // The mark is persisted before the KMS is asked, as for a local key
+        self.guard.lock().unwrap().check_and_record(&vote)?;
+        let message = vote.signing_bytes();
+        let (backend, http, request) = (self.backend.clone(), self.http.clone(), message.clone());
+        let started = Instant::now();
+        let runtime = self.runtime.as_ref().expect("runtime lives until drop");
+        let result = run(runtime, async move { backend.sign(&http, &request).await });
+        self.latency.lock().unwrap().record(started.elapsed(), result.is_ok());
+
+        let signature = Signature::from_slice(&result?)
+            .map_err(|_| SignerError::Remote("KMS returned a malformed signature".to_string()))?;
+        self.public_key
+            .verify_strict(&message, &signature)
+            .map_err(|_| SignerError::Remote("KMS signature does not verify against the cached public key".to_string()))?;
+        Ok(SignedVote { vote, signature })
+    }
+}
+
+impl Drop for KmsSigner {
+    fn drop(&mut self) {
+        if let Some(runtime) = self.runtime.take() {
+            runtime.shutdown_background();
+        }
+    }
+}
+
+// Runs `future` on the signer's runtime and waits for it, which works from
+// inside another runtime too
+fn run<T: Send + 'static>(
+    runtime: &tokio::runtime::Runtime,
+    future: impl Future<Output = Result<T, SignerError>> + Send + 'static,
+) -> Result<T, SignerError> {
+    let (tx, rx) = std::sync::mpsc::channel();
+    runtime.spawn(async move {
+        let _ = tx.send(future.await);
+    });
+    rx.recv_timeout(KMS_TIMEOUT * 2)
+        .map_err(|_| SignerError::Remote("KMS request timed out".to_string()))?
+}
+
+fn remote(e: impl fmt::Display) -> SignerError {
+    SignerError::Remote(e.to_string())
+}
+
+// The JSON body of a successful response; errors carry the provider's message
+async fn read_json(response: reqwest::Result<reqwest::Response>) -> Result<Value, SignerError> {
+    let response = response.map_err(remote)?;
+    let status = response.status();
+    let text = response.text().await.map_err(remote)?;
+    if !status.is_success() {
+        return Err(SignerError::Remote(format!("KMS returned {}: {}", status, text.trim())));
+    }
+    serde_json::from_str(&text).map_err(remote)
+}
+
+fn base64_field(reply: &Value, field: &str) -> Result<Vec<u8>, SignerError> {
+    reply[field]
+        .as_str()
+        .and_then(|text| base64::engine::general_purpose::STANDARD.decode(text).ok())
+        .ok_or_else(|| SignerError::Remote(format!("KMS response has no valid {}", field)))
+}
+
+fn spki_key(der: &[u8]) -> Result<VerifyingKey, SignerError> {
+    der.strip_prefix(&ED25519_SPKI_PREFIX[..])
+        .and_then(|key| <[u8; 32]>::try_from(key).ok())
+        .and_then(|key| VerifyingKey::from_bytes(&key).ok())
+        .ok_or_else(|| SignerError::Remote("KMS key is not an Ed25519 signing key".to_string()))
+}
+
+#[cfg(feature = "aws-kms")]
+mod aws {
+    use super::{base64_field, read_json, remote, spki_key, KmsKey};
+    use crate::SignerError;
+    use base64::Engine;
+    use ed25519_dalek::VerifyingKey;
+    use hmac::{Hmac, Mac};
+    use serde_json::{json, Value};
+    use sha2::{Digest, Sha256};
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+    const IMDS: &str = "http://169.254.169.254/latest";
+    // Instance role credentials rotate well before they expire
+    const INSTANCE_CREDENTIALS_TTL: Duration = Duration::from_secs(300);
+
+    pub(super) fn parse_key(key: &str) -> Result<KmsKey, String> {
+        // arn:aws:kms:REGION:ACCOUNT:key/ID
+        let region = if key.starts_with("arn:") {
+            key.split(':').nth(3).filter(|region| !region.is_empty()).map(str::to_string)
+        } else {
+            std::env::var("AWS_REGION").or_else(|_| std::env::var("AWS_DEFAULT_REGION")).ok()
+        };
+        match region {
+            Some(region) if !key.is_empty() => Ok(KmsKey::Aws { region, key_id: key.to_string() }),
+            _ if key.is_empty() => Err("AWS KMS key id is empty".to_string()),
+            _ => Err(format!("no region for AWS KMS key {}: use its ARN or set AWS_REGION", key)),
+        }
+    }
+
+    #[derive(Clone)]
+    struct Credentials {
+        access_key_id: String,
+        secret_access_key: String,
+        session_token: Option<String>,
+    }
+
+    pub(super) struct Kms {
+        region: String,
+        key_id: String,
+        // Instance role credentials and when they were fetched
+        instance_credentials: Mutex<Option<(Credentials, Instant)>>,
+    }
+
+    impl Kms {
+        pub(super) fn new(region: &str, key_id: &str) -> Self {
+            Self { region: region.to_string(), key_id: key_id.to_string(), instance_credentials: Mutex::new(None) }
+        }
+
+        pub(super) async fn public_key(&self, http: &reqwest::Client) -> Result<VerifyingKey, SignerError> {
+            let reply = self.call(http, "GetPublicKey", json!({ "KeyId": self.key_id })).await?;
+            spki_key(&base64_field(&reply, "PublicKey")?)
+        }
+
+        pub(super) async fn sign(&self, http: &reqwest::Client, message: &[u8]) -> Result<Vec<u8>, SignerError> {
+            let request = json!({
+                "KeyId": self.key_id,
+                "Message": base64::engine::general_purpose::STANDARD.encode(message),
+                "MessageType": "RAW",
+                "SigningAlgorithm": "ED25519_SHA_512",
+            });
+            base64_field(&self.call(http, "Sign", request).await?, "Signature")
+        }
+
+        async fn call(&self, http: &reqwest::Client, action: &str, body: Value) -> Result<Value, SignerError> {
+            let credentials = self.credentials(http).await?;
+            let host = format!("kms.{}.amazonaws.com", self.region);
+            let body = body.to_string();
+            let mut headers = vec![
+                ("content-type", "application/x-amz-json-1.1".to_string()),
+                ("host", host.clone()),
+                ("x-amz-date", amz_date(SystemTime::now())),
+            ];
+            if let Some(token) = &credentials.session_token {
+                headers.push(("x-amz-security-token", token.clone()));
+            }
+            headers.push(("x-amz-target", format!("TrentService.{}", action)));
+            let authorization = authorization(&credentials, &self.region, &headers, body.as_bytes());
+
+            let mut request = http.post(format!("https://{}/", host)).header("authorization", authorization);
+            for (name, value) in headers.into_iter().filter(|(name, _)| *name != "host") {
+                request = request.header(name, value);
+            }
+            read_json(request.body(body).send().await).await
+        }
+
+        // From the environment, else the instance role through IMDSv2
+        async fn credentials(&self, http: &reqwest::Client) -> Result<Credentials, SignerError> {
+            if let (Ok(access_key_id), Ok(secret_access_key)) =
+                (std::env::var("AWS_ACCESS_KEY_ID"), std::env::var("AWS_SECRET_ACCESS_KEY"))
+            {
+                let session_token = std::env::var("AWS_SESSION_TOKEN").ok();
+                return Ok(Credentials { access_key_id, secret_access_key, session_token });
+            }
+            if let Some((credentials, fetched)) = &*self.instance_credentials.lock().unwrap() {
+                if fetched.elapsed() < INSTANCE_CREDENTIALS_TTL {
+                    return Ok(credentials.clone());
+                }
+            }
+
+            let token = http
+                .put(format!("{}/api/token", IMDS))
+                .header("x-aws-ec2-metadata-token-ttl-seconds", "300")
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(|e| SignerError::Remote(format!("no AWS credentials in the environment or from instance metadata: {}", e)))?
+                .text()
+                .await
+                .map_err(remote)?;
+            let roles = http
+                .get(format!("{}/meta-data/iam/security-credentials/", IMDS))
+                .header("x-aws-ec2-metadata-token", &token)
+                .send()
+                .await
+                .and_then(|response| response.error_for_status())
+                .map_err(remote)?
+                .text()
+                .await
+                .map_err(remote)?;
+            let role = roles.lines().next().ok_or_else(|| SignerError::Remote("instance has no IAM role".to_string()))?;
+            let reply = read_json(
+                http.get(format!("{}/meta-data/iam/security-credentials/{}", IMDS, role))
+                    .header("x-aws-ec2-metadata-token", &token)
+                    .send()
+                    .await,
+            )
+            .await?;
+            let field = |name: &str| {
+                reply[name].as_str().map(str::to_string).ok_or_else(|| SignerError::Remote(format!("instance credentials have no {}", name)))
+            };
+            let credentials = Credentials {
+                access_key_id: field("AccessKeyId")?,
+                secret_access_key: field("SecretAccessKey")?,
+                session_token: Some(field("Token")?),
+            };
+            *self.instance_credentials.lock().unwrap() = Some((credentials.clone(), Instant::now()));
+            Ok(credentials)
+        }
+    }
+
+    // The Signature Version 4 Authorization header for a POST to / with
+    // `headers`, which must be lowercase and sorted
+    fn authorization(credentials: &Credentials, region: &str, headers: &[(&str, String)], body: &[u8]) -> String {
+        let datetime = &headers.iter().find(|(name, _)| *name == "x-amz-date").expect("x-amz-date is signed").1;
+        let signed_headers = headers.iter().map(|(name, _)| *name).collect::<Vec<_>>().join(";");
+        let canonical_headers: String = headers.iter().map(|(name, value)| format!("{}:{}\n", name, value.trim())).collect();
+        let canonical_request = format!(
+            "POST\n/\n\n{}\n{}\n{}",
+            canonical_headers,
+            signed_headers,
+            hex::encode(Sha256::digest(body))
+        );
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}/{}/kms/aws4_request, SignedHeaders={}, Signature={}",
+            credentials.access_key_id,
+            &datetime[..8],
+            region,
+            signed_headers,
+            signature(&credentials.secret_access_key, datetime, region, "kms", &canonical_request)
+        )
+    }
+
+    fn signature(secret: &str, datetime: &str, region: &str, service: &str, canonical_request: &str) -> String {
+        let date = &datetime[..8];
+        let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            datetime,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = [date, region, service, "aws4_request"]
+            .iter()
+            .fold(format!("AWS4{}", secret).into_bytes(), |key, part| hmac(&key, part.as_bytes()));
+        hex::encode(hmac(&key, string_to_sign.as_bytes()))
+    }
+
+    fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any length");
+        mac.update(data);
+        mac.finalize().into_bytes().to_vec()
+    }
+
+    // YYYYMMDD'T'HHMMSS'Z' in UTC
+    fn amz_date(now: SystemTime) -> String {
+        let secs = now.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        let (days, rest) = ((secs / 86_400) as i64, secs % 86_400);
+        // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm)
+        let z = days + 719_468;
+        let era = z.div_euclid(146_097);
+        let doe = z - era * 146_097;
+        let yoe = (doe - doe / 1460 + doe / 36_524 - doe / 146_096) / 365;
+        let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+        let mp = (5 * doy + 2) / 153;
+        let day = doy - (153 * mp + 2) / 5 + 1;
+        let month = if mp < 10 { mp + 3 } else { mp - 9 };
+        let year = yoe + era * 400 + i64::from(month <= 2);
+        format!("{:04}{:02}{:02}T{:02}{:02}{:02}Z", year, month, day, rest / 3600, rest % 3600 / 60, rest % 60)
+    }
+
+    #[cfg(test)]
+    mod tests {
+        use super::*;
+
+        #[test]
+        fn test_signature_v4_matches_aws_example() {
+            // The IAM ListUsers example from the Signature Version 4 docs
+            let canonical_request = "GET\n/\nAction=ListUsers&Version=2010-05-08\n\
+                content-type:application/x-www-form-urlencoded; charset=utf-8\n\
+                host:iam.amazonaws.com\nx-amz-date:20150830T123600Z\n\n\
+                content-type;host;x-amz-date\n\
+                e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+            assert_eq!(
+                signature("wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY", "20150830T123600Z", "us-east-1", "iam", canonical_request),
+                "5d672d79c15b13162d9279b0855cfba6789a8edb4c82c400e06b5924a6f2b5d7"
+            );
+            assert_eq!(amz_date(UNIX_EPOCH + Duration::from_secs(1_440_938_160)), "20150830T123600Z");
+            assert_eq!(amz_date(UNIX_EPOCH + Duration::from_secs(951_782_400)), "20000229T000000Z");
+
+            assert_eq!(
+                parse_key("arn:aws:kms:eu-west-1:111122223333:key/abc"),
+                Ok(KmsKey::Aws { region: "eu-west-1".to_string(), key_id: "arn:aws:kms:eu-west-1:111122223333:key/abc".to_string() })
+            );
+        }
+    }
+}
+
+#[cfg(feature = "gcp-kms")]
+mod gcp {
+    use super::{base64_field, read_json, remote, spki_key, KmsKey};
+    use crate::SignerError;
+    use base64::Engine;
+    use ed25519_dalek::VerifyingKey;
+    use serde_json::json;
+    use std::sync::Mutex;
+    use std::time::{Duration, Instant};
+
+    const API: &str = "https://cloudkms.googleapis.com/v1";
+    const METADATA_TOKEN: &str = "http://metadata.google.internal/computeMetadata/v1/instance/service-accounts/default/token";
+    // Tokens are refreshed this long before they expire
+    const TOKEN_MARGIN: Duration = Duration::from_secs(60);
+    const NAME_LABELS: [&str; 5] = ["projects", "locations", "keyRings", "cryptoKeys", "cryptoKeyVersions"];
+
+    pub(super) fn parse_key(version: &str) -> Result<KmsKey, String> {
+        let parts: Vec<&str> = version.split('/').collect();
+        let valid = parts.len() == 2 * NAME_LABELS.len()
+            && parts.chunks(2).zip(NAME_LABELS).all(|(pair, label)| pair[0] == label && !pair[1].is_empty());
+        if !valid {
+            return Err(format!("GCP KMS key '{}' must be a full key version name, {}", version, NAME_LABELS.join("/…/")));
+        }
+        Ok(KmsKey::Gcp { version: version.to_string() })
+    }
+
+    pub(super) struct Kms {
+        version: String,
+        // Metadata server token and when it expires
+        token: Mutex<Option<(String, Instant)>>,
+    }
+
+    impl Kms {
+        pub(super) fn new(version: &str) -> Self {
+            Self { version: version.to_string(), token: Mutex::new(None) }
+        }
+
+        pub(super) async fn public_key(&self, http: &reqwest::Client) -> Result<VerifyingKey, SignerError> {
+            let token = self.token(http).await?;
+            let reply = read_json(http.get(format!("{}/{}/publicKey", API, self.version)).bearer_auth(token).send().await).await?;
+            if reply["algorithm"] != "EC_SIGN_ED25519" {
+                return Err(SignerError::Remote(format!("GCP KMS key uses {}, not EC_SIGN_ED25519", reply["algorithm"])));
+            }
+            let pem = reply["pem"].as_str().unwrap_or_default();
+            let body: String = pem.lines().filter(|line| !line.starts_with("-----")).collect();
+            let der = base64::engine::general_purpose::STANDARD.decode(body.trim()).map_err(remote)?;
+            spki_key(&der)
+        }
+
+        pub(super) async fn sign(&self, http: &reqwest::Client, message: &[u8]) -> Result<Vec<u8>, SignerError> {
+            let token = self.token(http).await?;
+            let request = json!({ "data": base64::engine::general_purpose::STANDARD.encode(message) });
+            let url = format!("{}/{}:asymmetricSign", API, self.version);
+            base64_field(&read_json(http.post(url).bearer_auth(token).json(&request).send().await).await?, "signature")
+        }
+
+        // GOOGLE_OAUTH_ACCESS_TOKEN if set, else the instance's service
+        // account token from the metadata server
+        async fn token(&self, http: &reqwest::Client) -> Result<String, SignerError> {
+            if let Ok(token) = std::env::var("GOOGLE_OAUTH_ACCESS_TOKEN") {
+                return Ok(token);
+            }
+            if let Some((token, expires)) = &*self.token.lock().unwrap() {
+                if Instant::now() + TOKEN_MARGIN < *expires {
+                    return Ok(token.clone());
+                }
+            }
+            let reply = read_json(http.get(METADATA_TOKEN).header("metadata-flavor", "Google").send().await)
+                .await
+                .map_err(|e| SignerError::Remote(format!("no GOOGLE_OAUTH_ACCESS_TOKEN and no metadata server token: {}", e)))?;
+            let token = reply["access_token"]
+                .as_str()
+                .ok_or_else(|| SignerError::Remote("metadata server returned no access token".to_string()))?
+                .to_string();
+            let expires = Instant::now() + Duration::from_secs(reply["expires_in"].as_u64().unwrap_or(0));
+            *self.token.lock().unwrap() = Some((token.clone(), expires));
+            Ok(token)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_public_keys_and_latency_percentiles() {
+        let key = ed25519_dalek::SigningKey::from_bytes(&[7u8; 32]).verifying_key();
+        let mut der = ED25519_SPKI_PREFIX.to_vec();
+        der.extend_from_slice(key.as_bytes());
+        assert_eq!(spki_key(&der), Ok(key));
+        // A P-256 key's SPKI header is different
+        assert!(spki_key(&der[1..]).is_err());
+
+        let mut tracker = LatencyTracker::default();
+        for ms in 1..=100 {
+            tracker.record(Duration::from_millis(ms), true);
+        }
+        tracker.record(Duration::from_secs(9), false);
+        let latency = tracker.snapshot();
+        assert_eq!((latency.signatures, latency.failures), (100, 1));
+        assert_eq!((latency.p50_ms.round(), latency.p99_ms, latency.max_ms), (51.0, 99.0, 100.0));
+        assert!((latency.mean_ms - 50.5).abs() < 1e-9);
+
+        assert!("azure:key".parse::<KmsKey>().is_err());
+        assert!("no-provider".parse::<KmsKey>().is_err());
+    }
+}
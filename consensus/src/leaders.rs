@@ -0,0 +1,236 @@
+// Leader schedule, part of the replicated state. Round r of a height is led
+// by the r-th validator of the rotation. Each finalized block scores a turn
+// for its proposer and a failed turn for the leader of every round before it
+// at that height, since those rounds timed out. A leader whose recent turns
+// mostly failed is moved to the back of the rotation for a while, so heights
+// stop opening with a round that is likely to time out. At most a third of
+// the validators are demoted at once, and each demotion is kept as evidence
+// with the turns that caused it.
+use crate::{Round, ValidatorId};
+use serde::Serialize;
+use std::collections::{BTreeMap, VecDeque};
+
+// Demotions kept as evidence
+const MAX_DEMOTIONS: usize = 100;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LeaderPolicy {
+    // Recent turns the success rate is computed over
+    pub window: usize,
+    // Turns in the window before a leader can be demoted
+    pub min_turns: usize,
+    // Demoted when the recent success rate falls below this
+    pub min_success_percent: u32,
+    // Heights a demotion lasts
+    pub demotion_heights: u64,
+}
+
+impl Default for LeaderPolicy {
+    fn default() -> Self {
+        Self { window: 20, min_turns: 5, min_success_percent: 50, demotion_heights: 100 }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct Turn {
+    pub height: u64,
+    pub round: Round,
+    // False when the round timed out
+    pub finalized: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Demotion {
+    pub validator_id: ValidatorId,
+    // Height whose finalization triggered it
+    pub at_height: u64,
+    // First height the validator is back in its place
+    pub until_height: u64,
+    pub success_percent: u32,
+    // The recent turns it was judged on
+    pub evidence: Vec<Turn>,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct LeaderRecord {
+    pub turns: u64,
+    pub finalized: u64,
+    recent: VecDeque<Turn>,
+}
+
+impl LeaderRecord {
+    // Success rate over the recent window; None before any turn
+    pub fn success_percent(&self) -> Option<u32> {
+        let finalized = self.recent.iter().filter(|t| t.finalized).count();
+        (!self.recent.is_empty()).then(|| (finalized * 100 / self.recent.len()) as u32)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct LeaderSchedule {
+    policy: LeaderPolicy,
+    validators: Vec<ValidatorId>,
+    records: BTreeMap<ValidatorId, LeaderRecord>,
+    // Demoted validators and the first height they are back in place
+    demoted: BTreeMap<ValidatorId, u64>,
+    demotions: VecDeque<Demotion>,
+}
+
+impl LeaderSchedule {
+    pub fn new(validators: &[ValidatorId]) -> Self {
+        let mut validators = validators.to_vec();
+        validators.sort();
+        validators.dedup();
+        Self { validators, ..Default::default() }
+    }
+
+    pub fn with_policy(mut self, policy: LeaderPolicy) -> Self {
+        self.policy = policy;
+        self
+    }
+
+    pub fn policy(&self) -> LeaderPolicy {
+        self.policy
+    }
+
+    // Leaders in the order rounds at `height` use them: validators in good
+    // standing by id, then the demoted ones
+    pub fn rotation(&self, height: u64) -> Vec<ValidatorId> {
+        let demoted = |v: &ValidatorId| self.demoted.get(v).is_some_and(|&until| height < until);
+        let (mut rotation, back): (Vec<_>, Vec<_>) = self.validators.iter().copied().partition(|v| !demoted(v));
+        rotation.extend(back);
+        rotation
+    }
+
+    pub fn leader(&self, height: u64, round: Round) -> ValidatorId {
+        let rotation = self.rotation(height);
+        if rotation.is_empty() {
+            return ValidatorId::default();
+        }
+        rotation[(round.0 % rotation.len() as u64) as usize]
+    }
+
+    pub fn record(&self, validator: ValidatorId) -> Option<&LeaderRecord> {
+        self.records.get(&validator)
+    }
+
+    // Demoted validators at `height` and the first height they are back
+    pub fn demoted(&self, height: u64) -> impl Iterator<Item = (ValidatorId, u64)> + '_ {
+        self.demoted.iter().filter(move |(_, &until)| height < until).map(|(&v, &until)| (v, until))
+    }
+
+    // Oldest first
+    pub fn demotions(&self) -> impl Iterator<Item = &Demotion> {
+        self.demotions.iter()
+    }
+
+    pub(crate) fn on_finalized(&mut self, height: u64, round: Round, proposer: ValidatorId) {
+        let rotation = self.rotation(height);
+        if rotation.is_empty() {
+            return;
+        }
+
+        // Only the last few skipped rounds can still be in anyone's window
+        let first = round.0.saturating_sub((rotation.len() * self.policy.window) as u64);
+        for skipped in first..round.0 {
+            let leader = rotation[(skipped % rotation.len() as u64) as usize];
+            self.score(leader, Turn { height, round: Round(skipped), finalized: false });
+        }
+        self.score(proposer, Turn { height, round, finalized: true });
+
+        // Back in place with a clean slate
+        let next = height + 1;
+        let expired: Vec<ValidatorId> = self.demoted.iter().filter(|(_, &until)| until <= next).map(|(&v, _)| v).collect();
+        for validator in expired {
+            self.demoted.remove(&validator);
+            self.records.entry(validator).or_default().recent.clear();
+        }
+
+        for validator in rotation {
+            self.consider_demotion(validator, height);
+        }
+    }
+
+    fn score(&mut self, validator: ValidatorId, turn: Turn) {
+        let record = self.records.entry(validator).or_default();
+        record.turns += 1;
+        record.finalized += turn.finalized as u64;
+        record.recent.push_back(turn);
+        while record.recent.len() > self.policy.window.max(1) {
+            record.recent.pop_front();
+        }
+    }
+
+    fn consider_demotion(&mut self, validator: ValidatorId, height: u64) {
+        let max_demoted = self.validators.len().saturating_sub(1) / 3;
+        if self.demoted.contains_key(&validator) || self.demoted.len() >= max_demoted {
+            return;
+        }
+        let Some(record) = self.records.get(&validator) else {
+            return;
+        };
+        let Some(success_percent) = record.success_percent() else {
+            return;
+        };
+        if record.recent.len() < self.policy.min_turns || success_percent >= self.policy.min_success_percent {
+            return;
+        }
+
+        let until_height = height + 1 + self.policy.demotion_heights;
+        self.demoted.insert(validator, until_height);
+        self.demotions.push_back(Demotion {
+            validator_id: validator,
+            at_height: height,
+            until_height,
+            success_percent,
+            evidence: record.recent.iter().copied().collect(),
+        });
+        if self.demotions.len() > MAX_DEMOTIONS {
+            self.demotions.pop_front();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_failing_leader_is_demoted_for_a_while() {
+        let policy = LeaderPolicy { window: 4, min_turns: 3, min_success_percent: 50, demotion_heights: 10 };
+        let validators: Vec<ValidatorId> = (0..7).map(ValidatorId).collect();
+        let mut schedule = LeaderSchedule::new(&validators).with_policy(policy);
+        assert_eq!(schedule.leader(0, Round(0)), ValidatorId(0));
+        assert_eq!(schedule.leader(0, Round(8)), ValidatorId(1));
+
+        // Validator 0 times out the first round of every height; 1 takes over
+        for height in 0..3 {
+            schedule.on_finalized(height, Round(1), ValidatorId(1));
+        }
+        let demotion = schedule.demotions().next().unwrap();
+        assert_eq!((demotion.validator_id, demotion.at_height, demotion.until_height), (ValidatorId(0), 2, 13));
+        assert_eq!(demotion.success_percent, 0);
+        assert_eq!(demotion.evidence.len(), 3);
+        assert_eq!(schedule.leader(3, Round(0)), ValidatorId(1));
+        assert_eq!(schedule.rotation(3).last(), Some(&ValidatorId(0)));
+
+        // Two of seven validators may be demoted at once, never three
+        for height in 3..6 {
+            schedule.on_finalized(height, Round(2), ValidatorId(3));
+        }
+        assert_eq!(schedule.demoted(6).map(|(v, _)| v).collect::<Vec<_>>(), vec![ValidatorId(0), ValidatorId(1)]);
+        for height in 6..9 {
+            schedule.on_finalized(height, Round(1), ValidatorId(3));
+        }
+        assert_eq!(schedule.demoted(9).count(), 2);
+        assert!(schedule.record(ValidatorId(2)).unwrap().success_percent() < Some(50));
+
+        // Validator 0 returns with a clean slate
+        for height in 9..13 {
+            schedule.on_finalized(height, Round(0), ValidatorId(2));
+        }
+        assert_eq!(schedule.leader(13, Round(0)), ValidatorId(0));
+        assert_eq!(schedule.record(ValidatorId(0)).unwrap().success_percent(), None);
+        assert_eq!(schedule.record(ValidatorId(0)).unwrap().turns, 3);
+    }
+}
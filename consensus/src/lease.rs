@@ -0,0 +1,199 @@
+// Signing lease for hot-standby validators. Primary and standby share a
+// lease file (and the double-sign guard file); only the current holder may
+// sign, and a standby takes over once the primary stops renewing.
+use crate::{SignedVote, Signer, SignerError, Vote};
+use ed25519_dalek::VerifyingKey;
+use serde::{Deserialize, Serialize};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LeaseRecord {
+    pub holder: String,
+    pub expires_at_ms: u64,
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as u64)
+        .unwrap_or(0)
+}
+
+#[derive(Debug, Clone)]
+pub struct FileLease {
+    path: PathBuf,
+    holder: String,
+    ttl: Duration,
+}
+
+impl FileLease {
+    pub fn new(path: &Path, holder: impl Into<String>, ttl: Duration) -> Self {
+        Self {
+            path: path.to_path_buf(),
+            holder: holder.into(),
+            ttl,
+        }
+    }
+
+    pub fn ttl(&self) -> Duration {
+        self.ttl
+    }
+
+    pub fn read(&self) -> Option<LeaseRecord> {
+        let text = std::fs::read_to_string(&self.path).ok()?;
+        serde_json::from_str(&text).ok()
+    }
+
+    pub fn is_held(&self) -> bool {
+        self.read()
+            .is_some_and(|r| r.holder == self.holder && r.expires_at_ms > now_ms())
+    }
+
+    // Takes the lease if it's free or expired, or renews it if already ours.
+    // The read-check-write runs under an exclusively created claim file so
+    // two contenders can't both win.
+    pub fn try_acquire(&self) -> std::io::Result<bool> {
+        let claim = self.path.with_extension("claim");
+        let _claim = match ClaimFile::create(&claim, self.ttl) {
+            Ok(Some(c)) => c,
+            Ok(None) => return Ok(false),
+            Err(e) => return Err(e),
+        };
+
+        let now = now_ms();
+        if let Some(record) = self.read() {
+            if record.holder != self.holder && record.expires_at_ms > now {
+                return Ok(false);
+            }
+        }
+
+        let record = LeaseRecord {
+            holder: self.holder.clone(),
+            expires_at_ms: now + self.ttl.as_millis() as u64,
+        };
+        let tmp = self.path.with_extension("tmp");
+        let mut file = std::fs::File::create(&tmp)?;
+        file.write_all(serde_json::to_string(&record)?.as_bytes())?;
+        file.sync_all()?;
+        std::fs::rename(&tmp, &self.path)?;
+        Ok(true)
+    }
+
+    // Gives the lease up early so a standby can take over without waiting
+    pub fn release(&self) -> std::io::Result<()> {
+        if self.read().is_some_and(|r| r.holder == self.holder) {
+            std::fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+struct ClaimFile(PathBuf);
+
+impl ClaimFile {
+    fn create(path: &Path, ttl: Duration) -> std::io::Result<Option<Self>> {
+        match std::fs::OpenOptions::new().write(true).create_new(true).open(path) {
+            Ok(_) => Ok(Some(Self(path.to_path_buf()))),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => {
+                // A contender that crashed mid-claim leaves the file behind
+                let stale = std::fs::metadata(path)
+                    .and_then(|m| m.modified())
+                    .ok()
+                    .and_then(|t| t.elapsed().ok())
+                    .is_some_and(|age| age > ttl);
+                if stale {
+                    let _ = std::fs::remove_file(path);
+                }
+                Ok(None)
+            }
+            Err(e) => Err(e),
+        }
+    }
+}
+
+impl Drop for ClaimFile {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_file(&self.0);
+    }
+}
+
+// Signs only while holding the lease
+pub struct LeasedSigner<S> {
+    inner: S,
+    lease: FileLease,
+}
+
+impl<S: Signer> LeasedSigner<S> {
+    pub fn new(inner: S, lease: FileLease) -> Self {
+        Self { inner, lease }
+    }
+
+    pub fn lease(&self) -> &FileLease {
+        &self.lease
+    }
+}
+
+impl<S: Signer> Signer for LeasedSigner<S> {
+    fn public_key(&self) -> VerifyingKey {
+        self.inner.public_key()
+    }
+
+    fn sign_vote(&self, vote: Vote) -> Result<SignedVote, SignerError> {
+        if !self.lease.is_held() {
+            return Err(SignerError::NotLeaseHolder);
+        }
+        self.inner.sign_vote(vote)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockId, LocalSigner, Round, ValidatorId, VotePhase};
+    use ed25519_dalek::SigningKey;
+
+    #[test]
+    fn test_standby_takes_over_after_expiry() {
+        let dir = std::env::temp_dir();
+        let lease_path = dir.join(format!("mcn-lease-{}.json", std::process::id()));
+        let guard_path = dir.join(format!("mcn-lease-guard-{}.json", std::process::id()));
+        let _ = std::fs::remove_file(&lease_path);
+        let _ = std::fs::remove_file(&guard_path);
+
+        let key = SigningKey::from_bytes(&[6u8; 32]);
+        let ttl = Duration::from_millis(200);
+        let primary = LeasedSigner::new(
+            LocalSigner::with_guard_file(key.clone(), &guard_path).unwrap(),
+            FileLease::new(&lease_path, "primary", ttl),
+        );
+        let standby = LeasedSigner::new(
+            LocalSigner::with_guard_file(key, &guard_path).unwrap(),
+            FileLease::new(&lease_path, "standby", ttl),
+        );
+        let vote = |id: &str, height: u64| Vote {
+            proposal_id: BlockId::from(blake3::hash(id.as_bytes())),
+            height,
+            round: Round(0),
+            validator_id: ValidatorId(0),
+            phase: VotePhase::Precommit,
+        };
+
+        assert!(primary.lease().try_acquire().unwrap());
+        assert!(!standby.lease().try_acquire().unwrap());
+        assert!(primary.sign_vote(vote("a", 1)).is_ok());
+        assert_eq!(standby.sign_vote(vote("a", 1)).unwrap_err(), SignerError::NotLeaseHolder);
+
+        // Primary stops renewing; standby wins but the shared guard still
+        // blocks it from signing a conflicting vote at height 1
+        std::thread::sleep(ttl + Duration::from_millis(50));
+        assert!(standby.lease().try_acquire().unwrap());
+        assert!(matches!(standby.sign_vote(vote("b", 1)), Err(SignerError::DoubleSign { .. })));
+        assert!(standby.sign_vote(vote("c", 2)).is_ok());
+        assert_eq!(primary.sign_vote(vote("d", 3)).unwrap_err(), SignerError::NotLeaseHolder);
+
+        std::fs::remove_file(&lease_path).unwrap();
+        std::fs::remove_file(&guard_path).unwrap();
+    }
+}
@@ -1,11 +1,78 @@
+use ed25519_dalek::VerifyingKey;
 use serde::{Deserialize, Serialize};
 use std::collections::{HashMap, HashSet};
-use std::sync::{Arc, Mutex};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+pub mod archive;
+pub mod beacon;
+pub mod checkpoint;
+pub mod compression;
+pub mod guard;
+pub mod headers;
+pub mod ids;
+pub mod keys;
+#[cfg(any(feature = "aws-kms", feature = "gcp-kms"))]
+pub mod kms;
+pub mod leaders;
+pub mod lease;
+pub mod mempool;
+pub mod memory;
+pub mod metrics;
+pub mod params;
+pub mod payload_store;
+pub mod pruning;
+pub mod rewards;
+pub mod scrub;
+pub mod search;
+pub mod signer;
+pub mod smt;
+pub mod staking;
+pub mod state_machine;
+pub mod store;
+pub mod timeout;
+pub mod validation;
+pub mod verify;
+pub mod what_if;
+pub mod wire;
+
+pub use archive::{ArchiveEntry, ArchiveError, ArchiveHeader};
+pub use beacon::{Beacon, BeaconEntry, BeaconInfo, BeaconParticipant};
+pub use checkpoint::{Checkpoint, Checkpointer};
+pub use compression::{CompressionStats, StoredPayload};
+pub use guard::{DoubleSignGuard, HighWaterMark};
+pub use headers::CertifiedHeader;
+pub use ids::{BlockId, Round, ValidatorId};
+pub use keys::{KeyEntry, KeyError, KeyRegistry, KeyRotation};
+#[cfg(any(feature = "aws-kms", feature = "gcp-kms"))]
+pub use kms::{KmsKey, KmsLatency, KmsSigner};
+pub use leaders::{Demotion, LeaderPolicy, LeaderRecord, LeaderSchedule, Turn};
+pub use lease::{FileLease, LeasedSigner};
+pub use memory::{MemoryAccountant, MemoryUsage};
+pub use mempool::{AccountId, LaneDepth, Mempool, MempoolError, PendingTx, Transaction, TxBatch, TxHash};
+pub use metrics::{ConsensusMetrics, MetricsSnapshot};
+pub use params::{ConsensusParams, ParamsUpdate, ProposalError};
+pub use payload_store::{PayloadHash, PayloadStore};
+pub use pruning::Pruning;
+pub use rewards::{RewardLedger, RewardSchedule};
+pub use scrub::{Corruption, CorruptionKind, ScrubReport, Scrubber};
+pub use search::{BlockSummary, SearchResult};
+pub use signer::{LocalSigner, RemoteSigner, RemoteSignerServer, Signer, SignerError};
+pub use smt::{ProofLeaf, SparseMerkleProof, SparseMerkleTree};
+pub use staking::{StakeEntry, StakeStatus, Staking, StakingError, StakingTx};
+pub use state_machine::{StateMachine, StateRead};
+pub use store::{BlockStore, FileStore, MemoryStore, StoreError, StoreSnapshot, StoreWriter, VoteSet, WriteBatch};
+pub use timeout::{JustificationError, RoundTimeout, TimeoutBook, TimeoutCertificate};
+pub use validation::{Diagnostic, PayloadKind, ProposalReport, Severity};
+pub use verify::{SignedVote, VerificationPool};
+pub use what_if::{RejectedVote, WhatIf};
+pub use wire::{Wire, WireError};
 
-pub type BlockId = String;
-pub type ValidatorId = usize;
 pub type Bytes = Vec<u8>;
 
+// Bond every configured validator starts with
+pub const GENESIS_STAKE: u64 = 100;
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Block {
     pub id: BlockId,
@@ -13,149 +80,1126 @@ pub struct Block {
     pub payload: Bytes,
     pub height: u64,
     pub proposer: ValidatorId,
+    // Proposal attempt at this height, starting at 0
+    pub round: Round,
+    // Unix time in milliseconds when the block was proposed
+    pub timestamp: u64,
+    // Proof that the previous round at this height timed out
+    #[serde(default)]
+    pub timeout_certificate: Option<TimeoutCertificate>,
+    // Hex `StateMachine::state_root` after applying the parent; absent only
+    // in blocks from before it was committed
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub state_root: Option<String>,
+}
+
+impl Block {
+    // Recomputes the id from the block's contents; differs from `id` if
+    // anything was altered after proposal
+    pub fn computed_id(&self) -> BlockId {
+        block_id(&Header {
+            parent_id: self.parent_id.as_ref(),
+            payload: &self.payload,
+            height: self.height,
+            round: self.round,
+            proposer: self.proposer,
+            timestamp: self.timestamp,
+            timeout_certificate: self.timeout_certificate.as_ref(),
+            state_root: self.state_root.as_deref(),
+        })
+    }
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Proposal {
     pub block: Block,
-    pub round: u64,
+    pub round: Round,
 }
 
 #[derive(Debug, Clone)]
 pub struct Vote {
     pub proposal_id: BlockId,
+    // Height and round the vote was cast in; only votes for the round being
+    // decided are accepted
+    pub height: u64,
+    pub round: Round,
     pub validator_id: ValidatorId,
     pub phase: VotePhase,
 }
 
-#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+// Named in lowercase wherever it is serialized: API requests, events and
+// test vectors all take this enum, so a new phase is added here only
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum VotePhase {
     Precommit,
     Commit,
 }
 
-#[derive(Debug)]
-pub struct Consensus {
-    validators: Vec<ValidatorId>,
-    blocks: HashMap<BlockId, Block>,
-    votes: HashMap<BlockId, HashMap<VotePhase, HashSet<ValidatorId>>>,
-    leader: ValidatorId,
-    finalized_block: Option<BlockId>,
+impl VotePhase {
+    pub fn name(&self) -> &'static str {
+        match self {
+            VotePhase::Precommit => "precommit",
+            VotePhase::Commit => "commit",
+        }
+    }
 }
 
-impl Consensus {
-    pub fn new(validators: Vec<ValidatorId>) -> Self {
-        let leader = if validators.is_empty() { 0 } else { validators[0] };
-        
-        Self {
-            validators,
-            blocks: HashMap::new(),
-            votes: HashMap::new(),
-            leader,
-            finalized_block: None,
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum VoteError {
+    UnknownValidator(ValidatorId),
+    UnknownProposal(BlockId),
+    // The vote is for another height than the one being decided
+    WrongHeight { expected: u64, got: u64 },
+    // The vote is for a round that timed out or has not started
+    WrongRound { expected: Round, got: Round },
+    // The proposal was made at another height or round than the vote names
+    ProposalMismatch { proposal_id: BlockId, height: u64, round: Round },
+    // The proposal's timeout certificate does not justify its round
+    Unjustified(BlockId),
+    // The validator already voted for another proposal in this round and phase
+    AlreadyVoted { height: u64, round: Round, phase: VotePhase, proposal_id: BlockId },
+    // The proposal's state root is not the one this node computed
+    StateRootMismatch { proposal_id: BlockId, expected: String, got: Option<String> },
+}
+
+impl std::fmt::Display for VoteError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VoteError::UnknownValidator(id) => write!(f, "unknown validator {}", id),
+            VoteError::UnknownProposal(id) => write!(f, "unknown proposal {}", id),
+            VoteError::WrongHeight { expected, got } => {
+                write!(f, "vote for height {} but height {} is being decided", got, expected)
+            }
+            VoteError::WrongRound { expected, got } => {
+                write!(f, "vote for round {} but round {} is active", got, expected)
+            }
+            VoteError::ProposalMismatch { proposal_id, height, round } => {
+                write!(f, "proposal {} was made at height {} round {}", proposal_id, height, round)
+            }
+            VoteError::Unjustified(id) => write!(f, "proposal {} has no valid timeout certificate for its round", id),
+            VoteError::AlreadyVoted { height, round, phase, proposal_id } => write!(
+                f,
+                "already voted {} for {} at height {} round {}",
+                phase.name(),
+                proposal_id,
+                height,
+                round
+            ),
+            VoteError::StateRootMismatch { proposal_id, expected, got } => write!(
+                f,
+                "proposal {} carries state root {} but this node computed {}",
+                proposal_id,
+                got.as_deref().unwrap_or("none"),
+                expected
+            ),
         }
     }
+}
+
+impl std::error::Error for VoteError {}
+
+// Commit voters that finalized a block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct QuorumCertificate {
+    pub block_id: BlockId,
+    pub height: u64,
+    pub signers: Vec<ValidatorId>,
+}
+
+// Block header as kept in memory; the payload lives in the payload store
+#[derive(Debug, Clone)]
+struct StoredBlock {
+    id: BlockId,
+    parent_id: Option<BlockId>,
+    payload_hash: PayloadHash,
+    height: u64,
+    proposer: ValidatorId,
+    round: Round,
+    timestamp: u64,
+    timeout_certificate: Option<TimeoutCertificate>,
+    state_root: Option<String>,
+}
+
+// The fields a block id commits to
+struct Header<'a> {
+    parent_id: Option<&'a BlockId>,
+    payload: &'a [u8],
+    height: u64,
+    round: Round,
+    proposer: ValidatorId,
+    timestamp: u64,
+    timeout_certificate: Option<&'a TimeoutCertificate>,
+    state_root: Option<&'a str>,
+}
+
+// Round, proposer and time are part of the preimage so re-proposing a payload,
+// whether later at the same height or after a restart, never collides with an
+// earlier proposal and inherits its votes.
+fn block_id(header: &Header<'_>) -> BlockId {
+    let Header { parent_id, payload, height, round, proposer, timestamp, timeout_certificate, state_root } = *header;
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(b"mini-consensus/block/v2");
+    match parent_id {
+        // Ids enter the preimage as hex, as they did before they were typed
+        Some(parent) => hasher.update(&[1]).update(parent.to_hex().as_bytes()),
+        None => hasher.update(&[0]),
+    };
+    hasher
+        .update(&(payload.len() as u64).to_le_bytes())
+        .update(payload)
+        .update(&height.to_le_bytes())
+        .update(&round.0.to_le_bytes())
+        .update(&(proposer.0 as u64).to_le_bytes())
+        .update(&timestamp.to_le_bytes());
+    match timeout_certificate {
+        Some(tc) => {
+            hasher.update(&[1]).update(&tc.round.0.to_le_bytes()).update(&(tc.signers.len() as u64).to_le_bytes());
+            for &signer in &tc.signers {
+                hasher.update(&(signer.0 as u64).to_le_bytes());
+            }
+        }
+        None => {
+            hasher.update(&[0]);
+        }
+    }
+    // Appended only when present, so older blocks keep their ids
+    if let Some(root) = state_root {
+        hasher.update(&[1]).update(root.as_bytes());
+    }
+    hasher.finalize().into()
+}
+
+// A vote must name the height being decided and its active round, and the
+// proposal it is for must have been made in that round
+fn check_vote_round(
+    block: &StoredBlock,
+    height: u64,
+    round: Round,
+    active_height: u64,
+    active_round: Round,
+) -> Result<(), VoteError> {
+    if height != active_height {
+        return Err(VoteError::WrongHeight { expected: active_height, got: height });
+    }
+    if round != active_round {
+        return Err(VoteError::WrongRound { expected: active_round, got: round });
+    }
+    if block.height != height || block.round != round {
+        return Err(VoteError::ProposalMismatch {
+            proposal_id: block.id,
+            height: block.height,
+            round: block.round,
+        });
+    }
+    Ok(())
+}
+
+// A proposal commits to the state its parent left; voters that applied the
+// same blocks must arrive at the same root before they precommit
+fn check_state_root(block: &StoredBlock, state_machine: &StateMachine) -> Result<(), VoteError> {
+    let expected = state_machine.state_root();
+    if block.state_root.as_deref() != Some(expected.as_str()) {
+        return Err(VoteError::StateRootMismatch { proposal_id: block.id, expected, got: block.state_root.clone() });
+    }
+    Ok(())
+}
 
-    pub fn propose(&mut self, payload: Bytes) -> BlockId {
-        let parent_id = self.finalized_block.clone();
+// Read-mostly chain data: blocks and the finalized head.
+#[derive(Debug, Default)]
+pub struct Chain {
+    blocks: HashMap<BlockId, StoredBlock>,
+    payloads: PayloadStore,
+    finalized_block: Option<BlockId>,
+    // Finalized block ids indexed by height
+    finalized_chain: Vec<BlockId>,
+    certificates: HashMap<BlockId, QuorumCertificate>,
+    // Block carrying each finalized transaction
+    tx_index: HashMap<TxHash, BlockId>,
+    proposals_per_height: HashMap<u64, u64>,
+    // Proposal timestamps strictly increase, keeping ids distinct
+    last_timestamp: u64,
+    // Unix milliseconds; the system clock when unset
+    clock: Option<fn() -> u64>,
+}
+
+impl Chain {
+    // The round only advances past 0 with a timeout certificate for the
+    // round before it at this height
+    fn insert_proposal(
+        &mut self,
+        payload: Bytes,
+        proposer: ValidatorId,
+        timeout_certificate: Option<TimeoutCertificate>,
+        state_root: Option<String>,
+    ) -> BlockId {
+        let parent_id = self.finalized_block;
         let height = match parent_id {
             Some(ref id) => self.blocks.get(id).map(|b| b.height + 1).unwrap_or(0),
             None => 0,
         };
 
-        let block_content = format!(
-            "{:?}{:?}{}",
-            parent_id, payload, height
-        );
-        let id = blake3::hash(block_content.as_bytes()).to_string();
+        let timeout_certificate = timeout_certificate.filter(|tc| tc.height == height);
+        let round = timeout_certificate.as_ref().and_then(|tc| tc.round.next()).unwrap_or_default();
+        let now = match self.clock {
+            Some(clock) => clock(),
+            None => SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_millis() as u64).unwrap_or(0),
+        };
+        let timestamp = now.max(self.last_timestamp + 1);
+        self.last_timestamp = timestamp;
 
-        let block = Block {
-            id: id.clone(),
+        let id = block_id(&Header {
+            parent_id: parent_id.as_ref(),
+            payload: &payload,
+            height,
+            round,
+            proposer,
+            timestamp,
+            timeout_certificate: timeout_certificate.as_ref(),
+            state_root: state_root.as_deref(),
+        });
+        if self.blocks.contains_key(&id) {
+            return id;
+        }
+
+        let block = StoredBlock {
+            id,
             parent_id,
-            payload,
+            payload_hash: self.payloads.put(payload),
             height,
-            proposer: self.leader,
+            proposer,
+            round,
+            timestamp,
+            timeout_certificate,
+            state_root,
         };
+        *self.proposals_per_height.entry(height).or_default() += 1;
 
-        self.blocks.insert(id.clone(), block);
-        self.votes.insert(id.clone(), HashMap::new());
-        
+        self.blocks.insert(id, block);
         id
     }
 
-    pub fn vote(&mut self, proposal_id: BlockId, validator_id: ValidatorId, phase: VotePhase) -> bool {
-        if !self.validators.contains(&validator_id) {
-            return false;
+    // Re-adds a block read back from storage on top of the finalized chain
+    fn restore_finalized(&mut self, block: Block, certificate: Option<QuorumCertificate>) {
+        let height = block.height;
+        self.last_timestamp = self.last_timestamp.max(block.timestamp);
+        *self.proposals_per_height.entry(height).or_default() += 1;
+        if let Some(qc) = certificate {
+            self.certificates.insert(block.id, qc);
         }
+        self.finalized_chain.truncate(height as usize);
+        self.finalized_chain.push(block.id);
+        self.finalized_block = Some(block.id);
+        self.blocks.insert(block.id, StoredBlock {
+            id: block.id,
+            parent_id: block.parent_id,
+            payload_hash: self.payloads.put(block.payload),
+            height,
+            proposer: block.proposer,
+            round: block.round,
+            timestamp: block.timestamp,
+            timeout_certificate: block.timeout_certificate,
+            state_root: block.state_root,
+        });
+        self.index_transactions(&block.id);
+    }
 
-        if !self.blocks.contains_key(&proposal_id) {
-            return false;
+    pub fn contains(&self, id: &BlockId) -> bool {
+        self.blocks.contains_key(id)
+    }
+
+    // Height and round a proposal was made at
+    pub fn proposal_round(&self, id: &BlockId) -> Option<(u64, Round)> {
+        self.blocks.get(id).map(|b| (b.height, b.round))
+    }
+
+    pub fn get_block(&self, id: &BlockId) -> Option<Block> {
+        self.blocks.get(id).map(|b| self.to_block(b))
+    }
+
+    fn to_block(&self, stored: &StoredBlock) -> Block {
+        Block {
+            id: stored.id,
+            parent_id: stored.parent_id,
+            payload: self.payloads.get(&stored.payload_hash).unwrap_or_default(),
+            height: stored.height,
+            proposer: stored.proposer,
+            round: stored.round,
+            timestamp: stored.timestamp,
+            timeout_certificate: stored.timeout_certificate.clone(),
+            state_root: stored.state_root.clone(),
         }
+    }
 
-        let votes_for_proposal = self.votes.get_mut(&proposal_id).unwrap();
-        let phase_votes = votes_for_proposal.entry(phase.clone()).or_insert_with(HashSet::new);
-        
-        phase_votes.insert(validator_id);
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.payloads.compression_stats()
+    }
 
-        // Check if I can finalize
-        self.try_finalize(&proposal_id)
+    pub fn payload_store(&self) -> &PayloadStore {
+        &self.payloads
     }
 
-    fn try_finalize(&mut self, proposal_id: &BlockId) -> bool {
-        if let Some(votes) = self.votes.get(proposal_id) {
-            let precommit_votes = votes.get(&VotePhase::Precommit)
-                .map(|v| v.len())
-                .unwrap_or(0);
-            let commit_votes = votes.get(&VotePhase::Commit)
-                .map(|v| v.len())
-                .unwrap_or(0);
+    // Drops every block below `height`, releasing payload references;
+    // returns the pruned block ids.
+    fn prune_below(&mut self, height: u64) -> Vec<BlockId> {
+        let pruned: Vec<BlockId> = self.blocks
+            .values()
+            .filter(|b| b.height < height)
+            .map(|b| b.id)
+            .collect();
 
-            let quorum = (self.validators.len() * 2) / 3 + 1;
+        for id in &pruned {
+            if let Some(block) = self.blocks.remove(id) {
+                self.payloads.release(&block.payload_hash);
+            }
+            self.certificates.remove(id);
+        }
+        if !pruned.is_empty() {
+            self.tx_index.retain(|_, id| self.blocks.contains_key(id));
+        }
+        self.proposals_per_height.retain(|&h, _| h >= height);
+        pruned
+    }
 
-            if precommit_votes >= quorum && commit_votes >= quorum {
-                self.finalized_block = Some(proposal_id.clone());
-                return true;
+    pub fn finalized(&self) -> Option<BlockId> {
+        self.finalized_block
+    }
+
+    // Height the next proposal extends the chain to
+    pub fn next_height(&self) -> u64 {
+        self.finalized_height().map(|h| h + 1).unwrap_or(0)
+    }
+
+    pub fn proposals_at(&self, height: u64) -> u64 {
+        self.proposals_per_height.get(&height).copied().unwrap_or(0)
+    }
+
+    // Returns the newly finalized block ids, oldest first; empty if the block
+    // was already finalized (or is unknown)
+    fn mark_finalized(&mut self, id: BlockId, signers: Vec<ValidatorId>) -> Vec<BlockId> {
+        let Some(height) = self.blocks.get(&id).map(|b| b.height as usize) else {
+            return Vec::new();
+        };
+        // Late votes on an already finalized block must not rewind the head
+        if self.finalized_chain.get(height) == Some(&id) {
+            return Vec::new();
+        }
+
+        self.certificates.insert(id, QuorumCertificate {
+            block_id: id,
+            height: height as u64,
+            signers,
+        });
+
+        // Walk parents back to where the new head joins the existing finalized chain
+        let mut tail = Vec::new();
+        let mut cursor = Some(id);
+        while let Some(block) = cursor.as_ref().and_then(|c| self.blocks.get(c)) {
+            let height = block.height as usize;
+            if self.finalized_chain.get(height) == Some(&block.id) {
+                break;
             }
+            tail.push(block.id);
+            cursor = block.parent_id;
         }
-        false
+
+        let join_height = height + 1 - tail.len();
+        tail.reverse();
+        self.finalized_chain.truncate(join_height);
+        self.finalized_chain.extend(tail.iter().cloned());
+        for id in &tail {
+            self.index_transactions(id);
+        }
+        self.finalized_block = Some(id);
+        tail
     }
 
-    pub fn finalize(&self) -> Option<BlockId> {
-        self.finalized_block.clone()
+    pub fn finalized_height(&self) -> Option<u64> {
+        (self.finalized_chain.len() as u64).checked_sub(1)
     }
 
-    pub fn get_leader(&self, round: u64) -> ValidatorId {
-        self.validators[round as usize % self.validators.len()]
+    pub fn finalized_at(&self, height: u64) -> Option<&BlockId> {
+        self.finalized_chain.get(height as usize)
     }
 
-    pub fn get_validators(&self) -> &[ValidatorId] {
-        &self.validators
+    pub fn certificate(&self, id: &BlockId) -> Option<&QuorumCertificate> {
+        self.certificates.get(id)
+    }
+
+    // Hash chain over finalized block ids up to and including `height`
+    pub fn state_hash(&self, height: u64) -> Option<String> {
+        let ids = self.finalized_chain.get(..=height as usize)?;
+        let mut hasher = blake3::Hasher::new();
+        for id in ids {
+            hasher.update(id.to_hex().as_bytes());
+        }
+        Some(hasher.finalize().to_hex().to_string())
+    }
+
+    pub fn finalized_from(&self, from_height: u64) -> Vec<Block> {
+        self.finalized_chain
+            .iter()
+            .skip(from_height as usize)
+            .filter_map(|id| self.blocks.get(id).map(|b| self.to_block(b)))
+            .collect()
+    }
+}
+
+// Write-hot voting data, kept apart from the chain so reads don't wait on votes.
+#[derive(Debug, Default)]
+pub struct VoteBook {
+    votes: HashMap<BlockId, HashMap<VotePhase, HashSet<ValidatorId>>>,
+    timings: HashMap<BlockId, ProposalTiming>,
+    timeouts: TimeoutBook,
+    // The proposal each validator voted for per height, round and phase
+    cast: HashMap<(u64, Round, VotePhase, ValidatorId), BlockId>,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct ProposalTiming {
+    proposed_at: Instant,
+    first_vote: Option<Instant>,
+    last_vote: Option<Instant>,
+}
+
+impl VoteBook {
+    fn open(&mut self, proposal_id: BlockId) {
+        self.timings.entry(proposal_id).or_insert(ProposalTiming {
+            proposed_at: Instant::now(),
+            first_vote: None,
+            last_vote: None,
+        });
+        self.votes.entry(proposal_id).or_default();
+    }
+
+    fn close(&mut self, proposal_id: &BlockId) {
+        self.votes.remove(proposal_id);
+        self.timings.remove(proposal_id);
+    }
+
+    // Refuses a vote for a different proposal than one the validator already
+    // voted for in the same height, round and phase. Checks every phase
+    // before claiming any, so a combined vote is taken whole or not at all.
+    fn claim(
+        &mut self,
+        height: u64,
+        round: Round,
+        validator_id: ValidatorId,
+        phases: &[VotePhase],
+        proposal_id: &BlockId,
+    ) -> Result<(), VoteError> {
+        for phase in phases {
+            match self.cast.get(&(height, round, phase.clone(), validator_id)) {
+                Some(earlier) if earlier != proposal_id => {
+                    return Err(VoteError::AlreadyVoted {
+                        height,
+                        round,
+                        phase: phase.clone(),
+                        proposal_id: *earlier,
+                    });
+                }
+                _ => {}
+            }
+        }
+        for phase in phases {
+            self.cast.insert((height, round, phase.clone(), validator_id), *proposal_id);
+        }
+        Ok(())
+    }
+
+    // Rough heap bytes held, for memory accounting
+    fn heap_bytes(&self) -> usize {
+        let cast_entry = size_of::<((u64, Round, VotePhase, ValidatorId), BlockId)>();
+        let cast = self.cast.len() * cast_entry;
+        let proposals: HashSet<&BlockId> = self.votes.keys().chain(self.timings.keys()).collect();
+        proposals.into_iter().map(|id| self.proposal_bytes(id)).sum::<usize>() + cast
+    }
+
+    // The vote sets and timing kept for one proposal
+    fn proposal_bytes(&self, proposal_id: &BlockId) -> usize {
+        let key = size_of::<BlockId>();
+        let votes = self.votes.get(proposal_id).map_or(0, |phases| {
+            key + phases.values().map(|v| v.len() * size_of::<ValidatorId>() + size_of::<VotePhase>()).sum::<usize>()
+        });
+        let timing = if self.timings.contains_key(proposal_id) { key + size_of::<ProposalTiming>() } else { 0 };
+        votes + timing
+    }
+
+    // Drops the vote sets of proposals at decided heights, lowest first,
+    // until at least `bytes` are freed; finalized blocks keep their signers
+    // in their certificates. Returns the bytes freed.
+    fn evict_decided(&mut self, chain: &Chain, bytes: usize) -> usize {
+        let next_height = chain.next_height();
+        let mut decided: Vec<(u64, BlockId)> = self
+            .votes
+            .keys()
+            .filter_map(|id| chain.blocks.get(id).map(|b| (b.height, *id)))
+            .filter(|(height, _)| *height < next_height)
+            .collect();
+        decided.sort();
+        let mut freed = 0;
+        for (_, id) in decided {
+            if freed >= bytes {
+                break;
+            }
+            freed += self.proposal_bytes(&id);
+            self.close(&id);
+        }
+        freed
+    }
+
+    fn prune_cast_below(&mut self, height: u64) {
+        self.cast.retain(|&(h, ..), _| h >= height);
+    }
+
+    fn record(&mut self, proposal_id: &BlockId, validator_id: ValidatorId, phase: VotePhase) {
+        if let Some(votes_for_proposal) = self.votes.get_mut(proposal_id) {
+            votes_for_proposal.entry(phase).or_default().insert(validator_id);
+        }
+        if let Some(timing) = self.timings.get_mut(proposal_id) {
+            let now = Instant::now();
+            timing.first_vote.get_or_insert(now);
+            timing.last_vote = Some(now);
+        }
+    }
+
+    // (proposal-to-now, first-to-last vote) in milliseconds
+    fn elapsed_ms(&self, proposal_id: &BlockId) -> Option<(f64, f64)> {
+        let timing = self.timings.get(proposal_id)?;
+        let latency = timing.proposed_at.elapsed().as_secs_f64() * 1000.0;
+        let spread = match (timing.first_vote, timing.last_vote) {
+            (Some(first), Some(last)) => last.duration_since(first).as_secs_f64() * 1000.0,
+            _ => 0.0,
+        };
+        Some((latency, spread))
+    }
+
+    // More than two thirds of the total voting power in both phases
+    fn has_weighted_quorum(&self, proposal_id: &BlockId, power: impl Fn(ValidatorId) -> u64, total: u64) -> bool {
+        let Some(votes) = self.votes.get(proposal_id) else {
+            return false;
+        };
+        let weight = |phase: &VotePhase| -> u64 {
+            votes.get(phase).map(|v| v.iter().fold(0, |w: u64, &id| w.saturating_add(power(id)))).unwrap_or(0)
+        };
+
+        total > 0
+            && 3 * weight(&VotePhase::Precommit) as u128 > 2 * total as u128
+            && 3 * weight(&VotePhase::Commit) as u128 > 2 * total as u128
+    }
+
+    fn commit_signers(&self, proposal_id: &BlockId) -> Vec<ValidatorId> {
+        self.signers(proposal_id, &VotePhase::Commit)
+    }
+
+    fn signers(&self, proposal_id: &BlockId, phase: &VotePhase) -> Vec<ValidatorId> {
+        let mut signers: Vec<ValidatorId> = self.votes.get(proposal_id)
+            .and_then(|v| v.get(phase))
+            .map(|v| v.iter().copied().collect())
+            .unwrap_or_default();
+        signers.sort_unstable();
+        signers
     }
 }
 
-// Thread-safe wrapper
+// The consensus core, shared between threads. The chain sits behind a
+// RwLock so API reads never queue behind voting; votes have their own mutex.
+// Lock order: votes, chain, state machine, mempool.
 #[derive(Clone)]
 pub struct ConsensusState {
-    inner: Arc<Mutex<Consensus>>,
+    validators: Arc<Vec<ValidatorId>>,
+    chain: Arc<RwLock<Chain>>,
+    votes: Arc<Mutex<VoteBook>>,
+    checkpointer: Option<Arc<Checkpointer>>,
+    beacon: Option<Arc<Beacon>>,
+    metrics: Arc<Mutex<ConsensusMetrics>>,
+    state_machine: Arc<RwLock<StateMachine>>,
+    mempool: Arc<Mutex<Mempool>>,
+    store: Option<StoreWriter>,
+    pruning: Pruning,
 }
 
 impl ConsensusState {
     pub fn new(validators: Vec<ValidatorId>) -> Self {
+        let state_machine = StateMachine::genesis(&validators, GENESIS_STAKE);
+
         Self {
-            inner: Arc::new(Mutex::new(Consensus::new(validators))),
+            validators: Arc::new(validators),
+            chain: Arc::new(RwLock::new(Chain::default())),
+            votes: Arc::new(Mutex::new(VoteBook::default())),
+            checkpointer: None,
+            beacon: None,
+            metrics: Arc::new(Mutex::new(ConsensusMetrics::default())),
+            state_machine: Arc::new(RwLock::new(state_machine)),
+            mempool: Arc::new(Mutex::new(Mempool::default())),
+            store: None,
+            pruning: Pruning::Archive,
+        }
+    }
+
+    pub fn with_state_machine(mut self, state_machine: StateMachine) -> Self {
+        self.state_machine = Arc::new(RwLock::new(state_machine));
+        self
+    }
+
+    pub fn state_machine(&self) -> std::sync::RwLockReadGuard<'_, StateMachine> {
+        self.state_machine.read().unwrap()
+    }
+
+    // Genesis public keys of the validators; call before `restore` so that
+    // replayed key rotations apply on top of them
+    pub fn with_validator_keys(self, keys: impl IntoIterator<Item = (ValidatorId, VerifyingKey)>) -> Self {
+        self.state_machine.write().unwrap().keys = KeyRegistry::genesis(keys);
+        self
+    }
+
+    pub fn validator_key(&self, validator: ValidatorId) -> Option<KeyEntry> {
+        self.state_machine.read().unwrap().keys.get(validator)
+    }
+
+    pub fn read_state(&self, key: &str) -> StateRead {
+        self.state_machine.read().unwrap().read(key)
+    }
+
+    // Bounds for the adaptive round timeout
+    pub fn with_round_timeout(self, round_timeout: RoundTimeout) -> Self {
+        self.metrics.lock().unwrap().round_timeout = round_timeout;
+        self
+    }
+
+    pub fn round_timeout_ms(&self) -> u64 {
+        self.metrics.lock().unwrap().round_timeout.current_ms()
+    }
+
+    pub fn with_mempool(mut self, mempool: Mempool) -> Self {
+        self.mempool = Arc::new(Mutex::new(mempool));
+        self
+    }
+
+    pub fn submit_transaction(&self, tx: Transaction) -> Result<TxHash, MempoolError> {
+        let state_machine = self.state_machine.read().unwrap();
+        if let Some(height) = state_machine.replay.finalized_at(&tx.hash()) {
+            return Err(MempoolError::AlreadyFinalized { height });
+        }
+        let (bytes, max) = (TxBatch::EMPTY_LEN + tx.encoded_len(), state_machine.params.max_block_bytes);
+        if bytes > max {
+            return Err(MempoolError::TooLarge { bytes, max });
         }
+        let next_nonce = state_machine.next_nonce(&tx.sender);
+        self.mempool.lock().unwrap().insert(tx, next_nonce)
     }
 
-    pub fn propose(&self, payload: Bytes) -> BlockId {
-        self.inner.lock().unwrap().propose(payload)
+    pub fn mempool_len(&self) -> usize {
+        self.mempool.lock().unwrap().len()
+    }
+
+    pub fn mempool_lanes(&self) -> Vec<LaneDepth> {
+        self.mempool.lock().unwrap().lanes()
+    }
+
+    pub fn with_mempool_ttl(self, ttl: Duration) -> Self {
+        {
+            let mut mempool = self.mempool.lock().unwrap();
+            *mempool = std::mem::take(&mut *mempool).with_ttl(ttl);
+        }
+        self
+    }
+
+    // A page of pending transactions ordered by hash, after the `after` hash
+    pub fn pending_transactions(&self, after: Option<&str>, limit: usize) -> Vec<PendingTx> {
+        self.mempool.lock().unwrap().list(after, limit)
+    }
+
+    pub fn remove_transaction(&self, hash: &str) -> Option<Transaction> {
+        self.mempool.lock().unwrap().remove(hash)
+    }
+
+    // Drops and returns transactions pending for longer than the mempool TTL
+    pub fn expire_mempool(&self) -> Vec<Transaction> {
+        self.mempool.lock().unwrap().expire(Instant::now())
+    }
+
+    pub fn params(&self) -> ConsensusParams {
+        self.state_machine.read().unwrap().params
+    }
+
+    // Proposes a block of ready transactions within the block limits, or
+    // nothing if none are ready or the pool already proposed in the current
+    // round. The transactions stay in the mempool until finalized, so the
+    // next round, or the next height if another proposal wins, offers them
+    // again.
+    pub fn propose_from_mempool(&self) -> Option<BlockId> {
+        let position = {
+            let votes = self.votes.lock().unwrap();
+            let height = self.chain.read().unwrap().next_height();
+            (height, votes.timeouts.current_round(height))
+        };
+        let txs = {
+            let state_machine = self.state_machine.read().unwrap();
+            let params = state_machine.params;
+            let mempool = self.mempool.lock().unwrap();
+            if mempool.proposed_at == Some(position) {
+                return None;
+            }
+
+            // Skip what does not fit the byte limit, with the sender's later
+            // transactions, which could not execute without it
+            let mut size = TxBatch::EMPTY_LEN;
+            let mut skipped = HashSet::new();
+            let mut txs = Vec::new();
+            for tx in mempool.ready(|s| state_machine.next_nonce(s), usize::MAX) {
+                if txs.len() == params.max_txs_per_block {
+                    break;
+                }
+                let bytes = tx.encoded_len() + usize::from(!txs.is_empty());
+                if skipped.contains(&tx.sender) || size + bytes > params.max_block_bytes {
+                    skipped.insert(tx.sender.clone());
+                    continue;
+                }
+                size += bytes;
+                txs.push(tx);
+            }
+            txs
+        };
+        if txs.is_empty() {
+            return None;
+        }
+        let id = self.propose(TxBatch { txs }.encode()).ok()?;
+        let position = self.proposal_round(&id);
+        self.mempool.lock().unwrap().proposed_at = position;
+        Some(id)
     }
 
-    pub fn vote(&self, proposal_id: BlockId, validator_id: ValidatorId, phase: VotePhase) -> bool {
-        self.inner.lock().unwrap().vote(proposal_id, validator_id, phase)
+    // Every finalization is also committed to the store, off the vote path
+    pub fn with_store(mut self, writer: StoreWriter) -> Self {
+        self.store = Some(writer);
+        self
+    }
+
+    // Proposal timestamps are read from `clock` instead of the system clock.
+    // Since they enter block ids, nodes on the same fixed clock given the
+    // same proposals build identical chains
+    pub fn with_clock(self, clock: fn() -> u64) -> Self {
+        self.chain.write().unwrap().clock = Some(clock);
+        self
+    }
+
+    // Drops blocks below the retained range after each finalization
+    pub fn with_pruning(mut self, pruning: Pruning) -> Self {
+        self.pruning = pruning;
+        self
+    }
+
+    pub fn pruning(&self) -> Pruning {
+        self.pruning
+    }
+
+    // Lowest finalized height still held, once anything is finalized
+    pub fn retained_from(&self) -> Option<u64> {
+        self.finalized_height().map(|height| self.pruning.retain_from(height))
+    }
+
+    // Waits until every finalization so far is committed; for shutdown
+    pub async fn flush_store(&self) {
+        if let Some(writer) = &self.store {
+            writer.flush().await;
+        }
+    }
+
+    // Rebuilds the finalized chain and replicated state from a store's
+    // contents; call before any proposals are made.
+    pub fn restore(self, snapshot: StoreSnapshot) -> Self {
+        {
+            let mut chain = self.chain.write().unwrap();
+            let mut state_machine = self.state_machine.write().unwrap();
+            let mut certificates: HashMap<BlockId, QuorumCertificate> =
+                snapshot.certificates.into_iter().map(|qc| (qc.block_id, qc)).collect();
+            for block in snapshot.blocks {
+                let certificate = certificates.remove(&block.id);
+                let voters = certificate.as_ref().map(|qc| qc.signers.clone()).unwrap_or_default();
+                state_machine.apply_finalized(&block, &voters);
+                chain.restore_finalized(block, certificate);
+            }
+            if let Some(checkpointer) = &self.checkpointer {
+                checkpointer.observe(&chain, &self.validators);
+            }
+            if let Some(beacon) = &self.beacon {
+                beacon.observe(&chain);
+            }
+            if let Some(height) = chain.finalized_height() {
+                chain.prune_below(self.pruning.retain_from(height));
+            }
+        }
+        self
+    }
+
+    pub fn with_checkpointer(mut self, checkpointer: Checkpointer) -> Self {
+        self.checkpointer = Some(Arc::new(checkpointer));
+        self
+    }
+
+    pub fn latest_checkpoint(&self) -> Option<Checkpoint> {
+        self.checkpointer.as_ref().and_then(|c| c.latest())
+    }
+
+    pub fn with_beacon(mut self, beacon: Beacon) -> Self {
+        self.beacon = Some(Arc::new(beacon));
+        self
+    }
+
+    pub fn beacon(&self) -> Option<&Beacon> {
+        self.beacon.as_deref()
+    }
+
+    // Rejects payloads over the current block limits
+    pub fn propose(&self, payload: Bytes) -> Result<BlockId, ProposalError> {
+        let tx_count = TxBatch::decode(&payload).map(|b| b.txs.len()).unwrap_or(0);
+        self.params().check(payload.len(), tx_count)?;
+
+        let mut votes = self.votes.lock().unwrap();
+        let mut chain = self.chain.write().unwrap();
+        let height = chain.next_height();
+        let timeout_certificate = votes.timeouts.certificate(height).cloned();
+        let (leader, state_root) = {
+            let state_machine = self.state_machine.read().unwrap();
+            (state_machine.leaders.leader(height, votes.timeouts.current_round(height)), state_machine.state_root())
+        };
+        let id = chain.insert_proposal(payload, leader, timeout_certificate, Some(state_root));
+        votes.open(id);
+        Ok(id)
+    }
+
+    // Nil vote: `validator_id` saw no usable proposal in `round` at `height`.
+    // Returns the certificate once the stake behind the round's timeouts
+    // passes two thirds, moving the height to the next round.
+    pub fn vote_timeout(&self, height: u64, round: Round, validator_id: ValidatorId) -> Option<TimeoutCertificate> {
+        if !self.validators.contains(&validator_id) {
+            return None;
+        }
+
+        let mut votes = self.votes.lock().unwrap();
+        if self.chain.read().unwrap().next_height() != height {
+            return None;
+        }
+        let staking = &self.state_machine.read().unwrap().staking;
+        votes.timeouts.record(height, round, validator_id, |v| staking.voting_power(v), staking.total_power())
+    }
+
+    // Round the next proposal at the undecided height will use
+    pub fn current_round(&self) -> Round {
+        let votes = self.votes.lock().unwrap();
+        let height = self.chain.read().unwrap().next_height();
+        votes.timeouts.current_round(height)
+    }
+
+    pub fn timeout_certificate(&self) -> Option<TimeoutCertificate> {
+        let votes = self.votes.lock().unwrap();
+        let height = self.chain.read().unwrap().next_height();
+        votes.timeouts.certificate(height).cloned()
+    }
+
+    // Ok(true) once the vote finalizes the proposal
+    pub fn vote(&self, vote: Vote) -> Result<bool, VoteError> {
+        self.vote_phases(vote.proposal_id, vote.height, vote.round, vote.validator_id, &[vote.phase])
+    }
+
+    pub fn vote_combined(
+        &self,
+        proposal_id: BlockId,
+        height: u64,
+        round: Round,
+        validator_id: ValidatorId,
+    ) -> Result<bool, VoteError> {
+        self.vote_phases(proposal_id, height, round, validator_id, &[VotePhase::Precommit, VotePhase::Commit])
+    }
+
+    // Height and round a proposal was made at, which votes for it must name
+    pub fn proposal_round(&self, id: &BlockId) -> Option<(u64, Round)> {
+        self.chain.read().unwrap().proposal_round(id)
+    }
+
+    // Both phases land under one hold of the vote lock, so no reader or other
+    // voter ever observes half of a combined vote.
+    fn vote_phases(
+        &self,
+        proposal_id: BlockId,
+        height: u64,
+        round: Round,
+        validator_id: ValidatorId,
+        phases: &[VotePhase],
+    ) -> Result<bool, VoteError> {
+        if !self.validators.contains(&validator_id) {
+            return Err(VoteError::UnknownValidator(validator_id));
+        }
+
+        let mut votes = self.votes.lock().unwrap();
+        {
+            let chain = self.chain.read().unwrap();
+            let block = chain.blocks.get(&proposal_id).ok_or(VoteError::UnknownProposal(proposal_id))?;
+            // Rounds that timed out are over; everyone has moved on
+            let active_height = chain.next_height();
+            check_vote_round(block, height, round, active_height, votes.timeouts.current_round(active_height))?;
+            let state_machine = self.state_machine.read().unwrap();
+            if phases.contains(&VotePhase::Precommit) {
+                check_state_root(block, &state_machine)?;
+            }
+            let staking = &state_machine.staking;
+            let justified = timeout::check_justification(
+                block.height,
+                block.round,
+                block.timeout_certificate.as_ref(),
+                |v| staking.voting_power(v),
+                staking.total_power(),
+            );
+            if justified.is_err() {
+                return Err(VoteError::Unjustified(proposal_id));
+            }
+        }
+        votes.claim(height, round, validator_id, phases, &proposal_id)?;
+
+        for phase in phases {
+            votes.record(&proposal_id, validator_id, phase.clone());
+        }
+
+        let has_quorum = {
+            let staking = &self.state_machine.read().unwrap().staking;
+            votes.has_weighted_quorum(&proposal_id, |v| staking.voting_power(v), staking.total_power())
+        };
+        if has_quorum {
+            let signers = votes.commit_signers(&proposal_id);
+            let timing = votes.elapsed_ms(&proposal_id);
+            let mut chain = self.chain.write().unwrap();
+            // Every earlier round at this height was skipped
+            let round = chain.blocks.get(&proposal_id).map(|b| b.round);
+            let newly_finalized = chain.mark_finalized(proposal_id, signers);
+            if !newly_finalized.is_empty() {
+                let blocks: Vec<Block> = newly_finalized.iter().filter_map(|id| chain.get_block(id)).collect();
+                {
+                    let mut state_machine = self.state_machine.write().unwrap();
+                    for block in &blocks {
+                        let voters = chain.certificate(&block.id).map(|qc| qc.signers.as_slice()).unwrap_or(&[]);
+                        state_machine.apply_finalized(block, voters);
+                    }
+                    self.mempool.lock().unwrap().prune(|s| state_machine.next_nonce(s));
+                }
+                if let Some(store) = &self.store {
+                    let certificates = blocks.iter().filter_map(|b| chain.certificate(&b.id).cloned()).collect();
+                    let head = chain.finalized();
+                    let votes = head.iter().map(|id| VoteSet {
+                        block_id: *id,
+                        precommit: votes.signers(id, &VotePhase::Precommit),
+                        commit: votes.commit_signers(id),
+                    });
+                    store.submit(WriteBatch { votes: votes.collect(), blocks, certificates, head });
+                }
+                votes.timeouts.prune_below(chain.next_height());
+                votes.prune_cast_below(chain.next_height());
+                if let (Some((latency, spread)), Some(round)) = (timing, round) {
+                    self.metrics.lock().unwrap().record_finalization(latency, spread, round.0);
+                }
+                if let Some(checkpointer) = &self.checkpointer {
+                    checkpointer.observe(&chain, &self.validators);
+                }
+                if let Some(beacon) = &self.beacon {
+                    beacon.observe(&chain);
+                }
+                if let Some(height) = chain.finalized_height() {
+                    for id in chain.prune_below(self.pruning.retain_from(height)) {
+                        votes.close(&id);
+                    }
+                }
+            }
+            return Ok(true);
+        }
+        Ok(false)
+    }
+
+    // Applies votes that already passed signature verification, e.g. the
+    // output of `VerificationPool::verify`. Returns true if any finalized.
+    pub fn apply_verified(&self, votes: Vec<Vote>) -> bool {
+        let mut finalized = false;
+        for vote in votes {
+            finalized |= self.vote(vote).unwrap_or(false);
+        }
+        finalized
+    }
+
+    // Checks signatures against the registered validator keys and applies
+    // the votes that pass. Returns true if any finalized.
+    pub fn apply_signed(&self, pool: &VerificationPool, votes: Vec<SignedVote>) -> bool {
+        // A snapshot, so the registry isn't locked while the votes finalize
+        let keys = self.state_machine().keys.clone();
+        self.apply_verified(pool.verify(&keys, votes))
     }
 
     pub fn finalize(&self) -> Option<BlockId> {
-        self.inner.lock().unwrap().finalize()
+        self.chain.read().unwrap().finalized()
+    }
+
+    pub fn finalized_height(&self) -> Option<u64> {
+        self.chain.read().unwrap().finalized_height()
+    }
+
+    pub fn metrics(&self) -> MetricsSnapshot {
+        self.metrics.lock().unwrap().snapshot()
+    }
+
+    pub fn render_metrics(&self) -> String {
+        self.metrics.lock().unwrap().render_prometheus()
+    }
+
+    // Rough heap bytes of the pending transactions, for memory accounting
+    pub fn mempool_bytes(&self) -> usize {
+        self.mempool.lock().unwrap().heap_bytes()
+    }
+
+    pub fn evict_mempool(&self, bytes: usize) -> usize {
+        self.mempool.lock().unwrap().evict(bytes)
+    }
+
+    // Rough heap bytes of the vote sets and vote records
+    pub fn vote_bytes(&self) -> usize {
+        self.votes.lock().unwrap().heap_bytes()
+    }
+
+    // Only votes at decided heights can go; votes still deciding a height
+    // are never evicted
+    pub fn evict_votes(&self, bytes: usize) -> usize {
+        let mut votes = self.votes.lock().unwrap();
+        votes.evict_decided(&self.chain.read().unwrap(), bytes)
+    }
+
+    pub fn get_block(&self, id: &BlockId) -> Option<Block> {
+        self.chain.read().unwrap().get_block(id)
+    }
+
+    pub fn certificate(&self, id: &BlockId) -> Option<QuorumCertificate> {
+        self.chain.read().unwrap().certificate(id).cloned()
+    }
+
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.chain.read().unwrap().compression_stats()
+    }
+
+    // Removes blocks below `height` and their votes; payloads shared with
+    // surviving blocks stay in the store until their last reference goes.
+    pub fn prune_below(&self, height: u64) -> usize {
+        let mut votes = self.votes.lock().unwrap();
+        let pruned = self.chain.write().unwrap().prune_below(height);
+        for id in &pruned {
+            votes.close(id);
+        }
+        pruned.len()
+    }
+
+    // Snapshot of the finalized chain from `from_height`; the lock is released
+    // before the caller starts iterating.
+    pub fn iter_finalized(&self, from_height: u64) -> impl Iterator<Item = Block> {
+        self.chain.read().unwrap().finalized_from(from_height).into_iter()
+    }
+
+    pub fn get_validators(&self) -> &[ValidatorId] {
+        &self.validators
     }
 }
 
@@ -163,45 +1207,334 @@ impl ConsensusState {
 mod tests {
     use super::*;
 
+    fn ballot(position: (u64, Round), proposal_id: &BlockId, validator_id: ValidatorId, phase: VotePhase) -> Vote {
+        Vote { proposal_id: *proposal_id, height: position.0, round: position.1, validator_id, phase }
+    }
+
+    // Combined votes from `validators` for a proposal in its own round
+    fn vote_all(state: &ConsensusState, proposal_id: &BlockId, validators: impl IntoIterator<Item = u32>) -> bool {
+        let (height, round) = state.proposal_round(proposal_id).unwrap();
+        let mut finalized = false;
+        for validator in validators {
+            finalized |= state.vote_combined(*proposal_id, height, round, ValidatorId(validator)).unwrap();
+        }
+        finalized
+    }
+
+    // Four validators whose first proposer earns enough to bond 400
+    fn funded_state() -> ConsensusState {
+        let validators: Vec<ValidatorId> = (0..4).map(ValidatorId).collect();
+        let schedule = RewardSchedule { proposer: 400, voter: 1, halving_interval: None };
+        let state_machine = StateMachine::genesis(&validators, GENESIS_STAKE).with_reward_schedule(schedule);
+        let state = ConsensusState::new(validators).with_state_machine(state_machine);
+        let funding = state.propose(b"funding".to_vec()).unwrap();
+        assert!(vote_all(&state, &funding, 0..3));
+        state
+    }
+
     #[test]
     fn test_consensus_quorum() {
         // N=4 validators, f=1 faulty
-        let validators = vec![0, 1, 2, 3];
-        let mut consensus = Consensus::new(validators.clone());
+        let validators: Vec<ValidatorId> = (0..4).map(ValidatorId).collect();
+        let state = ConsensusState::new(validators.clone());
 
         // Leader proposes a block
-        let proposal_id = consensus.propose(b"test payload".to_vec());
+        let proposal_id = state.propose(b"test payload".to_vec()).unwrap();
+        let position = state.proposal_round(&proposal_id).unwrap();
 
         // Simulate 3 honest validators voting (excluding 1 faulty)
-        let honest_validators = vec![0, 1, 2]; // 3 out of 4 = 75% > 66%
+        let honest_validators = &validators[..3]; // 3 out of 4 = 75% > 66%
 
         // Precommit phase
-        for &validator in &honest_validators {
-            consensus.vote(proposal_id.clone(), validator, VotePhase::Precommit);
+        for &validator in honest_validators {
+            state.vote(ballot(position, &proposal_id, validator, VotePhase::Precommit)).unwrap();
         }
 
         // Commit phase  
-        for &validator in &honest_validators {
-            consensus.vote(proposal_id.clone(), validator, VotePhase::Commit);
+        for &validator in honest_validators {
+            state.vote(ballot(position, &proposal_id, validator, VotePhase::Commit)).unwrap();
         }
 
         // Should finalize with honest quorum
-        assert_eq!(consensus.finalize(), Some(proposal_id));
+        assert_eq!(state.finalize(), Some(proposal_id));
     }
 
     #[test]
     fn test_insufficient_votes() {
-        let validators = vec![0, 1, 2, 3];
-        let mut consensus = Consensus::new(validators);
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect());
 
-        let proposal_id = consensus.propose(b"test".to_vec());
+        let proposal_id = state.propose(b"test".to_vec()).unwrap();
+        let position = state.proposal_round(&proposal_id).unwrap();
 
         // Only 2 votes (50%) - should not finalize
-        consensus.vote(proposal_id.clone(), 0, VotePhase::Precommit);
-        consensus.vote(proposal_id.clone(), 1, VotePhase::Precommit);
-        consensus.vote(proposal_id.clone(), 0, VotePhase::Commit);
-        consensus.vote(proposal_id.clone(), 1, VotePhase::Commit);
+        for validator in [ValidatorId(0), ValidatorId(1)] {
+            state.vote(ballot(position, &proposal_id, validator, VotePhase::Precommit)).unwrap();
+            state.vote(ballot(position, &proposal_id, validator, VotePhase::Commit)).unwrap();
+        }
 
-        assert_eq!(consensus.finalize(), None);
+        assert_eq!(state.finalize(), None);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_reads_do_not_wait_on_vote_lock() {
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect());
+        let proposal_id = state.propose(b"shared".to_vec()).unwrap();
+
+        // Hold the vote lock; chain reads must still go through
+        let _votes = state.votes.lock().unwrap();
+        assert!(state.get_block(&proposal_id).is_some());
+        assert_eq!(state.finalize(), None);
+    }
+
+    #[test]
+    fn test_iter_finalized_from_height() {
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect());
+
+        let mut finalized = Vec::new();
+        for payload in [b"a".to_vec(), b"b".to_vec(), b"c".to_vec()] {
+            let id = state.propose(payload).unwrap();
+            let position = state.proposal_round(&id).unwrap();
+            for validator in (0..3).map(ValidatorId) {
+                state.vote(ballot(position, &id, validator, VotePhase::Precommit)).unwrap();
+                state.vote(ballot(position, &id, validator, VotePhase::Commit)).unwrap();
+            }
+            finalized.push(id);
+        }
+
+        let iter = state.iter_finalized(1);
+        // A new proposal while the snapshot is alive must not deadlock
+        state.propose(b"d".to_vec()).unwrap();
+
+        let heights: Vec<u64> = iter.map(|b| b.height).collect();
+        assert_eq!(heights, vec![1, 2]);
+        assert_eq!(state.iter_finalized(0).last().map(|b| b.id), finalized.last().cloned());
+    }
+
+    #[test]
+    fn test_combined_votes_finalize() {
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect());
+        let proposal_id = state.propose(b"piggyback".to_vec()).unwrap();
+
+        let vote = |validator| state.vote_combined(proposal_id, 0, Round(0), ValidatorId(validator));
+        assert_eq!(vote(0), Ok(false));
+        assert_eq!(vote(1), Ok(false));
+        assert_eq!(vote(2), Ok(true));
+        assert_eq!(state.finalize(), Some(proposal_id));
+
+        // Unknown validators are still rejected
+        let other = state.propose(b"other".to_vec()).unwrap();
+        assert_eq!(
+            state.vote_combined(other, 1, Round(0), ValidatorId(9)),
+            Err(VoteError::UnknownValidator(ValidatorId(9)))
+        );
+    }
+
+    #[test]
+    fn test_bonded_stake_sets_voting_weight() {
+        let state = funded_state();
+        // Bonds beyond what validator 0 earned, u64::MAX included, finalize with no effect
+        for amount in [1_000, u64::MAX] {
+            let bond = format!(r#"{{"type":"bond","validator_id":0,"amount":{}}}"#, amount);
+            let id = state.propose(bond.into_bytes()).unwrap();
+            assert!(vote_all(&state, &id, 0..3));
+        }
+        assert_eq!(state.state_machine().staking.voting_power(ValidatorId(0)), 100);
+
+        let bond = state.propose(br#"{"type":"bond","validator_id":0,"amount":400}"#.to_vec()).unwrap();
+        vote_all(&state, &bond, 0..3);
+        assert_eq!(state.state_machine().staking.voting_power(ValidatorId(0)), 500);
+
+        // 600 of 800: validators 0 and 1 now finalize without a third
+        let next = state.propose(b"weighted".to_vec()).unwrap();
+        assert!(!vote_all(&state, &next, [0]));
+        assert!(vote_all(&state, &next, [1]));
+        assert_eq!(state.finalize(), Some(next));
+
+        // Leader 0 proposed all five heights and paid for the bond; the last
+        // certificate has 0 and 1
+        let rewards = &state.state_machine().rewards;
+        assert_eq!(rewards.balance(ValidatorId(0)), 5 * (400 + 1) - 400);
+        assert_eq!(rewards.balance(ValidatorId(1)), 5);
+        assert_eq!(rewards.balance(ValidatorId(3)), 0);
+    }
+
+    #[test]
+    fn test_mempool_keeps_proposed_transactions_until_finalized() {
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect());
+        let tx = Transaction { sender: "alice".into(), nonce: 0, data: "hi".into(), priority: 0 };
+        state.submit_transaction(tx.clone()).unwrap();
+
+        let first = state.propose_from_mempool().unwrap();
+        assert_eq!(state.mempool_len(), 1);
+        assert_eq!(state.propose_from_mempool(), None);
+
+        // The round times out without the proposal; the next one offers the transaction again
+        for validator in 0..3 {
+            state.vote_timeout(0, Round(0), ValidatorId(validator));
+        }
+        let second = state.propose_from_mempool().unwrap();
+        assert_eq!(state.proposal_round(&second), Some((0, Round(1))));
+        assert_eq!(state.get_block(&second).unwrap().payload, state.get_block(&first).unwrap().payload);
+
+        assert!(vote_all(&state, &second, 0..3));
+        assert_eq!(state.mempool_len(), 0);
+        assert_eq!(state.propose_from_mempool(), None);
+    }
+
+    #[test]
+    fn test_mempool_blocks_skip_transactions_that_do_not_fit() {
+        let validators: Vec<ValidatorId> = (0..4).map(ValidatorId).collect();
+        let params = ConsensusParams { max_block_bytes: 1024, ..ConsensusParams::default() };
+        let state = ConsensusState::new(validators.clone())
+            .with_state_machine(StateMachine::genesis(&validators, GENESIS_STAKE).with_params(params));
+        let tx = |sender: &str, nonce, size, priority| {
+            Transaction { sender: sender.into(), nonce, data: "x".repeat(size), priority }
+        };
+
+        let oversized = tx("alice", 0, 1024, 0);
+        let bytes = TxBatch { txs: vec![oversized.clone()] }.encode().len();
+        assert_eq!(state.submit_transaction(oversized), Err(MempoolError::TooLarge { bytes, max: 1024 }));
+
+        // bob's first transaction fits alone but not after alice's, so bob's
+        // second waits with it while carol's still goes in
+        for tx in [tx("alice", 0, 600, 30), tx("bob", 0, 600, 20), tx("bob", 1, 1, 20), tx("carol", 0, 1, 10)] {
+            state.submit_transaction(tx).unwrap();
+        }
+        let id = state.propose_from_mempool().unwrap();
+        let batch = TxBatch::decode(&state.get_block(&id).unwrap().payload).unwrap();
+        let taken: Vec<&str> = batch.txs.iter().map(|tx| tx.sender.as_str()).collect();
+        assert_eq!(taken, vec!["alice", "carol"]);
+
+        assert!(vote_all(&state, &id, 0..3));
+        let id = state.propose_from_mempool().unwrap();
+        assert_eq!(TxBatch::decode(&state.get_block(&id).unwrap().payload).unwrap().txs.len(), 2);
+    }
+
+    #[test]
+    fn test_reproposals_get_distinct_ids() {
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect());
+        let first = state.propose(b"retry".to_vec()).unwrap();
+        let second = state.propose(b"retry".to_vec()).unwrap();
+        assert_ne!(first, second);
+        // Without a timeout certificate both stay in round 0
+        assert_eq!(state.get_block(&first).unwrap().round, Round(0));
+        assert_eq!(state.get_block(&second).unwrap().round, Round(0));
+        assert!(state.get_block(&second).unwrap().timestamp > state.get_block(&first).unwrap().timestamp);
+
+        // A validator votes for one proposal per round and phase
+        assert!(!vote_all(&state, &first, [0]));
+        assert!(matches!(state.vote_combined(second, 0, Round(0), ValidatorId(0)), Err(VoteError::AlreadyVoted { .. })));
+
+        // Votes for one proposal do not count towards the other
+        assert!(vote_all(&state, &first, 1..3));
+        assert_eq!(state.finalize(), Some(first));
+        assert_eq!(
+            state.vote_combined(second, 0, Round(0), ValidatorId(3)),
+            Err(VoteError::WrongHeight { expected: 1, got: 0 })
+        );
+
+        // A node restarted with an empty chain does not reuse the old id
+        let restarted = ConsensusState::new((0..4).map(ValidatorId).collect());
+        std::thread::sleep(std::time::Duration::from_millis(2));
+        assert_ne!(restarted.propose(b"retry".to_vec()).unwrap(), first);
+    }
+
+    #[test]
+    fn test_timeout_votes_skip_a_round() {
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect());
+        let stalled = state.propose(b"stalled".to_vec()).unwrap();
+
+        // Wrong height, and two of four is not enough
+        let timeout = |height, validator| state.vote_timeout(height, Round(0), ValidatorId(validator));
+        assert!(timeout(1, 0).is_none());
+        assert!(timeout(0, 0).is_none());
+        assert!(timeout(0, 1).is_none());
+        let tc = timeout(0, 2).unwrap();
+        assert_eq!((tc.height, tc.round, tc.signers.clone()), (0, Round(0), (0..3).map(ValidatorId).collect()));
+        assert_eq!(state.current_round(), Round(1));
+        assert!(timeout(0, 3).is_none());
+
+        // The timed-out proposal can no longer gather votes
+        assert_eq!(
+            state.vote_combined(stalled, 0, Round(0), ValidatorId(0)),
+            Err(VoteError::WrongRound { expected: Round(1), got: Round(0) })
+        );
+        assert!(matches!(
+            state.vote_combined(stalled, 0, Round(1), ValidatorId(0)),
+            Err(VoteError::ProposalMismatch { round: Round(0), .. })
+        ));
+
+        let next = state.propose(b"next".to_vec()).unwrap();
+        let block = state.get_block(&next).unwrap();
+        assert_eq!((block.round, block.proposer), (Round(1), ValidatorId(1)));
+        assert_eq!(block.timeout_certificate, Some(tc));
+        assert!(vote_all(&state, &next, 0..3));
+        assert_eq!(state.finalize(), Some(next));
+        let record = state.state_machine().leaders.record(ValidatorId(0)).cloned().unwrap();
+        assert_eq!((record.turns, record.finalized), (1, 0));
+        assert!(state.timeout_certificate().is_none());
+        assert_eq!(state.current_round(), Round(0));
+    }
+
+    #[test]
+    fn test_precommit_checks_state_root() {
+        let state = funded_state();
+        let funded_root = state.state_machine().state_root();
+        let bond = state.propose(br#"{"type":"bond","validator_id":0,"amount":400}"#.to_vec()).unwrap();
+        assert_eq!(state.get_block(&bond).unwrap().state_root, Some(funded_root.clone()));
+        assert!(vote_all(&state, &bond, 0..3));
+
+        // The next proposal commits to the state the bond left
+        let next = state.propose(b"after bond".to_vec()).unwrap();
+        let root = state.get_block(&next).unwrap().state_root.unwrap();
+        assert_ne!(root, funded_root);
+        assert_eq!(root, state.state_machine().state_root());
+
+        // A proposal claiming any other state gets no precommits
+        state.chain.write().unwrap().blocks.get_mut(&next).unwrap().state_root = Some(funded_root.clone());
+        assert_eq!(
+            state.vote_combined(next, 2, Round(0), ValidatorId(0)),
+            Err(VoteError::StateRootMismatch { proposal_id: next, expected: root, got: Some(funded_root) })
+        );
+    }
+
+    // consensus/vectors/block_id.json; payloads are hex
+    #[derive(Deserialize)]
+    struct BlockIdVectors {
+        cases: Vec<BlockIdCase>,
+    }
+
+    #[derive(Deserialize)]
+    struct BlockIdCase {
+        name: String,
+        parent_id: Option<BlockId>,
+        payload: String,
+        height: u64,
+        round: Round,
+        proposer: ValidatorId,
+        timestamp: u64,
+        timeout_certificate: Option<TimeoutCertificate>,
+        #[serde(default)]
+        state_root: Option<String>,
+        block_id: BlockId,
+    }
+
+    #[test]
+    fn test_block_ids_match_golden_vectors() {
+        let vectors: BlockIdVectors = serde_json::from_str(include_str!("../vectors/block_id.json")).unwrap();
+        for case in vectors.cases {
+            let payload = hex::decode(&case.payload).unwrap();
+            let id = block_id(&Header {
+                parent_id: case.parent_id.as_ref(),
+                payload: &payload,
+                height: case.height,
+                round: case.round,
+                proposer: case.proposer,
+                timestamp: case.timestamp,
+                timeout_certificate: case.timeout_certificate.as_ref(),
+                state_root: case.state_root.as_deref(),
+            });
+            assert_eq!(id, case.block_id, "{}", case.name);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+// Memory accounting for the structures that grow with load. Each subsystem
+// registers a callback reporting its approximate heap bytes and, if it can
+// shed data, one that frees at least the given number of bytes and returns
+// how many it freed. `enforce` measures every subsystem against its budget
+// and calls the eviction callback of each one over it; subsystems without a
+// budget, or without an eviction callback, are only measured.
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+
+type UsageFn = Box<dyn Fn() -> usize + Send + Sync>;
+type EvictFn = Box<dyn Fn(usize) -> usize + Send + Sync>;
+
+struct Subsystem {
+    name: &'static str,
+    usage: UsageFn,
+    evict: Option<EvictFn>,
+    bytes: usize,
+    evictions: u64,
+    evicted_bytes: u64,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct MemoryUsage {
+    pub subsystem: &'static str,
+    pub bytes: usize,
+    pub budget: Option<usize>,
+    // Still over budget after the last eviction, or over with nothing to evict
+    pub over_budget: bool,
+    pub evictions: u64,
+    pub evicted_bytes: u64,
+}
+
+#[derive(Clone, Default)]
+pub struct MemoryAccountant {
+    budgets: Arc<BTreeMap<String, usize>>,
+    subsystems: Arc<Mutex<Vec<Subsystem>>>,
+}
+
+impl MemoryAccountant {
+    // Budgets in bytes, by subsystem name
+    pub fn new(budgets: BTreeMap<String, usize>) -> Self {
+        Self { budgets: Arc::new(budgets), subsystems: Arc::default() }
+    }
+
+    pub fn track(&self, name: &'static str, usage: impl Fn() -> usize + Send + Sync + 'static) {
+        self.register(name, Box::new(usage), None);
+    }
+
+    pub fn track_evictable(
+        &self,
+        name: &'static str,
+        usage: impl Fn() -> usize + Send + Sync + 'static,
+        evict: impl Fn(usize) -> usize + Send + Sync + 'static,
+    ) {
+        self.register(name, Box::new(usage), Some(Box::new(evict)));
+    }
+
+    fn register(&self, name: &'static str, usage: UsageFn, evict: Option<EvictFn>) {
+        let bytes = usage();
+        self.subsystems.lock().unwrap().push(Subsystem { name, usage, evict, bytes, evictions: 0, evicted_bytes: 0 });
+    }
+
+    // Budgets naming no registered subsystem, e.g. a typo in the config
+    pub fn unknown_budgets(&self) -> Vec<String> {
+        let subsystems = self.subsystems.lock().unwrap();
+        self.budgets.keys().filter(|name| !subsystems.iter().any(|s| s.name == name.as_str())).cloned().collect()
+    }
+
+    // Returns the bytes freed across all subsystems
+    pub fn enforce(&self) -> usize {
+        let mut freed = 0;
+        for subsystem in self.subsystems.lock().unwrap().iter_mut() {
+            subsystem.bytes = (subsystem.usage)();
+            let Some(&budget) = self.budgets.get(subsystem.name) else {
+                continue;
+            };
+            let Some(evict) = subsystem.evict.as_ref().filter(|_| subsystem.bytes > budget) else {
+                continue;
+            };
+            let evicted = evict(subsystem.bytes - budget);
+            subsystem.evictions += 1;
+            subsystem.evicted_bytes += evicted as u64;
+            subsystem.bytes = (subsystem.usage)();
+            freed += evicted;
+        }
+        freed
+    }
+
+    // As of the last `enforce`, in registration order
+    pub fn snapshot(&self) -> Vec<MemoryUsage> {
+        self.subsystems
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|s| {
+                let budget = self.budgets.get(s.name).copied();
+                MemoryUsage {
+                    subsystem: s.name,
+                    bytes: s.bytes,
+                    budget,
+                    over_budget: budget.is_some_and(|budget| s.bytes > budget),
+                    evictions: s.evictions,
+                    evicted_bytes: s.evicted_bytes,
+                }
+            })
+            .collect()
+    }
+
+    pub fn render_prometheus(&self) -> String {
+        let usage = self.snapshot();
+        let mut out = String::new();
+        let mut family = |name: &str, kind: &str, help: &str, value: &dyn Fn(&MemoryUsage) -> Option<u64>| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            for u in &usage {
+                if let Some(v) = value(u) {
+                    let _ = writeln!(out, "{}{{subsystem=\"{}\"}} {}", name, u.subsystem, v);
+                }
+            }
+        };
+        family("memory_usage_bytes", "gauge", "Approximate heap bytes held by a subsystem", &|u| Some(u.bytes as u64));
+        family("memory_budget_bytes", "gauge", "Configured memory budget of a subsystem", &|u| u.budget.map(|b| b as u64));
+        family("memory_evictions_total", "counter", "Evictions run because a subsystem exceeded its budget", &|u| {
+            Some(u.evictions)
+        });
+        family("memory_evicted_bytes_total", "counter", "Bytes freed by evictions", &|u| Some(u.evicted_bytes));
+        out
+    }
+}
+
+// "65536", "512K", "64MiB", "1G" and so on; K, M and G are powers of 1024
+pub fn parse_size(s: &str) -> Result<usize, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (digits, unit) = s.split_at(split);
+    let value: usize = digits.parse().map_err(|_| format!("invalid size: {}", s))?;
+    let shift = match unit.trim().to_ascii_lowercase().as_str() {
+        "" | "b" => 0,
+        "k" | "kb" | "kib" => 10,
+        "m" | "mb" | "mib" => 20,
+        "g" | "gb" | "gib" => 30,
+        _ => return Err(format!("invalid size unit in {}", s)),
+    };
+    value.checked_mul(1 << shift).ok_or_else(|| format!("size too large: {}", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mempool::{Mempool, Transaction};
+
+    #[test]
+    fn test_evicts_subsystems_over_budget() {
+        let pool = Arc::new(Mutex::new(Mempool::default()));
+        for nonce in 0..8 {
+            let tx = Transaction { sender: "alice".into(), nonce, data: "x".repeat(100), priority: 0 };
+            pool.lock().unwrap().insert(tx, 0).unwrap();
+        }
+        let full = pool.lock().unwrap().heap_bytes();
+        let budgets = BTreeMap::from([("mempool".to_string(), full / 2), ("typo".to_string(), 1)]);
+        let accountant = MemoryAccountant::new(budgets);
+        let (usage, evict) = (pool.clone(), pool.clone());
+        accountant.track_evictable("mempool", move || usage.lock().unwrap().heap_bytes(), move |bytes| {
+            evict.lock().unwrap().evict(bytes)
+        });
+        accountant.track("fixed", || 10);
+        assert_eq!(accountant.unknown_budgets(), vec!["typo".to_string()]);
+
+        assert!(accountant.enforce() >= full / 2);
+        let usage = accountant.snapshot();
+        assert!(usage[0].bytes <= full / 2 && !usage[0].over_budget);
+        assert_eq!(usage[0].evictions, 1);
+        assert_eq!((usage[1].bytes, usage[1].budget), (10, None));
+        // The highest nonces go first, so what is left can still execute
+        let left = pool.lock().unwrap().len();
+        assert!(left > 0 && left < 8);
+        assert_eq!(pool.lock().unwrap().ready(|_| 0, 10).len(), left);
+        assert!(accountant.render_prometheus().contains("memory_usage_bytes{subsystem=\"fixed\"} 10"));
+
+        assert_eq!(parse_size("64MiB"), Ok(64 << 20));
+        assert_eq!(parse_size("512k"), Ok(512 << 10));
+        assert!(parse_size("12 parsecs").is_err());
+    }
+}
@@ -0,0 +1,477 @@
+// Pending transactions, ordered per sender by nonce. A sender's transactions
+// only become ready once every lower nonce has been finalized or is ready
+// ahead of them; nonces too far beyond the sender's next nonce are refused.
+// Among the ready ones, blocks take the highest priority first. Proposed
+// transactions stay pending until a finalized block executes them, so a
+// proposal that loses its round does not lose them. Anything still pending
+// after the TTL expires.
+use crate::{Bytes, Round};
+use serde::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, VecDeque};
+use std::fmt;
+use std::time::{Duration, Instant};
+
+pub type AccountId = String;
+pub type TxHash = String;
+
+// How far ahead of its next nonce a sender may queue transactions
+pub const DEFAULT_NONCE_WINDOW: u64 = 16;
+// How long a transaction may stay pending
+pub const DEFAULT_TX_TTL: Duration = Duration::from_secs(3600);
+// Finalized heights whose transaction hashes are remembered
+pub const DEFAULT_REPLAY_WINDOW: u64 = 10_000;
+
+// Fee lanes by minimum priority, highest first; a transaction is counted in
+// the first lane it reaches
+pub const LANES: [(&str, u64); 3] = [("high", 100), ("normal", 1), ("free", 0)];
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Transaction {
+    pub sender: AccountId,
+    pub nonce: u64,
+    pub data: String,
+    // Fee the sender offers; 0 when left out
+    #[serde(default, skip_serializing_if = "is_zero")]
+    pub priority: u64,
+}
+
+fn is_zero(priority: &u64) -> bool {
+    *priority == 0
+}
+
+impl Transaction {
+    pub fn hash(&self) -> TxHash {
+        let mut hasher = blake3::Hasher::new();
+        hasher
+            .update(b"mini-consensus/tx/v1")
+            .update(&(self.sender.len() as u64).to_le_bytes())
+            .update(self.sender.as_bytes())
+            .update(&self.nonce.to_le_bytes())
+            .update(self.data.as_bytes());
+        // Left out at 0 so transactions without one keep their hash
+        if self.priority > 0 {
+            hasher.update(&self.priority.to_le_bytes());
+        }
+        hasher.finalize().to_string()
+    }
+
+    pub fn lane(&self) -> &'static str {
+        LANES.iter().find(|(_, min)| self.priority >= *min).map_or("free", |(lane, _)| lane)
+    }
+
+    // Bytes of its JSON encoding, as a batch carries it
+    pub fn encoded_len(&self) -> usize {
+        serde_json::to_vec(self).map_or(usize::MAX / 2, |b| b.len())
+    }
+}
+
+// A pending transaction as listed by `Mempool::list`
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PendingTx {
+    pub hash: TxHash,
+    pub tx: Transaction,
+    pub age_ms: u64,
+    // Rough heap bytes, as counted for the memory budget
+    pub bytes: usize,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct LaneDepth {
+    pub lane: &'static str,
+    pub min_priority: u64,
+    pub pending: usize,
+}
+
+// Block payload carrying transactions
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct TxBatch {
+    pub txs: Vec<Transaction>,
+}
+
+impl TxBatch {
+    // Encoded size of a batch without transactions; each one adds its
+    // `encoded_len` and, after the first, a comma
+    pub const EMPTY_LEN: usize = r#"{"txs":[]}"#.len();
+
+    pub fn encode(&self) -> Bytes {
+        serde_json::to_vec(self).unwrap()
+    }
+
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        serde_json::from_slice(payload).ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MempoolError {
+    StaleNonce { nonce: u64, expected: u64 },
+    NonceGap { nonce: u64, expected: u64, window: u64 },
+    Duplicate { nonce: u64 },
+    AlreadyFinalized { height: u64 },
+    // Even alone, the transaction would not fit in a block
+    TooLarge { bytes: usize, max: usize },
+}
+
+impl fmt::Display for MempoolError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MempoolError::StaleNonce { nonce, expected } => {
+                write!(f, "nonce {} already used, next nonce is {}", nonce, expected)
+            }
+            MempoolError::NonceGap { nonce, expected, window } => {
+                write!(f, "nonce {} is outside the window of {} from next nonce {}", nonce, window, expected)
+            }
+            MempoolError::Duplicate { nonce } => write!(f, "a transaction with nonce {} is already pending", nonce),
+            MempoolError::AlreadyFinalized { height } => write!(f, "transaction was already finalized at height {}", height),
+            MempoolError::TooLarge { bytes, max } => {
+                write!(f, "a block carrying only this transaction is {} bytes, the limit is {}", bytes, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MempoolError {}
+
+// Hashes of the transactions executed in the last `heights` finalized
+// heights, so a resubmitted one is refused as a replay with the height it
+// finalized at
+#[derive(Debug, Clone)]
+pub struct ReplayWindow {
+    heights: u64,
+    by_hash: HashMap<TxHash, u64>,
+    by_height: VecDeque<(u64, Vec<TxHash>)>,
+}
+
+impl Default for ReplayWindow {
+    fn default() -> Self {
+        Self::new(DEFAULT_REPLAY_WINDOW)
+    }
+}
+
+impl ReplayWindow {
+    pub fn new(heights: u64) -> Self {
+        Self { heights: heights.max(1), by_hash: HashMap::new(), by_height: VecDeque::new() }
+    }
+
+    pub fn finalized_at(&self, hash: &str) -> Option<u64> {
+        self.by_hash.get(hash).copied()
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_hash.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_hash.is_empty()
+    }
+
+    pub(crate) fn record(&mut self, height: u64, hashes: Vec<TxHash>) {
+        for hash in &hashes {
+            self.by_hash.insert(hash.clone(), height);
+        }
+        self.by_height.push_back((height, hashes));
+        while let Some((oldest, _)) = self.by_height.front() {
+            if oldest + self.heights > height {
+                break;
+            }
+            let (_, hashes) = self.by_height.pop_front().unwrap();
+            for hash in hashes {
+                self.by_hash.remove(&hash);
+            }
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Pending {
+    tx: Transaction,
+    added_at: Instant,
+}
+
+#[derive(Debug)]
+pub struct Mempool {
+    by_sender: HashMap<AccountId, BTreeMap<u64, Pending>>,
+    window: u64,
+    ttl: Duration,
+    // (height, round) of the last proposal made from the pool
+    pub(crate) proposed_at: Option<(u64, Round)>,
+}
+
+impl Default for Mempool {
+    fn default() -> Self {
+        Self::new(DEFAULT_NONCE_WINDOW)
+    }
+}
+
+impl Mempool {
+    pub fn new(window: u64) -> Self {
+        Self {
+            by_sender: HashMap::new(),
+            window: window.max(1),
+            ttl: DEFAULT_TX_TTL,
+            proposed_at: None,
+        }
+    }
+
+    pub fn with_ttl(mut self, ttl: Duration) -> Self {
+        self.ttl = ttl;
+        self
+    }
+
+    // `next_nonce` is the sender's next nonce in the finalized state
+    pub fn insert(&mut self, tx: Transaction, next_nonce: u64) -> Result<TxHash, MempoolError> {
+        if tx.nonce < next_nonce {
+            return Err(MempoolError::StaleNonce { nonce: tx.nonce, expected: next_nonce });
+        }
+        if tx.nonce >= next_nonce + self.window {
+            return Err(MempoolError::NonceGap { nonce: tx.nonce, expected: next_nonce, window: self.window });
+        }
+
+        let queue = self.by_sender.entry(tx.sender.clone()).or_default();
+        if queue.contains_key(&tx.nonce) {
+            return Err(MempoolError::Duplicate { nonce: tx.nonce });
+        }
+        let hash = tx.hash();
+        queue.insert(tx.nonce, Pending { tx, added_at: Instant::now() });
+        Ok(hash)
+    }
+
+    // Up to `limit` transactions that can execute in order, left pending:
+    // each sender's run of consecutive nonces starting at its next nonce.
+    // The highest priority among the senders' next transactions goes first;
+    // equal priorities are taken round-robin across senders.
+    pub fn ready(&self, next_nonce: impl Fn(&str) -> u64, limit: usize) -> Vec<Transaction> {
+        // Each sender's next transaction as (priority, fewest taken from the
+        // sender so far, sender, nonce), largest first
+        let mut heads = BinaryHeap::new();
+        for (sender, queue) in &self.by_sender {
+            let nonce = next_nonce(sender);
+            if let Some(pending) = queue.get(&nonce) {
+                heads.push((pending.tx.priority, Reverse(0usize), Reverse(sender.clone()), nonce));
+            }
+        }
+
+        let mut ready = Vec::new();
+        while ready.len() < limit {
+            let Some((_, Reverse(taken), Reverse(sender), nonce)) = heads.pop() else {
+                break;
+            };
+            let queue = &self.by_sender[&sender];
+            ready.push(queue[&nonce].tx.clone());
+            if let Some(next) = queue.get(&(nonce + 1)) {
+                heads.push((next.tx.priority, Reverse(taken + 1), Reverse(sender), nonce + 1));
+            }
+        }
+        ready
+    }
+
+    // Pending transactions per fee lane, ready or not
+    pub fn lanes(&self) -> Vec<LaneDepth> {
+        let mut depths: Vec<LaneDepth> =
+            LANES.iter().map(|&(lane, min_priority)| LaneDepth { lane, min_priority, pending: 0 }).collect();
+        for pending in self.by_sender.values().flat_map(|q| q.values()) {
+            if let Some(depth) = depths.iter_mut().find(|d| d.lane == pending.tx.lane()) {
+                depth.pending += 1;
+            }
+        }
+        depths
+    }
+
+    // Up to `limit` pending transactions ordered by hash, starting after the
+    // `after` hash; pass the last hash of a page to get the next one
+    pub fn list(&self, after: Option<&str>, limit: usize) -> Vec<PendingTx> {
+        let now = Instant::now();
+        let mut txs: Vec<PendingTx> = self
+            .by_sender
+            .values()
+            .flat_map(|q| q.values())
+            .map(|pending| PendingTx {
+                hash: pending.tx.hash(),
+                tx: pending.tx.clone(),
+                age_ms: now.saturating_duration_since(pending.added_at).as_millis() as u64,
+                bytes: tx_bytes(&pending.tx),
+            })
+            .filter(|p| after.is_none_or(|after| p.hash.as_str() > after))
+            .collect();
+        txs.sort_by(|a, b| a.hash.cmp(&b.hash));
+        txs.truncate(limit);
+        txs
+    }
+
+    pub fn remove(&mut self, hash: &str) -> Option<Transaction> {
+        let (sender, nonce) = self
+            .by_sender
+            .iter()
+            .flat_map(|(sender, q)| q.values().map(move |p| (sender, &p.tx)))
+            .find(|(_, tx)| tx.hash() == hash)
+            .map(|(sender, tx)| (sender.clone(), tx.nonce))?;
+        let queue = self.by_sender.get_mut(&sender)?;
+        let removed = queue.remove(&nonce).map(|p| p.tx);
+        if queue.is_empty() {
+            self.by_sender.remove(&sender);
+        }
+        removed
+    }
+
+    // Removes and returns transactions pending for longer than the TTL as
+    // of `now`
+    pub fn expire(&mut self, now: Instant) -> Vec<Transaction> {
+        let mut expired = Vec::new();
+        for queue in self.by_sender.values_mut() {
+            let stale: Vec<u64> = queue
+                .iter()
+                .filter(|(_, p)| now.saturating_duration_since(p.added_at) > self.ttl)
+                .map(|(&nonce, _)| nonce)
+                .collect();
+            expired.extend(stale.into_iter().filter_map(|nonce| queue.remove(&nonce)).map(|p| p.tx));
+        }
+        self.by_sender.retain(|_, queue| !queue.is_empty());
+        expired
+    }
+
+    // Drops transactions whose nonces have been used
+    pub fn prune(&mut self, next_nonce: impl Fn(&str) -> u64) {
+        for (sender, queue) in self.by_sender.iter_mut() {
+            let next = next_nonce(sender);
+            queue.retain(|&nonce, _| nonce >= next);
+        }
+        self.by_sender.retain(|_, queue| !queue.is_empty());
+    }
+
+    pub fn len(&self) -> usize {
+        self.by_sender.values().map(|q| q.len()).sum()
+    }
+
+    // Rough heap bytes held, for memory accounting
+    pub fn heap_bytes(&self) -> usize {
+        self.by_sender
+            .iter()
+            .map(|(sender, queue)| sender_bytes(sender) + queue.values().map(|p| tx_bytes(&p.tx)).sum::<usize>())
+            .sum()
+    }
+
+    // Drops pending transactions until at least `bytes` are freed, the
+    // highest nonce of the longest queue first so that what remains can
+    // still execute in order. Returns the bytes freed.
+    pub fn evict(&mut self, bytes: usize) -> usize {
+        let mut freed = 0;
+        while freed < bytes {
+            let Some(sender) = self.by_sender.iter().max_by_key(|(sender, queue)| (queue.len(), *sender)).map(|(s, _)| s.clone())
+            else {
+                break;
+            };
+            let queue = self.by_sender.get_mut(&sender).unwrap();
+            if let Some((_, pending)) = queue.pop_last() {
+                freed += tx_bytes(&pending.tx);
+            }
+            if queue.is_empty() {
+                self.by_sender.remove(&sender);
+                freed += sender_bytes(&sender);
+            }
+        }
+        freed
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.by_sender.is_empty()
+    }
+}
+
+fn tx_bytes(tx: &Transaction) -> usize {
+    std::mem::size_of::<Transaction>() + tx.sender.len() + tx.data.len()
+}
+
+fn sender_bytes(sender: &AccountId) -> usize {
+    std::mem::size_of::<AccountId>() + sender.len()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tx(sender: &str, nonce: u64) -> Transaction {
+        Transaction { sender: sender.to_string(), nonce, data: String::new(), priority: 0 }
+    }
+
+    #[test]
+    fn test_orders_by_nonce_and_holds_back_gaps() {
+        let mut pool = Mempool::new(4);
+        pool.insert(tx("alice", 1), 0).unwrap();
+        pool.insert(tx("alice", 0), 0).unwrap();
+        pool.insert(tx("alice", 3), 0).unwrap();
+        pool.insert(tx("bob", 5), 5).unwrap();
+
+        assert_eq!(pool.insert(tx("alice", 0), 0), Err(MempoolError::Duplicate { nonce: 0 }));
+        assert_eq!(pool.insert(tx("alice", 4), 0), Err(MempoolError::NonceGap { nonce: 4, expected: 0, window: 4 }));
+        assert_eq!(pool.insert(tx("bob", 4), 5), Err(MempoolError::StaleNonce { nonce: 4, expected: 5 }));
+
+        // alice 3 waits for nonce 2
+        let next = |s: &str| if s == "bob" { 5 } else { 0 };
+        let ready: Vec<(String, u64)> = pool.ready(next, 10).into_iter().map(|t| (t.sender, t.nonce)).collect();
+        assert_eq!(ready, vec![("alice".into(), 0), ("bob".into(), 5), ("alice".into(), 1)]);
+        assert_eq!(pool.len(), 4);
+
+        // Once alice's nonce 2 is used elsewhere, 3 is ready and nothing stale remains
+        pool.prune(|s| if s == "bob" { 6 } else { 3 });
+        assert_eq!(pool.ready(|_| 3, 10), vec![tx("alice", 3)]);
+        assert_eq!(pool.len(), 1);
+    }
+
+    #[test]
+    fn test_higher_priority_goes_first_within_nonce_order() {
+        let with_priority = |sender, nonce, priority| Transaction { priority, ..tx(sender, nonce) };
+        let mut pool = Mempool::default();
+        pool.insert(with_priority("alice", 0, 0), 0).unwrap();
+        pool.insert(with_priority("alice", 1, 500), 0).unwrap();
+        pool.insert(with_priority("bob", 0, 5), 0).unwrap();
+        pool.insert(with_priority("carol", 0, 100), 0).unwrap();
+        pool.insert(with_priority("carol", 1, 1), 0).unwrap();
+
+        let pending: Vec<usize> = pool.lanes().iter().map(|d| d.pending).collect();
+        assert_eq!(pending, vec![2, 2, 1]);
+        assert_ne!(with_priority("alice", 1, 500).hash(), tx("alice", 1).hash());
+
+        // alice's 500 has to wait behind her own nonce 0
+        let batch = TxBatch { txs: pool.ready(|_| 0, 4) };
+        let lens: usize = batch.txs.iter().map(|tx| tx.encoded_len() + 1).sum();
+        assert_eq!(batch.encode().len(), TxBatch::EMPTY_LEN + lens - 1);
+
+        let order: Vec<(String, u64)> = pool.ready(|_| 0, 4).into_iter().map(|t| (t.sender, t.nonce)).collect();
+        assert_eq!(order, vec![("carol".into(), 0), ("bob".into(), 0), ("carol".into(), 1), ("alice".into(), 0)]);
+        pool.prune(|s| if s == "carol" { 2 } else { 1 });
+        assert_eq!(pool.ready(|_| 1, 4), vec![with_priority("alice", 1, 500)]);
+    }
+
+    #[test]
+    fn test_lists_by_hash_and_drops_expired_or_removed() {
+        let mut pool = Mempool::default().with_ttl(Duration::from_secs(60));
+        let hashes: Vec<TxHash> = (0..5).map(|nonce| pool.insert(tx("alice", nonce), 0).unwrap()).collect();
+        let mut sorted = hashes.clone();
+        sorted.sort();
+
+        let first = pool.list(None, 3);
+        assert_eq!(first.iter().map(|p| &p.hash).collect::<Vec<_>>(), sorted[..3].iter().collect::<Vec<_>>());
+        let rest = pool.list(Some(&first[2].hash), 3);
+        assert_eq!(rest.iter().map(|p| &p.hash).collect::<Vec<_>>(), sorted[3..].iter().collect::<Vec<_>>());
+
+        assert_eq!(pool.remove(&hashes[4]), Some(tx("alice", 4)));
+        assert_eq!(pool.remove(&hashes[4]), None);
+        assert!(pool.expire(Instant::now()).is_empty());
+        assert_eq!(pool.expire(Instant::now() + Duration::from_secs(61)).len(), 4);
+        assert!(pool.is_empty());
+    }
+
+    #[test]
+    fn test_replay_window_forgets_old_heights() {
+        let mut window = ReplayWindow::new(2);
+        window.record(0, vec![tx("alice", 0).hash()]);
+        window.record(1, vec![tx("alice", 1).hash()]);
+        assert_eq!(window.finalized_at(&tx("alice", 0).hash()), Some(0));
+
+        window.record(2, Vec::new());
+        assert_eq!(window.finalized_at(&tx("alice", 0).hash()), None);
+        assert_eq!(window.finalized_at(&tx("alice", 1).hash()), Some(1));
+        assert_eq!(window.len(), 1);
+    }
+}
@@ -0,0 +1,223 @@
+// Consensus timing metrics, rendered in the Prometheus text format.
+use crate::scrub::ScrubReport;
+use crate::timeout::RoundTimeout;
+use serde::Serialize;
+use std::fmt::Write;
+
+// Bucket upper bounds in milliseconds
+const LATENCY_BUCKETS_MS: &[f64] = &[5.0, 10.0, 25.0, 50.0, 100.0, 250.0, 500.0, 1000.0, 2500.0, 5000.0, 10000.0, 30000.0];
+
+#[derive(Debug, Clone)]
+pub struct Histogram {
+    bounds: &'static [f64],
+    counts: Vec<u64>,
+    sum: f64,
+    count: u64,
+}
+
+impl Histogram {
+    pub fn new(bounds: &'static [f64]) -> Self {
+        Self {
+            bounds,
+            counts: vec![0; bounds.len()],
+            sum: 0.0,
+            count: 0,
+        }
+    }
+
+    pub fn observe(&mut self, value: f64) {
+        if let Some(i) = self.bounds.iter().position(|&b| value <= b) {
+            self.counts[i] += 1;
+        }
+        self.sum += value;
+        self.count += 1;
+    }
+
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    pub fn mean(&self) -> f64 {
+        if self.count == 0 {
+            return 0.0;
+        }
+        self.sum / self.count as f64
+    }
+
+    // Upper bound of the bucket holding the q-th quantile
+    pub fn quantile(&self, q: f64) -> f64 {
+        let target = (q * self.count as f64).ceil() as u64;
+        let mut seen = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            seen += count;
+            if seen >= target && self.count > 0 {
+                return *bound;
+            }
+        }
+        f64::INFINITY
+    }
+
+    fn summary(&self) -> HistogramSummary {
+        HistogramSummary {
+            count: self.count,
+            mean: self.mean(),
+            p50: self.quantile(0.5),
+            p99: self.quantile(0.99),
+        }
+    }
+
+    fn write_prometheus(&self, out: &mut String, name: &str, help: &str) {
+        let _ = writeln!(out, "# HELP {} {}", name, help);
+        let _ = writeln!(out, "# TYPE {} histogram", name);
+        let mut cumulative = 0;
+        for (bound, count) in self.bounds.iter().zip(&self.counts) {
+            cumulative += count;
+            let _ = writeln!(out, "{}_bucket{{le=\"{}\"}} {}", name, bound, cumulative);
+        }
+        let _ = writeln!(out, "{}_bucket{{le=\"+Inf\"}} {}", name, self.count);
+        let _ = writeln!(out, "{}_sum {}", name, self.sum);
+        let _ = writeln!(out, "{}_count {}", name, self.count);
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HistogramSummary {
+    pub count: u64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p99: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct ConsensusMetrics {
+    // Proposal creation to finalization
+    pub finalization_latency_ms: Histogram,
+    // First to last vote received before finalization
+    pub vote_spread_ms: Histogram,
+    // Rounds that passed at a height before one finalized
+    pub round_skips_total: u64,
+    pub last_round_skips: u64,
+    pub finalized_total: u64,
+    // Adapts to the finalization latency
+    pub round_timeout: RoundTimeout,
+    pub scrub_runs_total: u64,
+    pub scrub_corruptions_total: u64,
+    pub scrub_repairs_total: u64,
+    pub last_scrub: Option<ScrubReport>,
+}
+
+impl Default for ConsensusMetrics {
+    fn default() -> Self {
+        Self {
+            finalization_latency_ms: Histogram::new(LATENCY_BUCKETS_MS),
+            vote_spread_ms: Histogram::new(LATENCY_BUCKETS_MS),
+            round_skips_total: 0,
+            last_round_skips: 0,
+            finalized_total: 0,
+            round_timeout: RoundTimeout::default(),
+            scrub_runs_total: 0,
+            scrub_corruptions_total: 0,
+            scrub_repairs_total: 0,
+            last_scrub: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MetricsSnapshot {
+    pub finalized_total: u64,
+    pub round_skips_total: u64,
+    pub last_round_skips: u64,
+    pub finalization_latency_ms: HistogramSummary,
+    pub vote_spread_ms: HistogramSummary,
+    // Effective timeout validators should wait out before a timeout vote
+    pub round_timeout_ms: u64,
+    pub latency_ewma_ms: Option<f64>,
+    pub scrub_corruptions_total: u64,
+    pub last_scrub: Option<ScrubReport>,
+}
+
+impl ConsensusMetrics {
+    pub fn record_finalization(&mut self, latency_ms: f64, vote_spread_ms: f64, round_skips: u64) {
+        self.finalization_latency_ms.observe(latency_ms);
+        self.vote_spread_ms.observe(vote_spread_ms);
+        self.round_skips_total += round_skips;
+        self.last_round_skips = round_skips;
+        self.finalized_total += 1;
+        self.round_timeout.observe(latency_ms);
+    }
+
+    pub fn record_scrub(&mut self, report: &ScrubReport) {
+        self.scrub_runs_total += 1;
+        self.scrub_corruptions_total += report.corruptions.len() as u64;
+        self.scrub_repairs_total += report.repaired as u64;
+        self.last_scrub = Some(report.clone());
+    }
+
+    pub fn snapshot(&self) -> MetricsSnapshot {
+        MetricsSnapshot {
+            finalized_total: self.finalized_total,
+            round_skips_total: self.round_skips_total,
+            last_round_skips: self.last_round_skips,
+            finalization_latency_ms: self.finalization_latency_ms.summary(),
+            vote_spread_ms: self.vote_spread_ms.summary(),
+            round_timeout_ms: self.round_timeout.current_ms(),
+            latency_ewma_ms: self.round_timeout.latency_ewma_ms(),
+            scrub_corruptions_total: self.scrub_corruptions_total,
+            last_scrub: self.last_scrub.clone(),
+        }
+    }
+
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        self.finalization_latency_ms.write_prometheus(
+            &mut out,
+            "consensus_finalization_latency_ms",
+            "Time from proposal to finalization in milliseconds",
+        );
+        self.vote_spread_ms.write_prometheus(
+            &mut out,
+            "consensus_vote_spread_ms",
+            "Time between the first and last vote counted for a finalized block",
+        );
+        let _ = writeln!(out, "# HELP consensus_round_skips_total Rounds skipped at a height before one finalized");
+        let _ = writeln!(out, "# TYPE consensus_round_skips_total counter");
+        let _ = writeln!(out, "consensus_round_skips_total {}", self.round_skips_total);
+        let _ = writeln!(out, "# HELP consensus_finalized_total Blocks finalized");
+        let _ = writeln!(out, "# TYPE consensus_finalized_total counter");
+        let _ = writeln!(out, "consensus_finalized_total {}", self.finalized_total);
+        let _ = writeln!(out, "# HELP consensus_round_timeout_ms Effective round timeout, adapted to finalization latency");
+        let _ = writeln!(out, "# TYPE consensus_round_timeout_ms gauge");
+        let _ = writeln!(out, "consensus_round_timeout_ms {}", self.round_timeout.current_ms());
+        let _ = writeln!(out, "# HELP storage_scrub_runs_total Completed storage scrubs");
+        let _ = writeln!(out, "# TYPE storage_scrub_runs_total counter");
+        let _ = writeln!(out, "storage_scrub_runs_total {}", self.scrub_runs_total);
+        let _ = writeln!(out, "# HELP storage_scrub_corruptions_total Corrupt or missing stored entries found by scrubs");
+        let _ = writeln!(out, "# TYPE storage_scrub_corruptions_total counter");
+        let _ = writeln!(out, "storage_scrub_corruptions_total {}", self.scrub_corruptions_total);
+        let _ = writeln!(out, "# HELP storage_scrub_repairs_total Scrubs that rewrote the store");
+        let _ = writeln!(out, "# TYPE storage_scrub_repairs_total counter");
+        let _ = writeln!(out, "storage_scrub_repairs_total {}", self.scrub_repairs_total);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_histogram_buckets_and_render() {
+        let mut metrics = ConsensusMetrics::default();
+        metrics.record_finalization(7.0, 3.0, 0);
+        metrics.record_finalization(120.0, 40.0, 2);
+
+        assert_eq!(metrics.finalization_latency_ms.quantile(0.5), 10.0);
+        assert_eq!(metrics.finalization_latency_ms.quantile(1.0), 250.0);
+
+        let text = metrics.render_prometheus();
+        assert!(text.contains("consensus_finalization_latency_ms_bucket{le=\"10\"} 1"));
+        assert!(text.contains("consensus_finalization_latency_ms_count 2"));
+        assert!(text.contains("consensus_round_skips_total 2"));
+    }
+}
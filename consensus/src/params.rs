@@ -0,0 +1,168 @@
+// Consensus parameters: set at genesis and changed afterwards only by a
+// finalized `set_params` transaction, i.e. with more than two thirds of the
+// stake behind it.
+use serde::{Deserialize, Serialize};
+use std::fmt;
+
+// Updates below these are ignored: a block must still fit a `set_params`
+// transaction that raises the limits again, and the proposer task must not
+// spin
+pub const MIN_BLOCK_TIME_MS: u64 = 100;
+pub const MIN_BLOCK_BYTES: usize = 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConsensusParams {
+    // Interval of the proposer task
+    pub block_time_ms: u64,
+    pub max_block_bytes: usize,
+    pub max_txs_per_block: usize,
+}
+
+impl Default for ConsensusParams {
+    fn default() -> Self {
+        Self {
+            block_time_ms: 1000,
+            max_block_bytes: 1 << 20,
+            max_txs_per_block: 256,
+        }
+    }
+}
+
+// Governance transaction, `{"type":"set_params", ...}`; omitted fields keep
+// their current value
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ParamsUpdate {
+    pub block_time_ms: Option<u64>,
+    pub max_block_bytes: Option<usize>,
+    pub max_txs_per_block: Option<usize>,
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+enum GovernanceTx {
+    SetParams(ParamsUpdate),
+}
+
+impl ParamsUpdate {
+    pub fn encode(&self) -> Vec<u8> {
+        serde_json::to_vec(&GovernanceTx::SetParams(self.clone())).unwrap()
+    }
+
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        match serde_json::from_slice(payload).ok()? {
+            GovernanceTx::SetParams(update) => Some(update),
+        }
+    }
+
+    // Fields `ConsensusParams::apply` would ignore, with their minimums
+    pub fn below_minimum(&self) -> Vec<(&'static str, u64)> {
+        let fields = [
+            ("block_time_ms", self.block_time_ms, MIN_BLOCK_TIME_MS),
+            ("max_block_bytes", self.max_block_bytes.map(|v| v as u64), MIN_BLOCK_BYTES as u64),
+            ("max_txs_per_block", self.max_txs_per_block.map(|v| v as u64), 1),
+        ];
+        fields
+            .into_iter()
+            .filter(|(_, value, min)| value.is_some_and(|v| v < *min))
+            .map(|(field, _, min)| (field, min))
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProposalError {
+    TooLarge { bytes: usize, max: usize },
+    TooManyTxs { count: usize, max: usize },
+}
+
+impl fmt::Display for ProposalError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ProposalError::TooLarge { bytes, max } => {
+                write!(f, "payload is {} bytes, the limit is {}", bytes, max)
+            }
+            ProposalError::TooManyTxs { count, max } => {
+                write!(f, "block carries {} transactions, the limit is {}", count, max)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ProposalError {}
+
+impl ConsensusParams {
+    // Values below the minimums are ignored so a bad update cannot halt the
+    // chain
+    pub fn apply(&mut self, update: &ParamsUpdate) {
+        if let Some(ms) = update.block_time_ms.filter(|&v| v >= MIN_BLOCK_TIME_MS) {
+            self.block_time_ms = ms;
+        }
+        if let Some(bytes) = update.max_block_bytes.filter(|&v| v >= MIN_BLOCK_BYTES) {
+            self.max_block_bytes = bytes;
+        }
+        if let Some(txs) = update.max_txs_per_block.filter(|&v| v > 0) {
+            self.max_txs_per_block = txs;
+        }
+    }
+
+    // `tx_count` is the number of transactions the payload carries, if any
+    pub fn check(&self, payload_len: usize, tx_count: usize) -> Result<(), ProposalError> {
+        if payload_len > self.max_block_bytes {
+            return Err(ProposalError::TooLarge { bytes: payload_len, max: self.max_block_bytes });
+        }
+        if tx_count > self.max_txs_per_block {
+            return Err(ProposalError::TooManyTxs { count: tx_count, max: self.max_txs_per_block });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_partial_update_and_limits() {
+        let mut params = ConsensusParams::default();
+        let update = ParamsUpdate::decode(br#"{"type":"set_params","max_txs_per_block":2,"block_time_ms":0}"#).unwrap();
+        params.apply(&update);
+
+        assert_eq!(params.max_txs_per_block, 2);
+        assert_eq!(params.block_time_ms, 1000);
+        assert!(ParamsUpdate::decode(br#"{"type":"bond","validator_id":0,"amount":1}"#).is_none());
+        assert_eq!(ParamsUpdate::decode(&update.encode()), Some(update));
+
+        assert_eq!(params.check(10, 3), Err(ProposalError::TooManyTxs { count: 3, max: 2 }));
+        assert_eq!(params.check(2 << 20, 0), Err(ProposalError::TooLarge { bytes: 2 << 20, max: 1 << 20 }));
+        assert!(params.check(10, 2).is_ok());
+    }
+
+    #[test]
+    fn test_updates_below_the_minimums_are_ignored() {
+        let mut params = ConsensusParams::default();
+        let tiny = ParamsUpdate { block_time_ms: Some(1), max_block_bytes: Some(10), max_txs_per_block: Some(0) };
+        params.apply(&tiny);
+        assert_eq!(params, ConsensusParams::default());
+        assert_eq!(
+            tiny.below_minimum(),
+            vec![("block_time_ms", MIN_BLOCK_TIME_MS), ("max_block_bytes", 1024), ("max_txs_per_block", 1)]
+        );
+
+        let floor = ParamsUpdate {
+            block_time_ms: Some(MIN_BLOCK_TIME_MS),
+            max_block_bytes: Some(MIN_BLOCK_BYTES),
+            max_txs_per_block: Some(1),
+        };
+        params.apply(&floor);
+        assert_eq!((params.block_time_ms, params.max_block_bytes, params.max_txs_per_block), (100, 1024, 1));
+        assert!(floor.below_minimum().is_empty());
+
+        // The smallest block still fits any update raising the limits again
+        let largest = ParamsUpdate {
+            block_time_ms: Some(u64::MAX),
+            max_block_bytes: Some(usize::MAX),
+            max_txs_per_block: Some(usize::MAX),
+        };
+        assert!(params.check(largest.encode().len(), 0).is_ok());
+    }
+}
@@ -0,0 +1,102 @@
+use crate::{Bytes, CompressionStats, StoredPayload};
+use std::collections::HashMap;
+
+pub type PayloadHash = String;
+
+#[derive(Debug)]
+struct Entry {
+    payload: StoredPayload,
+    refs: usize,
+}
+
+// Content-addressed payloads: competing blocks carrying the same payload
+// share one entry, freed when the last referencing block is pruned.
+#[derive(Debug, Default)]
+pub struct PayloadStore {
+    entries: HashMap<PayloadHash, Entry>,
+    compression: CompressionStats,
+}
+
+impl PayloadStore {
+    pub fn hash(payload: &[u8]) -> PayloadHash {
+        blake3::hash(payload).to_hex().to_string()
+    }
+
+    pub fn put(&mut self, payload: Bytes) -> PayloadHash {
+        let hash = Self::hash(&payload);
+        if let Some(entry) = self.entries.get_mut(&hash) {
+            entry.refs += 1;
+            return hash;
+        }
+
+        let payload = StoredPayload::encode(payload);
+        self.compression.record(&payload);
+        self.entries.insert(hash.clone(), Entry { payload, refs: 1 });
+        hash
+    }
+
+    pub fn get(&self, hash: &PayloadHash) -> Option<Bytes> {
+        self.entries.get(hash).map(|e| e.payload.decode())
+    }
+
+    // Drops one reference; returns true if the payload was garbage collected
+    pub fn release(&mut self, hash: &PayloadHash) -> bool {
+        let Some(entry) = self.entries.get_mut(hash) else {
+            return false;
+        };
+        entry.refs -= 1;
+        if entry.refs == 0 {
+            self.entries.remove(hash);
+            return true;
+        }
+        false
+    }
+
+    pub fn refs(&self, hash: &PayloadHash) -> usize {
+        self.entries.get(hash).map(|e| e.refs).unwrap_or(0)
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn compression_stats(&self) -> CompressionStats {
+        self.compression.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{ConsensusState, PayloadStore, Round, ValidatorId};
+
+    #[test]
+    fn test_duplicate_payloads_share_storage() {
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect());
+        let hash = PayloadStore::hash(b"same");
+
+        // The same payload finalized at height 0 and proposed again at height 1
+        let first = state.propose(b"same".to_vec()).unwrap();
+        for validator in 0..3 {
+            state.vote_combined(first, 0, Round(0), ValidatorId(validator)).unwrap();
+        }
+        let second = state.propose(b"same".to_vec()).unwrap();
+        assert_ne!(first, second);
+        {
+            let chain = state.chain.read().unwrap();
+            assert_eq!(chain.payload_store().len(), 1);
+            assert_eq!(chain.payload_store().refs(&hash), 2);
+        }
+
+        // Pruning height 0 drops one reference but keeps the shared payload
+        assert_eq!(state.prune_below(1), 1);
+        assert_eq!(state.get_block(&second).unwrap().payload, b"same".to_vec());
+        assert_eq!(state.chain.read().unwrap().payload_store().refs(&hash), 1);
+
+        state.prune_below(2);
+        assert!(state.chain.read().unwrap().payload_store().is_empty());
+    }
+}
@@ -0,0 +1,108 @@
+// How much of the finalized chain a node keeps in memory and serves. The
+// block store's log stays complete in every mode, since the replicated state
+// is rebuilt on startup by replaying it from genesis; pruning bounds the
+// node's memory and the heights its read API can answer for.
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+
+// Finalized heights kept by `default`, a little over a day at one block per
+// second
+pub const DEFAULT_KEEP: u64 = 100_000;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Pruning {
+    // Every block since genesis
+    Archive,
+    // The last N finalized heights
+    Keep(u64),
+    // Only the finalized head, which the next proposal extends
+    Minimal,
+}
+
+impl Pruning {
+    // Lowest height kept once `finalized_height` is finalized; blocks below it,
+    // finalized or not, are dropped
+    pub fn retain_from(&self, finalized_height: u64) -> u64 {
+        match self {
+            Pruning::Archive => 0,
+            Pruning::Keep(n) => (finalized_height + 1).saturating_sub((*n).max(1)),
+            Pruning::Minimal => finalized_height,
+        }
+    }
+}
+
+impl Default for Pruning {
+    fn default() -> Self {
+        Pruning::Keep(DEFAULT_KEEP)
+    }
+}
+
+impl fmt::Display for Pruning {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pruning::Archive => write!(f, "archive"),
+            Pruning::Keep(n) => write!(f, "default:{}", n),
+            Pruning::Minimal => write!(f, "minimal"),
+        }
+    }
+}
+
+// "archive", "minimal", "default" or "default:N"
+impl FromStr for Pruning {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            None if s == "archive" => Ok(Pruning::Archive),
+            None if s == "minimal" => Ok(Pruning::Minimal),
+            None if s == "default" => Ok(Pruning::default()),
+            Some(("default", n)) => match n.parse() {
+                Ok(n) if n > 0 => Ok(Pruning::Keep(n)),
+                _ => Err(format!("invalid number of heights to keep '{}'", n)),
+            },
+            _ => Err(format!("unknown pruning mode '{}' (use archive, default, default:N or minimal)", s)),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConsensusState, ValidatorId};
+
+    #[test]
+    fn test_pruned_node_keeps_recent_heights() {
+        assert_eq!("default:3".parse(), Ok(Pruning::Keep(3)));
+        assert_eq!("default".parse(), Ok(Pruning::Keep(DEFAULT_KEEP)));
+        assert!("default:0".parse::<Pruning>().is_err());
+        assert_eq!(Pruning::Keep(3).retain_from(1), 0);
+        assert_eq!(Pruning::Minimal.retain_from(5), 5);
+
+        let finalize = |state: &ConsensusState, payload: &[u8]| {
+            let id = state.propose(payload.to_vec()).unwrap();
+            let (height, round) = state.proposal_round(&id).unwrap();
+            for validator in 0..3 {
+                state.vote_combined(id, height, round, ValidatorId(validator)).unwrap();
+            }
+            id
+        };
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect()).with_pruning(Pruning::Keep(3));
+        let ids: Vec<_> = (0..6u8).map(|i| finalize(&state, &[i])).collect();
+        assert_eq!(state.retained_from(), Some(3));
+        assert!(state.get_block(&ids[2]).is_none());
+        assert!(state.get_block(&ids[3]).is_some());
+        assert_eq!(state.iter_finalized(0).count(), 3);
+        // Pruning leaves the replicated state and the head untouched
+        assert_eq!(state.finalized_height(), Some(5));
+        assert_eq!(state.state_machine().rewards.balance(ValidatorId(0)), 6 * 11);
+
+        let minimal = ConsensusState::new((0..4).map(ValidatorId).collect()).with_pruning(Pruning::Minimal);
+        let ids: Vec<_> = (0..3u8).map(|i| finalize(&minimal, &[i])).collect();
+        assert_eq!(minimal.iter_finalized(0).map(|b| b.id).collect::<Vec<_>>(), vec![ids[2]]);
+        // The next proposal still builds on the head
+        let next = minimal.propose(b"next".to_vec()).unwrap();
+        assert_eq!(minimal.get_block(&next).unwrap().parent_id, Some(ids[2]));
+    }
+}
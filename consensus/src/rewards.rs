@@ -0,0 +1,106 @@
+// Liveness rewards: every finalized height credits its proposer and the
+// validators whose commit votes are in the quorum certificate.
+use crate::ValidatorId;
+use std::collections::BTreeMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RewardSchedule {
+    pub proposer: u64,
+    pub voter: u64,
+    // Rewards halve every this many heights; None keeps them flat
+    pub halving_interval: Option<u64>,
+}
+
+impl Default for RewardSchedule {
+    fn default() -> Self {
+        Self {
+            proposer: 10,
+            voter: 1,
+            halving_interval: None,
+        }
+    }
+}
+
+impl RewardSchedule {
+    // (proposer, voter) reward at `height`
+    pub fn at(&self, height: u64) -> (u64, u64) {
+        let halvings = match self.halving_interval {
+            Some(interval) if interval > 0 => height / interval,
+            _ => 0,
+        };
+        if halvings >= 64 {
+            return (0, 0);
+        }
+        (self.proposer >> halvings, self.voter >> halvings)
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct RewardLedger {
+    schedule: RewardSchedule,
+    balances: BTreeMap<ValidatorId, u64>,
+}
+
+impl RewardLedger {
+    pub fn new(schedule: RewardSchedule) -> Self {
+        Self {
+            schedule,
+            balances: BTreeMap::new(),
+        }
+    }
+
+    pub fn schedule(&self) -> RewardSchedule {
+        self.schedule
+    }
+
+    pub fn balance(&self, account: ValidatorId) -> u64 {
+        self.balances.get(&account).copied().unwrap_or(0)
+    }
+
+    pub fn balances(&self) -> impl Iterator<Item = (ValidatorId, u64)> + '_ {
+        self.balances.iter().map(|(&id, &balance)| (id, balance))
+    }
+
+    pub fn credit_height(&mut self, height: u64, proposer: ValidatorId, voters: &[ValidatorId]) {
+        let (proposer_reward, voter_reward) = self.schedule.at(height);
+        self.credit(proposer, proposer_reward);
+        for &voter in voters {
+            self.credit(voter, voter_reward);
+        }
+    }
+
+    pub(crate) fn credit(&mut self, account: ValidatorId, amount: u64) {
+        if amount > 0 {
+            let balance = self.balances.entry(account).or_default();
+            *balance = balance.saturating_add(amount);
+        }
+    }
+
+    // Callers check the balance covers `amount` first
+    pub(crate) fn debit(&mut self, account: ValidatorId, amount: u64) {
+        if let Some(balance) = self.balances.get_mut(&account) {
+            *balance -= amount;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_schedule_halves_and_credits_voters() {
+        let schedule = RewardSchedule { proposer: 8, voter: 2, halving_interval: Some(10) };
+        assert_eq!(schedule.at(9), (8, 2));
+        assert_eq!(schedule.at(10), (4, 1));
+        assert_eq!(schedule.at(25), (2, 0));
+
+        let mut ledger = RewardLedger::new(schedule);
+        ledger.credit_height(0, ValidatorId(0), &[0, 1, 2].map(ValidatorId));
+        ledger.credit_height(10, ValidatorId(1), &[1, 2, 3].map(ValidatorId));
+        assert_eq!(ledger.balance(ValidatorId(0)), 10);
+        assert_eq!(ledger.balance(ValidatorId(1)), 2 + 4 + 1);
+        assert_eq!(ledger.balance(ValidatorId(3)), 1);
+        assert_eq!(ledger.balance(ValidatorId(9)), 0);
+    }
+}
@@ -0,0 +1,242 @@
+// Storage scrubbing: re-reads every stored batch and checks it against the
+// in-memory chain, so bit rot on disk is found while a good copy still exists.
+// Block ids are recomputed from their contents and certificates compared with
+// the chain's. Damaged stores are rewritten from the chain, falling back to
+// the stored copies that verified for heights already pruned from memory.
+use crate::store::{BlockStore, StoreError, WriteBatch};
+use crate::{Block, BlockId, ConsensusState, QuorumCertificate};
+use serde::Serialize;
+use std::collections::{BTreeMap, HashSet};
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CorruptionKind {
+    // The batch no longer decodes
+    Unreadable,
+    // The block's contents no longer hash to its id
+    IdMismatch,
+    // A different block is finalized at this height
+    NotFinalized,
+    CertificateMismatch,
+    MissingCertificate,
+    // No stored batch covers this height
+    MissingHeight,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Corruption {
+    // 1-based position of the batch in the store
+    pub batch: usize,
+    pub height: Option<u64>,
+    pub block_id: Option<BlockId>,
+    pub kind: CorruptionKind,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct ScrubReport {
+    pub scanned_batches: usize,
+    pub scanned_blocks: usize,
+    pub corruptions: Vec<Corruption>,
+    pub repaired: bool,
+    // Unix time in milliseconds
+    pub finished_at: u64,
+}
+
+type Verified = BTreeMap<u64, (Block, Option<QuorumCertificate>)>;
+
+pub struct Scrubber<S> {
+    store: Arc<S>,
+    consensus: ConsensusState,
+}
+
+impl<S: BlockStore> Scrubber<S> {
+    // `store` should be the one behind the consensus state's writer, so
+    // repairs are ordered with regular commits
+    pub fn new(store: Arc<S>, consensus: ConsensusState) -> Self {
+        Self { store, consensus }
+    }
+
+    pub async fn run_once(&self) -> Result<ScrubReport, StoreError> {
+        let batches = self.store.scan().await?;
+        let (mut report, verified) = check(&self.consensus, &batches);
+        if !report.corruptions.is_empty() {
+            report.repaired = repair(&self.consensus, verified);
+        }
+        report.finished_at = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        self.consensus.metrics.lock().unwrap().record_scrub(&report);
+        Ok(report)
+    }
+
+    // Scrubs every `interval`, forever
+    pub async fn run(&self, interval: Duration) {
+        let mut ticker = tokio::time::interval(interval);
+        loop {
+            ticker.tick().await;
+            match self.run_once().await {
+                Ok(report) if !report.corruptions.is_empty() => eprintln!(
+                    "Scrub found {} corrupt entries, {}",
+                    report.corruptions.len(),
+                    if report.repaired { "repaired" } else { "not repairable" }
+                ),
+                Ok(_) => {}
+                Err(e) => eprintln!("Scrub failed: {}", e),
+            }
+        }
+    }
+
+    // Must be called from within a tokio runtime
+    pub fn spawn(self, interval: Duration) -> tokio::task::JoinHandle<()>
+    where
+        S: Send + Sync + 'static,
+    {
+        tokio::spawn(async move { self.run(interval).await })
+    }
+}
+
+fn check(consensus: &ConsensusState, batches: &[Option<WriteBatch>]) -> (ScrubReport, Verified) {
+    let chain = consensus.chain.read().unwrap();
+    let mut scanned_blocks = 0;
+    let mut corruptions = Vec::new();
+    let mut verified = Verified::new();
+    let mut seen_heights = HashSet::new();
+    let mut corrupt = |batch: usize, block: Option<&Block>, kind| {
+        corruptions.push(Corruption {
+            batch,
+            height: block.map(|b| b.height),
+            block_id: block.map(|b| b.id),
+            kind,
+        });
+    };
+
+    for (i, batch) in batches.iter().enumerate() {
+        let position = i + 1;
+        let Some(batch) = batch else {
+            corrupt(position, None, CorruptionKind::Unreadable);
+            continue;
+        };
+
+        for block in &batch.blocks {
+            scanned_blocks += 1;
+            seen_heights.insert(block.height);
+            let certificate = batch.certificates.iter().find(|qc| qc.block_id == block.id);
+            if block.computed_id() != block.id {
+                corrupt(position, Some(block), CorruptionKind::IdMismatch);
+            } else if chain.finalized_at(block.height) != Some(&block.id) {
+                corrupt(position, Some(block), CorruptionKind::NotFinalized);
+            } else if certificate.is_none() && chain.certificate(&block.id).is_some() {
+                corrupt(position, Some(block), CorruptionKind::MissingCertificate);
+            } else if certificate.is_some_and(|qc| {
+                qc.height != block.height || chain.certificate(&block.id).is_some_and(|c| c != qc)
+            }) {
+                corrupt(position, Some(block), CorruptionKind::CertificateMismatch);
+            } else {
+                verified.insert(block.height, (block.clone(), certificate.cloned()));
+            }
+        }
+    }
+
+    // Stored heights must be contiguous from genesis
+    let stored_top = seen_heights.iter().max().copied();
+    for height in 0..stored_top.map(|h| h + 1).unwrap_or(0) {
+        if !seen_heights.contains(&height) {
+            corruptions.push(Corruption {
+                batch: 0,
+                height: Some(height),
+                block_id: chain.finalized_at(height).cloned(),
+                kind: CorruptionKind::MissingHeight,
+            });
+        }
+    }
+    let report = ScrubReport {
+        scanned_batches: batches.len(),
+        scanned_blocks,
+        corruptions,
+        ..Default::default()
+    };
+    (report, verified)
+}
+
+// Rewrites the store with one batch per finalized height, taken from the
+// chain or from verified stored copies. Gives up, leaving the store as it is,
+// if some height has neither.
+fn repair(consensus: &ConsensusState, verified: Verified) -> bool {
+    let Some(writer) = &consensus.store else {
+        return false;
+    };
+    // Held until the rewrite is queued so no finalization slips in between
+    let chain = consensus.chain.read().unwrap();
+    let Some(top) = chain.finalized_height() else {
+        return false;
+    };
+
+    let mut batches = Vec::new();
+    for height in 0..=top {
+        let from_chain = chain.finalized_at(height).and_then(|id| {
+            let block = chain.get_block(id)?;
+            Some((block, chain.certificate(id).cloned()))
+        });
+        let Some((block, certificate)) = from_chain.or_else(|| verified.get(&height).cloned()) else {
+            return false;
+        };
+        batches.push(WriteBatch {
+            head: Some(block.id),
+            blocks: vec![block],
+            votes: Vec::new(),
+            certificates: certificate.into_iter().collect(),
+        });
+    }
+    writer.rewrite(batches);
+    true
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::store::{FileStore, StoreWriter};
+    use crate::ValidatorId;
+
+    #[tokio::test]
+    async fn test_scrub_detects_and_repairs_corruption() {
+        let path = std::env::temp_dir().join(format!("mini-consensus-scrub-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = Arc::new(FileStore::open(&path));
+        let writer = StoreWriter::spawn(store.clone());
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect()).with_store(writer.clone());
+        for payload in [b"a", b"b", b"c"] {
+            let id = state.propose(payload.to_vec()).unwrap();
+            let (height, round) = state.proposal_round(&id).unwrap();
+            for validator in 0..3 {
+                state.vote_combined(id, height, round, ValidatorId(validator)).unwrap();
+            }
+        }
+        writer.flush().await;
+
+        let scrubber = Scrubber::new(store.clone(), state.clone());
+        assert!(scrubber.run_once().await.unwrap().corruptions.is_empty());
+
+        // Flip the payload of height 1 and garble height 2
+        let text = std::fs::read_to_string(&path).unwrap();
+        let mut lines: Vec<String> = text.lines().map(String::from).collect();
+        lines[1] = lines[1].replace("\"payload\":[98]", "\"payload\":[99]");
+        lines[2] = "{\"blocks\":[{\"id\"".to_string();
+        std::fs::write(&path, lines.join("\n") + "\n").unwrap();
+
+        let report = scrubber.run_once().await.unwrap();
+        let kinds: Vec<CorruptionKind> = report.corruptions.iter().map(|c| c.kind).collect();
+        assert_eq!(kinds, vec![CorruptionKind::IdMismatch, CorruptionKind::Unreadable]);
+        assert!(report.repaired);
+        assert_eq!(state.metrics().scrub_corruptions_total, 2);
+
+        writer.flush().await;
+        assert!(scrubber.run_once().await.unwrap().corruptions.is_empty());
+        let snapshot = store.load().await.unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(snapshot.blocks.len(), 3);
+        assert_eq!(snapshot.head, state.finalize());
+    }
+}
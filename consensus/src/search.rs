@@ -0,0 +1,189 @@
+// Lookups a block explorer needs: a single query box resolving block ids,
+// heights, transaction hashes and validator ids, and the blocks a validator
+// proposed. Only blocks the node still holds are found, so a pruned node
+// answers for its retained heights.
+use crate::{BlockId, Chain, ConsensusState, StoredBlock, Round, Transaction, TxBatch, TxHash, ValidatorId};
+use serde::Serialize;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct BlockSummary {
+    pub id: BlockId,
+    pub parent_id: Option<BlockId>,
+    pub height: u64,
+    pub round: Round,
+    pub proposer: ValidatorId,
+    pub timestamp: u64,
+    // Transactions carried, 0 for payloads that are not a transaction batch
+    pub tx_count: usize,
+    pub finalized: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum SearchResult {
+    Block(BlockSummary),
+    Transaction {
+        tx_hash: TxHash,
+        // Position within the block's batch
+        index: usize,
+        tx: Transaction,
+        block: BlockSummary,
+    },
+    Validator {
+        validator_id: ValidatorId,
+        voting_power: u64,
+        // Among the retained finalized blocks
+        blocks_proposed: usize,
+    },
+}
+
+impl Chain {
+    // Called for each block as it is finalized
+    pub(crate) fn index_transactions(&mut self, id: &BlockId) {
+        let payload = self.blocks.get(id).and_then(|b| self.payloads.get(&b.payload_hash));
+        let Some(batch) = payload.and_then(|p| TxBatch::decode(&p)) else {
+            return;
+        };
+        for tx in batch.txs {
+            self.tx_index.insert(tx.hash(), *id);
+        }
+    }
+
+    fn is_finalized(&self, block: &StoredBlock) -> bool {
+        self.finalized_at(block.height) == Some(&block.id)
+    }
+
+    fn summary(&self, block: &StoredBlock) -> BlockSummary {
+        let tx_count = self
+            .payloads
+            .get(&block.payload_hash)
+            .and_then(|p| TxBatch::decode(&p))
+            .map_or(0, |batch| batch.txs.len());
+        BlockSummary {
+            id: block.id,
+            parent_id: block.parent_id,
+            height: block.height,
+            round: block.round,
+            proposer: block.proposer,
+            timestamp: block.timestamp,
+            tx_count,
+            finalized: self.is_finalized(block),
+        }
+    }
+
+    fn find_transaction(&self, hash: &str) -> Option<SearchResult> {
+        let block = self.tx_index.get(hash).and_then(|id| self.blocks.get(id))?;
+        // A block replaced at its height keeps no claim on its transactions
+        if !self.is_finalized(block) {
+            return None;
+        }
+        let batch = self.payloads.get(&block.payload_hash).and_then(|p| TxBatch::decode(&p))?;
+        let (index, tx) = batch.txs.into_iter().enumerate().find(|(_, tx)| tx.hash() == hash)?;
+        Some(SearchResult::Transaction {
+            tx_hash: hash.to_string(),
+            index,
+            tx,
+            block: self.summary(block),
+        })
+    }
+
+    // Finalized blocks by `proposer` below height `before`, newest first
+    fn proposed_by(&self, proposer: ValidatorId, before: u64) -> impl Iterator<Item = &StoredBlock> {
+        self.finalized_chain
+            .iter()
+            .take(before.min(self.finalized_chain.len() as u64) as usize)
+            .rev()
+            .filter_map(|id| self.blocks.get(id))
+            .filter(move |b| b.proposer == proposer)
+    }
+}
+
+impl ConsensusState {
+    // Every match for `query`: a number may be both a height and a validator
+    // id, a hash either a block id or a transaction hash
+    pub fn search(&self, query: &str) -> Vec<SearchResult> {
+        let query = query.trim();
+        let chain = self.chain.read().unwrap();
+        let mut results = Vec::new();
+        if let Ok(number) = query.parse::<u64>() {
+            if let Some(block) = chain.finalized_at(number).and_then(|id| chain.blocks.get(id)) {
+                results.push(SearchResult::Block(chain.summary(block)));
+            }
+            let validator_id = u32::try_from(number).map(ValidatorId).ok();
+            if let Some(validator_id) = validator_id.filter(|id| self.validators.contains(id)) {
+                results.push(SearchResult::Validator {
+                    validator_id,
+                    voting_power: self.state_machine.read().unwrap().staking.voting_power(validator_id),
+                    blocks_proposed: chain.proposed_by(validator_id, u64::MAX).count(),
+                });
+            }
+            return results;
+        }
+
+        let query = query.to_ascii_lowercase();
+        if let Some(block) = query.parse().ok().and_then(|id: BlockId| chain.blocks.get(&id)) {
+            results.push(SearchResult::Block(chain.summary(block)));
+        }
+        results.extend(chain.find_transaction(&query));
+        results
+    }
+
+    // Up to `limit` finalized blocks proposed by `validator` below height
+    // `before`, newest first; None for an unknown validator
+    pub fn blocks_proposed_by(&self, validator: ValidatorId, before: Option<u64>, limit: usize) -> Option<Vec<BlockSummary>> {
+        if !self.validators.contains(&validator) {
+            return None;
+        }
+        let chain = self.chain.read().unwrap();
+        let blocks = chain
+            .proposed_by(validator, before.unwrap_or(u64::MAX))
+            .take(limit)
+            .map(|b| chain.summary(b))
+            .collect();
+        Some(blocks)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_search_resolves_heights_ids_and_transactions() {
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect());
+        let finalize = |payload: Vec<u8>| {
+            let id = state.propose(payload).unwrap();
+            let (height, round) = state.proposal_round(&id).unwrap();
+            for validator in 0..3 {
+                state.vote_combined(id, height, round, ValidatorId(validator)).unwrap();
+            }
+            id
+        };
+        let genesis = finalize(b"genesis".to_vec());
+        let tx = Transaction { sender: "alice".into(), nonce: 0, data: "hello".into(), priority: 0 };
+        let batch = finalize(TxBatch { txs: vec![tx.clone()] }.encode());
+        let pending = state.propose(b"pending".to_vec()).unwrap();
+
+        // Height 1 and validator 1
+        let results = state.search("1");
+        assert!(matches!(&results[0], SearchResult::Block(b) if b.id == batch && b.tx_count == 1));
+        assert!(matches!(results[1], SearchResult::Validator { validator_id: ValidatorId(1), blocks_proposed: 0, .. }));
+        assert!(matches!(&state.search(&genesis.to_string())[..], [SearchResult::Block(b)] if b.finalized && b.height == 0));
+        assert!(matches!(&state.search(&pending.to_string())[..], [SearchResult::Block(b)] if !b.finalized));
+        match &state.search(&tx.hash().to_uppercase())[..] {
+            [SearchResult::Transaction { index: 0, tx: found, block, .. }] => {
+                assert_eq!(found, &tx);
+                assert_eq!(block.id, batch);
+            }
+            other => panic!("{:?}", other),
+        }
+        assert!(state.search("9").is_empty());
+        assert!(state.search("not-a-hash").is_empty());
+
+        // Validator 0 leads every round
+        let proposed = state.blocks_proposed_by(ValidatorId(0), None, 10).unwrap();
+        assert_eq!(proposed.iter().map(|b| b.id).collect::<Vec<_>>(), vec![batch, genesis]);
+        assert_eq!(state.blocks_proposed_by(ValidatorId(0), Some(1), 10).unwrap()[0].id, genesis);
+        assert!(state.blocks_proposed_by(ValidatorId(9), None, 10).is_none());
+    }
+}
@@ -0,0 +1,361 @@
+// Vote signing behind a `Signer` trait so keys can live off the node host.
+// Whoever holds the key also holds the double-sign guard: a remote signer
+// refuses to equivocate no matter what the node asks for.
+use crate::guard::{DoubleSignGuard, HighWaterMark};
+use crate::wire::proto::{self, signer_request, signer_response};
+use crate::{BlockId, Round, SignedVote, Vote, VotePhase, Wire, WireError};
+use ed25519_dalek::{Signature, Signer as _, SigningKey, VerifyingKey};
+use hmac::{Hmac, Mac};
+use protobuf::{CodedInputStream, Message, MessageField};
+use sha2::Sha256;
+use std::fmt;
+use std::io::Write;
+use std::net::{SocketAddr, TcpListener, TcpStream};
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+type HmacSha256 = Hmac<Sha256>;
+
+const REMOTE_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignerError {
+    // A different proposal was already signed at this height and phase
+    DoubleSign { height: u64, phase: VotePhase, signed: BlockId },
+    // (height, round, phase) is below the signer's high-water mark
+    Regression { height: u64, round: Round, last_height: u64, last_round: Round },
+    Unauthorized,
+    // Hot-standby signer without the signing lease
+    NotLeaseHolder,
+    Remote(String),
+    Io(String),
+}
+
+impl fmt::Display for SignerError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignerError::DoubleSign { height, phase, signed } => {
+                write!(f, "already signed {:?} for {} at height {}", phase, signed, height)
+            }
+            SignerError::Regression { height, round, last_height, last_round } => {
+                write!(f, "height {} round {} is below last signed height {} round {}", height, round, last_height, last_round)
+            }
+            SignerError::Unauthorized => write!(f, "signer rejected request authentication"),
+            SignerError::NotLeaseHolder => write!(f, "signing lease is held by another node"),
+            SignerError::Remote(e) => write!(f, "remote signer error: {}", e),
+            SignerError::Io(e) => write!(f, "signer i/o error: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for SignerError {}
+
+pub trait Signer: Send + Sync {
+    fn public_key(&self) -> VerifyingKey;
+
+    // Signs `vote` at its height and round, refusing to equivocate
+    fn sign_vote(&self, vote: Vote) -> Result<SignedVote, SignerError>;
+}
+
+impl<S: Signer + ?Sized> Signer for Arc<S> {
+    fn public_key(&self) -> VerifyingKey {
+        (**self).public_key()
+    }
+
+    fn sign_vote(&self, vote: Vote) -> Result<SignedVote, SignerError> {
+        (**self).sign_vote(vote)
+    }
+}
+
+pub struct LocalSigner {
+    key: SigningKey,
+    guard: Mutex<DoubleSignGuard>,
+}
+
+impl LocalSigner {
+    // Guard state lives in memory only and is lost on restart
+    pub fn new(key: SigningKey) -> Self {
+        Self {
+            key,
+            guard: Mutex::new(DoubleSignGuard::default()),
+        }
+    }
+
+    pub fn with_guard_file(key: SigningKey, path: &Path) -> Result<Self, SignerError> {
+        let guard = DoubleSignGuard::open(path, &key.verifying_key())?;
+        Ok(Self {
+            key,
+            guard: Mutex::new(guard),
+        })
+    }
+
+    // Key files hold the 32-byte ed25519 secret as a hex line
+    pub fn read_key_file(path: &Path) -> Result<SigningKey, SignerError> {
+        let text = std::fs::read_to_string(path).map_err(|e| SignerError::Io(e.to_string()))?;
+        Self::key_from_hex(&text).ok_or_else(|| SignerError::Io(format!("{} is not a hex ed25519 key", path.display())))
+    }
+
+    // The contents of a key file
+    pub fn key_from_hex(text: &str) -> Option<SigningKey> {
+        let bytes: [u8; 32] = hex::decode(text.trim()).ok()?.try_into().ok()?;
+        Some(SigningKey::from_bytes(&bytes))
+    }
+
+    pub fn from_key_file(path: &Path) -> Result<Self, SignerError> {
+        Ok(Self::new(Self::read_key_file(path)?))
+    }
+
+    pub fn high_water_mark(&self) -> Option<HighWaterMark> {
+        self.guard.lock().unwrap().high_water_mark().cloned()
+    }
+}
+
+impl Signer for LocalSigner {
+    fn public_key(&self) -> VerifyingKey {
+        self.key.verifying_key()
+    }
+
+    fn sign_vote(&self, vote: Vote) -> Result<SignedVote, SignerError> {
+        // The mark is persisted before the signature leaves this function
+        self.guard.lock().unwrap().check_and_record(&vote)?;
+        let signature = self.key.sign(&vote.signing_bytes());
+        Ok(SignedVote { vote, signature })
+    }
+}
+
+// Wire protocol: length-delimited `SignerRequest`s and `SignerResponse`s (see
+// proto/consensus.proto). Sign requests carry an HMAC-SHA256 tag over the
+// vote signing bytes, which include height and round, keyed with a secret
+// shared between node and signer.
+#[derive(Debug)]
+enum Request {
+    PublicKey,
+    SignVote { vote: Vote, mac: Vec<u8> },
+}
+
+#[derive(Debug)]
+enum Response {
+    PublicKey(Vec<u8>),
+    Signature(Vec<u8>),
+    Error(String),
+    Unauthorized,
+}
+
+impl Wire for Request {
+    type Proto = proto::SignerRequest;
+
+    fn to_proto(&self) -> Self::Proto {
+        let op = match self {
+            Request::PublicKey => signer_request::Op::PublicKey(proto::Empty::new()),
+            Request::SignVote { vote, mac } => signer_request::Op::SignVote(proto::SignVoteRequest {
+                vote: MessageField::some(vote.to_proto()),
+                mac: mac.clone(),
+                ..Default::default()
+            }),
+        };
+        proto::SignerRequest { op: Some(op), ..Default::default() }
+    }
+
+    fn from_proto(proto: Self::Proto) -> Result<Self, WireError> {
+        match proto.op {
+            Some(signer_request::Op::PublicKey(_)) => Ok(Request::PublicKey),
+            Some(signer_request::Op::SignVote(request)) => {
+                let vote = request.vote.into_option().ok_or_else(|| WireError::Invalid {
+                    field: "vote",
+                    reason: "missing".to_string(),
+                })?;
+                Ok(Request::SignVote { vote: Vote::from_proto(vote)?, mac: request.mac })
+            }
+            None => Err(WireError::Invalid { field: "op", reason: "missing".to_string() }),
+        }
+    }
+}
+
+impl Wire for Response {
+    type Proto = proto::SignerResponse;
+
+    fn to_proto(&self) -> Self::Proto {
+        let result = match self {
+            Response::PublicKey(key) => signer_response::Result::PublicKey(key.clone()),
+            Response::Signature(signature) => signer_response::Result::Signature(signature.clone()),
+            Response::Error(e) => signer_response::Result::Error(e.clone()),
+            Response::Unauthorized => signer_response::Result::Unauthorized(proto::Empty::new()),
+        };
+        proto::SignerResponse { result: Some(result), ..Default::default() }
+    }
+
+    fn from_proto(proto: Self::Proto) -> Result<Self, WireError> {
+        match proto.result {
+            Some(signer_response::Result::PublicKey(key)) => Ok(Response::PublicKey(key)),
+            Some(signer_response::Result::Signature(signature)) => Ok(Response::Signature(signature)),
+            Some(signer_response::Result::Error(e)) => Ok(Response::Error(e)),
+            Some(signer_response::Result::Unauthorized(_)) => Ok(Response::Unauthorized),
+            None => Err(WireError::Invalid { field: "result", reason: "missing".to_string() }),
+        }
+    }
+}
+
+fn send(writer: &mut impl Write, message: &impl Wire) -> std::io::Result<()> {
+    message.to_proto().write_length_delimited_to_writer(writer).map_err(std::io::Error::other)
+}
+
+fn sign_request_mac(auth_key: &[u8], vote: &Vote) -> HmacSha256 {
+    let mut mac = HmacSha256::new_from_slice(auth_key).expect("HMAC accepts keys of any length");
+    mac.update(&vote.signing_bytes());
+    mac
+}
+
+pub struct RemoteSigner {
+    addr: SocketAddr,
+    auth_key: Vec<u8>,
+    public_key: VerifyingKey,
+}
+
+impl RemoteSigner {
+    pub fn connect(addr: SocketAddr, auth_key: Vec<u8>) -> Result<Self, SignerError> {
+        let response = Self::call(addr, &Request::PublicKey)?;
+        let Response::PublicKey(key) = response else {
+            return Err(SignerError::Remote(format!("unexpected response {:?}", response)));
+        };
+        let public_key = <[u8; 32]>::try_from(key)
+            .ok()
+            .and_then(|b| VerifyingKey::from_bytes(&b).ok())
+            .ok_or_else(|| SignerError::Remote("invalid public key".to_string()))?;
+
+        Ok(Self { addr, auth_key, public_key })
+    }
+
+    fn call(addr: SocketAddr, request: &Request) -> Result<Response, SignerError> {
+        let io = |e: std::io::Error| SignerError::Io(e.to_string());
+
+        let mut stream = TcpStream::connect_timeout(&addr, REMOTE_TIMEOUT).map_err(io)?;
+        stream.set_read_timeout(Some(REMOTE_TIMEOUT)).map_err(io)?;
+        send(&mut stream, request).map_err(io)?;
+
+        let reply = CodedInputStream::new(&mut stream).read_message().map_err(|e| SignerError::Io(e.to_string()))?;
+        Response::from_proto(reply).map_err(|e| SignerError::Remote(e.to_string()))
+    }
+}
+
+impl Signer for RemoteSigner {
+    fn public_key(&self) -> VerifyingKey {
+        self.public_key
+    }
+
+    fn sign_vote(&self, vote: Vote) -> Result<SignedVote, SignerError> {
+        let mac = sign_request_mac(&self.auth_key, &vote).finalize().into_bytes().to_vec();
+        let request = Request::SignVote { vote: vote.clone(), mac };
+
+        match Self::call(self.addr, &request)? {
+            Response::Signature(sig) => {
+                let signature = Signature::from_slice(&sig)
+                    .map_err(|_| SignerError::Remote("invalid signature".to_string()))?;
+                // Don't trust the signer blindly: the signature must match its key
+                self.public_key
+                    .verify_strict(&vote.signing_bytes(), &signature)
+                    .map_err(|_| SignerError::Remote("signature does not verify".to_string()))?;
+                Ok(SignedVote { vote, signature })
+            }
+            Response::Unauthorized => Err(SignerError::Unauthorized),
+            Response::Error(e) => Err(SignerError::Remote(e)),
+            other => Err(SignerError::Remote(format!("unexpected response {:?}", other))),
+        }
+    }
+}
+
+// Serves a local or KMS signer to remote nodes; one thread per connection
+pub struct RemoteSignerServer {
+    signer: Arc<dyn Signer>,
+    auth_key: Arc<Vec<u8>>,
+}
+
+impl RemoteSignerServer {
+    pub fn new(signer: impl Signer + 'static, auth_key: Vec<u8>) -> Self {
+        Self {
+            signer: Arc::new(signer),
+            auth_key: Arc::new(auth_key),
+        }
+    }
+
+    pub fn serve(&self, listener: TcpListener) {
+        for stream in listener.incoming().flatten() {
+            let signer = self.signer.clone();
+            let auth_key = self.auth_key.clone();
+            std::thread::spawn(move || {
+                let _ = handle_connection(stream, &signer, &auth_key);
+            });
+        }
+    }
+}
+
+fn handle_connection(stream: TcpStream, signer: &dyn Signer, auth_key: &[u8]) -> std::io::Result<()> {
+    stream.set_read_timeout(Some(REMOTE_TIMEOUT))?;
+    let mut writer = stream.try_clone()?;
+    let mut reader = stream;
+    let mut input = CodedInputStream::new(&mut reader);
+
+    while !input.eof().map_err(std::io::Error::other)? {
+        let request = input.read_message::<proto::SignerRequest>().map_err(|e| WireError::Malformed(e.to_string()));
+        // A frame that fails to parse may be partly unread, so nothing after
+        // it on this connection can be trusted
+        let framed = request.is_ok();
+        let response = match request.and_then(Request::from_proto) {
+            Ok(request) => handle_request(request, signer, auth_key),
+            Err(e) => Response::Error(format!("malformed request: {}", e)),
+        };
+        send(&mut writer, &response)?;
+        if !framed {
+            break;
+        }
+    }
+    Ok(())
+}
+
+fn handle_request(request: Request, signer: &dyn Signer, auth_key: &[u8]) -> Response {
+    match request {
+        Request::PublicKey => Response::PublicKey(signer.public_key().as_bytes().to_vec()),
+        Request::SignVote { vote, mac } => {
+            if sign_request_mac(auth_key, &vote).verify_slice(&mac).is_err() {
+                return Response::Unauthorized;
+            }
+
+            match signer.sign_vote(vote) {
+                Ok(signed) => Response::Signature(signed.signature.to_bytes().to_vec()),
+                Err(e) => Response::Error(e.to_string()),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ValidatorId;
+
+    fn vote(proposal: &str, height: u64, round: u64, phase: VotePhase) -> Vote {
+        let proposal_id = BlockId::from(blake3::hash(proposal.as_bytes()));
+        Vote { proposal_id, height, round: Round(round), validator_id: ValidatorId(0), phase }
+    }
+
+    #[test]
+    fn test_remote_signer_refuses_equivocation() {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let local = LocalSigner::new(SigningKey::from_bytes(&[3u8; 32]));
+        let expected_key = local.public_key();
+        std::thread::spawn(move || RemoteSignerServer::new(local, b"shared".to_vec()).serve(listener));
+
+        let remote = RemoteSigner::connect(addr, b"shared".to_vec()).unwrap();
+        assert_eq!(remote.public_key(), expected_key);
+
+        assert!(remote.sign_vote(vote("a", 5, 0, VotePhase::Precommit)).is_ok());
+        // Re-signing the same vote is fine, a conflicting one is not
+        assert!(remote.sign_vote(vote("a", 5, 0, VotePhase::Precommit)).is_ok());
+        assert!(matches!(remote.sign_vote(vote("b", 5, 0, VotePhase::Precommit)), Err(SignerError::Remote(_))));
+        assert!(matches!(remote.sign_vote(vote("c", 4, 0, VotePhase::Commit)), Err(SignerError::Remote(_))));
+
+        let intruder = RemoteSigner { addr, auth_key: b"wrong".to_vec(), public_key: expected_key };
+        assert_eq!(intruder.sign_vote(vote("d", 6, 0, VotePhase::Commit)).unwrap_err(), SignerError::Unauthorized);
+    }
+}
@@ -0,0 +1,198 @@
+// Sparse Merkle tree over the state machine's key-value view. A key sits on
+// the path given by the bits of its BLAKE3 hash, most significant first. A
+// subtree holding a single leaf is that leaf and an empty one hashes to
+// zeros, so the tree is only as deep as it takes to tell its keys apart. A
+// proof carries the sibling hashes from the root down to where the key's
+// path ends, and shows either the key's value or that the key is absent.
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+pub type Hash = [u8; 32];
+
+const LEAF_DOMAIN: &[u8] = b"mini-consensus/smt/leaf/v1";
+const NODE_DOMAIN: &[u8] = b"mini-consensus/smt/node/v1";
+
+// Root of the empty tree and of every empty subtree
+pub const EMPTY: Hash = [0; 32];
+
+pub fn key_hash(key: &str) -> Hash {
+    blake3::hash(key.as_bytes()).into()
+}
+
+fn leaf_hash(key_hash: &Hash, value_hash: &Hash) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(LEAF_DOMAIN).update(key_hash).update(value_hash);
+    hasher.finalize().into()
+}
+
+fn node_hash(left: &Hash, right: &Hash) -> Hash {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(NODE_DOMAIN).update(left).update(right);
+    hasher.finalize().into()
+}
+
+// True when the path goes right at `depth`
+fn bit(hash: &Hash, depth: usize) -> bool {
+    hash[depth / 8] >> (7 - depth % 8) & 1 == 1
+}
+
+fn decode_hash(hex: &str) -> Option<Hash> {
+    hex::decode(hex).ok()?.try_into().ok()
+}
+
+// `leaves` are sorted by key hash, so a subtree's left half is a prefix
+fn subtree_root(leaves: &[(Hash, Hash)], depth: usize) -> Hash {
+    match leaves {
+        [] => EMPTY,
+        [(key_hash, value_hash)] => leaf_hash(key_hash, value_hash),
+        _ => {
+            let split = leaves.partition_point(|(key_hash, _)| !bit(key_hash, depth));
+            node_hash(&subtree_root(&leaves[..split], depth + 1), &subtree_root(&leaves[split..], depth + 1))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct SparseMerkleTree {
+    // Value hash by key hash
+    leaves: BTreeMap<Hash, Hash>,
+}
+
+impl SparseMerkleTree {
+    pub fn insert(&mut self, key: &str, value: &[u8]) {
+        self.leaves.insert(key_hash(key), blake3::hash(value).into());
+    }
+
+    pub fn len(&self) -> usize {
+        self.leaves.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leaves.is_empty()
+    }
+
+    pub fn root(&self) -> Hash {
+        let leaves: Vec<(Hash, Hash)> = self.leaves.iter().map(|(&k, &v)| (k, v)).collect();
+        subtree_root(&leaves, 0)
+    }
+
+    pub fn prove(&self, key: &str) -> SparseMerkleProof {
+        let target = key_hash(key);
+        let leaves: Vec<(Hash, Hash)> = self.leaves.iter().map(|(&k, &v)| (k, v)).collect();
+        let mut subtree = &leaves[..];
+        let mut siblings = Vec::new();
+        while subtree.len() > 1 {
+            let depth = siblings.len();
+            let split = subtree.partition_point(|(key_hash, _)| !bit(key_hash, depth));
+            let (left, right) = subtree.split_at(split);
+            let (next, sibling) = if bit(&target, depth) { (right, left) } else { (left, right) };
+            siblings.push(hex::encode(subtree_root(sibling, depth + 1)));
+            subtree = next;
+        }
+        let leaf = subtree.first().map(|(key_hash, value_hash)| ProofLeaf {
+            key_hash: hex::encode(key_hash),
+            value_hash: hex::encode(value_hash),
+        });
+        SparseMerkleProof { leaf, siblings }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ProofLeaf {
+    pub key_hash: String,   // hex
+    pub value_hash: String, // hex
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct SparseMerkleProof {
+    // The leaf the key's path ends at: the key's own, another key's when the
+    // key is absent, or none for an empty subtree
+    pub leaf: Option<ProofLeaf>,
+    // Hex sibling hashes from the root down
+    pub siblings: Vec<String>,
+}
+
+impl SparseMerkleProof {
+    // Checks that `key` holds `value` under `root`, or with `value` None that
+    // the key is absent
+    pub fn verify(&self, root: &Hash, key: &str, value: Option<&[u8]>) -> bool {
+        let target = key_hash(key);
+        let Some(siblings) = self.siblings.iter().map(|s| decode_hash(s)).collect::<Option<Vec<Hash>>>() else {
+            return false;
+        };
+        if siblings.len() > 256 {
+            return false;
+        }
+        let leaf = match &self.leaf {
+            Some(leaf) => match (decode_hash(&leaf.key_hash), decode_hash(&leaf.value_hash)) {
+                (Some(key_hash), Some(value_hash)) => Some((key_hash, value_hash)),
+                _ => return false,
+            },
+            None => None,
+        };
+
+        let mut current = match (leaf, value) {
+            (Some((key_hash, value_hash)), Some(value)) => {
+                if key_hash != target || value_hash != <Hash>::from(blake3::hash(value)) {
+                    return false;
+                }
+                leaf_hash(&key_hash, &value_hash)
+            }
+            // Another key ends where this one's path does
+            (Some((key_hash, value_hash)), None) => {
+                if key_hash == target || (0..siblings.len()).any(|depth| bit(&key_hash, depth) != bit(&target, depth)) {
+                    return false;
+                }
+                leaf_hash(&key_hash, &value_hash)
+            }
+            (None, None) => EMPTY,
+            (None, Some(_)) => return false,
+        };
+        for (depth, sibling) in siblings.iter().enumerate().rev() {
+            current = if bit(&target, depth) { node_hash(sibling, &current) } else { node_hash(&current, sibling) };
+        }
+        current == *root
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_proofs_show_values_and_absence() {
+        let mut tree = SparseMerkleTree::default();
+        assert_eq!(tree.root(), EMPTY);
+        assert!(tree.prove("stake/0").verify(&EMPTY, "stake/0", None));
+
+        for i in 0..50 {
+            tree.insert(&format!("nonce/user{}", i), i.to_string().as_bytes());
+        }
+        let root = tree.root();
+
+        let proof = tree.prove("nonce/user7");
+        assert!(proof.verify(&root, "nonce/user7", Some(b"7")));
+        assert!(!proof.verify(&root, "nonce/user7", Some(b"8")));
+        assert!(!proof.verify(&root, "nonce/user7", None));
+        assert!(!proof.verify(&root, "nonce/user8", Some(b"7")));
+
+        // Absent keys end at another key's leaf or at an empty subtree
+        let absent: Vec<String> = (0..20).map(|i| format!("nonce/nobody{}", i)).collect();
+        for key in &absent {
+            let proof = tree.prove(key);
+            assert!(proof.verify(&root, key, None), "{}", key);
+            assert!(!proof.verify(&root, key, Some(b"0")), "{}", key);
+        }
+        assert!(absent.iter().any(|key| tree.prove(key).leaf.is_none()));
+        assert!(absent.iter().any(|key| tree.prove(key).leaf.is_some()));
+
+        // Any change to the tree or the proof breaks it
+        let mut tampered = proof.clone();
+        tampered.siblings[0] = hex::encode(EMPTY);
+        assert!(!tampered.verify(&root, "nonce/user7", Some(b"7")));
+        tree.insert("nonce/user7", b"8");
+        assert_ne!(tree.root(), root);
+        assert!(!proof.verify(&tree.root(), "nonce/user7", Some(b"7")));
+        assert!(tree.prove("nonce/user7").verify(&tree.root(), "nonce/user7", Some(b"8")));
+    }
+}
@@ -0,0 +1,284 @@
+// Bonded stake per validator. Stake changes arrive as staking transactions
+// in finalized block payloads; voting power is the bonded amount. Bonds are
+// paid for out of the validator's reward balance, and unbonded stake is held
+// for `unbonding_epochs` before it is paid back there; it can still be
+// slashed in the meantime.
+use crate::rewards::RewardLedger;
+use crate::ValidatorId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// Heights per staking epoch
+pub const DEFAULT_EPOCH_LENGTH: u64 = 100;
+pub const DEFAULT_UNBONDING_EPOCHS: u64 = 7;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
+pub enum StakingTx {
+    Bond { validator_id: ValidatorId, amount: u64 },
+    Unbond { validator_id: ValidatorId, amount: u64 },
+}
+
+impl StakingTx {
+    // Block payloads that are not staking transactions are ignored
+    pub fn decode(payload: &[u8]) -> Option<Self> {
+        serde_json::from_slice(payload).ok()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StakingError {
+    UnknownValidator(ValidatorId),
+    ZeroAmount,
+    InsufficientBond { bonded: u64, requested: u64 },
+    InsufficientBalance { balance: u64, requested: u64 },
+    // The bond would take the total voting power past u64::MAX
+    Overflow,
+}
+
+impl fmt::Display for StakingError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StakingError::UnknownValidator(id) => write!(f, "validator {} is not in the validator set", id),
+            StakingError::ZeroAmount => write!(f, "amount must be positive"),
+            StakingError::InsufficientBond { bonded, requested } => {
+                write!(f, "cannot unbond {} with only {} bonded", requested, bonded)
+            }
+            StakingError::InsufficientBalance { balance, requested } => {
+                write!(f, "cannot bond {} with a reward balance of {}", requested, balance)
+            }
+            StakingError::Overflow => write!(f, "bond would overflow the total voting power"),
+        }
+    }
+}
+
+impl std::error::Error for StakingError {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum StakeStatus {
+    Active,
+    Unbonding,
+    Inactive,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct Unbonding {
+    pub amount: u64,
+    pub release_epoch: u64,
+}
+
+#[derive(Debug, Clone, Default, Serialize)]
+pub struct StakeEntry {
+    pub bonded: u64,
+    pub unbonding: Vec<Unbonding>,
+}
+
+impl StakeEntry {
+    pub fn status(&self) -> StakeStatus {
+        if self.bonded > 0 {
+            StakeStatus::Active
+        } else if !self.unbonding.is_empty() {
+            StakeStatus::Unbonding
+        } else {
+            StakeStatus::Inactive
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Staking {
+    entries: BTreeMap<ValidatorId, StakeEntry>,
+    epoch: u64,
+    epoch_length: u64,
+    unbonding_epochs: u64,
+}
+
+impl Staking {
+    // Every validator starts with the same bond
+    pub fn genesis(validators: &[ValidatorId], stake: u64) -> Self {
+        Self {
+            entries: validators
+                .iter()
+                .map(|&id| (id, StakeEntry { bonded: stake, unbonding: Vec::new() }))
+                .collect(),
+            epoch: 0,
+            epoch_length: DEFAULT_EPOCH_LENGTH,
+            unbonding_epochs: DEFAULT_UNBONDING_EPOCHS,
+        }
+    }
+
+    pub fn with_epoch_length(mut self, heights: u64) -> Self {
+        self.epoch_length = heights.max(1);
+        self
+    }
+
+    pub fn with_unbonding_epochs(mut self, epochs: u64) -> Self {
+        self.unbonding_epochs = epochs;
+        self
+    }
+
+    pub fn epoch(&self) -> u64 {
+        self.epoch
+    }
+
+    pub fn epoch_length(&self) -> u64 {
+        self.epoch_length
+    }
+
+    pub fn entries(&self) -> impl Iterator<Item = (ValidatorId, &StakeEntry)> {
+        self.entries.iter().map(|(&id, entry)| (id, entry))
+    }
+
+    pub fn voting_power(&self, validator: ValidatorId) -> u64 {
+        self.entries.get(&validator).map(|e| e.bonded).unwrap_or(0)
+    }
+
+    // Bonds cannot take this past u64::MAX, so no sum of voting power
+    // overflows; only an oversized genesis saturates
+    pub fn total_power(&self) -> u64 {
+        self.entries.values().fold(0, |total, e| total.saturating_add(e.bonded))
+    }
+
+    // Applies a transaction as finalized: bonds are debited from `rewards`
+    pub fn apply(&mut self, tx: &StakingTx, rewards: &mut RewardLedger) -> Result<(), StakingError> {
+        match *tx {
+            StakingTx::Bond { validator_id, amount } => {
+                let balance = rewards.balance(validator_id);
+                let total = self.total_power();
+                let entry = self.entry(validator_id, amount)?;
+                if balance < amount {
+                    return Err(StakingError::InsufficientBalance { balance, requested: amount });
+                }
+                total.checked_add(amount).ok_or(StakingError::Overflow)?;
+                entry.bonded = entry.bonded.checked_add(amount).ok_or(StakingError::Overflow)?;
+                rewards.debit(validator_id, amount);
+            }
+            StakingTx::Unbond { validator_id, amount } => {
+                let release_epoch = self.epoch + self.unbonding_epochs;
+                let entry = self.entry(validator_id, amount)?;
+                if entry.bonded < amount {
+                    return Err(StakingError::InsufficientBond { bonded: entry.bonded, requested: amount });
+                }
+                entry.bonded -= amount;
+                entry.unbonding.push(Unbonding { amount, release_epoch });
+            }
+        }
+        Ok(())
+    }
+
+    fn entry(&mut self, validator: ValidatorId, amount: u64) -> Result<&mut StakeEntry, StakingError> {
+        if amount == 0 {
+            return Err(StakingError::ZeroAmount);
+        }
+        self.entries.get_mut(&validator).ok_or(StakingError::UnknownValidator(validator))
+    }
+
+    // Applies a finalized block: advances the epoch, releasing matured
+    // unbondings, then applies the payload if it is a staking transaction.
+    pub fn on_finalized(&mut self, height: u64, payload: &[u8], rewards: &mut RewardLedger) {
+        self.advance_to(height / self.epoch_length, rewards);
+        if let Some(tx) = StakingTx::decode(payload) {
+            // Invalid transactions are finalized but have no effect
+            let _ = self.apply(&tx, rewards);
+        }
+    }
+
+    // Released unbondings are credited back to `rewards`
+    pub fn advance_to(&mut self, epoch: u64, rewards: &mut RewardLedger) {
+        if epoch <= self.epoch {
+            return;
+        }
+        self.epoch = epoch;
+        for (&id, entry) in &mut self.entries {
+            entry.unbonding.retain(|u| {
+                if u.release_epoch <= epoch {
+                    rewards.credit(id, u.amount);
+                }
+                u.release_epoch > epoch
+            });
+        }
+    }
+
+    // Burns `basis_points` / 10000 of both bonded and still-unbonding stake;
+    // returns the amount burned
+    pub fn slash(&mut self, validator: ValidatorId, basis_points: u64) -> u64 {
+        let Some(entry) = self.entries.get_mut(&validator) else {
+            return 0;
+        };
+        let cut = |amount: &mut u64| {
+            let burned = (*amount as u128 * basis_points.min(10_000) as u128 / 10_000) as u64;
+            *amount -= burned;
+            burned
+        };
+
+        let mut burned = cut(&mut entry.bonded);
+        for unbonding in &mut entry.unbonding {
+            burned += cut(&mut unbonding.amount);
+        }
+        burned
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_unbonding_stays_slashable_until_release() {
+        let mut staking = Staking::genesis(&[0, 1, 2, 3].map(ValidatorId), 100)
+            .with_epoch_length(10)
+            .with_unbonding_epochs(2);
+        let mut rewards = RewardLedger::default();
+        rewards.credit(ValidatorId(1), 60);
+
+        staking.apply(&StakingTx::Bond { validator_id: ValidatorId(1), amount: 50 }, &mut rewards).unwrap();
+        assert_eq!(staking.voting_power(ValidatorId(1)), 150);
+        assert_eq!(staking.total_power(), 450);
+        assert_eq!(rewards.balance(ValidatorId(1)), 10);
+
+        let unbond = br#"{"type":"unbond","validator_id":0,"amount":100}"#;
+        staking.on_finalized(5, unbond, &mut rewards);
+        assert_eq!(staking.voting_power(ValidatorId(0)), 0);
+        assert_eq!(staking.entries[&ValidatorId(0)].status(), StakeStatus::Unbonding);
+
+        // Still slashable during the unbonding period
+        staking.on_finalized(15, b"not a transaction", &mut rewards);
+        assert_eq!(staking.slash(ValidatorId(0), 1_000), 10);
+        assert_eq!(staking.entries[&ValidatorId(0)].unbonding[0].amount, 90);
+
+        // Released back to the reward balance once epoch 2 is reached
+        staking.on_finalized(20, b"", &mut rewards);
+        assert_eq!(staking.entries[&ValidatorId(0)].status(), StakeStatus::Inactive);
+        assert_eq!(rewards.balance(ValidatorId(0)), 90);
+
+        assert_eq!(
+            staking.apply(&StakingTx::Unbond { validator_id: ValidatorId(2), amount: 101 }, &mut rewards),
+            Err(StakingError::InsufficientBond { bonded: 100, requested: 101 })
+        );
+        assert_eq!(
+            staking.apply(&StakingTx::Bond { validator_id: ValidatorId(9), amount: 1 }, &mut rewards),
+            Err(StakingError::UnknownValidator(ValidatorId(9)))
+        );
+    }
+
+    #[test]
+    fn test_bonds_are_funded_and_checked() {
+        let mut staking = Staking::genesis(&[0, 1].map(ValidatorId), 100);
+        let mut rewards = RewardLedger::default();
+        let bond = |amount| StakingTx::Bond { validator_id: ValidatorId(0), amount };
+
+        assert_eq!(
+            staking.apply(&bond(1), &mut rewards),
+            Err(StakingError::InsufficientBalance { balance: 0, requested: 1 })
+        );
+
+        rewards.credit(ValidatorId(0), u64::MAX);
+        assert_eq!(staking.apply(&bond(u64::MAX), &mut rewards), Err(StakingError::Overflow));
+        assert_eq!(staking.apply(&bond(u64::MAX - 200), &mut rewards), Ok(()));
+        assert_eq!(staking.total_power(), u64::MAX);
+        assert_eq!(staking.apply(&bond(1), &mut rewards), Err(StakingError::Overflow));
+        assert_eq!(rewards.balance(ValidatorId(0)), 200);
+    }
+}
@@ -0,0 +1,215 @@
+// Replicated application state, changed only by applying finalized blocks in
+// height order so every node that finalizes the same chain agrees on it.
+use crate::keys::KeyRegistry;
+use crate::leaders::{LeaderPolicy, LeaderSchedule};
+use crate::mempool::{AccountId, ReplayWindow, Transaction, TxBatch};
+use crate::params::{ConsensusParams, ParamsUpdate};
+use crate::rewards::{RewardLedger, RewardSchedule};
+use crate::smt::{SparseMerkleProof, SparseMerkleTree};
+use crate::staking::Staking;
+use crate::{Block, ValidatorId};
+use serde::Serialize;
+use std::collections::BTreeMap;
+
+fn json<T: Serialize + ?Sized>(value: &T) -> Vec<u8> {
+    serde_json::to_vec(value).expect("state values serialize")
+}
+
+fn tree(entries: &BTreeMap<String, Vec<u8>>) -> SparseMerkleTree {
+    let mut tree = SparseMerkleTree::default();
+    for (key, value) in entries {
+        tree.insert(key, value);
+    }
+    tree
+}
+
+// A state value and its proof against `state_root`
+#[derive(Debug, Clone)]
+pub struct StateRead {
+    // Finalized block whose `state_root` this is, so the state before its
+    // own transactions; None before one is finalized, when the current state
+    // is read
+    pub height: Option<u64>,
+    pub state_root: String, // hex
+    // JSON; None when the key is absent, which the proof then shows
+    pub value: Option<Vec<u8>>,
+    pub proof: SparseMerkleProof,
+}
+
+#[derive(Debug, Clone)]
+pub struct StateMachine {
+    pub staking: Staking,
+    pub rewards: RewardLedger,
+    pub params: ConsensusParams,
+    pub keys: KeyRegistry,
+    pub leaders: LeaderSchedule,
+    // Recently executed transactions, to refuse resubmissions
+    pub replay: ReplayWindow,
+    // Next nonce per sender; absent senders start at 0
+    nonces: BTreeMap<AccountId, u64>,
+    // Entries as the latest finalized block carrying a state root committed
+    // to them, and its height; proofs are served against these
+    certified: Option<(u64, BTreeMap<String, Vec<u8>>)>,
+}
+
+impl StateMachine {
+    pub fn genesis(validators: &[ValidatorId], stake: u64) -> Self {
+        Self {
+            staking: Staking::genesis(validators, stake),
+            rewards: RewardLedger::default(),
+            params: ConsensusParams::default(),
+            keys: KeyRegistry::default(),
+            leaders: LeaderSchedule::new(validators),
+            replay: ReplayWindow::default(),
+            nonces: BTreeMap::new(),
+            certified: None,
+        }
+    }
+
+    pub fn with_staking(mut self, staking: Staking) -> Self {
+        self.staking = staking;
+        self
+    }
+
+    pub fn with_reward_schedule(mut self, schedule: RewardSchedule) -> Self {
+        self.rewards = RewardLedger::new(schedule);
+        self
+    }
+
+    pub fn with_params(mut self, params: ConsensusParams) -> Self {
+        self.params = params;
+        self
+    }
+
+    pub fn with_keys(mut self, keys: KeyRegistry) -> Self {
+        self.keys = keys;
+        self
+    }
+
+    pub fn with_leader_policy(mut self, policy: LeaderPolicy) -> Self {
+        self.leaders = self.leaders.with_policy(policy);
+        self
+    }
+
+    pub fn with_replay_window(mut self, heights: u64) -> Self {
+        self.replay = ReplayWindow::new(heights);
+        self
+    }
+
+    pub fn next_nonce(&self, sender: &str) -> u64 {
+        self.nonces.get(sender).copied().unwrap_or(0)
+    }
+
+    // The state as key-value pairs, each value JSON: `epoch`, `params`,
+    // `stake/{id}`, `rewards/{id}`, `nonce/{sender}` and `key/{id}`. The
+    // leader schedule and replay window are derived from finalized blocks
+    // and left out; the view covers what transactions can change.
+    pub fn entries(&self) -> BTreeMap<String, Vec<u8>> {
+        let mut entries = BTreeMap::new();
+        entries.insert("epoch".to_string(), json(&self.staking.epoch()));
+        entries.insert("params".to_string(), json(&self.params));
+        for (id, entry) in self.staking.entries() {
+            entries.insert(format!("stake/{}", id), json(entry));
+        }
+        for (id, balance) in self.rewards.balances() {
+            entries.insert(format!("rewards/{}", id), json(&balance));
+        }
+        for (sender, nonce) in &self.nonces {
+            entries.insert(format!("nonce/{}", sender), json(nonce));
+        }
+        for (id, entry) in self.keys.entries() {
+            entries.insert(format!("key/{}", id), json(&hex::encode(entry.key.as_bytes())));
+        }
+        entries
+    }
+
+    pub fn tree(&self) -> SparseMerkleTree {
+        tree(&self.entries())
+    }
+
+    // Hex root of the sparse Merkle tree over `entries`
+    pub fn state_root(&self) -> String {
+        hex::encode(self.tree().root())
+    }
+
+    // Reads the state the latest finalized `state_root` commits to, so the
+    // proof checks against a certified header
+    pub fn read(&self, key: &str) -> StateRead {
+        let current;
+        let (height, entries) = match &self.certified {
+            Some((height, entries)) => (Some(*height), entries),
+            None => {
+                current = self.entries();
+                (None, &current)
+            }
+        };
+        let tree = tree(entries);
+        StateRead {
+            height,
+            state_root: hex::encode(tree.root()),
+            value: entries.get(key).cloned(),
+            proof: tree.prove(key),
+        }
+    }
+
+    // Executes a transaction if it carries the sender's next nonce; anything
+    // else is skipped, so a finalized block cannot replay or reorder them.
+    // Returns whether it executed.
+    fn execute(&mut self, tx: &Transaction) -> bool {
+        let next = self.nonces.entry(tx.sender.clone()).or_default();
+        let executed = tx.nonce == *next;
+        if executed {
+            *next += 1;
+        }
+        executed
+    }
+
+    // `voters` are the commit signers of the block's certificate; blocks
+    // finalized implicitly through a descendant have none.
+    pub(crate) fn apply_finalized(&mut self, block: &Block, voters: &[ValidatorId]) {
+        if block.state_root.is_some() {
+            self.certified = Some((block.height, self.entries()));
+        }
+        self.staking.on_finalized(block.height, &block.payload, &mut self.rewards);
+        self.keys.on_finalized(block.height, &block.payload);
+        if let Some(update) = ParamsUpdate::decode(&block.payload) {
+            self.params.apply(&update);
+        }
+        if let Some(batch) = TxBatch::decode(&block.payload) {
+            let executed = batch.txs.iter().filter(|tx| self.execute(tx)).map(Transaction::hash).collect();
+            self.replay.record(block.height, executed);
+        }
+        self.leaders.on_finalized(block.height, block.round, block.proposer);
+        self.rewards.credit_height(block.height, block.proposer, voters);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{BlockId, Round};
+
+    #[test]
+    fn test_only_next_nonce_executes() {
+        let mut state = StateMachine::genesis(&[ValidatorId(0)], 100);
+        let tx = |nonce| Transaction { sender: "alice".into(), nonce, data: String::new(), priority: 0 };
+        let batch = TxBatch { txs: vec![tx(0), tx(2), tx(1), tx(1)] };
+        let block = Block {
+            id: BlockId::default(),
+            parent_id: None,
+            payload: batch.encode(),
+            height: 0,
+            proposer: ValidatorId(0),
+            round: Round(0),
+            timestamp: 0,
+            timeout_certificate: None,
+            state_root: None,
+        };
+
+        state.apply_finalized(&block, &[]);
+        assert_eq!(state.next_nonce("alice"), 2);
+        assert_eq!(state.next_nonce("bob"), 0);
+        assert_eq!(state.replay.finalized_at(&tx(1).hash()), Some(0));
+        assert_eq!(state.replay.finalized_at(&tx(2).hash()), None);
+    }
+}
@@ -0,0 +1,408 @@
+// Durable storage behind the in-memory chain. Each finalization becomes one
+// `WriteBatch` (blocks, the votes and certificate that finalized them, and
+// the new head) that a store applies all-or-nothing. Batches are handed to a
+// background writer, so a slow disk never holds the consensus locks.
+use crate::{Block, BlockId, QuorumCertificate, ValidatorId};
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::future::Future;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use tokio::sync::{mpsc, oneshot};
+
+#[derive(Debug)]
+pub enum StoreError {
+    Io(std::io::Error),
+    Corrupt { line: usize, reason: String },
+}
+
+impl fmt::Display for StoreError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            StoreError::Io(e) => write!(f, "storage I/O failed: {}", e),
+            StoreError::Corrupt { line, reason } => write!(f, "batch {} is corrupt: {}", line, reason),
+        }
+    }
+}
+
+impl std::error::Error for StoreError {}
+
+impl From<std::io::Error> for StoreError {
+    fn from(e: std::io::Error) -> Self {
+        StoreError::Io(e)
+    }
+}
+
+// Precommit and commit voters of a finalized block
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct VoteSet {
+    pub block_id: BlockId,
+    pub precommit: Vec<ValidatorId>,
+    pub commit: Vec<ValidatorId>,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct WriteBatch {
+    // Newly finalized blocks, oldest first
+    pub blocks: Vec<Block>,
+    pub votes: Vec<VoteSet>,
+    pub certificates: Vec<QuorumCertificate>,
+    pub head: Option<BlockId>,
+}
+
+// What a store holds after replaying its committed batches
+#[derive(Debug, Clone, Default)]
+pub struct StoreSnapshot {
+    pub blocks: Vec<Block>,
+    pub certificates: Vec<QuorumCertificate>,
+    pub head: Option<BlockId>,
+}
+
+impl StoreSnapshot {
+    fn apply(&mut self, batch: WriteBatch) {
+        self.blocks.extend(batch.blocks);
+        self.certificates.extend(batch.certificates);
+        if batch.head.is_some() {
+            self.head = batch.head;
+        }
+    }
+}
+
+pub trait BlockStore: Send + Sync + 'static {
+    // Durable once the future resolves; a crash before that loses the whole
+    // batch, never part of it
+    fn commit(&self, batch: WriteBatch) -> impl Future<Output = Result<(), StoreError>> + Send;
+
+    fn load(&self) -> impl Future<Output = Result<StoreSnapshot, StoreError>> + Send;
+
+    // Every committed batch in order, with undecodable ones as `None`,
+    // for the scrubber
+    fn scan(&self) -> impl Future<Output = Result<Vec<Option<WriteBatch>>, StoreError>> + Send;
+
+    // Atomically replaces the whole store with `batches`
+    fn rewrite(&self, batches: Vec<WriteBatch>) -> impl Future<Output = Result<(), StoreError>> + Send;
+}
+
+#[derive(Debug, Default)]
+pub struct MemoryStore {
+    batches: Mutex<Vec<WriteBatch>>,
+}
+
+impl BlockStore for MemoryStore {
+    async fn commit(&self, batch: WriteBatch) -> Result<(), StoreError> {
+        self.batches.lock().unwrap().push(batch);
+        Ok(())
+    }
+
+    async fn load(&self) -> Result<StoreSnapshot, StoreError> {
+        let mut snapshot = StoreSnapshot::default();
+        for batch in self.batches.lock().unwrap().iter().cloned() {
+            snapshot.apply(batch);
+        }
+        Ok(snapshot)
+    }
+
+    async fn scan(&self) -> Result<Vec<Option<WriteBatch>>, StoreError> {
+        Ok(self.batches.lock().unwrap().iter().cloned().map(Some).collect())
+    }
+
+    async fn rewrite(&self, batches: Vec<WriteBatch>) -> Result<(), StoreError> {
+        *self.batches.lock().unwrap() = batches;
+        Ok(())
+    }
+}
+
+// Append-only log, one JSON batch per line, fsynced per commit. A crash
+// mid-write leaves at most a torn last line, which `load` cuts off so the
+// next append starts a line of its own.
+#[derive(Debug)]
+pub struct FileStore {
+    path: PathBuf,
+    // Serializes appends so lines never interleave
+    lock: Arc<Mutex<()>>,
+}
+
+impl FileStore {
+    // The log's name inside a node's data directory
+    pub const FILE_NAME: &'static str = "chain.jsonl";
+
+    pub fn in_dir(data_dir: impl AsRef<Path>) -> Self {
+        Self::open(data_dir.as_ref().join(Self::FILE_NAME))
+    }
+
+    pub fn open(path: impl AsRef<Path>) -> Self {
+        Self {
+            path: path.as_ref().to_path_buf(),
+            lock: Arc::new(Mutex::new(())),
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
+
+impl BlockStore for FileStore {
+    async fn commit(&self, batch: WriteBatch) -> Result<(), StoreError> {
+        let mut line = serde_json::to_vec(&batch).expect("write batch serializes");
+        line.push(b'\n');
+        let path = self.path.clone();
+        let lock = self.lock.clone();
+        tokio::task::spawn_blocking(move || -> Result<(), StoreError> {
+            let _guard = lock.lock().unwrap();
+            let mut file = std::fs::OpenOptions::new().create(true).append(true).open(&path)?;
+            file.write_all(&line)?;
+            file.sync_data()?;
+            Ok(())
+        })
+        .await
+        .map_err(|e| StoreError::Io(std::io::Error::other(e)))?
+    }
+
+    async fn load(&self) -> Result<StoreSnapshot, StoreError> {
+        self.truncate_torn_tail().await?;
+        let mut snapshot = StoreSnapshot::default();
+        for (i, batch) in self.read_lines().await?.into_iter().enumerate() {
+            match batch {
+                Ok(batch) => snapshot.apply(batch),
+                Err(reason) => return Err(StoreError::Corrupt { line: i + 1, reason }),
+            }
+        }
+        Ok(snapshot)
+    }
+
+    async fn scan(&self) -> Result<Vec<Option<WriteBatch>>, StoreError> {
+        Ok(self.read_lines().await?.into_iter().map(Result::ok).collect())
+    }
+
+    // Written to a temporary file and renamed over the log
+    async fn rewrite(&self, batches: Vec<WriteBatch>) -> Result<(), StoreError> {
+        let mut contents = Vec::new();
+        for batch in &batches {
+            contents.extend(serde_json::to_vec(batch).expect("write batch serializes"));
+            contents.push(b'\n');
+        }
+        let path = self.path.clone();
+        let lock = self.lock.clone();
+        tokio::task::spawn_blocking(move || -> Result<(), StoreError> {
+            let _guard = lock.lock().unwrap();
+            let tmp = path.with_extension("rewrite");
+            let mut file = std::fs::File::create(&tmp)?;
+            file.write_all(&contents)?;
+            file.sync_data()?;
+            std::fs::rename(&tmp, &path)?;
+            Ok(())
+        })
+        .await
+        .map_err(|e| StoreError::Io(std::io::Error::other(e)))?
+    }
+}
+
+impl FileStore {
+    async fn truncate_torn_tail(&self) -> Result<(), StoreError> {
+        let path = self.path.clone();
+        let lock = self.lock.clone();
+        tokio::task::spawn_blocking(move || -> Result<(), StoreError> {
+            let _guard = lock.lock().unwrap();
+            let bytes = match std::fs::read(&path) {
+                Ok(bytes) => bytes,
+                Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+                Err(e) => return Err(e.into()),
+            };
+            let complete = bytes.iter().rposition(|&b| b == b'\n').map_or(0, |i| i + 1);
+            if complete < bytes.len() {
+                let file = std::fs::OpenOptions::new().write(true).open(&path)?;
+                file.set_len(complete as u64)?;
+                file.sync_data()?;
+            }
+            Ok(())
+        })
+        .await
+        .map_err(|e| StoreError::Io(std::io::Error::other(e)))?
+    }
+
+    // Decoded lines in order. A crash mid-append leaves a final line without
+    // its newline, which is dropped; complete lines that fail to decode are
+    // reported as errors.
+    async fn read_lines(&self) -> Result<Vec<Result<WriteBatch, String>>, StoreError> {
+        let text = match tokio::fs::read(&self.path).await {
+            Ok(bytes) => String::from_utf8_lossy(&bytes).into_owned(),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+            Err(e) => return Err(e.into()),
+        };
+
+        Ok(text
+            .split_inclusive('\n')
+            .filter(|line| line.ends_with('\n'))
+            .map(|line| serde_json::from_str::<WriteBatch>(line).map_err(|e| e.to_string()))
+            .collect())
+    }
+}
+
+enum WriterMessage {
+    Batch(WriteBatch),
+    Rewrite(Vec<WriteBatch>),
+    Flush(oneshot::Sender<()>),
+}
+
+// Handle to the background task committing batches in submission order.
+// After a failed commit or rewrite nothing more is written, since later
+// batches would leave a gap in the stored chain; the node keeps running on
+// its in-memory chain.
+#[derive(Clone)]
+pub struct StoreWriter {
+    sender: mpsc::UnboundedSender<WriterMessage>,
+    failures: Arc<AtomicU64>,
+}
+
+impl StoreWriter {
+    // Must be called from within a tokio runtime
+    pub fn spawn<S: BlockStore>(store: Arc<S>) -> Self {
+        let (sender, mut receiver) = mpsc::unbounded_channel();
+        let failures = Arc::new(AtomicU64::new(0));
+        let failed = failures.clone();
+        tokio::spawn(async move {
+            while let Some(message) = receiver.recv().await {
+                let result = match message {
+                    WriterMessage::Flush(done) => {
+                        let _ = done.send(());
+                        continue;
+                    }
+                    _ if failed.load(Ordering::Relaxed) > 0 => {
+                        failed.fetch_add(1, Ordering::Relaxed);
+                        continue;
+                    }
+                    WriterMessage::Batch(batch) => store.commit(batch).await,
+                    WriterMessage::Rewrite(batches) => store.rewrite(batches).await,
+                };
+                if let Err(e) = result {
+                    failed.fetch_add(1, Ordering::Relaxed);
+                    eprintln!("block store write failed, nothing more will be stored: {}", e);
+                }
+            }
+        });
+        Self { sender, failures }
+    }
+
+    // Never blocks; the batch is committed after every earlier one
+    pub fn submit(&self, batch: WriteBatch) {
+        let _ = self.sender.send(WriterMessage::Batch(batch));
+    }
+
+    // Replaces the store's contents once every earlier batch is committed
+    pub fn rewrite(&self, batches: Vec<WriteBatch>) {
+        let _ = self.sender.send(WriterMessage::Rewrite(batches));
+    }
+
+    // Resolves once everything submitted so far has been committed
+    pub async fn flush(&self) {
+        let (done, wait) = oneshot::channel();
+        if self.sender.send(WriterMessage::Flush(done)).is_ok() {
+            let _ = wait.await;
+        }
+    }
+
+    // Batches and rewrites not stored: the failed one and every one after it
+    pub fn failures(&self) -> u64 {
+        self.failures.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConsensusState, RewardSchedule, StateMachine, GENESIS_STAKE};
+
+    #[tokio::test]
+    async fn test_file_store_restores_finalized_chain() {
+        let path = std::env::temp_dir().join(format!("mini-consensus-store-{}.jsonl", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let store = Arc::new(FileStore::open(&path));
+        let writer = StoreWriter::spawn(store.clone());
+
+        // Voters earn enough in the first block to pay for the bond in the second
+        let validators: Vec<ValidatorId> = (0..4).map(ValidatorId).collect();
+        let schedule = RewardSchedule { proposer: 10, voter: 50, halving_interval: None };
+        let genesis = StateMachine::genesis(&validators, GENESIS_STAKE).with_reward_schedule(schedule);
+        let state = ConsensusState::new(validators.clone())
+            .with_state_machine(genesis.clone())
+            .with_store(writer.clone());
+        for payload in [b"a".to_vec(), br#"{"type":"bond","validator_id":1,"amount":50}"#.to_vec()] {
+            let id = state.propose(payload).unwrap();
+            let (height, round) = state.proposal_round(&id).unwrap();
+            for validator in 0..3 {
+                state.vote_combined(id, height, round, ValidatorId(validator)).unwrap();
+            }
+        }
+        writer.flush().await;
+        assert_eq!(writer.failures(), 0);
+
+        // A crash mid-append leaves a torn line that is cut off, so later
+        // appends still decode
+        std::fs::OpenOptions::new().append(true).open(&path).unwrap().write_all(b"{\"blocks\":[").unwrap();
+        let snapshot = store.load().await.unwrap();
+        assert!(std::fs::read(&path).unwrap().ends_with(b"}\n"));
+        store.commit(WriteBatch::default()).await.unwrap();
+        assert_eq!(store.load().await.unwrap().blocks.len(), 2);
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(snapshot.blocks.len(), 2);
+        assert_eq!(snapshot.head, state.finalize());
+
+        let restored = ConsensusState::new(validators).with_state_machine(genesis).restore(snapshot);
+        assert_eq!(restored.finalize(), state.finalize());
+        assert_eq!(restored.finalized_height(), Some(1));
+        assert_eq!(restored.state_machine().staking.voting_power(ValidatorId(1)), 150);
+        assert_eq!(restored.state_machine().rewards.balance(ValidatorId(0)), state.state_machine().rewards.balance(ValidatorId(0)));
+
+        // The next proposal builds on the restored head
+        let next = restored.propose(b"c".to_vec()).unwrap();
+        assert_eq!(restored.get_block(&next).unwrap().parent_id, state.finalize());
+    }
+
+    // Fails every commit from the `fail_at`th on
+    struct FailingStore {
+        inner: MemoryStore,
+        commits: AtomicU64,
+        fail_at: u64,
+    }
+
+    impl BlockStore for FailingStore {
+        async fn commit(&self, batch: WriteBatch) -> Result<(), StoreError> {
+            if self.commits.fetch_add(1, Ordering::Relaxed) + 1 >= self.fail_at {
+                return Err(StoreError::Io(std::io::Error::other("disk full")));
+            }
+            self.inner.commit(batch).await
+        }
+
+        async fn load(&self) -> Result<StoreSnapshot, StoreError> {
+            self.inner.load().await
+        }
+
+        async fn scan(&self) -> Result<Vec<Option<WriteBatch>>, StoreError> {
+            self.inner.scan().await
+        }
+
+        async fn rewrite(&self, batches: Vec<WriteBatch>) -> Result<(), StoreError> {
+            self.inner.rewrite(batches).await
+        }
+    }
+
+    #[tokio::test]
+    async fn test_writer_stops_after_a_failed_commit() {
+        let store = Arc::new(FailingStore { inner: MemoryStore::default(), commits: AtomicU64::new(0), fail_at: 2 });
+        let writer = StoreWriter::spawn(store.clone());
+        let head = |n: u8| WriteBatch { head: Some(BlockId([n; 32])), ..WriteBatch::default() };
+        for n in 0..4 {
+            writer.submit(head(n));
+        }
+        writer.rewrite(Vec::new());
+        writer.flush().await;
+
+        // Only the batch before the failure is stored, with no gap after it
+        assert_eq!(writer.failures(), 4);
+        assert_eq!(store.commits.load(Ordering::Relaxed), 2);
+        assert_eq!(store.load().await.unwrap().head, Some(BlockId([0; 32])));
+    }
+}
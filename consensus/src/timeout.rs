@@ -0,0 +1,260 @@
+// Nil votes for rounds whose leader never produced a usable proposal. Once
+// more than two thirds of the stake times out a round, everyone moves to the
+// next round and the resulting certificate rides along in the next proposal
+// as proof the skip was legitimate.
+use crate::{Round, ValidatorId};
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::fmt;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TimeoutCertificate {
+    pub height: u64,
+    pub round: Round,
+    pub signers: Vec<ValidatorId>,
+}
+
+// Why a proposal's round is not justified
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum JustificationError {
+    MissingCertificate { round: Round },
+    WrongCertificate { height: u64, round: Round },
+    DuplicateSigner(ValidatorId),
+    InsufficientStake { weight: u64, total: u64 },
+}
+
+impl fmt::Display for JustificationError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            JustificationError::MissingCertificate { round } => {
+                write!(f, "round {} proposal carries no timeout certificate", round)
+            }
+            JustificationError::WrongCertificate { height, round } => {
+                write!(f, "timeout certificate is for round {} at height {}", round, height)
+            }
+            JustificationError::DuplicateSigner(id) => write!(f, "validator {} signed the certificate twice", id),
+            JustificationError::InsufficientStake { weight, total } => {
+                write!(f, "certificate carries {} of {} stake, more than two thirds needed", weight, total)
+            }
+        }
+    }
+}
+
+impl std::error::Error for JustificationError {}
+
+impl TimeoutCertificate {
+    // Distinct signers holding more than two thirds of the stake
+    pub fn verify(&self, power: impl Fn(ValidatorId) -> u64, total: u64) -> Result<(), JustificationError> {
+        let mut seen = HashSet::new();
+        let mut weight = 0u64;
+        for &signer in &self.signers {
+            if !seen.insert(signer) {
+                return Err(JustificationError::DuplicateSigner(signer));
+            }
+            weight = weight.saturating_add(power(signer));
+        }
+        if total == 0 || 3 * weight as u128 <= 2 * total as u128 {
+            return Err(JustificationError::InsufficientStake { weight, total });
+        }
+        Ok(())
+    }
+}
+
+// A proposal in round r > 0 must show that round r - 1 at its height timed
+// out. A QC from round r - 1 would already have finalized the height, so
+// within a height the timeout certificate is the only valid justification.
+pub fn check_justification(
+    height: u64,
+    round: Round,
+    certificate: Option<&TimeoutCertificate>,
+    power: impl Fn(ValidatorId) -> u64,
+    total: u64,
+) -> Result<(), JustificationError> {
+    match (round, certificate) {
+        (Round(0), None) => Ok(()),
+        (_, None) => Err(JustificationError::MissingCertificate { round }),
+        (_, Some(tc)) if tc.height != height || tc.round.next() != Some(round) => {
+            Err(JustificationError::WrongCertificate { height: tc.height, round: tc.round })
+        }
+        (_, Some(tc)) => tc.verify(power, total),
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct TimeoutBook {
+    votes: HashMap<(u64, Round), HashSet<ValidatorId>>,
+    // Highest certificate per height still being decided
+    certificates: HashMap<u64, TimeoutCertificate>,
+}
+
+impl TimeoutBook {
+    // Returns the certificate if this vote completed one. Only the current
+    // round can time out: votes for earlier rounds are stale, and votes for
+    // later ones would let a round be skipped before it was tried. The last
+    // round never times out, as there is no round after it.
+    pub(crate) fn record(
+        &mut self,
+        height: u64,
+        round: Round,
+        validator: ValidatorId,
+        power: impl Fn(ValidatorId) -> u64,
+        total: u64,
+    ) -> Option<TimeoutCertificate> {
+        if round != self.current_round(height) || round.next().is_none() {
+            return None;
+        }
+
+        let voters = self.votes.entry((height, round)).or_default();
+        voters.insert(validator);
+        let weight = voters.iter().fold(0, |w: u64, &v| w.saturating_add(power(v)));
+        if total == 0 || 3 * weight as u128 <= 2 * total as u128 {
+            return None;
+        }
+
+        let mut signers: Vec<ValidatorId> = voters.iter().copied().collect();
+        signers.sort();
+        let certificate = TimeoutCertificate { height, round, signers };
+        self.votes.retain(|&(h, r), _| h != height || r > round);
+        self.certificates.insert(height, certificate.clone());
+        Some(certificate)
+    }
+
+    // Rounds below this one have timed out at `height`
+    pub fn current_round(&self, height: u64) -> Round {
+        self.certificates.get(&height).and_then(|c| c.round.next()).unwrap_or_default()
+    }
+
+    pub fn certificate(&self, height: u64) -> Option<&TimeoutCertificate> {
+        self.certificates.get(&height)
+    }
+
+    // Forgets heights below `height` once they are finalized
+    pub(crate) fn prune_below(&mut self, height: u64) {
+        self.votes.retain(|&(h, _), _| h >= height);
+        self.certificates.retain(|&h, _| h >= height);
+    }
+}
+
+// How long a validator should wait in a round before voting to time it
+// out: a multiple of the recent proposal-to-finalization latency, smoothed
+// with an EWMA and kept within bounds so one slow height cannot stall the
+// chain and a burst of fast ones cannot make rounds flap.
+#[derive(Debug, Clone)]
+pub struct RoundTimeout {
+    ewma_ms: Option<f64>,
+    min_ms: u64,
+    max_ms: u64,
+}
+
+const TIMEOUT_EWMA_ALPHA: f64 = 0.2;
+// Rounds wait this many typical latencies before giving up
+const TIMEOUT_LATENCY_MULTIPLE: f64 = 3.0;
+// Until a height has finalized
+const DEFAULT_ROUND_TIMEOUT_MS: u64 = 3000;
+
+impl Default for RoundTimeout {
+    fn default() -> Self {
+        Self::new(500, 30_000)
+    }
+}
+
+impl RoundTimeout {
+    pub fn new(min_ms: u64, max_ms: u64) -> Self {
+        Self { ewma_ms: None, min_ms, max_ms: max_ms.max(min_ms) }
+    }
+
+    pub(crate) fn observe(&mut self, latency_ms: f64) {
+        self.ewma_ms = Some(match self.ewma_ms {
+            Some(ewma) => ewma + TIMEOUT_EWMA_ALPHA * (latency_ms - ewma),
+            None => latency_ms,
+        });
+    }
+
+    pub fn latency_ewma_ms(&self) -> Option<f64> {
+        self.ewma_ms
+    }
+
+    pub fn current_ms(&self) -> u64 {
+        let timeout = match self.ewma_ms {
+            Some(ewma) => (ewma * TIMEOUT_LATENCY_MULTIPLE).ceil() as u64,
+            None => DEFAULT_ROUND_TIMEOUT_MS,
+        };
+        timeout.clamp(self.min_ms, self.max_ms)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_justification_requires_matching_quorum_certificate() {
+        let power = |v: ValidatorId| if v.0 < 4 { 100 } else { 0 };
+        let tc = |round, signers: [u32; 3]| TimeoutCertificate { height: 5, round: Round(round), signers: signers.map(ValidatorId).to_vec() };
+
+        assert!(check_justification(5, Round(0), None, power, 400).is_ok());
+        assert!(check_justification(5, Round(2), Some(&tc(1, [0, 1, 2])), power, 400).is_ok());
+        assert_eq!(
+            check_justification(5, Round(1), None, power, 400),
+            Err(JustificationError::MissingCertificate { round: Round(1) })
+        );
+        assert_eq!(
+            check_justification(5, Round(3), Some(&tc(1, [0, 1, 2])), power, 400),
+            Err(JustificationError::WrongCertificate { height: 5, round: Round(1) })
+        );
+        assert_eq!(
+            check_justification(6, Round(2), Some(&tc(1, [0, 1, 2])), power, 400),
+            Err(JustificationError::WrongCertificate { height: 5, round: Round(1) })
+        );
+
+        // Repeated or unknown signers do not add up to a quorum
+        assert_eq!(tc(0, [0, 1, 1]).verify(power, 400), Err(JustificationError::DuplicateSigner(ValidatorId(1))));
+        assert_eq!(
+            tc(0, [0, 1, 9]).verify(power, 400),
+            Err(JustificationError::InsufficientStake { weight: 200, total: 400 })
+        );
+    }
+
+    #[test]
+    fn test_only_the_current_round_times_out() {
+        let mut book = TimeoutBook::default();
+        let mut vote = |round, validator| book.record(7, Round(round), ValidatorId(validator), |_| 100, 400);
+
+        // Votes for a later round are dropped, not banked
+        assert_eq!(vote(1, 0), None);
+        assert_eq!(vote(1, 1), None);
+        assert_eq!(vote(1, 2), None);
+        assert_eq!(vote(0, 0), None);
+        assert_eq!(vote(0, 1), None);
+        assert_eq!(vote(0, 2).map(|tc| tc.round), Some(Round(0)));
+
+        // Round 0 is over; round 1 needs its own votes
+        assert_eq!(vote(0, 3), None);
+        assert_eq!(vote(1, 0), None);
+        assert_eq!(vote(1, 1), None);
+        assert_eq!(vote(1, 2).map(|tc| tc.round), Some(Round(1)));
+        assert_eq!(book.current_round(7), Round(2));
+
+        assert_eq!(Round(u64::MAX).next(), None);
+        book.certificates.insert(7, TimeoutCertificate { height: 7, round: Round(u64::MAX - 1), signers: Vec::new() });
+        assert_eq!(book.current_round(7), Round(u64::MAX));
+        for validator in 0..4 {
+            assert_eq!(book.record(7, Round(u64::MAX), ValidatorId(validator), |_| 100, 400), None);
+        }
+    }
+
+    #[test]
+    fn test_round_timeout_follows_latency_within_bounds() {
+        let mut timeout = RoundTimeout::new(500, 10_000);
+        assert_eq!(timeout.current_ms(), 3000);
+        timeout.observe(100.0);
+        assert_eq!(timeout.current_ms(), 500);
+        timeout.observe(1100.0);
+        assert_eq!(timeout.latency_ewma_ms(), Some(300.0));
+        assert_eq!(timeout.current_ms(), 900);
+        for _ in 0..50 {
+            timeout.observe(60_000.0);
+        }
+        assert_eq!(timeout.current_ms(), 10_000);
+    }
+}
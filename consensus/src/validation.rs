@@ -0,0 +1,165 @@
+// Dry runs of a proposal: every check `propose` makes, plus what the state
+// machine would do with the payload once finalized, collected into a report
+// instead of stopping at the first failure. Nothing is admitted or changed.
+// Errors would get the proposal rejected; warnings mark parts of a payload
+// that would finalize without effect.
+use crate::keys::KeyRotation;
+use crate::mempool::TxBatch;
+use crate::params::{ParamsUpdate, ProposalError};
+use crate::staking::StakingTx;
+use crate::{ConsensusState, Round};
+use serde::Serialize;
+use std::collections::HashMap;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum PayloadKind {
+    TxBatch,
+    Staking,
+    SetParams,
+    KeyRotation,
+    // Any other bytes, finalized as they are
+    Opaque,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct Diagnostic {
+    // The check that failed, e.g. "size" or "nonce"
+    pub check: &'static str,
+    pub severity: Severity,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ProposalReport {
+    // No errors: `propose` would accept the payload now
+    pub valid: bool,
+    pub kind: PayloadKind,
+    pub bytes: usize,
+    pub tx_count: usize,
+    // Where the proposal would land
+    pub height: u64,
+    pub round: Round,
+    pub diagnostics: Vec<Diagnostic>,
+}
+
+impl ProposalReport {
+    pub fn push(&mut self, check: &'static str, severity: Severity, message: String) {
+        self.valid &= severity != Severity::Error;
+        self.diagnostics.push(Diagnostic { check, severity, message });
+    }
+}
+
+impl ConsensusState {
+    pub fn validate_proposal(&self, payload: &[u8]) -> ProposalReport {
+        let batch = TxBatch::decode(payload);
+        let staking_tx = StakingTx::decode(payload);
+        let update = ParamsUpdate::decode(payload);
+        let rotation = KeyRotation::decode(payload);
+        let kind = match (&batch, &staking_tx, &update, &rotation) {
+            (Some(_), _, _, _) => PayloadKind::TxBatch,
+            (_, Some(_), _, _) => PayloadKind::Staking,
+            (_, _, Some(_), _) => PayloadKind::SetParams,
+            (_, _, _, Some(_)) => PayloadKind::KeyRotation,
+            _ => PayloadKind::Opaque,
+        };
+        let tx_count = batch.as_ref().map_or(0, |b| b.txs.len());
+        let height = self.chain.read().unwrap().next_height();
+        let mut report = ProposalReport {
+            valid: true,
+            kind,
+            bytes: payload.len(),
+            tx_count,
+            height,
+            round: self.current_round(),
+            diagnostics: Vec::new(),
+        };
+
+        let state_machine = self.state_machine();
+        let params = state_machine.params;
+        if payload.len() > params.max_block_bytes {
+            let error = ProposalError::TooLarge { bytes: payload.len(), max: params.max_block_bytes };
+            report.push("size", Severity::Error, error.to_string());
+        }
+        if tx_count > params.max_txs_per_block {
+            let error = ProposalError::TooManyTxs { count: tx_count, max: params.max_txs_per_block };
+            report.push("tx_count", Severity::Error, error.to_string());
+        }
+
+        // Replays the batch against the senders' next nonces, as execution would
+        let mut next: HashMap<&str, u64> = HashMap::new();
+        for (i, tx) in batch.iter().flat_map(|b| b.txs.iter().enumerate()) {
+            let expected = next.entry(&tx.sender).or_insert_with(|| state_machine.next_nonce(&tx.sender));
+            if tx.nonce == *expected {
+                *expected += 1;
+            } else if let Some(finalized) = state_machine.replay.finalized_at(&tx.hash()) {
+                let message = format!("transaction {} from {} was finalized at height {}; it would be skipped", i, tx.sender, finalized);
+                report.push("replay", Severity::Warning, message);
+            } else {
+                let message =
+                    format!("transaction {} from {} has nonce {}, expected {}; it would be skipped", i, tx.sender, tx.nonce, expected);
+                report.push("nonce", Severity::Warning, message);
+            }
+        }
+
+        if let Some(tx) = &staking_tx {
+            let mut staking = state_machine.staking.clone();
+            let mut rewards = state_machine.rewards.clone();
+            staking.advance_to(height / staking.epoch_length(), &mut rewards);
+            if let Err(e) = staking.apply(tx, &mut rewards) {
+                report.push("staking", Severity::Warning, format!("staking transaction would have no effect: {}", e));
+            }
+        }
+
+        if let Some(rotation) = &rotation {
+            if let Err(e) = state_machine.keys.clone().apply(rotation, height) {
+                report.push("keys", Severity::Warning, format!("key rotation would have no effect: {}", e));
+            }
+        }
+
+        if let Some(update) = &update {
+            for (field, min) in update.below_minimum() {
+                report.push("params", Severity::Warning, format!("{} below the minimum of {} would be ignored", field, min));
+            }
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mempool::Transaction;
+    use crate::ValidatorId;
+
+    #[test]
+    fn test_validation_reports_every_problem_without_proposing() {
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect());
+        let tx = |nonce| Transaction { sender: "alice".into(), nonce, data: String::new(), priority: 0 };
+
+        let report = state.validate_proposal(&TxBatch { txs: vec![tx(0), tx(2), tx(1)] }.encode());
+        assert!(report.valid);
+        assert_eq!((report.kind, report.tx_count, report.height, report.round), (PayloadKind::TxBatch, 3, 0, Round(0)));
+        assert_eq!(report.diagnostics.len(), 1);
+        assert_eq!(report.diagnostics[0].check, "nonce");
+
+        let report = state.validate_proposal(&vec![b'x'; state.params().max_block_bytes + 1]);
+        assert!(!report.valid);
+        assert_eq!(report.kind, PayloadKind::Opaque);
+        assert_eq!(report.diagnostics[0].severity, Severity::Error);
+
+        let unbond = br#"{"type":"unbond","validator_id":9,"amount":5}"#;
+        assert_eq!(state.validate_proposal(unbond).diagnostics[0].check, "staking");
+
+        let rotate = br#"{"type":"rotate_key","validator_id":1,"public_key":"00","signature":"00"}"#;
+        let report = state.validate_proposal(rotate);
+        assert_eq!((report.kind, report.diagnostics[0].check), (PayloadKind::KeyRotation, "keys"));
+    }
+}
@@ -0,0 +1,130 @@
+// Vote simulation for stuck proposals: whether a proposal would finalize if
+// some more validators sent it combined votes, judged by the same vote
+// checks and stake-weighted quorum as real votes. Nothing is recorded.
+use crate::{check_state_root, check_vote_round, timeout, BlockId, ConsensusState, Round, ValidatorId, VoteError, VotePhase};
+use serde::Serialize;
+use std::collections::BTreeSet;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct RejectedVote {
+    pub validator_id: ValidatorId,
+    pub reason: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct WhatIf {
+    pub proposal_id: BlockId,
+    pub height: u64,
+    pub round: Round,
+    pub finalized: bool,
+    // Why no vote for the proposal would count any more, e.g. its round
+    // timed out
+    pub blocked: Option<String>,
+    pub would_finalize: bool,
+    pub total_power: u64,
+    // More than two thirds of `total_power`, needed in both phases
+    pub quorum_power: u64,
+    pub precommit_power: u64,
+    pub commit_power: u64,
+    // Voters in each phase, the hypothetical ones included
+    pub precommit: Vec<ValidatorId>,
+    pub commit: Vec<ValidatorId>,
+    pub rejected: Vec<RejectedVote>,
+}
+
+impl ConsensusState {
+    pub fn what_if(&self, proposal_id: &BlockId, voters: &[ValidatorId]) -> Result<WhatIf, VoteError> {
+        let votes = self.votes.lock().unwrap();
+        let chain = self.chain.read().unwrap();
+        let state_machine = self.state_machine.read().unwrap();
+        let staking = &state_machine.staking;
+        let block = chain.blocks.get(proposal_id).ok_or(VoteError::UnknownProposal(*proposal_id))?;
+
+        let active_height = chain.next_height();
+        let finalized = chain.finalized_chain.get(block.height as usize) == Some(proposal_id);
+        let active_round = votes.timeouts.current_round(active_height);
+        let blocked = check_vote_round(block, block.height, block.round, active_height, active_round)
+            .and_then(|()| {
+                let certificate = block.timeout_certificate.as_ref();
+                let power = |v| staking.voting_power(v);
+                timeout::check_justification(block.height, block.round, certificate, power, staking.total_power())
+                    .map_err(|_| VoteError::Unjustified(*proposal_id))
+            })
+            .and_then(|()| check_state_root(block, &state_machine))
+            .err()
+            .filter(|_| !finalized)
+            .map(|e| e.to_string());
+
+        let recorded = |phase| votes.votes.get(proposal_id).and_then(|v| v.get(&phase)).cloned().unwrap_or_default();
+        let mut precommit: BTreeSet<ValidatorId> = recorded(VotePhase::Precommit).into_iter().collect();
+        let mut commit: BTreeSet<ValidatorId> = recorded(VotePhase::Commit).into_iter().collect();
+        let mut rejected = Vec::new();
+        for &validator_id in voters {
+            let conflict = [VotePhase::Precommit, VotePhase::Commit].into_iter().find_map(|phase| {
+                let earlier = votes.cast.get(&(block.height, block.round, phase.clone(), validator_id))?;
+                let error = VoteError::AlreadyVoted { height: block.height, round: block.round, phase, proposal_id: *earlier };
+                (earlier != proposal_id).then_some(error)
+            });
+            let reason = if !self.validators.contains(&validator_id) {
+                Some(VoteError::UnknownValidator(validator_id).to_string())
+            } else if blocked.is_some() {
+                blocked.clone()
+            } else {
+                conflict.map(|e| e.to_string())
+            };
+            match reason {
+                Some(reason) => rejected.push(RejectedVote { validator_id, reason }),
+                None => {
+                    precommit.insert(validator_id);
+                    commit.insert(validator_id);
+                }
+            }
+        }
+
+        let total_power = staking.total_power();
+        let weight = |voters: &BTreeSet<ValidatorId>| {
+            voters.iter().fold(0, |w: u64, &v| w.saturating_add(staking.voting_power(v)))
+        };
+        let (precommit_power, commit_power) = (weight(&precommit), weight(&commit));
+        let quorum = |weight: u64| total_power > 0 && 3 * weight as u128 > 2 * total_power as u128;
+        Ok(WhatIf {
+            proposal_id: *proposal_id,
+            height: block.height,
+            round: block.round,
+            finalized,
+            would_finalize: finalized || (blocked.is_none() && quorum(precommit_power) && quorum(commit_power)),
+            blocked,
+            total_power,
+            quorum_power: total_power * 2 / 3 + 1,
+            precommit_power,
+            commit_power,
+            precommit: precommit.into_iter().collect(),
+            commit: commit.into_iter().collect(),
+            rejected,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_what_if_counts_only_votes_that_would_be_accepted() {
+        let state = ConsensusState::new((0..4).map(ValidatorId).collect());
+        let first = state.propose(b"a".to_vec()).unwrap();
+        let second = state.propose(b"b".to_vec()).unwrap();
+        state.vote_combined(first, 0, Round(0), ValidatorId(0)).unwrap();
+        state.vote_combined(second, 0, Round(0), ValidatorId(1)).unwrap();
+
+        // Validator 1 is committed to the other proposal and 9 is unknown
+        let report = state.what_if(&first, &[1, 2, 9].map(ValidatorId)).unwrap();
+        assert!(!report.would_finalize);
+        assert_eq!(report.precommit, [0, 2].map(ValidatorId));
+        assert_eq!(report.rejected.iter().map(|r| r.validator_id).collect::<Vec<_>>(), [1, 9].map(ValidatorId));
+
+        assert!(state.what_if(&first, &[2, 3].map(ValidatorId)).unwrap().would_finalize);
+        assert_eq!(state.what_if(&first, &[]).unwrap().commit, [ValidatorId(0)]);
+        assert!(state.what_if(&BlockId::default(), &[]).is_err());
+    }
+}
@@ -0,0 +1,196 @@
+// End-to-end test harness: N full nodes in one process, each serving the
+// whole API on an ephemeral loopback port from its own data directory.
+// Nodes do not talk to each other; the cluster sends every node the same
+// proposals and votes over HTTP. They run on a fixed clock and seeded
+// generators, so identical messages build identical chains, and a node
+// restarted from its data directory must carry on with the same chain.
+use api::{ApiServer, AppState, VotePolicy};
+use consensus::{BlockId, BlockStore, ConsensusState, FileStore, StoreWriter, ValidatorId};
+use mini_consensus_client::{Client, ConsensusClient};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use tokio::net::TcpListener;
+use tokio::task::JoinHandle;
+use trng::Trng;
+
+pub const VALIDATORS: u32 = 4;
+
+// Proposal timestamps then count up from 1, one per proposal
+fn fixed_clock() -> u64 {
+    0
+}
+
+pub struct TestNode {
+    pub index: usize,
+    pub url: String,
+    pub client: Client,
+    state: AppState,
+    data_dir: PathBuf,
+    server: JoinHandle<()>,
+}
+
+impl TestNode {
+    // Restores whatever chain `data_dir` holds
+    async fn start(index: usize, data_dir: PathBuf) -> Result<Self, String> {
+        std::fs::create_dir_all(&data_dir).map_err(|e| e.to_string())?;
+        let store = Arc::new(FileStore::in_dir(&data_dir));
+        let snapshot = store.load().await.map_err(|e| format!("node {}: {}", index, e))?;
+        let consensus = ConsensusState::new((0..VALIDATORS).map(ValidatorId).collect())
+            .with_clock(fixed_clock)
+            .restore(snapshot)
+            .with_store(StoreWriter::spawn(store));
+        let trng = Trng::from_seed(&(index as u64).to_le_bytes());
+        let server = ApiServer::builder()
+            .state(AppState::new(consensus, trng))
+            .vote_policy(VotePolicy::Any)
+            .build()
+            .await
+            .map_err(|e| format!("node {}: {}", index, e))?;
+        let state = server.state().clone();
+
+        // Served here rather than through `ApiServer::serve` to learn the port
+        let listener = TcpListener::bind("127.0.0.1:0").await.map_err(|e| e.to_string())?;
+        let url = format!("http://{}", listener.local_addr().map_err(|e| e.to_string())?);
+        let router = server.router();
+        let mut stop = state.shutdown.subscribe();
+        let server = tokio::spawn(async move {
+            let signal = async move {
+                let _ = stop.wait_for(|&stop| stop).await;
+            };
+            if let Err(e) = axum::serve(listener, router).with_graceful_shutdown(signal).await {
+                eprintln!("Node {} stopped serving: {}", index, e);
+            }
+        });
+        Ok(Self { index, client: Client::new(url.clone()), url, state, data_dir, server })
+    }
+
+    pub fn state(&self) -> &AppState {
+        &self.state
+    }
+
+    // Finalized block ids from genesis
+    pub fn chain(&self) -> Vec<BlockId> {
+        self.state.consensus.iter_finalized(0).map(|block| block.id).collect()
+    }
+
+    // As on SIGTERM: stops serving and the background tasks, then flushes
+    // the store. Nodes cannot catch up with each other, so a hard crash
+    // losing the last blocks would leave the node behind for good
+    async fn stop(self) -> PathBuf {
+        let _ = self.state.shutdown.send(true);
+        let _ = self.server.await;
+        self.state.tasks.shutdown().await;
+        self.state.consensus.flush_store().await;
+        self.data_dir
+    }
+}
+
+pub struct Cluster {
+    root: PathBuf,
+    nodes: Vec<TestNode>,
+}
+
+impl Cluster {
+    // Data directories go under a fresh directory in the system temp
+    // directory, removed when the cluster is stopped or dropped
+    pub async fn start(nodes: usize) -> Result<Self, String> {
+        static NEXT: AtomicUsize = AtomicUsize::new(0);
+        let root = std::env::temp_dir().join(format!("mcn-cluster-{}-{}", std::process::id(), NEXT.fetch_add(1, Ordering::Relaxed)));
+        let _ = std::fs::remove_dir_all(&root);
+
+        let mut cluster = Self { root, nodes: Vec::new() };
+        for index in 0..nodes {
+            let node = TestNode::start(index, cluster.root.join(format!("node{}", index))).await?;
+            cluster.nodes.push(node);
+        }
+        Ok(cluster)
+    }
+
+    pub fn nodes(&self) -> &[TestNode] {
+        &self.nodes
+    }
+
+    // Proposes `payload` on every node and has the validators vote for it
+    // until it finalizes; every node must have proposed the same block
+    pub async fn finalize(&self, payload: &str) -> Result<BlockId, String> {
+        let mut ids = Vec::new();
+        for node in &self.nodes {
+            let failed = |e| format!("node {}: {}", node.index, e);
+            let proposal = node.client.propose(payload).await.map_err(failed)?;
+            let mut finalized = false;
+            // A vote reports success once it completes the quorum
+            for validator in (0..VALIDATORS).map(ValidatorId) {
+                if node.client.vote_combined(&proposal, validator).await.map_err(failed)?.success {
+                    finalized = true;
+                    break;
+                }
+            }
+            if !finalized {
+                return Err(format!("node {}: block {} did not finalize", node.index, proposal.proposal_id));
+            }
+            ids.push(proposal.proposal_id);
+        }
+        match ids.first() {
+            Some(first) if ids.iter().all(|id| id == first) => Ok(*first),
+            Some(_) => Err(format!("nodes proposed different blocks: {:?}", ids)),
+            None => Err("the cluster has no nodes".to_string()),
+        }
+    }
+
+    // Stops node `index` and starts it again from its data directory, on a
+    // new port
+    pub async fn restart(&mut self, index: usize) -> Result<(), String> {
+        let data_dir = self.nodes.remove(index).stop().await;
+        let node = TestNode::start(index, data_dir).await?;
+        self.nodes.insert(index, node);
+        Ok(())
+    }
+
+    // The finalized chain all nodes share; panics naming the first node
+    // that differs
+    pub fn assert_chains_equal(&self) -> Vec<BlockId> {
+        let chains: Vec<Vec<BlockId>> = self.nodes.iter().map(TestNode::chain).collect();
+        for (node, chain) in self.nodes.iter().zip(&chains).skip(1) {
+            assert_eq!(chain, &chains[0], "node {} diverged from node 0", node.index);
+        }
+        chains.into_iter().next().unwrap_or_default()
+    }
+
+    pub async fn stop(mut self) {
+        for node in std::mem::take(&mut self.nodes) {
+            node.stop().await;
+        }
+    }
+}
+
+impl Drop for Cluster {
+    fn drop(&mut self) {
+        let _ = std::fs::remove_dir_all(&self.root);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn test_nodes_build_one_chain_across_restarts() {
+        let mut cluster = Cluster::start(3).await.unwrap();
+        for height in 0..5 {
+            cluster.finalize(&format!("block {}", height)).await.unwrap();
+        }
+        let chain = cluster.assert_chains_equal();
+        assert_eq!(chain.len(), 5);
+
+        cluster.restart(1).await.unwrap();
+        assert_eq!(cluster.nodes()[1].chain(), chain);
+        let status = cluster.nodes()[1].client.status().await.unwrap();
+        assert_eq!(status.finalized_block, chain.last().copied());
+
+        let next = cluster.finalize("after restart").await.unwrap();
+        assert_eq!(cluster.assert_chains_equal()[..5], chain[..]);
+        assert_eq!(cluster.nodes()[2].chain().last(), Some(&next));
+        cluster.stop().await;
+    }
+}
@@ -0,0 +1,244 @@
+// The verified header chain. Headers are appended in height order; each must
+// link to the one before, and a certificate counts only if its commit signers
+// hold more than two thirds of the stake, as the client tracks it from
+// genesis through the staking blocks before it. Bonds are paid out of reward
+// balances, so the client credits rewards from each header's proposer and
+// signers too. Headers finalized through a descendant wait until that
+// descendant's certificate arrives.
+use crate::LightError;
+use consensus::smt::Hash;
+use consensus::{
+    Block, CertifiedHeader, RewardLedger, RewardSchedule, Staking, Transaction, TxBatch, ValidatorId, GENESIS_STAKE,
+};
+use std::collections::{BTreeSet, VecDeque};
+
+pub const DEFAULT_RETAINED_HEADERS: usize = 1024;
+
+#[derive(Debug, Clone)]
+pub struct HeaderChain {
+    staking: Staking,
+    rewards: RewardLedger,
+    // Verified headers, oldest first
+    headers: VecDeque<CertifiedHeader>,
+    retained: usize,
+    // Uncertified headers waiting for a certified descendant
+    pending: Vec<CertifiedHeader>,
+    next_height: u64,
+}
+
+impl HeaderChain {
+    // Validators as the node starts them, each with the genesis stake
+    pub fn genesis(validators: &[ValidatorId]) -> Self {
+        Self::with_staking(Staking::genesis(validators, GENESIS_STAKE))
+    }
+
+    pub fn with_staking(staking: Staking) -> Self {
+        Self {
+            staking,
+            rewards: RewardLedger::default(),
+            headers: VecDeque::new(),
+            retained: DEFAULT_RETAINED_HEADERS,
+            pending: Vec::new(),
+            next_height: 0,
+        }
+    }
+
+    // Must match the node's schedule, or bonds are judged against the wrong balances
+    pub fn with_reward_schedule(mut self, schedule: RewardSchedule) -> Self {
+        self.rewards = RewardLedger::new(schedule);
+        self
+    }
+
+    // Headers kept for proofs; older ones are dropped
+    pub fn with_retained(mut self, headers: usize) -> Self {
+        self.retained = headers.max(1);
+        self
+    }
+
+    pub fn staking(&self) -> &Staking {
+        &self.staking
+    }
+
+    pub fn head(&self) -> Option<&CertifiedHeader> {
+        self.headers.back()
+    }
+
+    // Height of the next header to append, pending ones included
+    pub fn next_height(&self) -> u64 {
+        self.next_height
+    }
+
+    pub fn header(&self, height: u64) -> Option<&CertifiedHeader> {
+        let first = self.headers.front()?.height;
+        self.headers.get(height.checked_sub(first)? as usize)
+    }
+
+    pub fn append(&mut self, header: CertifiedHeader) -> Result<(), LightError> {
+        if header.height != self.next_height {
+            return Err(LightError::UnexpectedHeight { expected: self.next_height, got: header.height });
+        }
+        let parent = self.pending.last().or(self.headers.back()).map(|h| h.id);
+        if header.parent_id != parent {
+            return Err(LightError::ParentMismatch { height: header.height });
+        }
+        if header.payload.is_some() && header.computed_id() != Some(header.id) {
+            return Err(LightError::PayloadMismatch { height: header.height });
+        }
+        let Some(qc) = &header.qc else {
+            if self.pending.len() >= self.retained {
+                return Err(LightError::Uncertified { height: header.height });
+            }
+            self.pending.push(header);
+            self.next_height += 1;
+            return Ok(());
+        };
+        if qc.block_id != header.id || qc.height != header.height {
+            return Err(LightError::CertificateMismatch { height: header.height });
+        }
+
+        // The certificate was gathered with the stake the pending blocks left
+        let mut staking = self.staking.clone();
+        let mut rewards = self.rewards.clone();
+        for pending in &self.pending {
+            apply_header(&mut staking, &mut rewards, pending);
+        }
+        let signers: BTreeSet<ValidatorId> = qc.signers.iter().copied().collect();
+        if let Some(&unknown) = signers.iter().find(|&&v| staking.voting_power(v) == 0) {
+            return Err(LightError::UnknownSigner { height: header.height, validator_id: unknown });
+        }
+        let power = signers.iter().fold(0, |w: u64, &v| w.saturating_add(staking.voting_power(v)));
+        let total = staking.total_power();
+        if total == 0 || 3 * power as u128 <= 2 * total as u128 {
+            return Err(LightError::NoQuorum { height: header.height, power, total });
+        }
+
+        apply_header(&mut staking, &mut rewards, &header);
+        self.staking = staking;
+        self.rewards = rewards;
+        self.next_height += 1;
+        self.headers.extend(self.pending.drain(..));
+        self.headers.push_back(header);
+        while self.headers.len() > self.retained {
+            self.headers.pop_front();
+        }
+        Ok(())
+    }
+
+    // Checks a state read against the root in the header at `height`
+    pub fn verify_state(
+        &self,
+        height: u64,
+        state_root: &str,
+        key: &str,
+        value: Option<&[u8]>,
+        proof: &consensus::SparseMerkleProof,
+    ) -> Result<(), LightError> {
+        let header = self.header(height).ok_or(LightError::NotTracked { height })?;
+        if header.state_root.as_deref() != Some(state_root) {
+            return Err(LightError::InvalidProof(format!("state root differs from header {}", height)));
+        }
+        let root: Hash = hex::decode(state_root)
+            .ok()
+            .and_then(|root| root.try_into().ok())
+            .ok_or_else(|| LightError::InvalidProof("malformed state root".into()))?;
+        if !proof.verify(&root, key, value) {
+            return Err(LightError::InvalidProof(format!("proof for {} does not match the root", key)));
+        }
+        Ok(())
+    }
+
+    // Finds the transaction in `block`, which must be a tracked header's
+    // block: the payload is checked through the id
+    pub fn verify_transaction(&self, block: &Block, tx_hash: &str) -> Result<(usize, Transaction), LightError> {
+        let header = self.header(block.height).ok_or(LightError::NotTracked { height: block.height })?;
+        if header.id != block.id || block.computed_id() != block.id {
+            return Err(LightError::InvalidProof(format!("block does not match header {}", block.height)));
+        }
+        TxBatch::decode(&block.payload)
+            .and_then(|batch| batch.txs.into_iter().enumerate().find(|(_, tx)| tx.hash() == tx_hash))
+            .ok_or_else(|| LightError::InvalidProof(format!("transaction {} is not in block {}", tx_hash, block.id)))
+    }
+}
+
+// What finalizing the block did to stake and rewards. Headers carry a
+// payload only for staking transactions; the rest just advance the epoch.
+fn apply_header(staking: &mut Staking, rewards: &mut RewardLedger, header: &CertifiedHeader) {
+    let payload = header.payload.as_ref().and_then(|p| hex::decode(p).ok()).unwrap_or_default();
+    staking.on_finalized(header.height, &payload, rewards);
+    let voters = header.qc.as_ref().map(|qc| qc.signers.as_slice()).unwrap_or(&[]);
+    rewards.credit_height(header.height, header.proposer, voters);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus::{BlockId, ConsensusState, StateMachine};
+
+    fn finalize(state: &ConsensusState, payload: &[u8], voters: impl IntoIterator<Item = u32>) -> BlockId {
+        let id = state.propose(payload.to_vec()).unwrap();
+        let (height, round) = state.proposal_round(&id).unwrap();
+        for validator in voters {
+            state.vote_combined(id, height, round, ValidatorId(validator)).unwrap();
+        }
+        assert_eq!(state.finalize(), Some(id));
+        id
+    }
+
+    #[test]
+    fn test_follows_stake_and_verifies_proofs() {
+        let validators: Vec<ValidatorId> = (0..4).map(ValidatorId).collect();
+        // The first proposer earns enough to bond 400
+        let schedule = RewardSchedule { proposer: 400, voter: 1, halving_interval: None };
+        let genesis = StateMachine::genesis(&validators, GENESIS_STAKE).with_reward_schedule(schedule);
+        let state = ConsensusState::new(validators.clone()).with_state_machine(genesis);
+        finalize(&state, b"genesis", 0..3);
+        finalize(&state, br#"{"type":"bond","validator_id":0,"amount":400}"#, 0..3);
+        // 600 of 800 once the bond is applied
+        let tx = Transaction { sender: "alice".into(), nonce: 0, data: "hi".into(), priority: 0 };
+        let batch = finalize(&state, &TxBatch { txs: vec![tx.clone()] }.encode(), 0..2);
+        finalize(&state, b"head", 0..2);
+
+        let headers = state.certified_headers(0, 100);
+        assert!(headers[1].payload.is_some());
+        assert!(headers[2].payload.is_none());
+
+        // Without the bond, two signers are not a quorum
+        let mut chain = HeaderChain::genesis(&validators).with_reward_schedule(schedule);
+        let mut skipped = headers.clone();
+        skipped[1].payload = None;
+        chain.append(skipped[0].clone()).unwrap();
+        chain.append(skipped[1].clone()).unwrap();
+        assert_eq!(chain.append(skipped[2].clone()), Err(LightError::NoQuorum { height: 2, power: 200, total: 400 }));
+
+        // With default rewards the client sees an unfunded bond
+        let mut unfunded = HeaderChain::genesis(&validators);
+        unfunded.append(headers[0].clone()).unwrap();
+        unfunded.append(headers[1].clone()).unwrap();
+        assert!(matches!(unfunded.append(headers[2].clone()), Err(LightError::NoQuorum { height: 2, .. })));
+
+        let mut chain = HeaderChain::genesis(&validators).with_reward_schedule(schedule);
+        for header in &headers {
+            chain.append(header.clone()).unwrap();
+        }
+        assert_eq!(chain.head().map(|h| h.height), Some(3));
+        assert_eq!(chain.staking().voting_power(ValidatorId(0)), 500);
+        let mut forged = headers[3].clone();
+        forged.height = 4;
+        assert_eq!(chain.append(forged), Err(LightError::ParentMismatch { height: 4 }));
+
+        // The state the head's root commits to
+        let read = state.read_state("stake/0");
+        assert_eq!(read.height, Some(3));
+        let value = read.value.as_deref();
+        chain.verify_state(3, &read.state_root, "stake/0", value, &read.proof).unwrap();
+        assert!(chain.verify_state(2, &read.state_root, "stake/0", value, &read.proof).is_err());
+        assert!(chain.verify_state(3, &read.state_root, "stake/0", Some(b"{}"), &read.proof).is_err());
+
+        let block = state.get_block(&batch).unwrap();
+        assert_eq!(chain.verify_transaction(&block, &tx.hash()), Ok((0, tx.clone())));
+        assert!(chain.verify_transaction(&block, "00").is_err());
+        let mut tampered = block.clone();
+        tampered.payload = TxBatch { txs: vec![tx.clone(), tx.clone()] }.encode();
+        assert!(chain.verify_transaction(&tampered, &tx.hash()).is_err());
+    }
+}
@@ -0,0 +1,197 @@
+// Light client: follows the chain by finalized headers and their certificates
+// only, and checks what a node serves against them. State values are checked
+// with sparse Merkle proofs against a certified state root, transactions
+// through the id of the certified block that carries them. Certificates name
+// their signers but carry no signatures, so who signed is taken from the
+// node; that they hold a quorum of the stake is not.
+pub mod chain;
+pub mod proof;
+
+pub use chain::{HeaderChain, DEFAULT_RETAINED_HEADERS};
+pub use proof::{validator_set_commitment, BeaconAttestation, ProofBuilder};
+
+use consensus::{Block, BlockId, CertifiedHeader, SparseMerkleProof, Transaction, ValidatorId};
+use serde::de::DeserializeOwned;
+use serde::Deserialize;
+use std::fmt;
+use std::sync::Mutex;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum LightError {
+    Http(String),
+    Status { status: u16, body: String },
+    UnexpectedHeight { expected: u64, got: u64 },
+    ParentMismatch { height: u64 },
+    // A carried payload does not hash to the header's id
+    PayloadMismatch { height: u64 },
+    CertificateMismatch { height: u64 },
+    UnknownSigner { height: u64, validator_id: ValidatorId },
+    NoQuorum { height: u64, power: u64, total: u64 },
+    // Too many headers without a certificate in a row
+    Uncertified { height: u64 },
+    // The header is not (or no longer) held
+    NotTracked { height: u64 },
+    InvalidProof(String),
+}
+
+impl fmt::Display for LightError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LightError::Http(e) => write!(f, "request failed: {}", e),
+            LightError::Status { status, body } => write!(f, "server returned {}: {}", status, body),
+            LightError::UnexpectedHeight { expected, got } => write!(f, "expected header {}, got {}", expected, got),
+            LightError::ParentMismatch { height } => write!(f, "header {} does not extend the chain", height),
+            LightError::PayloadMismatch { height } => write!(f, "payload of header {} does not match its id", height),
+            LightError::CertificateMismatch { height } => {
+                write!(f, "certificate of header {} is for another block", height)
+            }
+            LightError::UnknownSigner { height, validator_id } => {
+                write!(f, "certificate of header {} is signed by unknown validator {}", height, validator_id)
+            }
+            LightError::NoQuorum { height, power, total } => {
+                write!(f, "certificate of header {} has {} of {} stake", height, power, total)
+            }
+            LightError::Uncertified { height } => write!(f, "no certificate up to header {}", height),
+            LightError::NotTracked { height } => write!(f, "header {} is not tracked", height),
+            LightError::InvalidProof(e) => write!(f, "invalid proof: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for LightError {}
+
+impl From<reqwest::Error> for LightError {
+    fn from(e: reqwest::Error) -> Self {
+        LightError::Http(e.to_string())
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedState {
+    pub key: String,
+    // Header whose state root the value was checked against
+    pub height: u64,
+    // JSON text; None when the key is proven absent
+    pub value: Option<String>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedTransaction {
+    pub block_id: BlockId,
+    pub height: u64,
+    // Position within the block's batch
+    pub index: usize,
+    pub tx: Transaction,
+}
+
+#[derive(Deserialize)]
+struct HeadersResponse {
+    finalized_height: Option<u64>,
+    headers: Vec<CertifiedHeader>,
+}
+
+#[derive(Deserialize)]
+struct StateResponse {
+    height: Option<u64>,
+    state_root: String,
+    value: Option<String>,
+    proof: SparseMerkleProof,
+}
+
+#[derive(Deserialize)]
+struct SearchResponse {
+    results: Vec<SearchHit>,
+}
+
+#[derive(Deserialize)]
+struct SearchHit {
+    #[serde(rename = "type")]
+    kind: String,
+    block: Option<BlockRef>,
+}
+
+#[derive(Deserialize)]
+struct BlockRef {
+    id: BlockId,
+}
+
+pub struct LightClient {
+    base_url: String,
+    http: reqwest::Client,
+    // Never held across an await
+    chain: Mutex<HeaderChain>,
+}
+
+impl LightClient {
+    pub fn new(base_url: impl Into<String>, chain: HeaderChain) -> Self {
+        Self {
+            base_url: base_url.into().trim_end_matches('/').to_string(),
+            http: reqwest::Client::new(),
+            chain: Mutex::new(chain),
+        }
+    }
+
+    pub fn head(&self) -> Option<CertifiedHeader> {
+        self.chain.lock().unwrap().head().cloned()
+    }
+
+    // Verifies headers up to the node's finalized head; returns the new head
+    pub async fn sync(&self) -> Result<Option<u64>, LightError> {
+        loop {
+            let from = self.chain.lock().unwrap().next_height();
+            let page: HeadersResponse = self.get(&format!("/headers?from={}", from)).await?;
+            let mut chain = self.chain.lock().unwrap();
+            let fetched = page.headers.len();
+            for header in page.headers {
+                // Appended meanwhile by a concurrent sync
+                if header.height < chain.next_height() {
+                    continue;
+                }
+                chain.append(header)?;
+            }
+            if fetched == 0 || page.finalized_height.is_none_or(|h| chain.next_height() > h) {
+                return Ok(chain.head().map(|h| h.height));
+            }
+        }
+    }
+
+    // A state value, checked against the state root of a certified header
+    pub async fn state(&self, key: &str) -> Result<VerifiedState, LightError> {
+        let read: StateResponse = self.get(&format!("/state/{}?proof=true", key)).await?;
+        let height = read.height.ok_or_else(|| LightError::InvalidProof("no certified state yet".into()))?;
+        if height >= self.chain.lock().unwrap().next_height() {
+            self.sync().await?;
+        }
+        let value = read.value.as_deref().map(str::as_bytes);
+        self.chain.lock().unwrap().verify_state(height, &read.state_root, key, value, &read.proof)?;
+        Ok(VerifiedState { key: key.to_string(), height, value: read.value })
+    }
+
+    // A finalized transaction, checked through its block's certified header
+    pub async fn transaction(&self, hash: &str) -> Result<VerifiedTransaction, LightError> {
+        let search: SearchResponse = self.get(&format!("/search?q={}", hash)).await?;
+        let block_id = search
+            .results
+            .into_iter()
+            .find(|hit| hit.kind == "transaction")
+            .and_then(|hit| hit.block)
+            .map(|block| block.id)
+            .ok_or_else(|| LightError::InvalidProof(format!("transaction {} is not finalized", hash)))?;
+        let block: Block = self.get(&format!("/blocks/{}", block_id)).await?;
+        if block.height >= self.chain.lock().unwrap().next_height() {
+            self.sync().await?;
+        }
+        let (index, tx) = self.chain.lock().unwrap().verify_transaction(&block, hash)?;
+        Ok(VerifiedTransaction { block_id, height: block.height, index, tx })
+    }
+
+    async fn get<T: DeserializeOwned>(&self, path: &str) -> Result<T, LightError> {
+        let response = self.http.get(format!("{}{}", self.base_url, path)).send().await?;
+        let status = response.status();
+        if !status.is_success() {
+            let body = response.text().await.unwrap_or_default();
+            return Err(LightError::Status { status: status.as_u16(), body });
+        }
+        Ok(response.json().await?)
+    }
+}
@@ -0,0 +1,150 @@
+// Proof builder: self-contained objects a node hands to other systems, which
+// check them offline with nothing but a trusted commitment to the validator
+// set. A beacon attestation binds a round's value to the finalized block of
+// that height. Its threshold signature verifies under the group key; the
+// commitment covers the validators, in DKG order, the group key and the
+// threshold, none of which change when shares are refreshed. The
+// certificate names the block's commit signers and carries no signatures
+// of its own.
+use crate::LightError;
+use consensus::{BeaconEntry, BlockId, ConsensusState, QuorumCertificate, ValidatorId};
+use serde::{Deserialize, Serialize};
+
+const COMMITMENT_DOMAIN: &[u8] = b"mini-consensus/validator-set/v1";
+
+// Hex BLAKE3 over the validators in order, the group key and the threshold
+pub fn validator_set_commitment(validators: &[ValidatorId], group_key: &str, threshold: usize) -> String {
+    let mut hasher = blake3::Hasher::new();
+    hasher.update(COMMITMENT_DOMAIN).update(&(validators.len() as u64).to_le_bytes());
+    for validator in validators {
+        hasher.update(&(validator.0 as u64).to_le_bytes());
+    }
+    hasher.update(group_key.as_bytes()).update(&(threshold as u64).to_le_bytes());
+    hasher.finalize().to_hex().to_string()
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BeaconAttestation {
+    pub height: u64,
+    pub block_id: BlockId,
+    pub beacon: BeaconEntry,
+    pub qc: QuorumCertificate,
+    pub validators: Vec<ValidatorId>,
+    pub group_key: String, // hex compressed G2 point
+    pub threshold: usize,
+    pub validator_set_commitment: String, // hex
+}
+
+impl BeaconAttestation {
+    // With `trusted_commitment`, the validator set must be that one too
+    pub fn verify(&self, trusted_commitment: Option<&str>) -> Result<(), LightError> {
+        let invalid = |reason: &str| Err(LightError::InvalidProof(reason.to_string()));
+        let commitment = validator_set_commitment(&self.validators, &self.group_key, self.threshold);
+        if commitment != self.validator_set_commitment {
+            return invalid("validator set does not match its commitment");
+        }
+        if trusted_commitment.is_some_and(|trusted| !trusted.eq_ignore_ascii_case(&commitment)) {
+            return invalid("validator set is not the trusted one");
+        }
+        if self.beacon.round != self.height || !self.beacon.verify(&self.group_key) {
+            return invalid("beacon signature does not verify for this height");
+        }
+        let members = |signers: &[ValidatorId]| signers.iter().all(|v| self.validators.contains(v));
+        if self.beacon.signers.len() < self.threshold || !members(&self.beacon.signers) {
+            return invalid("beacon signers are not a threshold of the validators");
+        }
+        if self.qc.block_id != self.block_id || self.qc.height != self.height {
+            return invalid("certificate is for another block");
+        }
+        if self.qc.signers.is_empty() || !members(&self.qc.signers) {
+            return invalid("certificate signers are not validators");
+        }
+        Ok(())
+    }
+}
+
+pub struct ProofBuilder<'a> {
+    consensus: &'a ConsensusState,
+}
+
+impl<'a> ProofBuilder<'a> {
+    pub fn new(consensus: &'a ConsensusState) -> Self {
+        Self { consensus }
+    }
+
+    // Fails with NotTracked when the node runs no beacon or no longer holds
+    // the round or block, and Uncertified for a block finalized through a
+    // descendant
+    pub fn beacon_attestation(&self, height: u64) -> Result<BeaconAttestation, LightError> {
+        let beacon = self.consensus.beacon().ok_or(LightError::NotTracked { height })?;
+        let entry = beacon.entry(height).ok_or(LightError::NotTracked { height })?;
+        let header = self.consensus.certified_headers(height, 1).into_iter().next().filter(|h| h.height == height);
+        let header = header.ok_or(LightError::NotTracked { height })?;
+        let qc = header.qc.ok_or(LightError::Uncertified { height })?;
+        let info = beacon.info();
+        let validators = self.consensus.get_validators().to_vec();
+
+        Ok(BeaconAttestation {
+            height,
+            block_id: header.id,
+            beacon: entry,
+            qc,
+            validator_set_commitment: validator_set_commitment(&validators, &info.group_key, info.threshold),
+            validators,
+            group_key: info.group_key,
+            threshold: info.threshold,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use consensus::Beacon;
+    use std::time::Duration;
+
+    #[test]
+    fn test_attestations_verify_offline() {
+        let validators: Vec<ValidatorId> = (0..4).map(ValidatorId).collect();
+        let beacon = Beacon::new(validators.clone(), [3u8; 32], 100);
+        let state = ConsensusState::new(validators).with_beacon(beacon);
+        for payload in [b"a", b"b"] {
+            let id = state.propose(payload.to_vec()).unwrap();
+            let (height, round) = state.proposal_round(&id).unwrap();
+            for validator in 0..3 {
+                state.vote_combined(id, height, round, ValidatorId(validator)).unwrap();
+            }
+        }
+        let builder = ProofBuilder::new(&state);
+        let mut attestation = builder.beacon_attestation(1);
+        for _ in 0..200 {
+            if attestation.is_ok() {
+                break;
+            }
+            std::thread::sleep(Duration::from_millis(50));
+            attestation = builder.beacon_attestation(1);
+        }
+        let attestation = attestation.unwrap();
+        assert_eq!(Some(attestation.block_id), state.finalize());
+        let trusted = attestation.validator_set_commitment.clone();
+        attestation.verify(Some(&trusted)).unwrap();
+
+        // A JSON round trip is all another system needs
+        let decoded: BeaconAttestation = serde_json::from_str(&serde_json::to_string(&attestation).unwrap()).unwrap();
+        decoded.verify(Some(&trusted)).unwrap();
+        assert!(attestation.verify(Some(&"00".repeat(32))).is_err());
+
+        let mut moved = attestation.clone();
+        moved.height = 0;
+        moved.qc.height = 0;
+        assert!(moved.verify(None).is_err());
+        let mut other_block = attestation.clone();
+        other_block.block_id = state.iter_finalized(0).next().unwrap().id;
+        assert!(other_block.verify(None).is_err());
+        let mut smaller_set = attestation.clone();
+        smaller_set.validators.pop();
+        assert!(smaller_set.verify(None).is_err());
+
+        assert_eq!(builder.beacon_attestation(5), Err(LightError::NotTracked { height: 5 }));
+    }
+}
@@ -0,0 +1,88 @@
+// Throughput of the generator against the OS generator and ChaCha20, lock
+// contention between concurrent callers, and how long a fresh generator takes
+// to collect its first round of entropy. Run with `cargo bench -p trng`.
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rand::rngs::OsRng;
+use rand::{RngCore, SeedableRng};
+use rand_chacha::ChaCha20Rng;
+use std::time::{Duration, Instant};
+use trng::Trng;
+
+const SIZES: [usize; 3] = [32, 4096, 1 << 20];
+const CALLERS: usize = 16;
+
+fn size_label(len: usize) -> String {
+    match len {
+        n if n >= 1 << 20 => format!("{}MB", n >> 20),
+        n if n >= 1024 => format!("{}KB", n >> 10),
+        n => format!("{}B", n),
+    }
+}
+
+fn rand_bytes(c: &mut Criterion) {
+    let trng = Trng::from_seed(b"bench");
+    let mut chacha = ChaCha20Rng::from_seed([7; 32]);
+    let mut group = c.benchmark_group("rand_bytes");
+    for len in SIZES {
+        group.throughput(Throughput::Bytes(len as u64));
+        group.bench_with_input(BenchmarkId::new("trng", size_label(len)), &len, |b, &len| {
+            b.iter(|| trng.rand_bytes(len))
+        });
+        group.bench_with_input(BenchmarkId::new("trng_parallel", size_label(len)), &len, |b, &len| {
+            b.iter(|| trng.rand_bytes_parallel(len))
+        });
+        group.bench_with_input(BenchmarkId::new("os_rng", size_label(len)), &len, |b, &len| {
+            b.iter(|| {
+                let mut output = vec![0u8; len];
+                OsRng.fill_bytes(&mut output);
+                output
+            })
+        });
+        group.bench_with_input(BenchmarkId::new("chacha20", size_label(len)), &len, |b, &len| {
+            b.iter(|| {
+                let mut output = vec![0u8; len];
+                chacha.fill_bytes(&mut output);
+                output
+            })
+        });
+    }
+    group.finish();
+}
+
+// Every caller shares one pool lock; the time reported is for all callers to
+// finish their draws
+fn contention(c: &mut Criterion) {
+    let trng = Trng::from_seed(b"bench");
+    let mut group = c.benchmark_group("contention");
+    group.throughput(Throughput::Elements(CALLERS as u64));
+    group.bench_function(format!("{}_callers_32B", CALLERS), |b| {
+        b.iter_custom(|iters| {
+            let started = Instant::now();
+            std::thread::scope(|scope| {
+                for _ in 0..CALLERS {
+                    scope.spawn(|| {
+                        for _ in 0..iters {
+                            trng.rand_bytes(32);
+                        }
+                    });
+                }
+            });
+            started.elapsed()
+        })
+    });
+    group.finish();
+}
+
+// One collection round from every source, what a fresh generator needs before
+// its output depends on collected entropy
+fn warm_up(c: &mut Criterion) {
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let trng = Trng::from_seed(b"bench");
+    let mut group = c.benchmark_group("warm_up");
+    group.measurement_time(Duration::from_secs(10));
+    group.bench_function("first_round", |b| b.iter(|| runtime.block_on(trng.reseed_from_sources())));
+    group.finish();
+}
+
+criterion_group!(benches, rand_bytes, contention, warm_up);
+criterion_main!(benches);
@@ -0,0 +1,104 @@
+// Side-by-side health of each raw source and of the conditioned output.
+// BLAKE3 makes the output pass the battery whatever went into the pool, so
+// a healthy output says little about the sources. Here the battery also runs
+// on each source's raw noise samples, the same low bytes of readings the
+// restart tests use. Each row carries a most-common-value min-entropy
+// estimate (SP 800-90B section 6.3.1) to set against what the collector
+// credits the source, and the tests the source fails but the output passes,
+// i.e. the weaknesses conditioning masks.
+use crate::policy::HealthPolicy;
+use crate::restart::{min_entropy_per_sample, most_common_count, noise_samples};
+use crate::sources::EntropySource;
+use crate::{HealthCheckResult, HealthTest, Trng};
+use serde::Serialize;
+
+// Upper bound of the 99% confidence interval, as in SP 800-90B
+const Z_99: f64 = 2.576;
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ComparisonRow {
+    // A source's name, or "conditioned" for the generator's output
+    pub output: String,
+    pub healthy: bool,
+    pub failed: Vec<HealthTest>,
+    // Estimated min-entropy, bits per byte
+    pub min_entropy: f64,
+    // What the collector credits a source per byte; none for the output
+    pub claimed_min_entropy: Option<f64>,
+    // Tests this source fails that the conditioned output passes
+    pub masked: Vec<HealthTest>,
+    pub health: HealthCheckResult,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceComparison {
+    pub sample_size: usize,
+    // Each source, then the conditioned output
+    pub rows: Vec<ComparisonRow>,
+}
+
+impl Trng {
+    // Samples every source, enabled or not, and the output, `sample_size`
+    // bytes each, and judges them all by `policy`
+    pub async fn compare_sources(&self, sample_size: usize, policy: &HealthPolicy) -> SourceComparison {
+        let conditioned = self.row("conditioned", &self.draw(sample_size), None, policy);
+        let mut rows = Vec::with_capacity(EntropySource::ALL.len() + 1);
+        for source in EntropySource::ALL {
+            let samples = noise_samples(source, sample_size).await;
+            let mut row = self.row(source.name(), &samples, Some(min_entropy_per_sample(source)), policy);
+            row.masked = row.failed.iter().copied().filter(|test| !conditioned.failed.contains(test)).collect();
+            rows.push(row);
+        }
+        rows.push(conditioned);
+        SourceComparison { sample_size, rows }
+    }
+
+    fn row(&self, output: &str, sample: &[u8], claimed: Option<f64>, policy: &HealthPolicy) -> ComparisonRow {
+        let health = self.health_check_sample(sample);
+        let failed: Vec<HealthTest> = policy.tests.iter().copied().filter(|&test| !policy.passes(&health, test)).collect();
+        ComparisonRow {
+            output: output.to_string(),
+            healthy: failed.is_empty(),
+            failed,
+            min_entropy: mcv_min_entropy(sample),
+            claimed_min_entropy: claimed,
+            masked: Vec::new(),
+            health,
+        }
+    }
+}
+
+// -log2 of the upper confidence bound on the most common byte's probability
+pub fn mcv_min_entropy(sample: &[u8]) -> f64 {
+    let n = sample.len();
+    if n < 2 {
+        return 0.0;
+    }
+    let p = most_common_count(sample.iter().copied()) as f64 / n as f64;
+    let upper = (p + Z_99 * (p * (1.0 - p) / (n - 1) as f64).sqrt()).min(1.0);
+    -upper.log2()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_compare_sources_reports_each_source_and_the_output() {
+        assert_eq!(mcv_min_entropy(&[0x55; 4096]), 0.0);
+        let uniform: Vec<u8> = (0..65536u32).map(|i| i as u8).collect();
+        assert!(mcv_min_entropy(&uniform) > 7.5);
+
+        let trng = Trng::from_seed(b"compare");
+        let policy = HealthPolicy::for_sample_size(4096);
+        let report = trng.compare_sources(4096, &policy).await;
+        let outputs: Vec<&str> = report.rows.iter().map(|row| row.output.as_str()).collect();
+        assert_eq!(outputs, ["os", "timing", "io", "conditioned"]);
+
+        let conditioned = &report.rows[3];
+        assert!(conditioned.healthy, "{:?}", conditioned);
+        assert_eq!((conditioned.claimed_min_entropy, conditioned.health.sample_size), (None, 4096));
+        assert_eq!(report.rows[0].claimed_min_entropy, Some(8.0));
+        assert!(report.rows.iter().all(|row| row.masked.iter().all(|test| row.failed.contains(test))));
+    }
+}
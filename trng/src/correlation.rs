@@ -0,0 +1,177 @@
+// Two sources secretly fed by the same underlying noise, e.g. the same timer,
+// would have their entropy counted twice. Each collection round reduces every
+// source's output to its mean byte value, and every few rounds the Pearson
+// correlation of those values is computed for each pair of sources over the
+// recent window. A pair whose correlation exceeds four standard deviations of
+// an independent pair is flagged, and the source credited with less entropy
+// has its credit scaled by 1 - r^2, the share of its variance the other one
+// does not already explain.
+use crate::sources::EntropySource;
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::sync::Mutex;
+
+// Rounds the correlation is computed over, 12.8 s of background collection
+const CORRELATION_WINDOW: usize = 128;
+// Rounds between recomputations
+const CORRELATION_EVERY: usize = 16;
+// Fewer paired rounds than this and no verdict is given
+const MIN_PAIRED_ROUNDS: usize = 32;
+// Standard deviations of r under independence, about 1 / sqrt(rounds)
+const CORRELATION_SIGMAS: f64 = 4.0;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct SourceCorrelation {
+    pub sources: [EntropySource; 2],
+    pub r: f64,
+    // Rounds in which both sources produced output
+    pub rounds: usize,
+    pub threshold: f64,
+    pub correlated: bool,
+}
+
+#[derive(Debug, Default)]
+struct State {
+    // Conditioned value per source per round, None where a source gave nothing
+    rounds: VecDeque<[Option<f64>; 3]>,
+    since_check: usize,
+    correlations: Vec<SourceCorrelation>,
+    // Credit factor per source from the last check
+    discounts: [f64; 3],
+}
+
+#[derive(Debug)]
+pub(crate) struct CorrelationMonitor {
+    state: Mutex<State>,
+}
+
+impl Default for CorrelationMonitor {
+    fn default() -> Self {
+        Self {
+            state: Mutex::new(State { discounts: [1.0; 3], ..State::default() }),
+        }
+    }
+}
+
+impl CorrelationMonitor {
+    // Records one round of raw source outputs and returns the factor each
+    // source's entropy credit is scaled by
+    pub(crate) fn observe(&self, outputs: [&[u8]; 3]) -> [f64; 3] {
+        let mut state = self.state.lock().unwrap();
+        if state.rounds.len() == CORRELATION_WINDOW {
+            state.rounds.pop_front();
+        }
+        state.rounds.push_back(outputs.map(condition));
+        state.since_check += 1;
+        if state.since_check >= CORRELATION_EVERY {
+            state.since_check = 0;
+            recompute(&mut state);
+        }
+        state.discounts
+    }
+
+    pub(crate) fn correlations(&self) -> Vec<SourceCorrelation> {
+        self.state.lock().unwrap().correlations.clone()
+    }
+}
+
+// Mean byte value of a round's output
+fn condition(output: &[u8]) -> Option<f64> {
+    if output.is_empty() {
+        return None;
+    }
+    Some(output.iter().map(|&b| b as f64).sum::<f64>() / output.len() as f64)
+}
+
+fn recompute(state: &mut State) {
+    let mut correlations = Vec::new();
+    let mut discounts = [1.0; 3];
+    for (i, &a) in EntropySource::ALL.iter().enumerate() {
+        for (j, &b) in EntropySource::ALL.iter().enumerate().skip(i + 1) {
+            let pairs: Vec<(f64, f64)> = state
+                .rounds
+                .iter()
+                .filter_map(|round| Some((round[i]?, round[j]?)))
+                .collect();
+            if pairs.len() < MIN_PAIRED_ROUNDS {
+                continue;
+            }
+            let r = pearson(&pairs);
+            let threshold = CORRELATION_SIGMAS / (pairs.len() as f64).sqrt();
+            let correlated = r.abs() > threshold;
+            if correlated {
+                let weaker = if a.credit_bits() <= b.credit_bits() { i } else { j };
+                discounts[weaker] *= 1.0 - r * r;
+                let was_correlated = state.correlations.iter().any(|c| c.sources == [a, b] && c.correlated);
+                if !was_correlated {
+                    eprintln!(
+                        "Warning: entropy sources {} and {} are correlated (r = {:.3} over {} rounds), \
+                         crediting {} with less entropy",
+                        a, b, r, pairs.len(), EntropySource::ALL[weaker]
+                    );
+                }
+            }
+            correlations.push(SourceCorrelation {
+                sources: [a, b],
+                r,
+                rounds: pairs.len(),
+                threshold,
+                correlated,
+            });
+        }
+    }
+    state.correlations = correlations;
+    state.discounts = discounts;
+}
+
+// 0 when either side is constant
+fn pearson(pairs: &[(f64, f64)]) -> f64 {
+    let n = pairs.len() as f64;
+    let mean_a = pairs.iter().map(|p| p.0).sum::<f64>() / n;
+    let mean_b = pairs.iter().map(|p| p.1).sum::<f64>() / n;
+    let (mut cov, mut var_a, mut var_b) = (0.0, 0.0, 0.0);
+    for &(a, b) in pairs {
+        cov += (a - mean_a) * (b - mean_b);
+        var_a += (a - mean_a).powi(2);
+        var_b += (b - mean_b).powi(2);
+    }
+    if var_a == 0.0 || var_b == 0.0 {
+        return 0.0;
+    }
+    cov / (var_a * var_b).sqrt()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Trng;
+
+    #[test]
+    fn test_correlated_sources_are_discounted() {
+        let trng = Trng::from_seed(b"correlation");
+        let monitor = CorrelationMonitor::default();
+        let mut discounts = [1.0; 3];
+        for _ in 0..CORRELATION_WINDOW {
+            let os = trng.rand_bytes(32);
+            let timing = trng.rand_bytes(64);
+            // io echoes the timing source with a little noise of its own
+            let io: Vec<u8> = timing.iter().map(|&b| b ^ (trng.rand_bytes(1)[0] & 0x03)).collect();
+            discounts = monitor.observe([&os, &timing, &io]);
+        }
+
+        let correlations = monitor.correlations();
+        assert_eq!(correlations.len(), 3);
+        let flagged: Vec<_> = correlations.iter().filter(|c| c.correlated).map(|c| c.sources).collect();
+        assert_eq!(flagged, vec![[EntropySource::Timing, EntropySource::Io]]);
+        assert_eq!(discounts[0], 1.0);
+        assert_eq!(discounts[1], 1.0);
+        assert!(discounts[2] < 0.1, "{:?}", discounts);
+
+        // A source that produced nothing is left out of its pairs
+        let monitor = CorrelationMonitor::default();
+        for _ in 0..CORRELATION_WINDOW {
+            monitor.observe([&trng.rand_bytes(32), &trng.rand_bytes(32), &[]]);
+        }
+        assert_eq!(monitor.correlations().len(), 1);
+    }
+}
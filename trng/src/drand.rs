@@ -0,0 +1,289 @@
+// The public drand beacon as an extra entropy input: a thread polls a drand
+// HTTP relay for the latest round of an unchained BLS12-381 chain (quicknet
+// by default), checks the round's signature against the chain's pinned
+// public key and its randomness against the signature, and hands each new
+// round to the pool. Anyone can read the beacon, so it adds no secrecy and
+// is credited zero bits unless configured; what it adds is an input stream
+// outsiders can audit. Relays are tried in order until one answers.
+use bls12_381::hash_to_curve::{ExpandMsgXmd, HashToCurve};
+use bls12_381::{pairing, G1Affine, G1Projective, G2Affine};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+// drand's quicknet: 3s rounds, signatures on G1
+pub const QUICKNET_CHAIN_HASH: &str = "52db9ba70e0cc0f6eaf7803dd07447a1f5477735fd3f661792ba94600c84e971";
+pub const QUICKNET_PUBLIC_KEY: &str = "83cf0f2896adee7eb8b5f01fcad3912212c437e0073e911fb90022d3e760183c8c4b450b6a0a6c3ac6a5776a2d1064510d1fec758c921cc22b0e17e63aaf4bcb5ed66304de9cf809bd274ca73bab4af5a6e9c76a4bc09e76eae8991ef5ece45a";
+const DST: &[u8] = b"BLS_SIG_BLS12381G1_XMD:SHA-256_SSWU_RO_NUL_";
+const FETCH_TIMEOUT: Duration = Duration::from_secs(5);
+// Verified rounds waiting for the pool
+const MAX_PENDING: usize = 16;
+
+fn default_urls() -> Vec<String> {
+    vec!["https://api.drand.sh".to_string(), "https://drand.cloudflare.com".to_string()]
+}
+
+fn default_chain_hash() -> String {
+    QUICKNET_CHAIN_HASH.to_string()
+}
+
+fn default_public_key() -> String {
+    QUICKNET_PUBLIC_KEY.to_string()
+}
+
+fn default_poll_secs() -> u64 {
+    3
+}
+
+// e.g. `{"urls": ["https://api.drand.sh"], "credit_bits": 0}`; the chain must
+// be an unchained one with signatures on G1, like quicknet
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct DrandConfig {
+    #[serde(default = "default_urls")]
+    pub urls: Vec<String>,
+    #[serde(default = "default_chain_hash")]
+    pub chain_hash: String,
+    // Hex compressed G2 group key of the chain
+    #[serde(default = "default_public_key")]
+    pub public_key: String,
+    #[serde(default = "default_poll_secs")]
+    pub poll_secs: u64,
+    // Entropy credited per verified round
+    #[serde(default)]
+    pub credit_bits: u64,
+}
+
+impl Default for DrandConfig {
+    fn default() -> Self {
+        Self {
+            urls: default_urls(),
+            chain_hash: default_chain_hash(),
+            public_key: default_public_key(),
+            poll_secs: default_poll_secs(),
+            credit_bits: 0,
+        }
+    }
+}
+
+impl DrandConfig {
+    fn group_key(&self) -> Result<G2Affine, String> {
+        hex::decode(&self.public_key)
+            .ok()
+            .and_then(|bytes| <[u8; 96]>::try_from(bytes).ok())
+            .and_then(|bytes| Option::<G2Affine>::from(G2Affine::from_compressed(&bytes)))
+            .ok_or_else(|| "drand public key is not a compressed G2 point".to_string())
+    }
+
+    fn validate(&self) -> Result<G2Affine, String> {
+        if self.urls.is_empty() {
+            return Err("drand source has no relay URLs".to_string());
+        }
+        if self.poll_secs == 0 {
+            return Err("drand poll interval must be at least 1s".to_string());
+        }
+        if self.credit_bits > 256 {
+            return Err(format!("drand credit of {} bits is more than a round's 256", self.credit_bits));
+        }
+        self.group_key()
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct DrandStatus {
+    pub chain_hash: String,
+    pub last_round: Option<u64>,
+    // Unix seconds of the last verified round
+    pub last_verified_at: Option<u64>,
+    pub rounds_mixed: u64,
+    pub credited_bits: u64,
+    pub fetch_errors: u64,
+    pub verification_failures: u64,
+    pub last_error: Option<String>,
+}
+
+// A round as relays serve it
+#[derive(Debug, Deserialize)]
+struct Round {
+    round: u64,
+    randomness: String,
+    signature: String,
+}
+
+pub(crate) struct DrandSource {
+    config: DrandConfig,
+    group_key: G2Affine,
+    pending: Mutex<Vec<Vec<u8>>>,
+    last_round: Mutex<Option<(u64, u64)>>,
+    rounds_mixed: AtomicU64,
+    credited_bits: AtomicU64,
+    fetch_errors: AtomicU64,
+    verification_failures: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+impl DrandSource {
+    // Starts the polling thread
+    pub(crate) fn spawn(config: DrandConfig) -> Result<Arc<Self>, String> {
+        let group_key = config.validate()?;
+        let source = Arc::new(Self {
+            config,
+            group_key,
+            pending: Mutex::new(Vec::new()),
+            last_round: Mutex::new(None),
+            rounds_mixed: AtomicU64::new(0),
+            credited_bits: AtomicU64::new(0),
+            fetch_errors: AtomicU64::new(0),
+            verification_failures: AtomicU64::new(0),
+            last_error: Mutex::new(None),
+        });
+        let poller = source.clone();
+        std::thread::Builder::new()
+            .name("drand".to_string())
+            .spawn(move || poller.poll_forever())
+            .map_err(|e| format!("cannot start drand poller: {}", e))?;
+        Ok(source)
+    }
+
+    // Verified rounds not yet in the pool, each credited as it is taken;
+    // returns their bytes and the bits credited
+    pub(crate) fn take(&self) -> (Vec<u8>, u64) {
+        let rounds = std::mem::take(&mut *self.pending.lock().unwrap());
+        let bits = rounds.len() as u64 * self.config.credit_bits;
+        self.rounds_mixed.fetch_add(rounds.len() as u64, Ordering::Relaxed);
+        self.credited_bits.fetch_add(bits, Ordering::Relaxed);
+        (rounds.concat(), bits)
+    }
+
+    pub(crate) fn status(&self) -> DrandStatus {
+        let last = *self.last_round.lock().unwrap();
+        DrandStatus {
+            chain_hash: self.config.chain_hash.clone(),
+            last_round: last.map(|(round, _)| round),
+            last_verified_at: last.map(|(_, at)| at),
+            rounds_mixed: self.rounds_mixed.load(Ordering::Relaxed),
+            credited_bits: self.credited_bits.load(Ordering::Relaxed),
+            fetch_errors: self.fetch_errors.load(Ordering::Relaxed),
+            verification_failures: self.verification_failures.load(Ordering::Relaxed),
+            last_error: self.last_error.lock().unwrap().clone(),
+        }
+    }
+
+    fn poll_forever(&self) {
+        let client = match reqwest::blocking::Client::builder().timeout(FETCH_TIMEOUT).build() {
+            Ok(client) => client,
+            Err(e) => return self.fail(&self.fetch_errors, format!("cannot build HTTP client: {}", e)),
+        };
+        loop {
+            self.poll(&client);
+            std::thread::sleep(Duration::from_secs(self.config.poll_secs));
+        }
+    }
+
+    fn poll(&self, client: &reqwest::blocking::Client) {
+        let mut errors = Vec::new();
+        for url in &self.config.urls {
+            let url = format!("{}/{}/public/latest", url.trim_end_matches('/'), self.config.chain_hash);
+            match client.get(&url).send().and_then(|r| r.error_for_status()).and_then(|r| r.json::<Round>()) {
+                Ok(round) => return self.accept(round),
+                Err(e) => errors.push(format!("{}: {}", url, e)),
+            }
+        }
+        self.fail(&self.fetch_errors, errors.join("; "));
+    }
+
+    // Rounds that are not newer than the last are ignored
+    fn accept(&self, round: Round) {
+        let mut last = self.last_round.lock().unwrap();
+        if last.is_some_and(|(seen, _)| round.round <= seen) {
+            return;
+        }
+        let Some(randomness) = verify(&self.group_key, &round) else {
+            drop(last);
+            return self.fail(&self.verification_failures, format!("round {} failed verification", round.round));
+        };
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+        *last = Some((round.round, now));
+
+        let mut input = round.round.to_be_bytes().to_vec();
+        input.extend_from_slice(&randomness);
+        let mut pending = self.pending.lock().unwrap();
+        pending.push(input);
+        let excess = pending.len().saturating_sub(MAX_PENDING);
+        pending.drain(..excess);
+    }
+
+    fn fail(&self, counter: &AtomicU64, error: String) {
+        if counter.fetch_add(1, Ordering::Relaxed) == 0 {
+            eprintln!("drand entropy source: {}", error);
+        }
+        *self.last_error.lock().unwrap() = Some(error);
+    }
+}
+
+// The round's randomness once its signature verifies against the group key
+// and the randomness is the signature's SHA-256
+fn verify(group_key: &G2Affine, round: &Round) -> Option<[u8; 32]> {
+    let signature = hex::decode(&round.signature).ok().and_then(|b| <[u8; 48]>::try_from(b).ok())?;
+    let point = Option::<G1Affine>::from(G1Affine::from_compressed(&signature))?;
+    let message = Sha256::digest(round.round.to_be_bytes());
+    let hashed = G1Affine::from(<G1Projective as HashToCurve<ExpandMsgXmd<sha2_09::Sha256>>>::hash_to_curve(message, DST));
+    if pairing(&point, &G2Affine::generator()) != pairing(&hashed, group_key) {
+        return None;
+    }
+    let randomness: [u8; 32] = Sha256::digest(signature).into();
+    (hex::encode(randomness) == round.randomness.to_ascii_lowercase()).then_some(randomness)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bls12_381::{G2Projective, Scalar};
+
+    #[test]
+    fn test_drand_rounds_verify_against_the_group_key() {
+        assert!(DrandConfig::default().validate().is_ok());
+
+        // A chain with a known secret, signing as drand does
+        let secret = Scalar::from(0x5eed_u64);
+        let group_key = G2Affine::from(G2Projective::generator() * secret);
+        let sign = |round: u64| {
+            let message = Sha256::digest(round.to_be_bytes());
+            let point = <G1Projective as HashToCurve<ExpandMsgXmd<sha2_09::Sha256>>>::hash_to_curve(message, DST);
+            let signature = G1Affine::from(point * secret).to_compressed();
+            Round { round, randomness: hex::encode(Sha256::digest(signature)), signature: hex::encode(signature) }
+        };
+
+        let good = sign(7);
+        assert_eq!(verify(&group_key, &good).map(hex::encode), Some(good.randomness.clone()));
+        // Another round's signature, or randomness not derived from it
+        let replayed = Round { round: 8, ..sign(7) };
+        assert!(verify(&group_key, &replayed).is_none());
+        let forged = Round { randomness: "00".repeat(32), ..sign(7) };
+        assert!(verify(&group_key, &forged).is_none());
+        let other_chain = G2Affine::from(G2Projective::generator() * Scalar::from(2u64));
+        assert!(verify(&other_chain, &good).is_none());
+
+        let source = DrandSource {
+            config: DrandConfig { credit_bits: 8, ..DrandConfig::default() },
+            group_key,
+            pending: Mutex::new(Vec::new()),
+            last_round: Mutex::new(None),
+            rounds_mixed: AtomicU64::new(0),
+            credited_bits: AtomicU64::new(0),
+            fetch_errors: AtomicU64::new(0),
+            verification_failures: AtomicU64::new(0),
+            last_error: Mutex::new(None),
+        };
+        source.accept(sign(7));
+        source.accept(sign(7));
+        source.accept(sign(6));
+        source.accept(forged);
+        assert_eq!(source.take().0.len(), 40);
+        let status = source.status();
+        assert_eq!((status.last_round, status.rounds_mixed, status.credited_bits), (Some(7), 1, 8));
+        assert_eq!(status.verification_failures, 0);
+    }
+}
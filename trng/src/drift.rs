@@ -0,0 +1,166 @@
+// Long-running drift detection: the health battery over a sliding window and
+// a CUSUM over the bit bias of each fresh step, so a source that degrades
+// slowly is caught before any single window fails outright.
+use crate::{HealthCheckResult, Trng};
+use serde::Serialize;
+use std::collections::VecDeque;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Drift {
+    // Too many ones
+    Up,
+    // Too many zeros
+    Down,
+}
+
+// Two-sided tabular CUSUM over standardized observations. `slack` is the
+// shift (in standard deviations) tolerated per step, `threshold` the
+// accumulated excess that raises an alarm.
+#[derive(Debug, Clone)]
+pub struct Cusum {
+    slack: f64,
+    threshold: f64,
+    high: f64,
+    low: f64,
+}
+
+impl Cusum {
+    pub fn new(slack: f64, threshold: f64) -> Self {
+        Self { slack, threshold, high: 0.0, low: 0.0 }
+    }
+
+    // Resets after an alarm so later drift is reported again
+    pub fn update(&mut self, z: f64) -> Option<Drift> {
+        self.high = (self.high + z - self.slack).max(0.0);
+        self.low = (self.low - z - self.slack).max(0.0);
+
+        let drift = if self.high > self.threshold {
+            Some(Drift::Up)
+        } else if self.low > self.threshold {
+            Some(Drift::Down)
+        } else {
+            None
+        };
+        if drift.is_some() {
+            self.high = 0.0;
+            self.low = 0.0;
+        }
+        drift
+    }
+
+    pub fn high(&self) -> f64 {
+        self.high
+    }
+
+    pub fn low(&self) -> f64 {
+        self.low
+    }
+}
+
+impl Default for Cusum {
+    fn default() -> Self {
+        Self::new(0.5, 5.0)
+    }
+}
+
+// Ones in excess of half the bits, in standard deviations (N(0,1) for a fair source)
+pub fn bit_bias_z(data: &[u8]) -> f64 {
+    let bits = (data.len() * 8) as f64;
+    if bits == 0.0 {
+        return 0.0;
+    }
+    let ones: u64 = data.iter().map(|b| b.count_ones() as u64).sum();
+    (ones as f64 - bits / 2.0) / (bits.sqrt() / 2.0)
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SoakSample {
+    pub step: u64,
+    pub bias_z: f64,
+    pub cusum_high: f64,
+    pub cusum_low: f64,
+    pub drift: Option<Drift>,
+    pub health: HealthCheckResult,
+}
+
+pub struct DriftMonitor {
+    cusum: Cusum,
+    window: VecDeque<u8>,
+    window_size: usize,
+    step_size: usize,
+    steps: u64,
+}
+
+impl DriftMonitor {
+    pub fn new(window_size: usize, step_size: usize) -> Self {
+        Self {
+            cusum: Cusum::default(),
+            window: VecDeque::with_capacity(window_size),
+            window_size,
+            step_size: step_size.clamp(1, window_size.max(1)),
+            steps: 0,
+        }
+    }
+
+    pub fn with_cusum(mut self, cusum: Cusum) -> Self {
+        self.cusum = cusum;
+        self
+    }
+
+    pub fn observe(&mut self, trng: &Trng) -> SoakSample {
+        let fresh = trng.draw(self.step_size);
+        self.observe_bytes(trng, &fresh)
+    }
+
+    fn observe_bytes(&mut self, trng: &Trng, fresh: &[u8]) -> SoakSample {
+        self.window.extend(fresh);
+        let excess = self.window.len().saturating_sub(self.window_size);
+        self.window.drain(..excess);
+        self.steps += 1;
+
+        let bias_z = bit_bias_z(fresh);
+        let drift = self.cusum.update(bias_z);
+        let window: Vec<u8> = self.window.iter().copied().collect();
+
+        SoakSample {
+            step: self.steps,
+            bias_z,
+            cusum_high: self.cusum.high(),
+            cusum_low: self.cusum.low(),
+            drift,
+            health: trng.health_check_sample(&window),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cusum_flags_slow_bias() {
+        let trng = Trng::with_pool(Vec::new());
+        let mut monitor = DriftMonitor::new(4096, 1024).with_cusum(Cusum::new(0.5, 8.0));
+
+        // A fair source stays quiet
+        for _ in 0..20 {
+            assert_eq!(monitor.observe(&trng).drift, None);
+        }
+
+        // About 1.4 standard deviations of excess ones per step: no single
+        // window fails the battery, but the CUSUM accumulates it
+        let mut flagged = None;
+        for i in 0..50 {
+            let mut step = trng.rand_bytes(1024);
+            for byte in step.iter_mut().take(64) {
+                *byte = 0x1f;
+            }
+            if let Some(drift) = monitor.observe_bytes(&trng, &step).drift {
+                flagged = Some((i, drift));
+                break;
+            }
+        }
+        assert!(matches!(flagged, Some((_, Drift::Up))), "{:?}", flagged);
+    }
+}
@@ -0,0 +1,401 @@
+// Hardware generators feeding the pool: the kernel's /dev/hwrng, serial
+// TRNG dongles (USB CDC devices such as TrueRNG or OneRNG) and, with the
+// `tpm` feature, a TPM 2.0's TPM2_GetRandom. Each device gets
+// a reader thread that keeps to its throughput limit and runs every 4 KiB
+// block through the health battery; blocks that fail are dropped, and after
+// a few failures in a row the device counts as unhealthy until a block
+// passes again. Only bytes that passed reach the pool, each credited the
+// configured entropy.
+use crate::{HealthPolicy, HealthTest, Trng};
+use serde::{Deserialize, Serialize};
+use std::collections::VecDeque;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Bytes each health check covers
+const HEALTH_BLOCK: usize = 4096;
+// Failed blocks in a row before a device is reported unhealthy
+const UNHEALTHY_AFTER: u32 = 3;
+// Checked bytes a device may have waiting for the pool
+const MAX_BUFFERED: usize = 64 * 1024;
+const READ_TICK: Duration = Duration::from_millis(100);
+const REOPEN_DELAY: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Framing {
+    // Random bytes as they come
+    #[default]
+    Raw,
+    // One hex string per line
+    HexLines,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum DeviceKind {
+    // A character device that returns random bytes on read, e.g. /dev/hwrng
+    Hwrng,
+    Serial {
+        #[serde(default = "default_baud")]
+        baud: u32,
+        #[serde(default)]
+        framing: Framing,
+        // Written once after opening, e.g. "cmdO\n" to start a OneRNG
+        #[serde(default)]
+        init: Option<String>,
+    },
+    // A TPM 2.0 device such as /dev/tpmrm0; needs the `tpm` feature
+    Tpm,
+}
+
+fn default_baud() -> u32 {
+    115_200
+}
+
+fn default_max_bytes_per_sec() -> usize {
+    4096
+}
+
+fn default_credit() -> f64 {
+    4.0
+}
+
+// e.g. `{"name": "truerng", "kind": "serial", "path": "/dev/ttyACM0"}`
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct HardwareConfig {
+    pub name: String,
+    pub path: PathBuf,
+    #[serde(flatten)]
+    pub kind: DeviceKind,
+    #[serde(default = "default_max_bytes_per_sec")]
+    pub max_bytes_per_sec: usize,
+    // Entropy credited per byte that passed the health checks, 0 to 8 bits
+    #[serde(default = "default_credit")]
+    pub credit_bits_per_byte: f64,
+}
+
+impl HardwareConfig {
+    fn validate(&self) -> Result<(), String> {
+        if self.name.is_empty() {
+            return Err("hardware source name is empty".to_string());
+        }
+        if self.max_bytes_per_sec == 0 {
+            return Err(format!("hardware source {} has a zero throughput limit", self.name));
+        }
+        if !(0.0..=8.0).contains(&self.credit_bits_per_byte) {
+            return Err(format!("hardware source {} credits {} bits per byte, outside 0..=8", self.name, self.credit_bits_per_byte));
+        }
+        if let DeviceKind::Serial { baud, .. } = self.kind {
+            baud_rate(baud).map_err(|e| format!("hardware source {}: {}", self.name, e))?;
+        }
+        if self.kind == DeviceKind::Tpm && !cfg!(feature = "tpm") {
+            return Err(format!("hardware source {} is a TPM, but this build has no TPM support (enable the tpm feature)", self.name));
+        }
+        Ok(())
+    }
+
+    fn kind_name(&self) -> &'static str {
+        match self.kind {
+            DeviceKind::Hwrng => "hwrng",
+            DeviceKind::Serial { .. } => "serial",
+            DeviceKind::Tpm => "tpm",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HardwareStatus {
+    pub name: String,
+    pub path: PathBuf,
+    pub kind: &'static str,
+    pub healthy: bool,
+    pub bytes_read: u64,
+    // Bytes that passed the health checks and went into the pool
+    pub bytes_delivered: u64,
+    pub credited_bits: u64,
+    pub blocks_rejected: u64,
+    pub read_errors: u64,
+    pub last_error: Option<String>,
+}
+
+pub(crate) struct HardwareSource {
+    config: HardwareConfig,
+    // Checked bytes waiting for the next collection round
+    ready: Mutex<VecDeque<u8>>,
+    healthy: AtomicBool,
+    bytes_read: AtomicU64,
+    bytes_delivered: AtomicU64,
+    credited_bits: AtomicU64,
+    blocks_rejected: AtomicU64,
+    read_errors: AtomicU64,
+    last_error: Mutex<Option<String>>,
+}
+
+impl HardwareSource {
+    // Starts the device's reader thread; a machine without a TPM runs on
+    // without its TPM source
+    pub(crate) fn spawn(config: HardwareConfig) -> Result<Option<Arc<Self>>, String> {
+        config.validate()?;
+        if config.kind == DeviceKind::Tpm && !config.path.exists() {
+            eprintln!("No TPM at {}, continuing without hardware source {}", config.path.display(), config.name);
+            return Ok(None);
+        }
+        let source = Arc::new(Self {
+            config,
+            ready: Mutex::new(VecDeque::new()),
+            healthy: AtomicBool::new(true),
+            bytes_read: AtomicU64::new(0),
+            bytes_delivered: AtomicU64::new(0),
+            credited_bits: AtomicU64::new(0),
+            blocks_rejected: AtomicU64::new(0),
+            read_errors: AtomicU64::new(0),
+            last_error: Mutex::new(None),
+        });
+        let reader = source.clone();
+        std::thread::Builder::new()
+            .name(format!("hwrng-{}", reader.config.name))
+            .spawn(move || reader.read_forever())
+            .map_err(|e| format!("cannot start reader for {}: {}", source.config.name, e))?;
+        Ok(Some(source))
+    }
+
+    // Takes up to one tick's worth of checked bytes for the pool and credits
+    // them, returning the bytes and the bits credited
+    pub(crate) fn take(&self) -> (Vec<u8>, u64) {
+        let per_tick = (self.config.max_bytes_per_sec / 10).max(1);
+        let mut ready = self.ready.lock().unwrap();
+        let n = per_tick.min(ready.len());
+        let bytes: Vec<u8> = ready.drain(..n).collect();
+        drop(ready);
+        self.bytes_delivered.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        let bits = (bytes.len() as f64 * self.config.credit_bits_per_byte) as u64;
+        self.credited_bits.fetch_add(bits, Ordering::Relaxed);
+        (bytes, bits)
+    }
+
+    pub(crate) fn status(&self) -> HardwareStatus {
+        HardwareStatus {
+            name: self.config.name.clone(),
+            path: self.config.path.clone(),
+            kind: self.config.kind_name(),
+            healthy: self.healthy.load(Ordering::Relaxed),
+            bytes_read: self.bytes_read.load(Ordering::Relaxed),
+            bytes_delivered: self.bytes_delivered.load(Ordering::Relaxed),
+            credited_bits: self.credited_bits.load(Ordering::Relaxed),
+            blocks_rejected: self.blocks_rejected.load(Ordering::Relaxed),
+            read_errors: self.read_errors.load(Ordering::Relaxed),
+            last_error: self.last_error.lock().unwrap().clone(),
+        }
+    }
+
+    fn read_forever(&self) {
+        // Bit tests at five standard deviations plus entropy and
+        // autocorrelation; the NIST tests at alpha 0.01 would drop one good
+        // block in a few dozen
+        let policy = HealthPolicy::for_sample_size(HEALTH_BLOCK).with_tests(vec![
+            HealthTest::Monobit,
+            HealthTest::Runs,
+            HealthTest::Entropy,
+            HealthTest::Autocorrelation,
+        ]);
+        let checker = Trng::from_seed(b"hardware health");
+        loop {
+            if let Err(e) = self.read_device(&policy, &checker) {
+                self.read_errors.fetch_add(1, Ordering::Relaxed);
+                eprintln!("Hardware entropy source {} failed: {}", self.config.name, e);
+                *self.last_error.lock().unwrap() = Some(e.to_string());
+            }
+            std::thread::sleep(REOPEN_DELAY);
+        }
+    }
+
+    // Reads until the device fails
+    fn read_device(&self, policy: &HealthPolicy, checker: &Trng) -> io::Result<()> {
+        let mut framing = Framing::Raw;
+        let mut device: Box<dyn Read> = match &self.config.kind {
+            DeviceKind::Tpm => open_tpm(&self.config.path)?,
+            kind => {
+                let mut device = File::options()
+                    .read(true)
+                    .write(matches!(kind, DeviceKind::Serial { init: Some(_), .. }))
+                    .open(&self.config.path)?;
+                if let DeviceKind::Serial { baud, framing: device_framing, init } = kind {
+                    configure_serial(&device, *baud)?;
+                    if let Some(init) = init {
+                        device.write_all(init.as_bytes())?;
+                    }
+                    framing = *device_framing;
+                }
+                Box::new(device)
+            }
+        };
+
+        let per_tick = (self.config.max_bytes_per_sec / 10).max(1);
+        let mut block = Vec::with_capacity(HEALTH_BLOCK);
+        let mut line = Vec::new();
+        let mut failed_in_a_row = 0;
+        loop {
+            let started = Instant::now();
+            let mut raw = vec![0u8; per_tick];
+            let n = device.read(&mut raw)?;
+            if n == 0 {
+                return Err(io::Error::new(io::ErrorKind::UnexpectedEof, "device returned no data"));
+            }
+            raw.truncate(n);
+            let bytes = match framing {
+                Framing::Raw => raw,
+                Framing::HexLines => decode_hex_lines(&mut line, &raw),
+            };
+            self.bytes_read.fetch_add(bytes.len() as u64, Ordering::Relaxed);
+
+            for byte in bytes {
+                block.push(byte);
+                if block.len() < HEALTH_BLOCK {
+                    continue;
+                }
+                if policy.is_healthy(&checker.health_check_sample(&block)) {
+                    failed_in_a_row = 0;
+                    if !self.healthy.swap(true, Ordering::Relaxed) {
+                        eprintln!("Hardware entropy source {} healthy again", self.config.name);
+                    }
+                    let mut ready = self.ready.lock().unwrap();
+                    ready.extend(block.drain(..));
+                    let excess = ready.len().saturating_sub(MAX_BUFFERED);
+                    ready.drain(..excess);
+                } else {
+                    block.clear();
+                    self.blocks_rejected.fetch_add(1, Ordering::Relaxed);
+                    failed_in_a_row += 1;
+                    if failed_in_a_row == UNHEALTHY_AFTER && self.healthy.swap(false, Ordering::Relaxed) {
+                        eprintln!("Hardware entropy source {} failed {} health checks in a row", self.config.name, UNHEALTHY_AFTER);
+                    }
+                }
+            }
+            if let Some(rest) = READ_TICK.checked_sub(started.elapsed()) {
+                std::thread::sleep(rest);
+            }
+        }
+    }
+}
+
+// Reads as many bytes as asked for from TPM2_GetRandom
+#[cfg(feature = "tpm")]
+struct TpmReader(crate::tpm::Tpm);
+
+#[cfg(feature = "tpm")]
+impl Read for TpmReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let random = self.0.get_random(buf.len()).map_err(io::Error::other)?;
+        buf.copy_from_slice(&random);
+        Ok(buf.len())
+    }
+}
+
+#[cfg(feature = "tpm")]
+fn open_tpm(path: &Path) -> io::Result<Box<dyn Read>> {
+    Ok(Box::new(TpmReader(crate::tpm::Tpm::open_path(path).map_err(io::Error::other)?)))
+}
+
+#[cfg(not(feature = "tpm"))]
+fn open_tpm(_path: &Path) -> io::Result<Box<dyn Read>> {
+    Err(io::Error::other("this build has no TPM support"))
+}
+
+// Appends `raw` to the partial `line` and decodes each complete line;
+// malformed lines are skipped
+fn decode_hex_lines(line: &mut Vec<u8>, raw: &[u8]) -> Vec<u8> {
+    let mut bytes = Vec::new();
+    for &c in raw {
+        if c != b'\n' {
+            line.push(c);
+            continue;
+        }
+        if let Ok(decoded) = std::str::from_utf8(line).map_err(|_| ()).and_then(|s| hex::decode(s.trim()).map_err(|_| ())) {
+            bytes.extend(decoded);
+        }
+        line.clear();
+    }
+    bytes
+}
+
+// Puts a terminal into raw mode at `baud`; anything else, e.g. a pipe, is
+// left alone
+fn configure_serial(device: &File, baud: u32) -> io::Result<()> {
+    use std::os::fd::AsRawFd;
+    let fd = device.as_raw_fd();
+    let speed = baud_rate(baud).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+    // SAFETY: `fd` is open for the duration of the calls and `termios` is
+    // initialized by tcgetattr before it is read
+    unsafe {
+        if libc::isatty(fd) == 0 {
+            return Ok(());
+        }
+        let mut termios: libc::termios = std::mem::zeroed();
+        if libc::tcgetattr(fd, &mut termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+        libc::cfmakeraw(&mut termios);
+        libc::cfsetispeed(&mut termios, speed);
+        libc::cfsetospeed(&mut termios, speed);
+        if libc::tcsetattr(fd, libc::TCSANOW, &termios) != 0 {
+            return Err(io::Error::last_os_error());
+        }
+    }
+    Ok(())
+}
+
+fn baud_rate(baud: u32) -> Result<libc::speed_t, String> {
+    match baud {
+        9600 => Ok(libc::B9600),
+        19200 => Ok(libc::B19200),
+        38400 => Ok(libc::B38400),
+        57600 => Ok(libc::B57600),
+        115_200 => Ok(libc::B115200),
+        _ => Err(format!("unsupported baud rate {} (use 9600, 19200, 38400, 57600 or 115200)", baud)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_hardware_source_delivers_only_healthy_blocks() {
+        let dir = std::env::temp_dir().join(format!("hwrng-test-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let good = dir.join("good");
+        let stuck = dir.join("stuck");
+        std::fs::write(&good, Trng::from_seed(b"device").rand_bytes(3 * HEALTH_BLOCK)).unwrap();
+        std::fs::write(&stuck, vec![0x5a; 3 * HEALTH_BLOCK]).unwrap();
+        let config = |name: &str, path: &PathBuf| HardwareConfig {
+            name: name.to_string(),
+            path: path.clone(),
+            kind: DeviceKind::Hwrng,
+            max_bytes_per_sec: 1 << 20,
+            credit_bits_per_byte: 2.0,
+        };
+
+        let trng = Trng::from_seed(b"pool");
+        trng.add_hardware_source(config("good", &good)).unwrap();
+        trng.add_hardware_source(config("stuck", &stuck)).unwrap();
+        assert!(trng.add_hardware_source(config("good", &good)).is_err());
+        std::thread::sleep(Duration::from_millis(300));
+        trng.reseed_from_sources().await;
+
+        let statuses = trng.hardware_sources();
+        assert_eq!(statuses[0].bytes_read, 3 * HEALTH_BLOCK as u64);
+        assert!(statuses[0].healthy && statuses[0].blocks_rejected == 0);
+        assert_eq!(statuses[0].bytes_delivered, 3 * HEALTH_BLOCK as u64);
+        assert_eq!(statuses[0].credited_bits, 2 * statuses[0].bytes_delivered);
+        assert_eq!(statuses[1].blocks_rejected, 3);
+        assert!(!statuses[1].healthy && statuses[1].bytes_delivered == 0);
+
+        assert_eq!(decode_hex_lines(&mut Vec::new(), b"00ff\nzz\nab"), vec![0x00, 0xff]);
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+// Recent health of the running generator for dashboards: a ring buffer of
+// periodic samples, each the battery over the drift monitor's sliding window,
+// the CUSUM verdict for the fresh step and how fast the sources fed the pool
+// since the previous sample. Samples are judged by the generator's policy.
+use crate::drift::{Drift, DriftMonitor};
+use crate::{HealthCheckResult, Trng};
+use serde::Serialize;
+use std::collections::VecDeque;
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Serialize)]
+pub struct HealthSample {
+    // Unix time in milliseconds
+    pub at: u64,
+    pub healthy: bool,
+    pub health: HealthCheckResult,
+    pub bias_z: f64,
+    pub drift: Option<Drift>,
+    // Bytes per second collected from all sources since the previous sample
+    pub entropy_rate: f64,
+}
+
+pub struct HealthHistory {
+    samples: VecDeque<HealthSample>,
+    capacity: usize,
+    monitor: DriftMonitor,
+    // When the previous sample was taken and the source byte total then
+    last_collected: Option<(Instant, u64)>,
+}
+
+impl HealthHistory {
+    pub fn new(capacity: usize, monitor: DriftMonitor) -> Self {
+        Self {
+            samples: VecDeque::with_capacity(capacity),
+            capacity: capacity.max(1),
+            monitor,
+            last_collected: None,
+        }
+    }
+
+    // Draws a fresh step from `trng`, evicting the oldest sample when full
+    pub fn record(&mut self, trng: &Trng) -> &HealthSample {
+        let soak = self.monitor.observe(trng);
+
+        let collected: u64 = trng.sources().iter().map(|s| s.bytes_collected).sum();
+        let now = Instant::now();
+        let entropy_rate = match self.last_collected {
+            Some((then, before)) if now > then => {
+                collected.saturating_sub(before) as f64 / now.duration_since(then).as_secs_f64()
+            }
+            _ => 0.0,
+        };
+        self.last_collected = Some((now, collected));
+
+        if self.samples.len() == self.capacity {
+            self.samples.pop_front();
+        }
+        self.samples.push_back(HealthSample {
+            at: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|d| d.as_millis() as u64)
+                .unwrap_or(0),
+            healthy: trng.health_policy().is_healthy(&soak.health),
+            health: soak.health,
+            bias_z: soak.bias_z,
+            drift: soak.drift,
+            entropy_rate,
+        });
+        self.samples.back().expect("just pushed")
+    }
+
+    // Samples taken at or after `since` (Unix milliseconds), oldest first
+    pub fn since(&self, since: u64) -> Vec<HealthSample> {
+        self.samples.iter().filter(|s| s.at >= since).cloned().collect()
+    }
+
+    pub fn latest(&self) -> Option<&HealthSample> {
+        self.samples.back()
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samples.is_empty()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_history_keeps_the_latest_samples() {
+        let trng = Trng::from_seed(b"history");
+        let mut history = HealthHistory::new(3, DriftMonitor::new(4096, 1024));
+        for _ in 0..5 {
+            history.record(&trng);
+        }
+        assert_eq!(history.len(), 3);
+
+        let samples = history.since(0);
+        assert!(samples.windows(2).all(|w| w[0].at <= w[1].at));
+        assert_eq!(samples.last().unwrap().health.sample_size, 4096);
+        assert!(history.since(u64::MAX).is_empty());
+        // A seeded generator has no sources feeding it
+        assert_eq!(history.latest().unwrap().entropy_rate, 0.0);
+    }
+}
@@ -1,62 +1,230 @@
-use blake3;
 use getrandom::getrandom;
-use std::time::{Duration, Instant};
-use std::sync::{Arc, Mutex};
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use std::str::FromStr;
+use std::time::Instant;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
 use tokio::net::TcpStream;
 use tokio::time;
 
-const ENTROPY_BUFFER_SIZE: usize = 1024;
+pub mod compare;
+pub mod correlation;
+pub mod drift;
+pub mod drand;
+pub mod hardware;
+pub mod hd;
+pub mod history;
+pub mod policy;
+pub mod pool;
+pub mod restart;
+pub mod secret;
+pub mod sources;
+pub mod sss;
+#[cfg(feature = "tpm")]
+pub mod tpm;
+
+pub use compare::{ComparisonRow, SourceComparison};
+pub use correlation::SourceCorrelation;
+pub use drift::{Cusum, Drift, DriftMonitor, SoakSample};
+pub use drand::{DrandConfig, DrandStatus};
+pub use hardware::{DeviceKind, Framing, HardwareConfig, HardwareStatus};
+pub use hd::{KeyPurpose, MasterSeed};
+pub use history::{HealthHistory, HealthSample};
+pub use policy::HealthPolicy;
+pub use pool::{PoolConfig, PoolStatus};
+pub use restart::RestartTestResult;
+pub use secret::{Charset, Secret, SecretError, SecretPolicy};
+pub use sources::{EntropySource, SourceError, SourceStatus};
+use correlation::CorrelationMonitor;
+use drand::DrandSource;
+use hardware::HardwareSource;
+use pool::{EntropyPool, PoolLevel};
+use sources::SourceRegistry;
+
+// Output chunk each rayon task fills in parallel generation
+pub const PARALLEL_CHUNK: usize = 1 << 20;
+// Domain separation for the key of parallel generation
+const PARALLEL_CONTEXT: &str = "mini-consensus-true-rng 2024 parallel xof";
+
+// BIP-39 English list: 2048 words, 11 bits of entropy per word
+const WORDLIST: &str = include_str!("wordlist/bip39-english.txt");
+
+// Chi-square critical value for 255 degrees of freedom at alpha = 0.01
+const CHI2_CRITICAL_255: f64 = 310.457;
+// Minimum p-value for the NIST SP 800-22 frequency test
+const NIST_ALPHA: f64 = 0.01;
+// Lags the autocorrelation test covers, enough for timer artifacts up to
+// four bytes apart
+const AUTOCORRELATION_LAGS: usize = 32;
 
 #[derive(Clone)]
 pub struct Trng {
-    entropy_pool: Arc<Mutex<Vec<u8>>>,
+    entropy_pool: Arc<Mutex<EntropyPool>>,
+    // Sizing and credited fill of the pool
+    level: Arc<Mutex<PoolLevel>>,
+    // Mixed into every output so back-to-back draws differ between pool refreshes
+    draws: Arc<AtomicU64>,
+    // Collection rounds completed, for liveness checks
+    rounds: Arc<AtomicU64>,
+    sources: Arc<SourceRegistry>,
+    correlation: Arc<CorrelationMonitor>,
+    // Swapped whole by `set_health_policy`
+    health_policy: Arc<RwLock<Arc<HealthPolicy>>>,
+    hardware: Arc<RwLock<Vec<Arc<HardwareSource>>>>,
+    drand: Arc<RwLock<Option<Arc<DrandSource>>>>,
 }
 
 impl Trng {
     pub fn new() -> Self {
-        let trng = Self {
-            entropy_pool: Arc::new(Mutex::new(Vec::new())),
-        };
+        let trng = Self::with_pool(Vec::new());
         
         let trng_clone = trng.clone();
         tokio::spawn(async move {
-            trng_clone.collect_entropy_background().await;
+            trng_clone.collect_entropy().await;
         });
 
         trng
     }
 
-    async fn collect_entropy_background(&self) {
-        let mut interval = time::interval(Duration::from_millis(100));
-        
+    // Thresholds and sample size the node judges its own health by
+    pub fn with_health_policy(mut self, policy: HealthPolicy) -> Self {
+        self.health_policy = Arc::new(RwLock::new(Arc::new(policy)));
+        self
+    }
+
+    pub fn health_policy(&self) -> Arc<HealthPolicy> {
+        self.health_policy.read().unwrap().clone()
+    }
+
+    // Replaces the policy for every clone of this generator
+    pub fn set_health_policy(&self, policy: HealthPolicy) {
+        *self.health_policy.write().unwrap() = Arc::new(policy);
+    }
+
+    // Reproducible output with no background entropy collection: for tests
+    // and simulations only, never for keys or secrets
+    pub fn from_seed(seed: &[u8]) -> Self {
+        Self::with_pool(seed.to_vec())
+    }
+
+    // Pool sizing and watermarks
+    pub fn with_pool_config(self, config: PoolConfig) -> Self {
+        self.entropy_pool.lock().unwrap().resize(config.capacity);
+        self.level.lock().unwrap().set_config(config);
+        self
+    }
+
+    pub fn pool_config(&self) -> PoolConfig {
+        self.level.lock().unwrap().config()
+    }
+
+    // Resizes the pool for every clone of this generator
+    pub fn set_pool_config(&self, config: PoolConfig) -> Result<(), String> {
+        config.validate()?;
+        let mut pool = self.entropy_pool.lock().unwrap();
+        pool.resize(config.capacity);
+        self.level.lock().unwrap().set_config(config);
+        Ok(())
+    }
+
+    pub fn pool_status(&self) -> PoolStatus {
+        self.level.lock().unwrap().status()
+    }
+
+    // A pool seeded up front, from any non-empty `seed`, starts full and warm
+    fn with_pool(seed: Vec<u8>) -> Self {
+        let config = PoolConfig::default();
+        let mut level = PoolLevel::new(config);
+        let pool = if seed.is_empty() {
+            EntropyPool::new(config.capacity)
+        } else {
+            level.set_fill(usize::MAX);
+            EntropyPool::seeded(config.capacity, &seed)
+        };
+        Self {
+            entropy_pool: Arc::new(Mutex::new(pool)),
+            level: Arc::new(Mutex::new(level)),
+            draws: Arc::new(AtomicU64::new(0)),
+            rounds: Arc::new(AtomicU64::new(0)),
+            sources: Arc::new(SourceRegistry::default()),
+            correlation: Arc::new(CorrelationMonitor::default()),
+            health_policy: Arc::new(RwLock::new(Arc::new(HealthPolicy::default()))),
+            hardware: Arc::new(RwLock::new(Vec::new())),
+            drand: Arc::new(RwLock::new(None)),
+        }
+    }
+
+    // Like `new`, but entropy is only collected while the caller drives
+    // `collect_entropy`, e.g. under a supervisor that restarts it
+    pub fn without_collector() -> Self {
+        Self::with_pool(Vec::new())
+    }
+
+    // Collects rounds forever, faster while the pool is below its low
+    // watermark and slower once it is at the high one
+    pub async fn collect_entropy(&self) {
         loop {
-            interval.tick().await;
             self.collect_entropy_round().await;
+            let interval = {
+                let level = self.level.lock().unwrap();
+                level.config().collect_interval(level.fill())
+            };
+            time::sleep(interval).await;
         }
     }
 
+    // Disabled sources are skipped. Each source is credited its entropy
+    // estimate, discounted while it correlates with another source
     async fn collect_entropy_round(&self) {
-        let mut entropy = Vec::new();
+        let mut outputs: [Vec<u8>; 3] = Default::default();
 
-        
-        let mut os_entropy = vec![0u8; 32];
-        if getrandom(&mut os_entropy).is_ok() {
-            entropy.extend_from_slice(&os_entropy);
+        if self.sources.is_enabled(EntropySource::Os) {
+            let mut os_entropy = vec![0u8; 32];
+            if getrandom(&mut os_entropy).is_ok() {
+                outputs[0] = os_entropy;
+            }
         }
-        
-        entropy.extend_from_slice(&self.collect_timing_jitter());
 
-        if let Some(io_entropy) = self.collect_io_jitter().await {
-            entropy.extend_from_slice(&io_entropy);
+        if self.sources.is_enabled(EntropySource::Timing) {
+            outputs[1] = self.collect_timing_jitter();
         }
 
-        let mut pool = self.entropy_pool.lock().unwrap();
-        pool.extend(entropy);
-        
-        if pool.len() > ENTROPY_BUFFER_SIZE {
-            let excess = pool.len() - ENTROPY_BUFFER_SIZE;
-            pool.drain(0..excess);
+        if self.sources.is_enabled(EntropySource::Io) {
+            outputs[2] = self.collect_io_jitter().await.unwrap_or_default();
         }
+
+        let discounts = self.correlation.observe([&outputs[0], &outputs[1], &outputs[2]]);
+        let mut credited_bits = 0;
+        for (i, source) in EntropySource::ALL.into_iter().enumerate() {
+            if !self.sources.is_enabled(source) {
+                continue;
+            }
+            self.sources.record(source, outputs[i].len());
+            if !outputs[i].is_empty() {
+                let bits = (source.credit_bits() as f64 * discounts[i]) as u64;
+                self.sources.credit(source, bits);
+                credited_bits += bits;
+            }
+        }
+
+        // Hardware devices are credited as their bytes are taken
+        let mut hardware = Vec::new();
+        for device in self.hardware.read().unwrap().iter() {
+            let (bytes, bits) = device.take();
+            hardware.extend(bytes);
+            credited_bits += bits;
+        }
+        let (drand, drand_bits) = self.drand.read().unwrap().as_ref().map(|beacon| beacon.take()).unwrap_or_default();
+        credited_bits += drand_bits;
+
+        let mut pool = self.entropy_pool.lock().unwrap();
+        pool.mix(&outputs.concat());
+        pool.mix(&hardware);
+        pool.mix(&drand);
+        self.level.lock().unwrap().credit((credited_bits / 8) as usize);
+        self.rounds.fetch_add(1, Ordering::Relaxed);
     }
 
     fn collect_timing_jitter(&self) -> Vec<u8> {
@@ -76,38 +244,184 @@ impl Trng {
         let start = Instant::now();
         
         
-        match TcpStream::connect("127.0.0.1:9").await {
-            Ok(_) => {},
-            Err(_) => {} 
-        }
+        let _ = TcpStream::connect("127.0.0.1:9").await;
         
         let elapsed = start.elapsed();
         Some(elapsed.as_nanos().to_le_bytes().to_vec())
     }
 
+    // Output is debited from the pool's fill level
     pub fn rand_bytes(&self, len: usize) -> Vec<u8> {
+        self.level.lock().unwrap().debit(len);
+        self.draw(len)
+    }
+
+    // Output for the generator's own monitoring, which leaves the fill alone
+    pub(crate) fn draw(&self, len: usize) -> Vec<u8> {
         let pool = self.entropy_pool.lock().unwrap();
         
-        if pool.is_empty() {
-            
+        let Some(state) = pool.state() else {
             let mut fallback = vec![0u8; len];
             getrandom(&mut fallback).ok();
             return fallback;
-        }
+        };
 
         
         let mut hasher = blake3::Hasher::new();
-        hasher.update(&pool);
+        hasher.update(state);
         hasher.update(&len.to_le_bytes());
+        hasher.update(&self.draws.fetch_add(1, Ordering::Relaxed).to_le_bytes());
         
         let mut output = vec![0u8; len];
         hasher.finalize_xof().fill(&mut output);
         output
     }
 
+    // For bulk output: the pool state is hashed once into a key, and chunk i
+    // of the output is the keyed XOF of the counter i, so chunks are filled
+    // on all cores. The result depends only on the generator state, not on
+    // the thread count.
+    pub fn rand_bytes_parallel(&self, len: usize) -> Vec<u8> {
+        self.level.lock().unwrap().debit(len);
+        let key = {
+            let pool = self.entropy_pool.lock().unwrap();
+            let Some(state) = pool.state() else {
+                drop(pool);
+                return self.draw(len);
+            };
+            let mut material = state.to_vec();
+            material.extend_from_slice(&len.to_le_bytes());
+            material.extend_from_slice(&self.draws.fetch_add(1, Ordering::Relaxed).to_le_bytes());
+            blake3::derive_key(PARALLEL_CONTEXT, &material)
+        };
+
+        let mut output = vec![0u8; len];
+        output
+            .par_chunks_mut(PARALLEL_CHUNK)
+            .enumerate()
+            .for_each(|(i, chunk)| {
+                blake3::Hasher::new_keyed(&key)
+                    .update(&(i as u64).to_le_bytes())
+                    .finalize_xof()
+                    .fill(chunk);
+            });
+        output
+    }
+
+    // Uniform integer in 0..bound via rejection sampling (no modulo bias)
+    pub fn random_index(&self, bound: usize) -> usize {
+        assert!(bound > 0, "bound must be positive");
+        let bound = bound as u64;
+        let zone = u64::MAX - (u64::MAX % bound);
+
+        loop {
+            let mut bytes = [0u8; 8];
+            bytes.copy_from_slice(&self.rand_bytes(8));
+            let value = u64::from_le_bytes(bytes);
+            if value < zone {
+                return (value % bound) as usize;
+            }
+        }
+    }
+
+    // Diceware-style passphrase drawn from the bundled BIP-39 wordlist
+    pub fn passphrase(&self, words: usize, separator: &str) -> String {
+        let list: Vec<&str> = WORDLIST.lines().collect();
+        (0..words)
+            .map(|_| list[self.random_index(list.len())])
+            .collect::<Vec<_>>()
+            .join(separator)
+    }
+
+    // Random (version 4) UUID
+    pub fn uuid_v4(&self) -> String {
+        let mut bytes = self.rand_bytes(16);
+        bytes[6] = (bytes[6] & 0x0f) | 0x40;
+        bytes[8] = (bytes[8] & 0x3f) | 0x80;
+
+        let hex = hex::encode(bytes);
+        format!("{}-{}-{}-{}-{}", &hex[0..8], &hex[8..12], &hex[12..16], &hex[16..20], &hex[20..32])
+    }
+
     pub fn reseed(&self) {
         let mut pool = self.entropy_pool.lock().unwrap();
         pool.clear();
+        self.level.lock().unwrap().set_fill(0);
+    }
+
+    // Replaces the pool with a state expanded from caller-supplied entropy;
+    // deterministic, so the startup self-test can check reseeding against
+    // known answers
+    pub fn reseed_with(&self, entropy: &[u8]) {
+        let mut pool = self.entropy_pool.lock().unwrap();
+        pool.seed(entropy);
+        self.level.lock().unwrap().set_fill(entropy.len());
+    }
+
+    // Discards the pool and refills it at once from the enabled sources, so
+    // nothing drawn before the reseed influences later output
+    pub async fn reseed_from_sources(&self) {
+        self.reseed();
+        self.collect_entropy_round().await;
+        self.sources.record_reseed();
+    }
+
+    pub fn reseeds(&self) -> u64 {
+        self.sources.reseeds()
+    }
+
+    pub fn collection_rounds(&self) -> u64 {
+        self.rounds.load(Ordering::Relaxed)
+    }
+
+    // Heap bytes of the pool, which is always its capacity
+    pub fn pool_bytes(&self) -> usize {
+        self.entropy_pool.lock().unwrap().len()
+    }
+
+    // Starts reading a hardware generator into the pool
+    pub fn add_hardware_source(&self, config: HardwareConfig) -> Result<(), String> {
+        let mut hardware = self.hardware.write().unwrap();
+        if hardware.iter().any(|device| device.status().name == config.name) {
+            return Err(format!("hardware source {} is defined twice", config.name));
+        }
+        hardware.extend(HardwareSource::spawn(config)?);
+        Ok(())
+    }
+
+    pub fn hardware_sources(&self) -> Vec<HardwareStatus> {
+        self.hardware.read().unwrap().iter().map(|device| device.status()).collect()
+    }
+
+    // Starts mixing verified drand rounds into the pool
+    pub fn add_drand_source(&self, config: DrandConfig) -> Result<(), String> {
+        let mut drand = self.drand.write().unwrap();
+        if drand.is_some() {
+            return Err("drand source is defined twice".to_string());
+        }
+        *drand = Some(DrandSource::spawn(config)?);
+        Ok(())
+    }
+
+    pub fn drand_source(&self) -> Option<DrandStatus> {
+        self.drand.read().unwrap().as_ref().map(|beacon| beacon.status())
+    }
+
+    pub fn set_source_enabled(&self, source: EntropySource, enabled: bool) -> Result<(), SourceError> {
+        self.sources.set_enabled(source, enabled)
+    }
+
+    pub fn sources(&self) -> Vec<SourceStatus> {
+        self.sources.statuses()
+    }
+
+    // Pairwise correlation of the sources from the latest check
+    pub fn source_correlations(&self) -> Vec<SourceCorrelation> {
+        self.correlation.correlations()
+    }
+
+    pub fn credited_entropy_bits(&self) -> u64 {
+        self.sources().iter().map(|s| s.credited_bits).sum()
     }
 
     
@@ -165,14 +479,215 @@ impl Trng {
         entropy
     }
 
+    // Chi-square statistic of the byte histogram against a uniform distribution
+    pub fn chi_square_test(&self, data: &[u8]) -> f64 {
+        let mut frequency = [0usize; 256];
+
+        for &byte in data {
+            frequency[byte as usize] += 1;
+        }
+
+        let expected = data.len() as f64 / 256.0;
+        if expected == 0.0 {
+            return 0.0;
+        }
+
+        frequency
+            .iter()
+            .map(|&count| {
+                let diff = count as f64 - expected;
+                diff * diff / expected
+            })
+            .sum()
+    }
+
+    // NIST SP 800-22 frequency (monobit) test, returns the p-value
+    pub fn nist_frequency_test(&self, data: &[u8]) -> f64 {
+        let total_bits = data.len() * 8;
+        if total_bits == 0 {
+            return 0.0;
+        }
+
+        let ones: i64 = data.iter().map(|b| b.count_ones() as i64).sum();
+        let sum = 2 * ones - total_bits as i64;
+        let s_obs = (sum.abs() as f64) / (total_bits as f64).sqrt();
+
+        erfc(s_obs / std::f64::consts::SQRT_2)
+    }
+
+    // NIST SP 800-22 block frequency test over blocks of `block_bits` bits,
+    // returns the p-value; leftover bits are ignored
+    pub fn block_frequency_test(&self, data: &[u8], block_bits: usize) -> f64 {
+        let block_bits = block_bits.max(1);
+        let blocks = data.len() * 8 / block_bits;
+        if blocks == 0 {
+            return 0.0;
+        }
+
+        let chi_square: f64 = (0..blocks)
+            .map(|block| {
+                let ones = (block * block_bits..(block + 1) * block_bits).filter(|&i| bit_at(data, i)).count();
+                let proportion = ones as f64 / block_bits as f64;
+                (proportion - 0.5).powi(2)
+            })
+            .sum::<f64>()
+            * 4.0
+            * block_bits as f64;
+
+        igamc(blocks as f64 / 2.0, chi_square / 2.0)
+    }
+
+    // NIST SP 800-22 longest run of ones in a block, returns the p-value.
+    // Uses 8-bit blocks below 6272 bits and 128-bit blocks from there on.
+    pub fn longest_run_test(&self, data: &[u8]) -> f64 {
+        let total_bits = data.len() * 8;
+        let (block_bits, bounds, probabilities): (usize, [usize; 2], &[f64]) = if total_bits < 6272 {
+            (8, [1, 4], &[0.2148, 0.3672, 0.2305, 0.1875])
+        } else {
+            (128, [4, 9], &[0.1174, 0.2430, 0.2493, 0.1752, 0.1027, 0.1124])
+        };
+        let blocks = total_bits / block_bits;
+        if blocks < 16 {
+            return 0.0;
+        }
+
+        // Longest runs clamped to [bounds[0], bounds[1]] index the classes
+        let mut counts = vec![0usize; probabilities.len()];
+        for block in 0..blocks {
+            let (mut run, mut longest) = (0, 0);
+            for i in block * block_bits..(block + 1) * block_bits {
+                run = if bit_at(data, i) { run + 1 } else { 0 };
+                longest = longest.max(run);
+            }
+            counts[longest.clamp(bounds[0], bounds[1]) - bounds[0]] += 1;
+        }
+
+        let chi_square: f64 = counts
+            .iter()
+            .zip(probabilities)
+            .map(|(&count, &p)| {
+                let expected = blocks as f64 * p;
+                (count as f64 - expected).powi(2) / expected
+            })
+            .sum();
+        igamc((probabilities.len() - 1) as f64 / 2.0, chi_square / 2.0)
+    }
+
+    // Bit-level autocorrelation at lags 1..=max_lag as standard normal
+    // scores: for a fair source, the disagreements between bits d apart
+    // number (n - d) / 2 with standard deviation sqrt(n - d) / 2
+    pub fn autocorrelation_spectrum(&self, data: &[u8], max_lag: usize) -> Vec<f64> {
+        let bits: Vec<bool> = (0..data.len() * 8).map(|i| bit_at(data, i)).collect();
+        (1..=max_lag)
+            .map(|lag| {
+                let pairs = bits.len().saturating_sub(lag);
+                if pairs == 0 {
+                    return 0.0;
+                }
+                let disagreements = bits.iter().zip(&bits[lag..]).filter(|(a, b)| a != b).count();
+                (disagreements as f64 - pairs as f64 / 2.0) / ((pairs as f64).sqrt() / 2.0)
+            })
+            .collect()
+    }
+
+    // The lag in 1..=AUTOCORRELATION_LAGS with the largest |z|, and that |z|
+    pub fn autocorrelation_test(&self, data: &[u8]) -> (usize, f64) {
+        self.autocorrelation_spectrum(data, AUTOCORRELATION_LAGS)
+            .into_iter()
+            .enumerate()
+            .map(|(i, z)| (i + 1, z.abs()))
+            .fold((0, 0.0), |worst, lag| if lag.1 > worst.1 { lag } else { worst })
+    }
+
+    // Arithmetic mean of the bytes, 127.5 for uniform data
+    pub fn arithmetic_mean(&self, data: &[u8]) -> f64 {
+        if data.is_empty() {
+            return 0.0;
+        }
+        data.iter().map(|&b| b as f64).sum::<f64>() / data.len() as f64
+    }
+
+    // ent's Monte Carlo estimate of pi: consecutive 6-byte groups are 24-bit
+    // (x, y) points, and the fraction inside the inscribed circle approaches pi/4
+    pub fn monte_carlo_pi(&self, data: &[u8]) -> f64 {
+        const RADIUS: f64 = 16_777_215.0; // 2^24 - 1
+        let mut inside = 0u64;
+        let mut points = 0u64;
+
+        for chunk in data.chunks_exact(6) {
+            let x = u32::from_be_bytes([0, chunk[0], chunk[1], chunk[2]]) as f64;
+            let y = u32::from_be_bytes([0, chunk[3], chunk[4], chunk[5]]) as f64;
+            if x * x + y * y <= RADIUS * RADIUS {
+                inside += 1;
+            }
+            points += 1;
+        }
+
+        if points == 0 {
+            return 0.0;
+        }
+        4.0 * inside as f64 / points as f64
+    }
+
+    // Serial correlation coefficient between each byte and the next (wrapping
+    // around, as ent does); near 0 for random data
+    pub fn serial_correlation(&self, data: &[u8]) -> f64 {
+        let n = data.len() as f64;
+        if data.len() < 2 {
+            return 0.0;
+        }
+
+        let (mut sum, mut sum_sq, mut sum_next) = (0.0, 0.0, 0.0);
+        for (i, &b) in data.iter().enumerate() {
+            let x = b as f64;
+            let next = data[(i + 1) % data.len()] as f64;
+            sum += x;
+            sum_sq += x * x;
+            sum_next += x * next;
+        }
+
+        let denominator = n * sum_sq - sum * sum;
+        if denominator == 0.0 {
+            return 1.0;
+        }
+        (n * sum_next - sum * sum) / denominator
+    }
+
+    // Compressed size over original size at zstd level 1; random data does not
+    // compress, so anything well under 1.0 means structure in the output
+    pub fn compression_ratio(&self, data: &[u8]) -> f64 {
+        if data.is_empty() {
+            return 1.0;
+        }
+        match zstd::bulk::compress(data, 1) {
+            Ok(compressed) => compressed.len() as f64 / data.len() as f64,
+            Err(_) => 1.0,
+        }
+    }
+
     pub fn health_check(&self, sample_size: usize) -> HealthCheckResult {
-        let sample = self.rand_bytes(sample_size);
-        
+        let sample = self.draw(sample_size);
+        self.health_check_sample(&sample)
+    }
+
+    // Runs the battery over bytes drawn elsewhere, e.g. a sliding window
+    pub fn health_check_sample(&self, sample: &[u8]) -> HealthCheckResult {
+        let (autocorrelation_lag, autocorrelation_z) = self.autocorrelation_test(sample);
         HealthCheckResult {
-            monobit_deviation: self.monobit_test(&sample),
-            runs_deviation: self.runs_test(&sample),
-            shannon_entropy: self.approximate_entropy(&sample),
-            sample_size,
+            monobit_deviation: self.monobit_test(sample),
+            runs_deviation: self.runs_test(sample),
+            shannon_entropy: self.approximate_entropy(sample),
+            chi_square: self.chi_square_test(sample),
+            nist_frequency_p: self.nist_frequency_test(sample),
+            longest_run_p: self.longest_run_test(sample),
+            block_frequency_p: self.block_frequency_test(sample, self.health_policy().block_frequency_bits),
+            autocorrelation_z,
+            autocorrelation_lag,
+            arithmetic_mean: self.arithmetic_mean(sample),
+            monte_carlo_pi: self.monte_carlo_pi(sample),
+            serial_correlation: self.serial_correlation(sample),
+            compression_ratio: self.compression_ratio(sample),
+            sample_size: sample.len(),
         }
     }
 }
@@ -183,26 +698,208 @@ impl Default for Trng {
     }
 }
 
-#[derive(Debug, Clone)]
+// Bit `i` of `data`, most significant bit of each byte first
+fn bit_at(data: &[u8], i: usize) -> bool {
+    data[i / 8] & (0x80 >> (i % 8)) != 0
+}
+
+// Regularized upper incomplete gamma function Q(a, x) (Numerical Recipes
+// gammq): a series below x = a + 1, a continued fraction above
+fn igamc(a: f64, x: f64) -> f64 {
+    const EPS: f64 = 1e-12;
+    const MAX_ITERATIONS: usize = 1000;
+    if x <= 0.0 {
+        return 1.0;
+    }
+    let log_prefactor = -x + a * x.ln() - ln_gamma(a);
+
+    if x < a + 1.0 {
+        let (mut term, mut sum, mut ap) = (1.0 / a, 1.0 / a, a);
+        for _ in 0..MAX_ITERATIONS {
+            ap += 1.0;
+            term *= x / ap;
+            sum += term;
+            if term.abs() < sum.abs() * EPS {
+                break;
+            }
+        }
+        return (1.0 - sum * log_prefactor.exp()).max(0.0);
+    }
+
+    // Modified Lentz's method
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..MAX_ITERATIONS {
+        let an = -(i as f64) * (i as f64 - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < EPS {
+            break;
+        }
+    }
+    (log_prefactor.exp() * h).min(1.0)
+}
+
+// Lanczos approximation of ln Γ(x) for x > 0
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 6] = [
+        76.18009172947146,
+        -86.50532032941677,
+        24.01409824083091,
+        -1.231739572450155,
+        0.1208650973866179e-2,
+        -0.5395239384953e-5,
+    ];
+    let tmp = x + 5.5;
+    let tmp = tmp - (x + 0.5) * tmp.ln();
+    let mut series = 1.000000000190015;
+    for (i, coefficient) in COEFFICIENTS.iter().enumerate() {
+        series += coefficient / (x + 1.0 + i as f64);
+    }
+    -tmp + (2.5066282746310005 * series / x).ln()
+}
+
+// Complementary error function (Numerical Recipes erfcc, |error| < 1.2e-7)
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1.0 / (1.0 + 0.5 * z);
+    let r = t * (-z * z - 1.26551223
+        + t * (1.00002368
+        + t * (0.37409196
+        + t * (0.09678418
+        + t * (-0.18628806
+        + t * (0.27886807
+        + t * (-1.13520398
+        + t * (1.48851587
+        + t * (-0.82215223
+        + t * 0.17087277))))))))).exp();
+
+    if x >= 0.0 { r } else { 2.0 - r }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum HealthTest {
+    Monobit,
+    Runs,
+    Entropy,
+    Chi2,
+    Nist,
+    LongestRun,
+    BlockFrequency,
+    Autocorrelation,
+}
+
+impl HealthTest {
+    pub const DEFAULT: [HealthTest; 3] = [HealthTest::Monobit, HealthTest::Runs, HealthTest::Entropy];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            HealthTest::Monobit => "monobit",
+            HealthTest::Runs => "runs",
+            HealthTest::Entropy => "entropy",
+            HealthTest::Chi2 => "chi2",
+            HealthTest::Nist => "nist",
+            HealthTest::LongestRun => "longest_run",
+            HealthTest::BlockFrequency => "block_frequency",
+            HealthTest::Autocorrelation => "autocorrelation",
+        }
+    }
+}
+
+impl fmt::Display for HealthTest {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for HealthTest {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim() {
+            "monobit" => Ok(HealthTest::Monobit),
+            "runs" => Ok(HealthTest::Runs),
+            "entropy" | "shannon" => Ok(HealthTest::Entropy),
+            "chi2" => Ok(HealthTest::Chi2),
+            "nist" => Ok(HealthTest::Nist),
+            "longest_run" => Ok(HealthTest::LongestRun),
+            "block_frequency" => Ok(HealthTest::BlockFrequency),
+            "autocorrelation" => Ok(HealthTest::Autocorrelation),
+            other => Err(format!(
+                "unknown health test '{}' (expected monobit, runs, entropy, chi2, nist, longest_run, block_frequency \
+                 or autocorrelation)",
+                other
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct HealthCheckResult {
     pub monobit_deviation: f64,
     pub runs_deviation: f64,
     pub shannon_entropy: f64,
+    pub chi_square: f64,
+    pub nist_frequency_p: f64,
+    pub longest_run_p: f64,
+    pub block_frequency_p: f64,
+    // Largest |z| over lags 1..=32 and the lag it occurred at
+    pub autocorrelation_z: f64,
+    pub autocorrelation_lag: usize,
+    // ent-style summary statistics, informational
+    pub arithmetic_mean: f64,
+    pub monte_carlo_pi: f64,
+    pub serial_correlation: f64,
+    pub compression_ratio: f64,
     pub sample_size: usize,
 }
 
 impl HealthCheckResult {
+    // Judged by the default policy for this sample's size
     pub fn is_healthy(&self) -> bool {
-        
-        self.monobit_deviation < 0.01 &&    
-        self.runs_deviation < 0.1 &&        
-        self.shannon_entropy > 7.5          
+        self.is_healthy_for(&HealthTest::DEFAULT)
+    }
+
+    pub fn is_healthy_for(&self, tests: &[HealthTest]) -> bool {
+        tests.iter().all(|&test| self.passes(test))
+    }
+
+    pub fn passes(&self, test: HealthTest) -> bool {
+        HealthPolicy::for_sample_size(self.sample_size).passes(self, test)
+    }
+
+    pub fn value(&self, test: HealthTest) -> f64 {
+        match test {
+            HealthTest::Monobit => self.monobit_deviation,
+            HealthTest::Runs => self.runs_deviation,
+            HealthTest::Entropy => self.shannon_entropy,
+            HealthTest::Chi2 => self.chi_square,
+            HealthTest::Nist => self.nist_frequency_p,
+            HealthTest::LongestRun => self.longest_run_p,
+            HealthTest::BlockFrequency => self.block_frequency_p,
+            HealthTest::Autocorrelation => self.autocorrelation_z,
+        }
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
+    use std::time::Duration;
 
     #[tokio::test]
     async fn test_trng_health() {
@@ -227,9 +924,7 @@ mod tests {
     #[test]
     fn test_negative_control() {
         let constant_data = vec![0x55u8; 8192]; 
-        let trng = Trng {
-            entropy_pool: Arc::new(Mutex::new(Vec::new())),
-        };
+        let trng = Trng::with_pool(Vec::new());
     
         let monobit_dev = trng.monobit_test(&constant_data);
         let runs_dev = trng.runs_test(&constant_data);
@@ -247,9 +942,7 @@ mod tests {
     #[test]
     fn test_health_check_methods() {
         
-        let trng = Trng {
-            entropy_pool: Arc::new(Mutex::new(vec![0xAAu8; 1024])), 
-        };
+        let trng = Trng::with_pool(vec![0xAAu8; 1024]);
         
         let health = trng.health_check(1024);
          
@@ -257,4 +950,102 @@ mod tests {
         assert!(health.runs_deviation >= 0.0);
         assert!(health.shannon_entropy >= 0.0);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn test_chi2_and_nist_reject_constant_data() {
+        let trng = Trng::with_pool(Vec::new());
+        let constant_data = vec![0xFFu8; 8192];
+
+        assert!(trng.chi_square_test(&constant_data) > CHI2_CRITICAL_255);
+        assert!(trng.nist_frequency_test(&constant_data) < NIST_ALPHA);
+
+        // Perfectly balanced bits give the maximal p-value
+        assert!((trng.nist_frequency_test(&[0x0F; 64]) - 1.0).abs() < 1e-6);
+        assert_eq!("chi2".parse::<HealthTest>(), Ok(HealthTest::Chi2));
+        assert_eq!("longest_run".parse::<HealthTest>(), Ok(HealthTest::LongestRun));
+        assert!("bogus".parse::<HealthTest>().is_err());
+    }
+
+    #[test]
+    fn test_longest_run_and_block_frequency() {
+        let trng = Trng::with_pool(Vec::new());
+
+        // NIST SP 800-22 section 2.4.8 example: 128 bits, p = 0.180609
+        let bits = "11001100000101010110110001001100111000000000001001001101010100010001001111010110100000001101011111001100111001101101100010110010";
+        let example: Vec<u8> = bits.as_bytes().chunks(8).map(|c| u8::from_str_radix(std::str::from_utf8(c).unwrap(), 2).unwrap()).collect();
+        assert!((trng.longest_run_test(&example) - 0.180609).abs() < 1e-4);
+
+        // One all-ones and one all-zeros block: chi-square 16, p = e^-8
+        assert!((trng.block_frequency_test(&[0xFF, 0x00], 8) - (-8.0f64).exp()).abs() < 1e-9);
+        assert!(trng.block_frequency_test(&[0xFF; 8192], 128) < NIST_ALPHA);
+
+        let random = Trng::from_seed(b"longest run").rand_bytes(8192);
+        assert!(trng.longest_run_test(&random) >= NIST_ALPHA);
+        assert!(trng.block_frequency_test(&random, 128) >= NIST_ALPHA);
+        assert!(trng.longest_run_test(&[0xFF; 8192]) < NIST_ALPHA);
+    }
+
+    #[test]
+    fn test_autocorrelation_spectrum() {
+        let trng = Trng::with_pool(Vec::new());
+        let random = Trng::from_seed(b"autocorrelation").rand_bytes(8192);
+        let (_, z) = trng.autocorrelation_test(&random);
+        assert!(z < HealthPolicy::default().max_autocorrelation_z, "{}", z);
+
+        // A timer artifact: every fourth byte repeats the one 32 bits earlier,
+        // which leaves the bit and byte frequencies untouched
+        let mut periodic = random.clone();
+        for i in (4..periodic.len()).step_by(4) {
+            periodic[i] = periodic[i - 4];
+        }
+        assert_eq!(trng.autocorrelation_test(&periodic).0, 32);
+        assert!(trng.autocorrelation_test(&periodic).1 > 10.0);
+        assert_eq!(trng.autocorrelation_spectrum(&periodic, 32).len(), 32);
+    }
+
+    #[test]
+    fn test_parallel_generation_is_deterministic() {
+        let len = 2 * PARALLEL_CHUNK + 100;
+        let a = Trng::from_seed(b"parallel").rand_bytes_parallel(len);
+        let b = Trng::from_seed(b"parallel").rand_bytes_parallel(len);
+        assert_eq!(a.len(), len);
+        assert_eq!(a, b);
+        // Chunks are domain-separated by their counter
+        assert_ne!(a[..64], a[PARALLEL_CHUNK..PARALLEL_CHUNK + 64]);
+
+        let trng = Trng::from_seed(b"parallel");
+        assert_ne!(trng.rand_bytes_parallel(64), trng.rand_bytes_parallel(64));
+        assert!(trng.monobit_test(&a) < HealthPolicy::for_sample_size(len).max_monobit_deviation);
+    }
+
+    #[test]
+    fn test_ent_statistics() {
+        let trng = Trng::with_pool(Vec::new());
+        let random = trng.rand_bytes(65536);
+
+        assert!((trng.arithmetic_mean(&random) - 127.5).abs() < 2.0);
+        assert!((trng.monte_carlo_pi(&random) - std::f64::consts::PI).abs() < 0.1);
+        assert!(trng.serial_correlation(&random).abs() < 0.05);
+        assert!(trng.compression_ratio(&random) > 0.99);
+
+        let ramp: Vec<u8> = (0..65536).map(|i| i as u8).collect();
+        assert!(trng.serial_correlation(&ramp) > 0.9);
+        assert!(trng.compression_ratio(&ramp) < 0.1);
+    }
+
+    #[test]
+    fn test_draws_differ_and_formats() {
+        let trng = Trng::with_pool(vec![0x42u8; 1024]);
+
+        // Same pool, same length: consecutive draws must still differ
+        assert_ne!(trng.rand_bytes(32), trng.rand_bytes(32));
+
+        let uuid = trng.uuid_v4();
+        assert_eq!(uuid.len(), 36);
+        assert_eq!(&uuid[14..15], "4");
+
+        let phrase = trng.passphrase(6, " ");
+        assert_eq!(phrase.split(' ').count(), 6);
+        assert!(phrase.split(' ').all(|w| WORDLIST.lines().any(|l| l == w)));
+    }
+}
@@ -0,0 +1,164 @@
+// Pass/fail thresholds for the health battery. Fixed cut-offs are too strict
+// for small samples and too lax for large ones, so the defaults scale with
+// the sample size: the bit tests allow five standard deviations of a fair
+// source, and the entropy floor accounts for the plug-in estimator's
+// downward bias on short samples.
+use crate::{HealthCheckResult, HealthTest, CHI2_CRITICAL_255, NIST_ALPHA};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_SAMPLE_SIZE: usize = 8192;
+const DEFAULT_BLOCK_BITS: usize = 128;
+// Standard deviations a fair source may stray in the monobit and runs tests
+const BIT_TEST_SIGMAS: f64 = 5.0;
+// Entropy allowed below the expected estimate, in bits per byte
+const ENTROPY_MARGIN: f64 = 0.5;
+// Two-sided normal quantile for alpha 0.01 split over the 32 autocorrelation
+// lags (Bonferroni), so a fair source trips the test 1% of the time
+const AUTOCORRELATION_Z: f64 = 3.6;
+
+// Thresholds left out of a deserialized policy get the defaults for its
+// sample size
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(from = "PolicySpec")]
+pub struct HealthPolicy {
+    // Bytes drawn per health check
+    pub sample_size: usize,
+    // Tests that must pass for the generator to count as healthy
+    pub tests: Vec<HealthTest>,
+    pub max_monobit_deviation: f64,
+    pub max_runs_deviation: f64,
+    pub min_shannon_entropy: f64,
+    pub max_chi_square: f64,
+    // Significance level of the NIST frequency, longest run and block
+    // frequency tests
+    pub nist_alpha: f64,
+    // Block size of the block frequency test, in bits
+    pub block_frequency_bits: usize,
+    // Largest |z| any autocorrelation lag may reach
+    pub max_autocorrelation_z: f64,
+}
+
+impl HealthPolicy {
+    pub fn for_sample_size(sample_size: usize) -> Self {
+        let bits = (sample_size.max(1) * 8) as f64;
+        let bytes = sample_size.max(1) as f64;
+        Self {
+            sample_size,
+            tests: HealthTest::DEFAULT.to_vec(),
+            // The ones proportion has standard deviation 0.5 / sqrt(bits)
+            max_monobit_deviation: BIT_TEST_SIGMAS * 0.5 / bits.sqrt(),
+            // The run count, relative to its mean bits / 2, has standard
+            // deviation 1 / sqrt(bits)
+            max_runs_deviation: BIT_TEST_SIGMAS / bits.sqrt(),
+            // Miller-Madow: the estimate falls (256 - 1) / (2n ln 2) short
+            min_shannon_entropy: 8.0 - 255.0 / (2.0 * bytes * std::f64::consts::LN_2) - ENTROPY_MARGIN,
+            max_chi_square: CHI2_CRITICAL_255,
+            nist_alpha: NIST_ALPHA,
+            block_frequency_bits: DEFAULT_BLOCK_BITS,
+            max_autocorrelation_z: AUTOCORRELATION_Z,
+        }
+    }
+
+    pub fn with_tests(mut self, tests: Vec<HealthTest>) -> Self {
+        self.tests = tests;
+        self
+    }
+
+    pub fn passes(&self, result: &HealthCheckResult, test: HealthTest) -> bool {
+        let value = result.value(test);
+        match test {
+            HealthTest::Entropy => value > self.threshold(test),
+            HealthTest::Nist | HealthTest::LongestRun | HealthTest::BlockFrequency => value >= self.threshold(test),
+            _ => value < self.threshold(test),
+        }
+    }
+
+    pub fn is_healthy(&self, result: &HealthCheckResult) -> bool {
+        self.tests.iter().all(|&test| self.passes(result, test))
+    }
+
+    pub fn threshold(&self, test: HealthTest) -> f64 {
+        match test {
+            HealthTest::Monobit => self.max_monobit_deviation,
+            HealthTest::Runs => self.max_runs_deviation,
+            HealthTest::Entropy => self.min_shannon_entropy,
+            HealthTest::Chi2 => self.max_chi_square,
+            HealthTest::Nist | HealthTest::LongestRun | HealthTest::BlockFrequency => self.nist_alpha,
+            HealthTest::Autocorrelation => self.max_autocorrelation_z,
+        }
+    }
+
+    // The pass condition for display, e.g. "< 0.009766"
+    pub fn describe(&self, test: HealthTest) -> String {
+        let comparison = match test {
+            HealthTest::Entropy => ">",
+            HealthTest::Nist | HealthTest::LongestRun | HealthTest::BlockFrequency => ">=",
+            _ => "<",
+        };
+        format!("{} {:.6}", comparison, self.threshold(test))
+    }
+}
+
+#[derive(Deserialize)]
+struct PolicySpec {
+    sample_size: Option<usize>,
+    tests: Option<Vec<HealthTest>>,
+    max_monobit_deviation: Option<f64>,
+    max_runs_deviation: Option<f64>,
+    min_shannon_entropy: Option<f64>,
+    max_chi_square: Option<f64>,
+    nist_alpha: Option<f64>,
+    block_frequency_bits: Option<usize>,
+    max_autocorrelation_z: Option<f64>,
+}
+
+impl From<PolicySpec> for HealthPolicy {
+    fn from(spec: PolicySpec) -> Self {
+        let defaults = HealthPolicy::for_sample_size(spec.sample_size.unwrap_or(DEFAULT_SAMPLE_SIZE));
+        HealthPolicy {
+            tests: spec.tests.unwrap_or(defaults.tests.clone()),
+            max_monobit_deviation: spec.max_monobit_deviation.unwrap_or(defaults.max_monobit_deviation),
+            max_runs_deviation: spec.max_runs_deviation.unwrap_or(defaults.max_runs_deviation),
+            min_shannon_entropy: spec.min_shannon_entropy.unwrap_or(defaults.min_shannon_entropy),
+            max_chi_square: spec.max_chi_square.unwrap_or(defaults.max_chi_square),
+            nist_alpha: spec.nist_alpha.unwrap_or(defaults.nist_alpha),
+            block_frequency_bits: spec.block_frequency_bits.unwrap_or(defaults.block_frequency_bits),
+            max_autocorrelation_z: spec.max_autocorrelation_z.unwrap_or(defaults.max_autocorrelation_z),
+            ..defaults
+        }
+    }
+}
+
+impl Default for HealthPolicy {
+    fn default() -> Self {
+        Self::for_sample_size(DEFAULT_SAMPLE_SIZE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Trng;
+
+    #[test]
+    fn test_thresholds_scale_with_sample_size() {
+        let small = HealthPolicy::for_sample_size(256);
+        let large = HealthPolicy::for_sample_size(65536);
+        assert!(small.max_monobit_deviation > large.max_monobit_deviation);
+        assert!(small.min_shannon_entropy < large.min_shannon_entropy);
+        assert!((HealthPolicy::default().max_monobit_deviation - 0.01).abs() < 0.001);
+
+        // A short random sample fails a fixed 7.5 bit entropy floor but not
+        // the scaled one
+        let trng = Trng::from_seed(b"policy");
+        let result = trng.health_check(256);
+        assert!(result.shannon_entropy < 7.5);
+        assert!(small.is_healthy(&result), "{:?}", result);
+
+        let constant = trng.health_check_sample(&[0x55; 256]);
+        assert!(!small.is_healthy(&constant));
+
+        let parsed: HealthPolicy = serde_json::from_str(r#"{"sample_size":256,"nist_alpha":0.001}"#).unwrap();
+        assert_eq!(parsed, HealthPolicy { nist_alpha: 0.001, ..small });
+    }
+}
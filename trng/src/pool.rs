@@ -0,0 +1,297 @@
+// The entropy pool and its sizing. The pool is a fixed `capacity` bytes of
+// state that collected input is mixed into: each 32-byte block is replaced
+// by its BLAKE3 keyed hash of the next 32 bytes of input, moving round the
+// pool, so new input never pushes out what earlier input contributed. Its
+// fill level is the entropy credited to the state, in bytes and at most
+// `capacity`. Collection rounds add what their sources were credited and
+// output handed out takes as many bytes as it returns.
+// The collector runs faster below the low watermark and slower from the
+// high one on, and a fresh pool is warming up until it first reaches the
+// high watermark. Dropping below the low watermark is counted once, then
+// not again until the pool is back at the high one.
+use serde::{Deserialize, Serialize};
+use std::fmt::Write;
+use std::time::Duration;
+
+// Collection interval below the low watermark, between the two, and from
+// the high one on
+const FAST_INTERVAL: Duration = Duration::from_millis(25);
+const NORMAL_INTERVAL: Duration = Duration::from_millis(100);
+const SLOW_INTERVAL: Duration = Duration::from_millis(500);
+
+// Smallest pool: one block
+const MIN_CAPACITY: usize = BLOCK;
+// Mixing works a block, one BLAKE3 key, at a time
+const BLOCK: usize = blake3::KEY_LEN;
+// Domain separation for expanding seed material into the state
+const SEED_CONTEXT: &str = "mini-consensus-true-rng 2024 pool seed";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PoolConfig {
+    pub capacity: usize,
+    pub low_watermark: usize,
+    pub high_watermark: usize,
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        Self { capacity: 1024, low_watermark: 256, high_watermark: 768 }
+    }
+}
+
+impl PoolConfig {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.capacity < MIN_CAPACITY {
+            return Err(format!("pool capacity must be at least {} bytes", MIN_CAPACITY));
+        }
+        if self.low_watermark > self.high_watermark || self.high_watermark > self.capacity {
+            return Err("pool watermarks must satisfy low_watermark <= high_watermark <= capacity".to_string());
+        }
+        Ok(())
+    }
+
+    pub fn collect_interval(&self, fill: usize) -> Duration {
+        if fill < self.low_watermark {
+            FAST_INTERVAL
+        } else if fill >= self.high_watermark {
+            SLOW_INTERVAL
+        } else {
+            NORMAL_INTERVAL
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct PoolStatus {
+    #[serde(flatten)]
+    pub config: PoolConfig,
+    pub fill: usize,
+    // False until the pool first reached the high watermark
+    pub warm: bool,
+    pub below_low_watermark: bool,
+    // Drops below the low watermark since start
+    pub low_crossings: u64,
+}
+
+impl PoolStatus {
+    pub fn render_prometheus(&self) -> String {
+        let mut out = String::new();
+        let mut family = |name: &str, kind: &str, help: &str, value: u64| {
+            let _ = writeln!(out, "# HELP {} {}", name, help);
+            let _ = writeln!(out, "# TYPE {} {}", name, kind);
+            let _ = writeln!(out, "{} {}", name, value);
+        };
+        family("trng_pool_fill_bytes", "gauge", "Credited entropy held in the pool", self.fill as u64);
+        family("trng_pool_capacity_bytes", "gauge", "Bytes the pool keeps", self.config.capacity as u64);
+        let (low, high) = (self.config.low_watermark as u64, self.config.high_watermark as u64);
+        family("trng_pool_low_watermark_bytes", "gauge", "Fill below which collection speeds up", low);
+        family("trng_pool_high_watermark_bytes", "gauge", "Fill from which collection slows down", high);
+        family("trng_pool_warm", "gauge", "1 once the pool first reached the high watermark", self.warm as u64);
+        family("trng_pool_low_crossings_total", "counter", "Drops below the low watermark", self.low_crossings);
+        out
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct EntropyPool {
+    state: Vec<u8>,
+    // Block the next input is mixed into
+    cursor: usize,
+    // False until the first input, and again after a reseed
+    seeded: bool,
+}
+
+impl EntropyPool {
+    pub(crate) fn new(capacity: usize) -> Self {
+        Self { state: vec![0; capacity], cursor: 0, seeded: false }
+    }
+
+    // The state expanded from `material` alone, e.g. a known seed
+    pub(crate) fn seeded(capacity: usize, material: &[u8]) -> Self {
+        let mut pool = Self::new(capacity);
+        pool.seed(material);
+        pool
+    }
+
+    pub(crate) fn seed(&mut self, material: &[u8]) {
+        blake3::Hasher::new_derive_key(SEED_CONTEXT).update(material).finalize_xof().fill(&mut self.state);
+        self.cursor = 0;
+        self.seeded = true;
+    }
+
+    // The state output is drawn from, None until seeded
+    pub(crate) fn state(&self) -> Option<&[u8]> {
+        self.seeded.then_some(&self.state[..])
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.state.len()
+    }
+
+    pub(crate) fn mix(&mut self, input: &[u8]) {
+        if input.is_empty() || self.state.is_empty() {
+            return;
+        }
+        let capacity = self.state.len();
+        let blocks = capacity.div_ceil(BLOCK);
+        for chunk in input.chunks(BLOCK) {
+            let start = self.cursor * BLOCK;
+            let block = &mut self.state[start..(start + BLOCK).min(capacity)];
+            // The last block is short when the capacity is not a multiple
+            // of one; its key is zero-padded
+            let mut key = [0u8; BLOCK];
+            key[..block.len()].copy_from_slice(block);
+            let mixed = blake3::keyed_hash(&key, chunk);
+            block.copy_from_slice(&mixed.as_bytes()[..block.len()]);
+            self.cursor = (self.cursor + 1) % blocks;
+        }
+        self.seeded = true;
+    }
+
+    pub(crate) fn clear(&mut self) {
+        self.state.fill(0);
+        self.cursor = 0;
+        self.seeded = false;
+    }
+
+    // Growing adds zeroed blocks; shrinking mixes the cut-off bytes into
+    // the rest, so the state keeps what it can of them
+    pub(crate) fn resize(&mut self, capacity: usize) {
+        let cut = self.state.split_off(capacity.min(self.state.len()));
+        self.state.resize(capacity, 0);
+        self.cursor = 0;
+        if self.seeded {
+            self.mix(&cut);
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub(crate) struct PoolLevel {
+    config: PoolConfig,
+    fill: usize,
+    warm: bool,
+    // Whether the next drop below the low watermark counts
+    armed: bool,
+    low_crossings: u64,
+}
+
+impl PoolLevel {
+    pub(crate) fn new(config: PoolConfig) -> Self {
+        Self { config, fill: 0, warm: false, armed: false, low_crossings: 0 }
+    }
+
+    pub(crate) fn config(&self) -> PoolConfig {
+        self.config
+    }
+
+    pub(crate) fn set_config(&mut self, config: PoolConfig) {
+        self.config = config;
+        self.set_fill(self.fill);
+    }
+
+    pub(crate) fn fill(&self) -> usize {
+        self.fill
+    }
+
+    pub(crate) fn credit(&mut self, bytes: usize) {
+        self.set_fill(self.fill.saturating_add(bytes));
+    }
+
+    pub(crate) fn debit(&mut self, bytes: usize) {
+        self.set_fill(self.fill.saturating_sub(bytes));
+    }
+
+    pub(crate) fn set_fill(&mut self, fill: usize) {
+        self.fill = fill.min(self.config.capacity);
+        if self.fill >= self.config.high_watermark {
+            self.warm = true;
+            self.armed = true;
+        } else if self.fill < self.config.low_watermark && self.armed {
+            self.armed = false;
+            self.low_crossings += 1;
+        }
+    }
+
+    pub(crate) fn status(&self) -> PoolStatus {
+        PoolStatus {
+            config: self.config,
+            fill: self.fill,
+            warm: self.warm,
+            below_low_watermark: self.fill < self.config.low_watermark,
+            low_crossings: self.low_crossings,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_watermarks_gate_warm_up_and_count_drops() {
+        let config = PoolConfig { capacity: 100, low_watermark: 20, high_watermark: 80 };
+        assert!(config.validate().is_ok());
+        assert!(PoolConfig { high_watermark: 101, ..config }.validate().is_err());
+        assert!(PoolConfig { low_watermark: 81, ..config }.validate().is_err());
+
+        let mut level = PoolLevel::new(config);
+        assert_eq!(config.collect_interval(level.fill()), FAST_INTERVAL);
+        // Warming up does not count as a drop
+        level.credit(50);
+        level.debit(50);
+        assert_eq!((level.status().warm, level.status().low_crossings), (false, 0));
+
+        level.credit(500);
+        assert_eq!(level.fill(), 100);
+        assert!(level.status().warm);
+        assert_eq!(config.collect_interval(level.fill()), SLOW_INTERVAL);
+        level.debit(90);
+        assert_eq!(level.status().low_crossings, 1);
+        assert!(level.status().below_low_watermark);
+        // Counted again only after refilling to the high watermark
+        level.credit(50);
+        assert_eq!(config.collect_interval(level.fill()), NORMAL_INTERVAL);
+        level.debit(50);
+        assert_eq!(level.status().low_crossings, 1);
+        level.credit(80);
+        level.debit(80);
+        assert_eq!(level.status().low_crossings, 2);
+
+        // A smaller capacity caps the fill at once
+        level.set_config(PoolConfig { capacity: 40, low_watermark: 10, high_watermark: 40 });
+        level.credit(100);
+        assert_eq!(level.fill(), 40);
+    }
+
+    #[test]
+    fn test_mixing_keeps_earlier_input() {
+        let mut a = EntropyPool::new(64);
+        let mut b = EntropyPool::new(64);
+        assert_eq!(a.state(), None);
+        a.mix(b"first");
+        b.mix(b"other");
+        // Far more common input than the pool holds would have evicted
+        // both under first in, first out
+        let common = vec![7u8; 1000];
+        a.mix(&common);
+        b.mix(&common);
+        assert_eq!(a.len(), 64);
+        assert_ne!(a.state(), b.state());
+
+        // Shrinking folds the cut-off half in
+        let mut c = a.clone();
+        let mut d = a.clone();
+        d.state[63] ^= 1;
+        c.resize(32);
+        d.resize(32);
+        assert_ne!(c.state(), d.state());
+
+        c.clear();
+        assert_eq!(c.state(), None);
+        let seeded = EntropyPool::seeded(48, b"seed");
+        assert_eq!(seeded.state(), EntropyPool::seeded(48, b"seed").state());
+        assert_eq!(seeded.len(), 48);
+    }
+}
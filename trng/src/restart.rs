@@ -0,0 +1,171 @@
+// NIST SP 800-90B section 3.1.4 restart tests: a source that starts up the
+// same way every time, e.g. a timer read at a fixed offset after boot, can
+// look random within one run yet be predictable across restarts. Each source
+// is restarted ROWS times and COLUMNS noise samples are taken after each
+// restart. In that matrix no row (one restart) and no column (the same sample
+// position across restarts) may repeat its most common value more often than
+// the source's claimed min-entropy allows. The standard uses a 1000 x 1000
+// matrix; a smaller one keeps the startup self-test to about a second.
+use crate::sources::EntropySource;
+use crate::{ln_gamma, Trng};
+use serde::Serialize;
+use std::time::Instant;
+use tokio::net::TcpStream;
+
+pub const ROWS: usize = 128;
+pub const COLUMNS: usize = 128;
+// Overall false alarm probability; SP 800-90B spreads it over every row,
+// column and possible sample value
+const SANITY_ALPHA: f64 = 0.01;
+const SAMPLE_VALUES: usize = 256;
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct RestartTestResult {
+    pub source: EntropySource,
+    pub rows: usize,
+    pub columns: usize,
+    // Claimed min-entropy per 8-bit noise sample
+    pub min_entropy: f64,
+    // Most frequent value's count in the worst row and the worst column
+    pub max_row_count: usize,
+    pub max_column_count: usize,
+    // Counts at or above this fail
+    pub cutoff: usize,
+    pub passed: bool,
+}
+
+impl Trng {
+    // Runs the restart sanity check on `source`, which is restarted even
+    // while disabled
+    pub async fn restart_test(&self, source: EntropySource) -> RestartTestResult {
+        let mut matrix = Vec::with_capacity(ROWS);
+        for _ in 0..ROWS {
+            matrix.push(noise_samples(source, COLUMNS).await);
+        }
+        sanity_check(source, &matrix)
+    }
+}
+
+// Noise samples from a freshly started source: the raw getrandom bytes, or
+// the low byte of each clock or connect latency reading
+pub(crate) async fn noise_samples(source: EntropySource, count: usize) -> Vec<u8> {
+    match source {
+        EntropySource::Os => {
+            let mut bytes = vec![0u8; count];
+            if getrandom::getrandom(&mut bytes).is_err() {
+                bytes.clear();
+            }
+            bytes
+        }
+        EntropySource::Timing => {
+            let start = Instant::now();
+            (0..count).map(|_| start.elapsed().as_nanos() as u8).collect()
+        }
+        EntropySource::Io => {
+            let mut samples = Vec::with_capacity(count);
+            for _ in 0..count {
+                let start = Instant::now();
+                let _ = TcpStream::connect("127.0.0.1:9").await;
+                samples.push(start.elapsed().as_nanos() as u8);
+            }
+            samples
+        }
+    }
+}
+
+// Claimed min-entropy of one noise sample: the source's credit per round
+// spread over the samples in a round
+pub fn min_entropy_per_sample(source: EntropySource) -> f64 {
+    let samples_per_round = match source {
+        EntropySource::Os => 32.0,
+        EntropySource::Timing => 1000.0,
+        EntropySource::Io => 1.0,
+    };
+    (source.credit_bits() as f64 / samples_per_round).min(8.0)
+}
+
+pub(crate) fn sanity_check(source: EntropySource, matrix: &[Vec<u8>]) -> RestartTestResult {
+    let rows = matrix.len();
+    let columns = matrix.first().map_or(0, Vec::len);
+    let min_entropy = min_entropy_per_sample(source);
+    let complete = rows > 0 && columns > 0 && matrix.iter().all(|row| row.len() == columns);
+
+    let max_row_count = matrix.iter().map(|row| most_common_count(row.iter().copied())).max().unwrap_or(0);
+    let max_column_count = if complete {
+        (0..columns)
+            .map(|c| most_common_count(matrix.iter().map(|row| row[c])))
+            .max()
+            .unwrap_or(0)
+    } else {
+        0
+    };
+    // Rows and columns have the same length in the square matrix used here;
+    // the longer one sets a single cutoff otherwise
+    let alpha = SANITY_ALPHA / (SAMPLE_VALUES * (rows + columns).max(1)) as f64;
+    let cutoff = binomial_cutoff(rows.max(columns), 2f64.powf(-min_entropy), alpha);
+
+    RestartTestResult {
+        source,
+        rows,
+        columns,
+        min_entropy,
+        max_row_count,
+        max_column_count,
+        cutoff,
+        passed: complete && max_row_count < cutoff && max_column_count < cutoff,
+    }
+}
+
+pub(crate) fn most_common_count(values: impl Iterator<Item = u8>) -> usize {
+    let mut counts = [0usize; 256];
+    for value in values {
+        counts[value as usize] += 1;
+    }
+    counts.into_iter().max().unwrap_or(0)
+}
+
+// Smallest u with P(X >= u) <= alpha for X ~ Binomial(n, p)
+fn binomial_cutoff(n: usize, p: f64, alpha: f64) -> usize {
+    if p >= 1.0 {
+        return n + 1;
+    }
+    let ln_pmf = |k: usize| {
+        let k = k as f64;
+        let n = n as f64;
+        ln_gamma(n + 1.0) - ln_gamma(k + 1.0) - ln_gamma(n - k + 1.0) + k * p.ln() + (n - k) * (1.0 - p).ln()
+    };
+    let mut tail = 0.0;
+    for u in (0..=n).rev() {
+        tail += ln_pmf(u).exp();
+        if tail > alpha {
+            return u + 1;
+        }
+    }
+    0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_restart_sanity_check() {
+        // Full entropy: a Binomial(128, 1/256) count reaching 8 is rarer than
+        // the false alarm rate for one of 256 values in one of 256 rows and
+        // columns
+        assert_eq!(binomial_cutoff(128, 1.0 / 256.0, 0.01 / (256.0 * 256.0)), 8);
+
+        let trng = Trng::with_pool(Vec::new());
+        let result = trng.restart_test(EntropySource::Os).await;
+        assert!(result.passed, "{:?}", result);
+        assert_eq!((result.rows, result.columns, result.min_entropy), (ROWS, COLUMNS, 8.0));
+
+        // Each restart yields fresh values, but the same sequence every time
+        let seeded = Trng::from_seed(b"restart").rand_bytes(COLUMNS);
+        let repeating = vec![seeded; ROWS];
+        let result = sanity_check(EntropySource::Os, &repeating);
+        assert!(result.max_row_count < result.cutoff);
+        assert_eq!(result.max_column_count, ROWS);
+        assert!(!result.passed);
+    }
+}
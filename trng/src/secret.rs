@@ -0,0 +1,161 @@
+use crate::Trng;
+use std::fmt;
+use std::str::FromStr;
+
+const DIGITS: &str = "0123456789";
+const LOWER: &str = "abcdefghijklmnopqrstuvwxyz";
+const UPPER: &str = "ABCDEFGHIJKLMNOPQRSTUVWXYZ";
+const SYMBOLS: &str = "!#$%&()*+,-./:;<=>?@[]^_{|}~";
+const HEX_DIGITS: &str = "0123456789abcdef";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Charset {
+    Digits,
+    Hex,
+    Alpha,
+    Alnum,
+    AlnumSymbols,
+}
+
+impl Charset {
+    // Character classes; a generated secret contains at least one of each
+    fn classes(&self) -> &'static [&'static str] {
+        match self {
+            Charset::Digits => &[DIGITS],
+            Charset::Hex => &[HEX_DIGITS],
+            Charset::Alpha => &[LOWER, UPPER],
+            Charset::Alnum => &[LOWER, UPPER, DIGITS],
+            Charset::AlnumSymbols => &[LOWER, UPPER, DIGITS, SYMBOLS],
+        }
+    }
+}
+
+impl FromStr for Charset {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "digits" => Ok(Charset::Digits),
+            "hex" => Ok(Charset::Hex),
+            "alpha" => Ok(Charset::Alpha),
+            "alnum" => Ok(Charset::Alnum),
+            "alnum-symbols" => Ok(Charset::AlnumSymbols),
+            other => Err(format!("unknown charset '{}' (expected digits, hex, alpha, alnum or alnum-symbols)", other)),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct SecretPolicy {
+    pub length: usize,
+    pub charset: Charset,
+    pub min_entropy_bits: Option<f64>,
+}
+
+#[derive(Debug, Clone)]
+pub struct Secret {
+    pub value: String,
+    pub entropy_bits: f64,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SecretError {
+    TooShort { length: usize, classes: usize },
+    InsufficientEntropy { achieved: f64, required: f64 },
+}
+
+impl fmt::Display for SecretError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SecretError::TooShort { length, classes } => {
+                write!(f, "length {} cannot cover {} required character classes", length, classes)
+            }
+            SecretError::InsufficientEntropy { achieved, required } => {
+                write!(f, "policy yields {:.1} bits of entropy, {:.1} required", achieved, required)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SecretError {}
+
+impl SecretPolicy {
+    // Entropy of a uniform pick among all strings that satisfy the policy:
+    // L*log2(N) plus log2 of the fraction containing every class
+    // (inclusion-exclusion over the classes that are missing).
+    pub fn entropy_bits(&self) -> f64 {
+        let classes = self.charset.classes();
+        let alphabet: usize = classes.iter().map(|c| c.len()).sum();
+        let n = alphabet as f64;
+
+        let mut fraction = 0.0;
+        for mask in 0u32..(1 << classes.len()) {
+            let missing: usize = classes.iter()
+                .enumerate()
+                .filter(|(i, _)| mask & (1 << i) != 0)
+                .map(|(_, c)| c.len())
+                .sum();
+            let sign = if mask.count_ones() % 2 == 0 { 1.0 } else { -1.0 };
+            fraction += sign * ((n - missing as f64) / n).powi(self.length as i32);
+        }
+
+        self.length as f64 * n.log2() + fraction.log2()
+    }
+}
+
+impl Trng {
+    // Every character is an unbiased pick from the full alphabet; candidates
+    // missing a class are redrawn, which keeps the result uniform over the
+    // strings the policy allows.
+    pub fn generate_secret(&self, policy: &SecretPolicy) -> Result<Secret, SecretError> {
+        let classes = policy.charset.classes();
+        if policy.length < classes.len() {
+            return Err(SecretError::TooShort { length: policy.length, classes: classes.len() });
+        }
+
+        let entropy_bits = policy.entropy_bits();
+        if let Some(required) = policy.min_entropy_bits {
+            if entropy_bits < required {
+                return Err(SecretError::InsufficientEntropy { achieved: entropy_bits, required });
+            }
+        }
+
+        let alphabet: Vec<char> = classes.iter().flat_map(|c| c.chars()).collect();
+        loop {
+            let value: String = (0..policy.length)
+                .map(|_| alphabet[self.random_index(alphabet.len())])
+                .collect();
+
+            if classes.iter().all(|class| value.chars().any(|ch| class.contains(ch))) {
+                return Ok(Secret { value, entropy_bits });
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_secret_policy() {
+        let trng = Trng::with_pool(vec![0x13u8; 1024]);
+        let policy = SecretPolicy {
+            length: 32,
+            charset: Charset::AlnumSymbols,
+            min_entropy_bits: Some(128.0),
+        };
+
+        let secret = trng.generate_secret(&policy).unwrap();
+        assert_eq!(secret.value.chars().count(), 32);
+        for class in Charset::AlnumSymbols.classes() {
+            assert!(secret.value.chars().any(|ch| class.contains(ch)));
+        }
+        // Slightly below 32 * log2(90) because of the class requirement
+        assert!(secret.entropy_bits < 32.0 * 90f64.log2());
+        assert!(secret.entropy_bits > 200.0);
+
+        let weak = SecretPolicy { length: 8, charset: Charset::Digits, min_entropy_bits: Some(128.0) };
+        assert!(matches!(trng.generate_secret(&weak), Err(SecretError::InsufficientEntropy { .. })));
+    }
+}
@@ -0,0 +1,197 @@
+// Named entropy sources feeding the pool. Each can be switched off at runtime,
+// e.g. when an operator suspects it is compromised or misbehaving, and keeps
+// counters so its contribution can be watched. At least one source always
+// stays enabled, otherwise the pool would never be refreshed again.
+use serde::Serialize;
+use std::fmt;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum EntropySource {
+    // getrandom(2)
+    Os,
+    // Clock jitter over a tight loop
+    Timing,
+    // Latency of a loopback connect
+    Io,
+}
+
+impl EntropySource {
+    pub const ALL: [EntropySource; 3] = [EntropySource::Os, EntropySource::Timing, EntropySource::Io];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            EntropySource::Os => "os",
+            EntropySource::Timing => "timing",
+            EntropySource::Io => "io",
+        }
+    }
+
+    // Conservative entropy estimate for one round of output, in bits:
+    // getrandom is full entropy, the jitter sources only a few low bits
+    // per sample
+    pub fn credit_bits(&self) -> u64 {
+        match self {
+            EntropySource::Os => 256,
+            EntropySource::Timing => 64,
+            EntropySource::Io => 4,
+        }
+    }
+
+    fn index(&self) -> usize {
+        *self as usize
+    }
+}
+
+impl fmt::Display for EntropySource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())
+    }
+}
+
+impl FromStr for EntropySource {
+    type Err = SourceError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EntropySource::ALL
+            .into_iter()
+            .find(|source| source.name() == s.trim())
+            .ok_or_else(|| SourceError::Unknown(s.to_string()))
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SourceError {
+    Unknown(String),
+    // Refused: disabling it would leave no source feeding the pool
+    LastEnabled(EntropySource),
+}
+
+impl fmt::Display for SourceError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SourceError::Unknown(name) => write!(f, "unknown entropy source '{}' (expected os, timing or io)", name),
+            SourceError::LastEnabled(source) => {
+                write!(f, "{} is the last enabled entropy source and cannot be disabled", source)
+            }
+        }
+    }
+}
+
+impl std::error::Error for SourceError {}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct SourceStatus {
+    pub name: EntropySource,
+    pub enabled: bool,
+    pub bytes_collected: u64,
+    // Entropy credited so far, after any discount for correlation with
+    // another source
+    pub credited_bits: u64,
+    // Collections that yielded nothing, e.g. a failed getrandom call
+    pub failures: u64,
+    // Unix time in milliseconds, 0 if never
+    pub last_collected_at: u64,
+}
+
+#[derive(Debug, Default)]
+struct SourceState {
+    disabled: AtomicBool,
+    bytes_collected: AtomicU64,
+    credited_bits: AtomicU64,
+    failures: AtomicU64,
+    last_collected_at: AtomicU64,
+}
+
+#[derive(Debug, Default)]
+pub(crate) struct SourceRegistry {
+    states: [SourceState; 3],
+    reseeds: AtomicU64,
+}
+
+impl SourceRegistry {
+    pub(crate) fn is_enabled(&self, source: EntropySource) -> bool {
+        !self.states[source.index()].disabled.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn set_enabled(&self, source: EntropySource, enabled: bool) -> Result<(), SourceError> {
+        let others_enabled = EntropySource::ALL.iter().any(|&s| s != source && self.is_enabled(s));
+        if !enabled && !others_enabled {
+            return Err(SourceError::LastEnabled(source));
+        }
+        self.states[source.index()].disabled.store(!enabled, Ordering::Relaxed);
+        Ok(())
+    }
+
+    pub(crate) fn record(&self, source: EntropySource, bytes: usize) {
+        let state = &self.states[source.index()];
+        if bytes == 0 {
+            state.failures.fetch_add(1, Ordering::Relaxed);
+            return;
+        }
+        state.bytes_collected.fetch_add(bytes as u64, Ordering::Relaxed);
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_millis() as u64)
+            .unwrap_or(0);
+        state.last_collected_at.store(now, Ordering::Relaxed);
+    }
+
+    pub(crate) fn credit(&self, source: EntropySource, bits: u64) {
+        self.states[source.index()].credited_bits.fetch_add(bits, Ordering::Relaxed);
+    }
+
+    pub(crate) fn record_reseed(&self) {
+        self.reseeds.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub(crate) fn reseeds(&self) -> u64 {
+        self.reseeds.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn statuses(&self) -> Vec<SourceStatus> {
+        EntropySource::ALL
+            .into_iter()
+            .map(|source| {
+                let state = &self.states[source.index()];
+                SourceStatus {
+                    name: source,
+                    enabled: self.is_enabled(source),
+                    bytes_collected: state.bytes_collected.load(Ordering::Relaxed),
+                    credited_bits: state.credited_bits.load(Ordering::Relaxed),
+                    failures: state.failures.load(Ordering::Relaxed),
+                    last_collected_at: state.last_collected_at.load(Ordering::Relaxed),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::Trng;
+
+    #[tokio::test]
+    async fn test_disabled_sources_stop_contributing() {
+        let trng = Trng::from_seed(b"sources");
+        trng.set_source_enabled(EntropySource::Io, false).unwrap();
+        trng.set_source_enabled(EntropySource::Timing, false).unwrap();
+        assert_eq!(
+            trng.set_source_enabled(EntropySource::Os, false),
+            Err(SourceError::LastEnabled(EntropySource::Os))
+        );
+
+        trng.reseed_from_sources().await;
+        let statuses = trng.sources();
+        assert!(statuses[0].enabled && statuses[0].bytes_collected == 32);
+        assert_eq!(statuses[0].credited_bits, 256);
+        assert!(statuses[1..].iter().all(|s| !s.enabled && s.bytes_collected == 0));
+        assert_eq!(trng.reseeds(), 1);
+        assert_eq!("timing".parse::<EntropySource>(), Ok(EntropySource::Timing));
+        assert!("tpm".parse::<EntropySource>().is_err());
+    }
+}
@@ -0,0 +1,186 @@
+// Shamir's Secret Sharing over GF(256), one polynomial per secret byte.
+// Coefficients come from the TRNG; share indices run 1..=n (x = 0 is the secret).
+use crate::Trng;
+use std::fmt;
+use std::str::FromStr;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Share {
+    pub index: u8,
+    pub data: Vec<u8>,
+}
+
+// Text form: "<index>-<hex data>"
+impl fmt::Display for Share {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}-{}", self.index, hex::encode(&self.data))
+    }
+}
+
+impl FromStr for Share {
+    type Err = SssError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (index, data) = s.trim().split_once('-').ok_or(SssError::MalformedShare)?;
+        let index: u8 = index.parse().map_err(|_| SssError::MalformedShare)?;
+        if index == 0 {
+            return Err(SssError::MalformedShare);
+        }
+        let data = hex::decode(data).map_err(|_| SssError::MalformedShare)?;
+        Ok(Share { index, data })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SssError {
+    InvalidThreshold { threshold: u8, shares: u8 },
+    EmptySecret,
+    NotEnoughShares,
+    DuplicateShare(u8),
+    LengthMismatch,
+    MalformedShare,
+}
+
+impl fmt::Display for SssError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SssError::InvalidThreshold { threshold, shares } => {
+                write!(f, "threshold {} must be between 1 and the share count {}", threshold, shares)
+            }
+            SssError::EmptySecret => write!(f, "secret is empty"),
+            SssError::NotEnoughShares => write!(f, "no shares given"),
+            SssError::DuplicateShare(index) => write!(f, "share {} given more than once", index),
+            SssError::LengthMismatch => write!(f, "shares have different lengths"),
+            SssError::MalformedShare => write!(f, "share must look like <index>-<hex>"),
+        }
+    }
+}
+
+impl std::error::Error for SssError {}
+
+// Multiplication modulo the AES polynomial x^8 + x^4 + x^3 + x + 1
+fn gf_mul(mut a: u8, mut b: u8) -> u8 {
+    let mut product = 0u8;
+    while b != 0 {
+        if b & 1 != 0 {
+            product ^= a;
+        }
+        let carry = a & 0x80 != 0;
+        a <<= 1;
+        if carry {
+            a ^= 0x1b;
+        }
+        b >>= 1;
+    }
+    product
+}
+
+// a^254 = a^-1 in GF(256)
+fn gf_inv(a: u8) -> u8 {
+    let mut result = 1u8;
+    let mut base = a;
+    let mut exp = 254u8;
+    while exp != 0 {
+        if exp & 1 != 0 {
+            result = gf_mul(result, base);
+        }
+        base = gf_mul(base, base);
+        exp >>= 1;
+    }
+    result
+}
+
+pub fn split(trng: &Trng, secret: &[u8], threshold: u8, shares: u8) -> Result<Vec<Share>, SssError> {
+    if threshold == 0 || threshold > shares {
+        return Err(SssError::InvalidThreshold { threshold, shares });
+    }
+    if secret.is_empty() {
+        return Err(SssError::EmptySecret);
+    }
+
+    let mut out: Vec<Share> = (1..=shares)
+        .map(|index| Share { index, data: Vec::with_capacity(secret.len()) })
+        .collect();
+
+    let degree = threshold as usize - 1;
+    let randomness = trng.rand_bytes(secret.len() * degree);
+
+    for (i, &byte) in secret.iter().enumerate() {
+        let coefficients = &randomness[i * degree..(i + 1) * degree];
+        for share in out.iter_mut() {
+            // Horner evaluation of secret + c1*x + ... + c(t-1)*x^(t-1)
+            let x = share.index;
+            let mut y = 0u8;
+            for &c in coefficients.iter().rev() {
+                y = gf_mul(y, x) ^ c;
+            }
+            y = gf_mul(y, x) ^ byte;
+            share.data.push(y);
+        }
+    }
+
+    Ok(out)
+}
+
+// Lagrange interpolation at x = 0. Any `threshold` shares recover the secret;
+// fewer yield unrelated bytes, which this function cannot detect.
+pub fn combine(shares: &[Share]) -> Result<Vec<u8>, SssError> {
+    let first = shares.first().ok_or(SssError::NotEnoughShares)?;
+    let len = first.data.len();
+
+    for (i, share) in shares.iter().enumerate() {
+        if share.data.len() != len {
+            return Err(SssError::LengthMismatch);
+        }
+        if shares[..i].iter().any(|s| s.index == share.index) {
+            return Err(SssError::DuplicateShare(share.index));
+        }
+    }
+
+    let weights: Vec<u8> = shares
+        .iter()
+        .map(|share| {
+            let mut numerator = 1u8;
+            let mut denominator = 1u8;
+            for other in shares.iter().filter(|o| o.index != share.index) {
+                numerator = gf_mul(numerator, other.index);
+                denominator = gf_mul(denominator, other.index ^ share.index);
+            }
+            gf_mul(numerator, gf_inv(denominator))
+        })
+        .collect();
+
+    Ok((0..len)
+        .map(|i| {
+            shares
+                .iter()
+                .zip(&weights)
+                .fold(0u8, |acc, (share, &w)| acc ^ gf_mul(share.data[i], w))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_split_and_combine_with_threshold() {
+        let trng = Trng::with_pool(vec![0x5Au8; 1024]);
+        let secret = b"validator signing key material!!".to_vec();
+
+        let shares = split(&trng, &secret, 3, 5).unwrap();
+        assert_eq!(shares.len(), 5);
+
+        // Any 3 shares, in any order, recover the secret
+        let picked = vec![shares[4].clone(), shares[0].clone(), shares[2].clone()];
+        assert_eq!(combine(&picked).unwrap(), secret);
+
+        // Two shares are not enough
+        assert_ne!(combine(&shares[..2]).unwrap(), secret);
+
+        let parsed: Share = shares[1].to_string().parse().unwrap();
+        assert_eq!(parsed, shares[1]);
+        assert_eq!(split(&trng, &secret, 6, 5), Err(SssError::InvalidThreshold { threshold: 6, shares: 5 }));
+    }
+}
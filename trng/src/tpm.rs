@@ -0,0 +1,359 @@
+// TPM 2.0 support, spoken directly to the kernel's resource manager
+// (/dev/tpmrm0, or /dev/tpm0 on older kernels) so no TSS libraries are
+// needed: TPM2_GetRandom as an entropy source, and sealing small secrets
+// such as the master seed or a signer key file to this machine's TPM.
+//
+// Sealed objects live under a primary ECC P-256 storage key in the owner
+// hierarchy. The TPM derives that key from its own seed, so it is recreated
+// identically whenever it is needed and never stored. The sealed blob
+// decrypts only on the TPM that created it; both the owner hierarchy and
+// the object use empty authorization.
+use std::fmt;
+use std::fs::File;
+use std::io::{Read, Write};
+use std::path::{Path, PathBuf};
+
+pub const DEVICES: [&str; 2] = ["/dev/tpmrm0", "/dev/tpm0"];
+// First line of a sealed file
+pub const SEALED_HEADER: &str = "tpm2-sealed v1";
+// Largest secret a sealed data object can hold (MAX_SYM_DATA)
+pub const MAX_SEALED_LEN: usize = 128;
+// Bytes asked for per TPM2_GetRandom, the size of a SHA-256 digest
+const RANDOM_CHUNK: usize = 32;
+const MAX_RESPONSE: usize = 4096;
+
+const TPM_ST_NO_SESSIONS: u16 = 0x8001;
+const TPM_ST_SESSIONS: u16 = 0x8002;
+const TPM_CC_CREATE_PRIMARY: u32 = 0x0000_0131;
+const TPM_CC_CREATE: u32 = 0x0000_0153;
+const TPM_CC_LOAD: u32 = 0x0000_0157;
+const TPM_CC_UNSEAL: u32 = 0x0000_015E;
+const TPM_CC_FLUSH_CONTEXT: u32 = 0x0000_0165;
+const TPM_CC_GET_RANDOM: u32 = 0x0000_017B;
+const TPM_RH_OWNER: u32 = 0x4000_0001;
+const TPM_RS_PW: u32 = 0x4000_0009;
+const TPM_ALG_AES: u16 = 0x0006;
+const TPM_ALG_KEYEDHASH: u16 = 0x0008;
+const TPM_ALG_SHA256: u16 = 0x000B;
+const TPM_ALG_NULL: u16 = 0x0010;
+const TPM_ALG_ECC: u16 = 0x0023;
+const TPM_ALG_CFB: u16 = 0x0043;
+const TPM_ECC_NIST_P256: u16 = 0x0003;
+// fixedTPM | fixedParent | sensitiveDataOrigin | userWithAuth | noDA |
+// restricted | decrypt
+const STORAGE_KEY_ATTRIBUTES: u32 = 0x0003_0472;
+// fixedTPM | fixedParent | userWithAuth | noDA
+const SEALED_ATTRIBUTES: u32 = 0x0000_0452;
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TpmError {
+    // No TPM device on this machine
+    NotFound,
+    Io(String),
+    // The TPM's response code
+    Response(u32),
+    Malformed(&'static str),
+    TooLarge(usize),
+}
+
+impl fmt::Display for TpmError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TpmError::NotFound => write!(f, "no TPM found at {}", DEVICES.join(" or ")),
+            TpmError::Io(e) => write!(f, "TPM I/O failed: {}", e),
+            TpmError::Response(rc) => write!(f, "TPM returned error 0x{:03x}", rc),
+            TpmError::Malformed(what) => write!(f, "malformed TPM data: {}", what),
+            TpmError::TooLarge(len) => write!(f, "{} bytes is more than the {} a TPM can seal", len, MAX_SEALED_LEN),
+        }
+    }
+}
+
+impl std::error::Error for TpmError {}
+
+impl From<std::io::Error> for TpmError {
+    fn from(e: std::io::Error) -> Self {
+        TpmError::Io(e.to_string())
+    }
+}
+
+// A sealed object as TPM2_Create returned it, each part with its size prefix
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SealedBlob {
+    private: Vec<u8>,
+    public: Vec<u8>,
+}
+
+impl SealedBlob {
+    // The header line, then the private and public parts as hex lines
+    pub fn encode(&self) -> String {
+        format!("{}\n{}\n{}\n", SEALED_HEADER, hex::encode(&self.private), hex::encode(&self.public))
+    }
+
+    pub fn decode(text: &str) -> Result<Self, TpmError> {
+        let mut lines = text.lines().map(str::trim);
+        if lines.next() != Some(SEALED_HEADER) {
+            return Err(TpmError::Malformed("not a sealed file"));
+        }
+        let mut part = || {
+            lines
+                .next()
+                .and_then(|line| hex::decode(line).ok())
+                .ok_or(TpmError::Malformed("sealed file is truncated"))
+        };
+        Ok(Self { private: part()?, public: part()? })
+    }
+
+    pub fn is_sealed(text: &str) -> bool {
+        text.lines().next().map(str::trim) == Some(SEALED_HEADER)
+    }
+}
+
+pub struct Tpm {
+    device: File,
+    path: PathBuf,
+}
+
+impl Tpm {
+    // The first TPM device that exists
+    pub fn open() -> Result<Self, TpmError> {
+        let path = DEVICES.iter().map(Path::new).find(|path| path.exists()).ok_or(TpmError::NotFound)?;
+        Self::open_path(path)
+    }
+
+    pub fn open_path(path: &Path) -> Result<Self, TpmError> {
+        if !path.exists() {
+            return Err(TpmError::NotFound);
+        }
+        let device = File::options().read(true).write(true).open(path)?;
+        Ok(Self { device, path: path.to_path_buf() })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn get_random(&mut self, len: usize) -> Result<Vec<u8>, TpmError> {
+        let mut bytes = Vec::with_capacity(len);
+        while bytes.len() < len {
+            let want = (len - bytes.len()).min(RANDOM_CHUNK) as u16;
+            let response = self.execute(&command(TPM_CC_GET_RANDOM, &[], false, &want.to_be_bytes()))?;
+            let random = Reader::new(parameters(&response, false)?).tpm2b()?;
+            if random.is_empty() {
+                return Err(TpmError::Malformed("TPM2_GetRandom returned nothing"));
+            }
+            bytes.extend_from_slice(random);
+        }
+        bytes.truncate(len);
+        Ok(bytes)
+    }
+
+    pub fn seal(&mut self, secret: &[u8]) -> Result<SealedBlob, TpmError> {
+        if secret.len() > MAX_SEALED_LEN {
+            return Err(TpmError::TooLarge(secret.len()));
+        }
+        let primary = self.create_primary()?;
+        let mut params = Vec::new();
+        // TPM2B_SENSITIVE_CREATE: empty userAuth, the secret as data
+        put_u16(&mut params, (4 + secret.len()) as u16);
+        put_tpm2b(&mut params, &[]);
+        put_tpm2b(&mut params, secret);
+        // TPMT_PUBLIC of a keyed hash object with no scheme: sealed data
+        let mut public = Vec::new();
+        put_u16(&mut public, TPM_ALG_KEYEDHASH);
+        put_u16(&mut public, TPM_ALG_SHA256);
+        public.extend_from_slice(&SEALED_ATTRIBUTES.to_be_bytes());
+        put_tpm2b(&mut public, &[]);
+        put_u16(&mut public, TPM_ALG_NULL);
+        put_tpm2b(&mut public, &[]);
+        put_tpm2b(&mut params, &public);
+        put_tpm2b(&mut params, &[]);
+        params.extend_from_slice(&0u32.to_be_bytes());
+
+        let response = self.execute(&command(TPM_CC_CREATE, &[primary], true, &params));
+        self.flush(primary);
+        let response = response?;
+        let mut reader = Reader::new(parameters(&response, true)?);
+        let private = reader.tpm2b_with_size()?;
+        let public = reader.tpm2b_with_size()?;
+        Ok(SealedBlob { private, public })
+    }
+
+    pub fn unseal(&mut self, blob: &SealedBlob) -> Result<Vec<u8>, TpmError> {
+        let primary = self.create_primary()?;
+        let mut params = blob.private.clone();
+        params.extend_from_slice(&blob.public);
+        let loaded = self.execute(&command(TPM_CC_LOAD, &[primary], true, &params));
+        self.flush(primary);
+        let object = handle(&loaded?)?;
+
+        let response = self.execute(&command(TPM_CC_UNSEAL, &[object], true, &[]));
+        self.flush(object);
+        let response = response?;
+        Ok(Reader::new(parameters(&response, true)?).tpm2b()?.to_vec())
+    }
+
+    // The owner hierarchy's storage key for sealed objects
+    fn create_primary(&mut self) -> Result<u32, TpmError> {
+        let mut params = Vec::new();
+        // Empty TPM2B_SENSITIVE_CREATE
+        put_u16(&mut params, 4);
+        put_tpm2b(&mut params, &[]);
+        put_tpm2b(&mut params, &[]);
+        let mut public = Vec::new();
+        put_u16(&mut public, TPM_ALG_ECC);
+        put_u16(&mut public, TPM_ALG_SHA256);
+        public.extend_from_slice(&STORAGE_KEY_ATTRIBUTES.to_be_bytes());
+        put_tpm2b(&mut public, &[]);
+        // AES-128-CFB for the children, no signing scheme, P-256, no KDF
+        for field in [TPM_ALG_AES, 128, TPM_ALG_CFB, TPM_ALG_NULL, TPM_ECC_NIST_P256, TPM_ALG_NULL] {
+            put_u16(&mut public, field);
+        }
+        put_tpm2b(&mut public, &[]);
+        put_tpm2b(&mut public, &[]);
+        put_tpm2b(&mut params, &public);
+        put_tpm2b(&mut params, &[]);
+        params.extend_from_slice(&0u32.to_be_bytes());
+
+        handle(&self.execute(&command(TPM_CC_CREATE_PRIMARY, &[TPM_RH_OWNER], true, &params))?)
+    }
+
+    // Best effort: the resource manager flushes what is left on close
+    fn flush(&mut self, handle: u32) {
+        let _ = self.execute(&command(TPM_CC_FLUSH_CONTEXT, &[], false, &handle.to_be_bytes()));
+    }
+
+    // Sends one command and returns the response once its code is success
+    fn execute(&mut self, command: &[u8]) -> Result<Vec<u8>, TpmError> {
+        self.device.write_all(command)?;
+        let mut response = vec![0u8; MAX_RESPONSE];
+        let n = self.device.read(&mut response)?;
+        response.truncate(n);
+        let mut reader = Reader::new(&response);
+        reader.u16()?;
+        let size = reader.u32()? as usize;
+        let rc = reader.u32()?;
+        if rc != 0 {
+            return Err(TpmError::Response(rc));
+        }
+        if size != n {
+            return Err(TpmError::Malformed("response size does not match its header"));
+        }
+        Ok(response)
+    }
+}
+
+// A command with `handles` and, when `authorized`, one password session
+// with empty authorization
+fn command(code: u32, handles: &[u32], authorized: bool, params: &[u8]) -> Vec<u8> {
+    let tag = if authorized { TPM_ST_SESSIONS } else { TPM_ST_NO_SESSIONS };
+    let mut body = Vec::new();
+    for handle in handles {
+        body.extend_from_slice(&handle.to_be_bytes());
+    }
+    if authorized {
+        // sessionHandle, empty nonce, no attributes, empty HMAC
+        body.extend_from_slice(&9u32.to_be_bytes());
+        body.extend_from_slice(&TPM_RS_PW.to_be_bytes());
+        put_tpm2b(&mut body, &[]);
+        body.push(0);
+        put_tpm2b(&mut body, &[]);
+    }
+    body.extend_from_slice(params);
+
+    let mut command = Vec::with_capacity(10 + body.len());
+    put_u16(&mut command, tag);
+    command.extend_from_slice(&((10 + body.len()) as u32).to_be_bytes());
+    command.extend_from_slice(&code.to_be_bytes());
+    command.extend_from_slice(&body);
+    command
+}
+
+// The parameter area of a response without handles; with sessions it is
+// prefixed by its size and followed by the session area
+fn parameters(response: &[u8], sessions: bool) -> Result<&[u8], TpmError> {
+    let rest = response.get(10..).ok_or(TpmError::Malformed("response is truncated"))?;
+    if !sessions {
+        return Ok(rest);
+    }
+    let size = Reader::new(rest).u32()? as usize;
+    rest.get(4..4 + size).ok_or(TpmError::Malformed("response parameters are truncated"))
+}
+
+// The object handle a CreatePrimary or Load response leads with
+fn handle(response: &[u8]) -> Result<u32, TpmError> {
+    Reader::new(response.get(10..).unwrap_or_default()).u32()
+}
+
+fn put_u16(buf: &mut Vec<u8>, value: u16) {
+    buf.extend_from_slice(&value.to_be_bytes());
+}
+
+fn put_tpm2b(buf: &mut Vec<u8>, bytes: &[u8]) {
+    put_u16(buf, bytes.len() as u16);
+    buf.extend_from_slice(bytes);
+}
+
+struct Reader<'a> {
+    buf: &'a [u8],
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn new(buf: &'a [u8]) -> Self {
+        Self { buf, pos: 0 }
+    }
+
+    fn take(&mut self, n: usize) -> Result<&'a [u8], TpmError> {
+        let bytes = self.buf.get(self.pos..self.pos + n).ok_or(TpmError::Malformed("response is truncated"))?;
+        self.pos += n;
+        Ok(bytes)
+    }
+
+    fn u16(&mut self) -> Result<u16, TpmError> {
+        Ok(u16::from_be_bytes(self.take(2)?.try_into().expect("two bytes")))
+    }
+
+    fn u32(&mut self) -> Result<u32, TpmError> {
+        Ok(u32::from_be_bytes(self.take(4)?.try_into().expect("four bytes")))
+    }
+
+    fn tpm2b(&mut self) -> Result<&'a [u8], TpmError> {
+        let len = self.u16()? as usize;
+        self.take(len)
+    }
+
+    fn tpm2b_with_size(&mut self) -> Result<Vec<u8>, TpmError> {
+        let start = self.pos;
+        self.tpm2b()?;
+        Ok(self.buf[start..self.pos].to_vec())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_commands_and_sealed_files_round_trip() {
+        // TPM2_GetRandom for 16 bytes, as in the TPM 2.0 spec's examples
+        assert_eq!(
+            command(TPM_CC_GET_RANDOM, &[], false, &16u16.to_be_bytes()),
+            [0x80, 0x01, 0, 0, 0, 12, 0, 0, 0x01, 0x7b, 0, 16]
+        );
+        let unseal = command(TPM_CC_UNSEAL, &[0x8000_0001], true, &[]);
+        assert_eq!(unseal.len(), 10 + 4 + 4 + 9);
+        assert_eq!(&unseal[14..18], &9u32.to_be_bytes());
+
+        // Response: header, parameterSize, TPM2B of three bytes, session area
+        let response = [0x80, 0x02, 0, 0, 0, 24, 0, 0, 0, 0, 0, 0, 0, 5, 0, 3, 7, 8, 9, 0, 0, 1, 0, 0];
+        assert_eq!(Reader::new(parameters(&response, true).unwrap()).tpm2b().unwrap(), &[7, 8, 9]);
+        assert!(parameters(&response[..12], true).is_err());
+
+        let blob = SealedBlob { private: vec![0, 2, 0xaa, 0xbb], public: vec![0, 1, 0xcc] };
+        let text = blob.encode();
+        assert!(SealedBlob::is_sealed(&text));
+        assert_eq!(SealedBlob::decode(&text), Ok(blob));
+        assert!(!SealedBlob::is_sealed("00ff\n"));
+        assert!(SealedBlob::decode(SEALED_HEADER).is_err());
+    }
+}